@@ -0,0 +1,23 @@
+/*
+    Build script that records the git commit ndmig is built from, for `--version`.
+*/
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=NDMIG_GIT_COMMIT={}", commit);
+    }
+}
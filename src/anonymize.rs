@@ -0,0 +1,258 @@
+/*
+    Replaces the Discord IDs in a plain dump with pseudonyms while it streams, for `--anonymize`.
+*/
+
+use sha2::{Digest, Sha256};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::AsyncWrite;
+
+/// The columns that hold Discord user, guild or channel IDs, per table. Columns a Ballsdex version lacks are skipped.
+const IDENTIFYING_COLUMNS: &[(&str, &[&str])] = &[
+    ("player", &["discord_id"]),
+    ("guildconfig", &["guild_id", "spawn_channel"]),
+    ("ballinstance", &["server_id"]),
+    ("blacklistedid", &["discord_id", "moderator_id"]),
+    ("blacklistedguild", &["discord_id", "moderator_id"]),
+    ("blacklisthistory", &["discord_id", "moderator_id"]),
+];
+
+/// The block size of SHA-256, used for the HMAC padding.
+const BLOCK_SIZE: usize = 64;
+
+///
+/// A writer that rewrites the identifying columns of the `COPY` sections of a plain dump.
+///
+/// Every ID is replaced with an HMAC-SHA256 of it under a random key that only lives for this run, so the same ID
+/// gets the same pseudonym in every table while the original cannot be recovered from the dump.
+///
+pub struct Anonymizer<W> {
+    inner: W,
+    key: [u8; 32],
+    /// The start of a line that has not been completed yet.
+    input: Vec<u8>,
+    /// Rewritten bytes the inner writer has not accepted yet.
+    output: Vec<u8>,
+    written: usize,
+    /// The indexes of the columns to rewrite while inside a `COPY` section.
+    columns: Option<Vec<usize>>,
+}
+
+impl<W> Anonymizer<W> {
+    ///
+    /// Wraps a writer, with a new random key.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The writer the rewritten dump is passed on to.
+    ///
+    /// #### Returns
+    ///
+    /// The anonymizer, or a message if no random key could be generated.
+    ///
+    pub fn new(inner: W) -> Result<Self, String> {
+        let mut key = [0; 32];
+        getrandom::fill(&mut key).map_err(|e| format!("Failed to generate an anonymization key: {}", e))?;
+
+        Ok(Anonymizer {
+            inner,
+            key,
+            input: Vec::new(),
+            output: Vec::new(),
+            written: 0,
+            columns: None,
+        })
+    }
+
+    ///
+    /// Rewrites a single line of the dump and queues it for the inner writer.
+    ///
+    /// #### Arguments
+    ///
+    /// * `line`: The line, without its newline.
+    /// * `newline`: Whether the line ended with a newline.
+    ///
+    fn process_line(&mut self, line: &[u8], newline: bool) {
+        match &self.columns {
+            Some(_) if line == b"\\." => {
+                self.columns = None;
+                self.output.extend_from_slice(line);
+            }
+            Some(columns) => {
+                for (index, field) in line.split(|byte| *byte == b'\t').enumerate() {
+                    if index > 0 {
+                        self.output.push(b'\t');
+                    }
+
+                    // `\N` is NULL in COPY data and stays NULL.
+                    if columns.contains(&index) && field != b"\\N" {
+                        self.output.extend_from_slice(pseudonym(&self.key, field).as_bytes());
+                    } else {
+                        self.output.extend_from_slice(field);
+                    }
+                }
+            }
+            None => {
+                if line.starts_with(b"COPY ") {
+                    self.columns = identifying_columns(&String::from_utf8_lossy(line));
+                }
+                self.output.extend_from_slice(line);
+            }
+        }
+
+        if newline {
+            self.output.push(b'\n');
+        }
+    }
+
+    ///
+    /// Rewrites every complete line of the pending input.
+    ///
+    /// #### Arguments
+    ///
+    /// * `finish`: Whether the dump ended, so a last line without a newline is rewritten as well.
+    ///
+    fn process_input(&mut self, finish: bool) {
+        let input = std::mem::take(&mut self.input);
+        let mut start = 0;
+
+        while let Some(end) = input[start..].iter().position(|byte| *byte == b'\n') {
+            self.process_line(&input[start..start + end], true);
+            start += end + 1;
+        }
+
+        if finish {
+            if start < input.len() {
+                self.process_line(&input[start..], false);
+            }
+        } else {
+            self.input = input[start..].to_vec();
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Anonymizer<W> {
+    ///
+    /// Passes the rewritten bytes on to the inner writer.
+    ///
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.output.len() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.written += written;
+        }
+
+        self.output.clear();
+        self.written = 0;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Anonymizer<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        // Only take new bytes once the previous ones are passed on, so the buffer does not grow without bound.
+        ready!(this.poll_drain(cx))?;
+
+        this.input.extend_from_slice(buf);
+        this.process_input(false);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        this.process_input(true);
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+///
+/// Works out which columns of a `COPY` section hold Discord IDs.
+///
+/// #### Arguments
+///
+/// * `header`: The `COPY public.player (id, discord_id, ...) FROM stdin;` line.
+///
+/// #### Returns
+///
+/// The indexes of the columns to rewrite, or `None` if the table has none.
+///
+fn identifying_columns(header: &str) -> Option<Vec<usize>> {
+    let rest = header.strip_prefix("COPY ")?;
+    let (table, rest) = rest.split_once(" (")?;
+    let (columns, _) = rest.split_once(") FROM stdin")?;
+
+    let table = table.rsplit('.').next()?.trim_matches('"');
+    let (_, identifying) = IDENTIFYING_COLUMNS.iter().find(|(name, _)| *name == table)?;
+
+    let indexes: Vec<usize> = columns
+        .split(", ")
+        .enumerate()
+        .filter(|(_, column)| identifying.contains(&column.trim_matches('"')))
+        .map(|(index, _)| index)
+        .collect();
+
+    (!indexes.is_empty()).then_some(indexes)
+}
+
+///
+/// Derives the pseudonym of an ID.
+///
+/// #### Arguments
+///
+/// * `key`: The key of this run.
+/// * `id`: The original ID, as written in the dump.
+///
+/// #### Returns
+///
+/// A positive 63-bit number, so it still fits the `bigint` columns Discord IDs are stored in.
+///
+fn pseudonym(key: &[u8; 32], id: &[u8]) -> String {
+    let mac = hmac_sha256(key, id);
+    let value = u64::from_be_bytes(mac[..8].try_into().expect("HMAC is 32 bytes")) & (i64::MAX as u64);
+
+    value.to_string()
+}
+
+///
+/// Computes HMAC-SHA256 as specified in RFC 2104.
+///
+/// #### Arguments
+///
+/// * `key`: The key, shorter than the block size.
+/// * `message`: The message to authenticate.
+///
+fn hmac_sha256(key: &[u8; 32], message: &[u8]) -> [u8; 32] {
+    let mut inner_pad = [0x36; BLOCK_SIZE];
+    let mut outer_pad = [0x5c; BLOCK_SIZE];
+
+    for (index, byte) in key.iter().enumerate() {
+        inner_pad[index] ^= byte;
+        outer_pad[index] ^= byte;
+    }
+
+    let inner = Sha256::new().chain_update(inner_pad).chain_update(message).finalize();
+
+    Sha256::new()
+        .chain_update(outer_pad)
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
@@ -0,0 +1,748 @@
+/*
+    Copies files out of a container through the archive endpoint of the Docker API.
+*/
+
+use crate::error::{Error, ErrorKind};
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use bollard::query_parameters::{DownloadFromContainerOptions, UploadToContainerOptions};
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+
+/// The size of a tar block. Headers take one block, file contents are padded to whole blocks.
+const BLOCK_SIZE: usize = 512;
+
+/// How much of an archive is sent to Docker at a time when copying files into a container.
+const UPLOAD_CHUNK_BYTES: usize = 64 * 1024;
+
+///
+/// Where the tar reader is in the archive.
+///
+enum State {
+    /// Waiting for the next header.
+    Header,
+    /// Skipping the contents of an entry that is not a regular file, such as a pax extended header.
+    Skip(u64),
+    /// Copying the contents of the file.
+    Data(u64),
+    /// The file was copied, the rest of the archive is ignored.
+    Done,
+}
+
+///
+/// What a downloaded archive holds.
+///
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ArchiveContents {
+    /// The number of regular files.
+    pub files: u64,
+    /// The total size of the regular files, without the tar headers.
+    pub bytes: u64,
+}
+
+///
+/// Counts the files of a tar archive while it streams past, without holding more than one header in memory.
+///
+#[derive(Default)]
+struct TarScanner {
+    /// The start of a header that has not been completed yet.
+    header: Vec<u8>,
+    /// How many bytes of the current entry are still to come.
+    skip: u64,
+    contents: ArchiveContents,
+    /// The paths of the regular files inside the copied directory, only collected if set.
+    paths: Option<Vec<PathBuf>>,
+}
+
+impl TarScanner {
+    ///
+    /// Reads the next bytes of the archive.
+    ///
+    /// #### Arguments
+    ///
+    /// * `chunk`: The bytes, in the order they arrived.
+    ///
+    /// #### Returns
+    ///
+    /// Nothing, or `None` if a header is invalid.
+    ///
+    fn feed(&mut self, mut chunk: &[u8]) -> Option<()> {
+        while !chunk.is_empty() {
+            if self.skip > 0 {
+                let skipped = self.skip.min(chunk.len() as u64);
+                self.skip -= skipped;
+                chunk = &chunk[skipped as usize..];
+                continue;
+            }
+
+            let taken = (BLOCK_SIZE - self.header.len()).min(chunk.len());
+            self.header.extend_from_slice(&chunk[..taken]);
+            chunk = &chunk[taken..];
+
+            if self.header.len() < BLOCK_SIZE {
+                break;
+            }
+
+            // The empty blocks at the end of the archive have no entry.
+            if self.header.iter().any(|byte| *byte != 0) {
+                let size = entry_size(&self.header)?;
+
+                if matches!(self.header[156], b'0' | 0) {
+                    self.contents.files += 1;
+                    self.contents.bytes += size;
+
+                    if let Some(paths) = &mut self.paths
+                        && let Some(path) = entry_path(&self.header)
+                    {
+                        paths.push(path);
+                    }
+                }
+
+                self.skip = padded(size);
+            }
+
+            self.header.clear();
+        }
+
+        Some(())
+    }
+}
+
+///
+/// Copies a directory out of a container as a tar archive.
+///
+/// The archive is streamed to disk as it arrives, under `<destination>.partial` until it is complete.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `source`: The absolute path of the directory inside the container.
+/// * `destination`: Where the archive is written.
+/// * `progress`: Reports the received bytes.
+///
+/// #### Returns
+///
+/// The files in the archive, or an error.
+///
+pub async fn download_archive(
+    docker: &Docker,
+    container_id: &str,
+    source: &str,
+    destination: &Path,
+    progress: &Progress,
+) -> Result<ArchiveContents, Error> {
+    output::debug(format!("download_from_container {}: {}", container_id, source));
+
+    let mut stream = docker.download_from_container(
+        container_id,
+        Some(DownloadFromContainerOptions {
+            path: source.to_string(),
+        }),
+    );
+
+    let partial = crate::partial_path(destination);
+    let write_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", destination.display(), e),
+        )
+    };
+
+    let mut writer = BufWriter::new(tokio::fs::File::create(&partial).await.map_err(write_error)?);
+    let mut scanner = TarScanner::default();
+
+    let result = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| {
+                Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("Failed to copy {} out of the container: {}", source, e),
+                )
+            })?;
+
+            scanner.feed(&chunk).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("Docker sent an invalid archive of {}.", source),
+                )
+            })?;
+            writer.write_all(&chunk).await.map_err(write_error)?;
+            progress.inc(chunk.len() as u64);
+        }
+
+        writer.flush().await.map_err(write_error)?;
+        writer.get_ref().sync_all().await.map_err(write_error)
+    }
+    .await;
+
+    progress.finish();
+
+    if let Err(error) = result {
+        drop(writer);
+        let _ = std::fs::remove_file(&partial);
+        return Err(error);
+    }
+
+    std::fs::rename(&partial, destination).map_err(write_error)?;
+
+    Ok(scanner.contents)
+}
+
+///
+/// Lists the regular files of a directory inside a container, without copying them to the host.
+///
+/// The container does not have to be running.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `source`: The absolute path of the directory inside the container.
+///
+/// #### Returns
+///
+/// The paths of the files relative to the directory, `None` if it does not exist in the container, or an error.
+///
+pub async fn list_files(docker: &Docker, container_id: &str, source: &str) -> Result<Option<Vec<PathBuf>>, Error> {
+    output::debug(format!("download_from_container {}: {}", container_id, source));
+
+    let mut stream = docker.download_from_container(
+        container_id,
+        Some(DownloadFromContainerOptions {
+            path: source.to_string(),
+        }),
+    );
+
+    let mut scanner = TarScanner {
+        paths: Some(Vec::new()),
+        ..Default::default()
+    };
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => return Ok(None),
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("Failed to list {} in the container: {}", source, e),
+                ));
+            }
+        };
+
+        scanner.feed(&chunk).ok_or_else(|| {
+            Error::new(
+                ErrorKind::DumpFailed,
+                format!("Docker sent an invalid archive of {}.", source),
+            )
+        })?;
+    }
+
+    Ok(scanner.paths)
+}
+
+///
+/// Copies a single file out of a container into a file on the host.
+///
+/// It is written to `<destination>.partial` first and only moved into place once it is complete.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `source`: The absolute path of the file inside the container.
+/// * `destination`: Where the file is written.
+///
+/// #### Returns
+///
+/// The size of the file, `None` if it does not exist in the container, or an error.
+///
+pub async fn download_file(
+    docker: &Docker,
+    container_id: &str,
+    source: &str,
+    destination: &Path,
+) -> Result<Option<u64>, Error> {
+    let partial = crate::partial_path(destination);
+    let write_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", destination.display(), e),
+        )
+    };
+
+    let mut writer = BufWriter::new(tokio::fs::File::create(&partial).await.map_err(write_error)?);
+
+    let written = match stream_file(docker, container_id, source, &mut writer, None).await {
+        Ok(Some(written)) => written,
+        other => {
+            drop(writer);
+            let _ = std::fs::remove_file(&partial);
+            return other;
+        }
+    };
+
+    writer.flush().await.map_err(write_error)?;
+    writer.into_inner().sync_all().await.map_err(write_error)?;
+    std::fs::rename(&partial, destination).map_err(write_error)?;
+
+    Ok(Some(written))
+}
+
+///
+/// Copies a single file out of a container into a writer.
+///
+/// Docker sends the file as a tar archive, which is unpacked while it streams, so the file is never held in memory.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `source`: The absolute path of the file inside the container.
+/// * `writer`: Where the contents of the file are written.
+/// * `progress`: Reports the received bytes, if given.
+///
+/// #### Returns
+///
+/// The size of the file, `None` if it does not exist in the container, or an error.
+///
+pub async fn stream_file(
+    docker: &Docker,
+    container_id: &str,
+    source: &str,
+    writer: &mut (impl AsyncWrite + Unpin),
+    progress: Option<&Progress>,
+) -> Result<Option<u64>, Error> {
+    output::debug(format!("download_from_container {}: {}", container_id, source));
+
+    let mut stream = docker.download_from_container(
+        container_id,
+        Some(DownloadFromContainerOptions {
+            path: source.to_string(),
+        }),
+    );
+
+    let write_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write the copy of {}: {}", source, e),
+        )
+    };
+
+    let mut buffer = Vec::new();
+    let mut state = State::Header;
+    let mut found = false;
+    let mut written = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => return Ok(None),
+            Err(e) => {
+                return Err(Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("Failed to copy {} out of the container: {}", source, e),
+                ));
+            }
+        };
+
+        buffer.extend_from_slice(&chunk);
+
+        loop {
+            match state {
+                State::Header => {
+                    if buffer.len() < BLOCK_SIZE {
+                        break;
+                    }
+
+                    let header: Vec<u8> = buffer.drain(..BLOCK_SIZE).collect();
+
+                    // Two empty blocks end an archive.
+                    if header.iter().all(|byte| *byte == 0) {
+                        state = State::Done;
+                        continue;
+                    }
+
+                    let size = entry_size(&header).ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::DumpFailed,
+                            format!("Docker sent an invalid archive of {}.", source),
+                        )
+                    })?;
+
+                    state = match header[156] {
+                        b'0' | 0 => {
+                            found = true;
+                            State::Data(size)
+                        }
+                        _ => State::Skip(padded(size)),
+                    };
+                }
+                State::Skip(remaining) => {
+                    let skipped = remaining.min(buffer.len() as u64);
+                    buffer.drain(..skipped as usize);
+
+                    if skipped < remaining {
+                        state = State::Skip(remaining - skipped);
+                        break;
+                    }
+
+                    state = State::Header;
+                }
+                State::Data(remaining) => {
+                    let copied = remaining.min(buffer.len() as u64);
+
+                    writer
+                        .write_all(&buffer[..copied as usize])
+                        .await
+                        .map_err(write_error)?;
+                    buffer.drain(..copied as usize);
+                    written += copied;
+
+                    if let Some(progress) = progress {
+                        progress.inc(copied);
+                    }
+
+                    if copied < remaining {
+                        state = State::Data(remaining - copied);
+                        break;
+                    }
+
+                    state = State::Done;
+                }
+                State::Done => break,
+            }
+        }
+
+        if matches!(state, State::Done) {
+            break;
+        }
+    }
+
+    if !found || !matches!(state, State::Done) {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            format!("The archive of {} ended before the file was complete.", source),
+        ));
+    }
+
+    Ok(Some(written))
+}
+
+///
+/// Copies a directory out of a container and unpacks it into a directory on the host.
+///
+/// Only regular files and directories are unpacked, links and other entries are skipped. The archive is unpacked while
+/// it streams, so no file is held in memory.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `source`: The absolute path of the directory inside the container.
+/// * `destination`: The directory the contents of `source` are written into. It must exist.
+/// * `progress`: Reports the unpacked bytes.
+///
+/// #### Returns
+///
+/// The unpacked files, or an error.
+///
+pub async fn extract_archive(
+    docker: &Docker,
+    container_id: &str,
+    source: &str,
+    destination: &Path,
+    progress: &Progress,
+) -> Result<ArchiveContents, Error> {
+    output::debug(format!("download_from_container {}: {}", container_id, source));
+
+    let mut stream = docker.download_from_container(
+        container_id,
+        Some(DownloadFromContainerOptions {
+            path: source.to_string(),
+        }),
+    );
+
+    let write_error = |path: &Path, e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    };
+    let invalid = || {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("Docker sent an invalid archive of {}.", source),
+        )
+    };
+
+    let mut buffer = Vec::new();
+    let mut state = State::Header;
+    let mut file: Option<(PathBuf, BufWriter<tokio::fs::File>)> = None;
+    let mut contents = ArchiveContents::default();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            Error::new(
+                ErrorKind::DumpFailed,
+                format!("Failed to copy {} out of the container: {}", source, e),
+            )
+        })?;
+
+        buffer.extend_from_slice(&chunk);
+
+        loop {
+            match state {
+                State::Header => {
+                    if buffer.len() < BLOCK_SIZE {
+                        break;
+                    }
+
+                    let header: Vec<u8> = buffer.drain(..BLOCK_SIZE).collect();
+
+                    if header.iter().all(|byte| *byte == 0) {
+                        state = State::Done;
+                        continue;
+                    }
+
+                    let size = entry_size(&header).ok_or_else(invalid)?;
+                    let path = entry_path(&header).map(|path| destination.join(path));
+
+                    state = match (header[156], path) {
+                        (b'0' | 0, Some(path)) => {
+                            let created = tokio::fs::File::create(&path)
+                                .await
+                                .map_err(|e| write_error(&path, e))?;
+                            file = Some((path, BufWriter::new(created)));
+                            contents.files += 1;
+                            contents.bytes += size;
+                            State::Data(size)
+                        }
+                        (b'5', Some(path)) => {
+                            std::fs::create_dir_all(&path).map_err(|e| write_error(&path, e))?;
+                            State::Skip(padded(size))
+                        }
+                        // The entry of `source` itself, and entries such as links or pax headers.
+                        _ => State::Skip(padded(size)),
+                    };
+                }
+                State::Skip(remaining) => {
+                    let skipped = remaining.min(buffer.len() as u64);
+                    buffer.drain(..skipped as usize);
+
+                    if skipped < remaining {
+                        state = State::Skip(remaining - skipped);
+                        break;
+                    }
+
+                    if let Some((path, mut writer)) = file.take() {
+                        writer.flush().await.map_err(|e| write_error(&path, e))?;
+                    }
+
+                    state = State::Header;
+                }
+                State::Data(remaining) => {
+                    let copied = remaining.min(buffer.len() as u64);
+
+                    if let Some((path, writer)) = &mut file {
+                        writer
+                            .write_all(&buffer[..copied as usize])
+                            .await
+                            .map_err(|e| write_error(path, e))?;
+                    }
+
+                    buffer.drain(..copied as usize);
+                    progress.inc(copied);
+
+                    if copied < remaining {
+                        state = State::Data(remaining - copied);
+                        break;
+                    }
+
+                    // The padding after the contents, the file is flushed once it was skipped.
+                    state = State::Skip(padded(remaining) - remaining);
+                }
+                State::Done => break,
+            }
+        }
+
+        if matches!(state, State::Done) {
+            break;
+        }
+    }
+
+    if !matches!(state, State::Done) {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            format!("The archive of {} ended before it was complete.", source),
+        ));
+    }
+
+    Ok(contents)
+}
+
+///
+/// Reads the path of an entry from its tar header, without the directory that was copied.
+///
+/// #### Arguments
+///
+/// * `header`: The header block.
+///
+/// #### Returns
+///
+/// The relative path, or `None` for the copied directory itself and for paths that would leave it.
+///
+fn entry_path(header: &[u8]) -> Option<PathBuf> {
+    let field = |range: std::ops::Range<usize>| {
+        let bytes = &header[range];
+        let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+
+    let name = field(0..100);
+    // Only ustar headers have a prefix, older GNU headers keep other fields there.
+    let prefix = match &header[257..263] == b"ustar\0" {
+        true => field(345..500),
+        false => String::new(),
+    };
+    let name = match prefix.is_empty() {
+        true => name,
+        false => format!("{}/{}", prefix, name),
+    };
+
+    let path: PathBuf = Path::new(&name).components().skip(1).collect();
+    let safe = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    (safe && !path.as_os_str().is_empty()).then_some(path)
+}
+
+///
+/// Unpacks a tar archive into a directory of a container, replacing the files it already has.
+///
+/// The archive is streamed from the reader, so it never has to fit in memory. The container does not have to be
+/// running.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `destination`: The absolute path of the directory inside the container, which must exist.
+/// * `archive`: The tar archive.
+///
+/// #### Returns
+///
+/// Nothing, or an error if Docker refused the archive.
+///
+pub async fn upload_archive(
+    docker: &Docker,
+    container_id: &str,
+    destination: &str,
+    archive: impl AsyncRead + Send + Unpin + 'static,
+) -> Result<(), Error> {
+    output::debug(format!("upload_to_container {}: {}", container_id, destination));
+
+    // The reader is dropped after an error, which ends the upload.
+    let chunks = futures_util::stream::unfold(Some(archive), |archive| async move {
+        let mut archive = archive?;
+        let mut chunk = vec![0; UPLOAD_CHUNK_BYTES];
+
+        match archive.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(chunk.into()), Some(archive)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    docker
+        .upload_to_container(
+            container_id,
+            Some(UploadToContainerOptions {
+                path: destination.to_string(),
+                ..Default::default()
+            }),
+            bollard::body_try_stream(chunks),
+        )
+        .await
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::ImportFailed,
+                format!("Failed to copy files into {} in the container: {}", destination, e),
+            )
+        })
+}
+
+///
+/// Checks the checksum of a tar header, which tells a damaged header from one that is merely unusual.
+///
+/// #### Arguments
+///
+/// * `header`: The header block.
+///
+pub fn valid_checksum(header: &[u8]) -> bool {
+    let digits = String::from_utf8_lossy(&header[148..156]);
+    let Ok(expected) = u32::from_str_radix(digits.trim_matches(|c: char| c == '\0' || c == ' '), 8) else {
+        return false;
+    };
+
+    // The checksum is the sum of all bytes, with its own field counted as spaces.
+    let sum: u32 = header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| match index {
+            148..156 => u32::from(b' '),
+            _ => u32::from(*byte),
+        })
+        .sum();
+
+    sum == expected
+}
+
+///
+/// Reads the size of an entry from its tar header.
+///
+/// #### Arguments
+///
+/// * `header`: The header block.
+///
+/// #### Returns
+///
+/// The size in bytes, or `None` if the field is invalid.
+///
+pub fn entry_size(header: &[u8]) -> Option<u64> {
+    let field = &header[124..136];
+
+    // GNU tar stores sizes of 8 GiB and more as a big-endian number, marked by the high bit.
+    if field[0] & 0x80 != 0 {
+        return Some(field[1..].iter().fold(0, |size, byte| (size << 8) | u64::from(*byte)));
+    }
+
+    let digits = std::str::from_utf8(field)
+        .ok()?
+        .trim_matches(|c: char| c == '\0' || c == ' ');
+
+    if digits.is_empty() {
+        return Some(0);
+    }
+
+    u64::from_str_radix(digits, 8).ok()
+}
+
+///
+/// Rounds the size of an entry up to whole blocks.
+///
+/// #### Arguments
+///
+/// * `size`: The size of the entry.
+///
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
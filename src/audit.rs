@@ -0,0 +1,365 @@
+/*
+    Checks that no row of a database points at a row that does not exist, through any of its foreign keys.
+
+    postgres checks a foreign key on every insert, but not for rows loaded with `NOT VALID` (`--no-fk-check`) or while
+    a merge disables the triggers of the target, and a mistake in a rewrite shows up the same way: ballinstances whose
+    player is gone, or trade objects of a deleted trade. The audit runs after merges and as `ndmig audit`.
+*/
+
+use crate::cli::AuditArgs;
+use crate::container::{self, Database, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::json::quote;
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use crate::validate::Check;
+use bollard::Docker;
+use colored::*;
+use serde::Serialize;
+
+/// How many of the rows that point nowhere are named per foreign key.
+const SAMPLES: usize = 5;
+
+///
+/// A foreign key of the `public` schema, as `pg_constraint` describes it.
+///
+struct ForeignKey {
+    /// The name of the constraint, e.g. `ballinstance_player_id_fkey`.
+    name: String,
+    /// The table holding the reference, quoted if needed.
+    table: String,
+    /// The referencing columns, unquoted.
+    columns: Vec<String>,
+    /// The referenced table, quoted if needed.
+    referenced: String,
+    /// The referenced columns, in the order of `columns`.
+    referenced_columns: Vec<String>,
+    /// Whether the table has an `id` column to name its rows by, as every Ballsdex table does.
+    has_id: bool,
+}
+
+///
+/// A foreign key that rows of its table break.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    /// The name of the constraint.
+    pub constraint: String,
+    /// The referencing columns, e.g. `ballinstance.player_id`.
+    pub from: String,
+    /// The referenced columns, e.g. `player.id`.
+    pub to: String,
+    /// The number of rows that point at a row that does not exist.
+    pub rows: u64,
+    /// What the samples are, `id` or the referencing columns for a table without one.
+    pub key: String,
+    /// Some of those rows, at most [`SAMPLES`], by their key.
+    pub samples: Vec<String>,
+}
+
+impl Violation {
+    ///
+    /// Describes the rows, e.g. `3 rows point nowhere, e.g. id 12, 40, 41`.
+    ///
+    pub fn describe(&self) -> String {
+        let more = match self.rows > self.samples.len() as u64 {
+            true => ", ...",
+            false => "",
+        };
+
+        format!(
+            "{} row(s) point nowhere, e.g. {} {}{}",
+            self.rows,
+            self.key,
+            self.samples.join(", "),
+            more
+        )
+    }
+}
+
+///
+/// The outcome of an audit.
+///
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Audit {
+    /// The number of foreign keys that were checked.
+    pub checked: usize,
+    /// The foreign keys that rows break.
+    pub violations: Vec<Violation>,
+}
+
+impl Audit {
+    ///
+    /// Turns the audit into checks of the validation of an import: one per broken foreign key, or a single one that
+    /// passed.
+    ///
+    pub fn checks(&self) -> Vec<Check> {
+        if self.violations.is_empty() {
+            return vec![Check {
+                name: format!("rows pointing nowhere ({} foreign keys)", self.checked),
+                expected: Some("0".to_string()),
+                actual: "0".to_string(),
+                passed: true,
+            }];
+        }
+
+        self.violations
+            .iter()
+            .map(|violation| Check {
+                name: format!("{} -> {}", violation.from, violation.to),
+                expected: Some("0".to_string()),
+                actual: violation.describe(),
+                passed: false,
+            })
+            .collect()
+    }
+}
+
+///
+/// Reads the foreign keys of the `public` schema.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database to read.
+///
+async fn foreign_keys(docker: &Docker, container_id: &str, database: &Database) -> Result<Vec<ForeignKey>, Error> {
+    // The columns of a constraint keep their order, so the nth referencing column matches the nth referenced one.
+    let keys = container::rows(
+        docker,
+        container_id,
+        database,
+        "SELECT c.conname, c.conrelid::regclass, c.confrelid::regclass, \
+         array_to_string(ARRAY(SELECT a.attname FROM unnest(c.conkey) WITH ORDINALITY k(attnum, n) \
+         JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum ORDER BY k.n), ','), \
+         array_to_string(ARRAY(SELECT a.attname FROM unnest(c.confkey) WITH ORDINALITY k(attnum, n) \
+         JOIN pg_attribute a ON a.attrelid = c.confrelid AND a.attnum = k.attnum ORDER BY k.n), ','), \
+         EXISTS (SELECT 1 FROM pg_attribute a WHERE a.attrelid = c.conrelid AND a.attname = 'id' \
+         AND NOT a.attisdropped) FROM pg_constraint c JOIN pg_namespace n ON n.oid = c.connamespace \
+         WHERE c.contype = 'f' AND n.nspname = 'public' ORDER BY 2, 1",
+        ErrorKind::ChecksFailed,
+        "read the foreign keys of the database",
+    )
+    .await?;
+
+    Ok(keys
+        .into_iter()
+        .filter_map(|row| match row.as_slice() {
+            [name, table, referenced, columns, referenced_columns, has_id] => Some(ForeignKey {
+                name: name.clone(),
+                table: table.clone(),
+                columns: columns.split(',').map(str::to_string).collect(),
+                referenced: referenced.clone(),
+                referenced_columns: referenced_columns.split(',').map(str::to_string).collect(),
+                has_id: has_id == "t",
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+///
+/// Checks every foreign key of the `public` schema of a database for rows that point at a row that does not exist.
+///
+/// A reference with a `NULL` column points at nothing on purpose and is left alone, as postgres does.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database to check.
+///
+/// #### Returns
+///
+/// The foreign keys that were checked and those that rows break, or an error if they could not be read.
+///
+pub async fn run(docker: &Docker, container_id: &str, database: &Database) -> Result<Audit, Error> {
+    let keys = foreign_keys(docker, container_id, database).await?;
+
+    // Every foreign key is checked in the same query, which says how many rows break it and names a few.
+    let statements: Vec<String> = keys
+        .iter()
+        .enumerate()
+        .map(|(index, key)| {
+            let columns = key
+                .columns
+                .iter()
+                .map(|column| format!("t.{}", quote(column)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let (sample, order) = match key.has_id {
+                true => ("t.id::text".to_string(), "t.id".to_string()),
+                false => (format!("concat_ws(', ', {})", columns), columns),
+            };
+            let present = key
+                .columns
+                .iter()
+                .map(|column| format!("t.{} IS NOT NULL", quote(column)))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            let matches = key
+                .columns
+                .iter()
+                .zip(&key.referenced_columns)
+                .map(|(column, referenced)| format!("r.{} = t.{}", quote(referenced), quote(column)))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+
+            format!(
+                "SELECT {index}, count(*), coalesce(string_agg(sample, '|' ORDER BY n) FILTER (WHERE n <= {SAMPLES}), \
+                 '') FROM (SELECT {sample} AS sample, row_number() OVER (ORDER BY {order}) AS n FROM {table} t \
+                 WHERE {present} AND NOT EXISTS (SELECT 1 FROM {referenced} r WHERE {matches})) v",
+                table = key.table,
+                referenced = key.referenced,
+            )
+        })
+        .collect();
+
+    if statements.is_empty() {
+        return Ok(Audit::default());
+    }
+
+    let counted = container::rows(
+        docker,
+        container_id,
+        database,
+        &statements.join(" UNION ALL "),
+        ErrorKind::ChecksFailed,
+        "check the foreign keys of the database",
+    )
+    .await?;
+
+    let violations = counted
+        .into_iter()
+        .filter_map(|row| {
+            let (index, count, samples) = (row.first()?, row.get(1)?, &row[2..]);
+            let rows = count.parse::<u64>().ok().filter(|rows| *rows > 0)?;
+            let key = keys.get(index.parse::<usize>().ok()?)?;
+
+            Some(Violation {
+                constraint: key.name.clone(),
+                from: format!("{}.{}", key.table, key.columns.join(", ")),
+                to: format!("{}.{}", key.referenced, key.referenced_columns.join(", ")),
+                rows,
+                key: match key.has_id {
+                    true => "id".to_string(),
+                    false => key.columns.join(", "),
+                },
+                samples: samples.iter().filter(|sample| !sample.is_empty()).cloned().collect(),
+            })
+        })
+        .collect();
+
+    Ok(Audit {
+        checked: keys.len(),
+        violations,
+    })
+}
+
+///
+/// Prints the outcome of an audit.
+///
+/// #### Arguments
+///
+/// * `instance`: The name of the audited instance.
+/// * `audit`: The outcome.
+///
+fn print(instance: &str, audit: &Audit) {
+    if audit.violations.is_empty() {
+        output::status(
+            format!(
+                "{} All {} foreign keys of {} hold, no row points nowhere.",
+                Glyph::Ok,
+                audit.checked,
+                instance
+            )
+            .green(),
+        );
+        return;
+    }
+
+    output::info(format!(
+        "{} of the {} foreign keys of {} are broken:\n",
+        audit.violations.len(),
+        audit.checked,
+        instance
+    ));
+
+    let width = audit
+        .violations
+        .iter()
+        .map(|violation| violation.from.len() + violation.to.len() + 4)
+        .max()
+        .unwrap_or(0);
+
+    for violation in &audit.violations {
+        output::info(format!(
+            "  {} {}  {}",
+            Glyph::Error.to_string().red(),
+            format!("{:width$}", format!("{} -> {}", violation.from, violation.to)).bold(),
+            violation.describe()
+        ));
+    }
+}
+
+///
+/// Runs `ndmig audit`, which checks the foreign keys of an instance without changing anything.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered instances.
+/// * `args`: The audit arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the instance is not running or rows point nowhere.
+///
+pub async fn command(
+    docker: &Docker,
+    instances: &[Instance],
+    args: &AuditArgs,
+    options: &Options,
+) -> Result<(), Error> {
+    let instance = crate::match_instance(instances, &args.instance, options)
+        .map_err(|message| Error::new(ErrorKind::InstanceNotFound, message))?;
+    let name = crate::format_name(&instance.name);
+
+    if !container::is_running(docker, &instance.container_id)
+        .await
+        .unwrap_or(instance.running)
+    {
+        return Err(Error::new(
+            ErrorKind::ChecksFailed,
+            format!(
+                "{} is stopped, start it with `docker start {}` to audit it.",
+                name,
+                crate::short_id(&instance.container_id)
+            ),
+        ));
+    }
+
+    let database = container::database(docker, &instance.container_id, options).await;
+    output::status(format!("{} Checking the foreign keys of {}...", Glyph::Pending, name).yellow());
+    let audit = run(docker, &instance.container_id, &database).await?;
+
+    if output::is_json() {
+        output::json(&audit);
+    } else {
+        print(&name, &audit);
+    }
+
+    match audit.violations.is_empty() {
+        true => Ok(()),
+        false => Err(Error::new(
+            ErrorKind::ChecksFailed,
+            format!(
+                "{} foreign key(s) of {} have rows that point nowhere.",
+                audit.violations.len(),
+                name
+            ),
+        )),
+    }
+}
@@ -0,0 +1,115 @@
+/*
+    Copies the configuration of the Ballsdex bot container next to a dump, and reads which migrations its image ships.
+*/
+
+use crate::archive;
+use crate::container;
+use crate::output;
+use bollard::Docker;
+use std::path::{Path, PathBuf};
+
+/// What is appended to the dump path to get the path of the copied `config.yml`.
+pub const CONFIG_SUFFIX: &str = ".config.yml";
+
+/// Where the Ballsdex image keeps its configuration.
+pub const DEFAULT_CONFIG_PATH: &str = "/code/config.yml";
+
+/// Where the Ballsdex and NationDex images keep the aerich migrations of their models.
+pub const MIGRATIONS_PATH: &str = "/code/migrations/models";
+
+///
+/// Returns the path of the bot configuration that belongs to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn config_path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(CONFIG_SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// Copies `config.yml` out of the bot container of an instance.
+///
+/// The container does not have to be running.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+/// * `source`: The path of the configuration inside the bot container.
+/// * `dump_path`: The path of the dump the configuration belongs to.
+///
+/// #### Returns
+///
+/// The size of the copied file, or a message if there was nothing to copy. The dump is complete without it.
+///
+pub async fn export_config(docker: &Docker, instance: &str, source: &str, dump_path: &Path) -> Result<u64, String> {
+    let bot = container::find_service(docker, instance, "bot")
+        .await
+        .map_err(|e| format!("Failed to look for the bot container: {}", e))?
+        .ok_or_else(|| {
+            format!(
+                "{}-bot-1 does not exist, the bot configuration was not copied.",
+                container::project(instance)
+            )
+        })?;
+
+    let destination = config_path(dump_path);
+
+    match archive::download_file(docker, &bot.container_id, source, &destination).await {
+        Ok(Some(bytes)) => Ok(bytes),
+        Ok(None) => Err(format!(
+            "{} has no {}, the bot configuration was not copied. Pass --bot-config if it lives elsewhere.",
+            bot.name, source
+        )),
+        Err(error) => Err(error.message),
+    }
+}
+
+///
+/// Returns the newest aerich migration the bot image of an instance ships, e.g. `12_20240105120000_update.py`.
+///
+/// aerich numbers its migrations in the order they were made, and records the file name of each one it applied in
+/// the `version` column of its table.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+///
+/// #### Returns
+///
+/// The file name of the migration, or `None` if the bot container or its migrations could not be found.
+///
+pub async fn latest_migration(docker: &Docker, instance: &str) -> Option<String> {
+    let bot = match container::find_service(docker, instance, "bot").await {
+        Ok(bot) => bot?,
+        Err(e) => {
+            output::debug(format!("Failed to look for the bot container: {}", e));
+            return None;
+        }
+    };
+
+    let files = match archive::list_files(docker, &bot.container_id, MIGRATIONS_PATH).await {
+        Ok(files) => files?,
+        Err(error) => {
+            output::debug(format!(
+                "Failed to list the migrations of {}: {}",
+                bot.name, error.message
+            ));
+            return None;
+        }
+    };
+
+    files
+        .iter()
+        .filter(|path| path.components().count() == 1)
+        .filter_map(|path| path.to_str())
+        .filter(|name| name.ends_with(".py"))
+        .filter_map(|name| Some((name.split('_').next()?.parse::<u64>().ok()?, name)))
+        .max()
+        .map(|(_, name)| name.to_string())
+}
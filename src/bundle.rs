@@ -0,0 +1,651 @@
+/*
+    Single-file bundles of a dump and everything that belongs to it, for `--bundle`, and reading them for `import`.
+
+    A bundle is a plain tar archive. The dump comes first, followed by the files an export without `--bundle` writes
+    next to it, under the same names, so `tar -xf` gives back exactly the loose layout. An import reads the files
+    where they are in the archive, so nothing is extracted to disk.
+*/
+
+use crate::archive;
+use crate::bot;
+use crate::checksum::{self, HashingWriter};
+use crate::cli::ExportArgs;
+use crate::container::{Database, DatabaseDump, Instance};
+use crate::dumps;
+use crate::error::{Error, ErrorKind};
+use crate::manifest::{self, Manifest};
+use crate::media;
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// The extension of a bundle, e.g. `mydex-20250101-120000.ndmig.tar`.
+pub const SUFFIX: &str = ".ndmig.tar";
+
+/// The size of a tar block.
+pub const BLOCK_SIZE: u64 = 512;
+
+///
+/// A file inside a bundle.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Member {
+    pub name: String,
+    pub bytes: u64,
+    /// Where the contents of the file start in the bundle.
+    #[serde(skip)]
+    pub offset: u64,
+}
+
+///
+/// What an import found in a bundle.
+///
+#[derive(Debug, Clone)]
+pub struct Contents {
+    /// The dump, the first file of the bundle.
+    pub dump: Member,
+    /// The manifest of the dump, `None` if the bundle has none.
+    pub manifest: Option<Manifest>,
+    /// The SHA-256 of the dump from the checksum file of the bundle, `None` if it has none.
+    pub sha256: Option<String>,
+    /// The `config.yml` of the bot, `None` if it was not exported.
+    pub config: Option<Member>,
+    /// The archive of the media directory, `None` if the export did not include it.
+    pub media: Option<Member>,
+}
+
+///
+/// What an import does with the `config.yml` and media of a bundle.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleFiles {
+    /// Ask for each of them once the dump is imported, and leave them where they are without a terminal.
+    #[default]
+    Ask,
+    /// Copy them into the containers of the target without asking.
+    Restore,
+    /// Leave them in the bundle.
+    Skip,
+}
+
+///
+/// Returns the path of the bundle that holds a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump, e.g. `mydex-20250101-120000.sql.zst`.
+///
+/// #### Returns
+///
+/// The path in the same directory with the extensions replaced, e.g. `mydex-20250101-120000.ndmig.tar`.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let name = dump.file_name().unwrap_or_default().to_string_lossy();
+    let stem = name.split_once('.').map_or(name.as_ref(), |(stem, _)| stem);
+
+    dump.with_file_name(format!("{}{}", stem, SUFFIX))
+}
+
+///
+/// Checks whether a path names a bundle.
+///
+/// #### Arguments
+///
+/// * `path`: The path to check.
+///
+pub fn is_bundle(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(SUFFIX)
+}
+
+///
+/// Builds the ustar header of a regular file.
+///
+/// #### Arguments
+///
+/// * `name`: The name of the file inside the archive.
+/// * `size`: The size of the file.
+///
+/// #### Returns
+///
+/// The header block, or a message if the name does not fit.
+///
+pub fn header(name: &str, size: u64) -> Result<[u8; BLOCK_SIZE as usize], String> {
+    if name.len() >= 100 {
+        return Err(format!("{} is too long a name for the bundle.", name));
+    }
+
+    let mut header = [0; BLOCK_SIZE as usize];
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+
+    // Sizes of 8 GiB and more do not fit the octal field, they are stored as a big-endian number like GNU tar does.
+    if size < 0o77777777777 {
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    } else {
+        header[124] = 0x80;
+        header[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+
+    header[136..148].copy_from_slice(format!("{:011o}\0", mtime).as_bytes());
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    seal(&mut header);
+
+    Ok(header)
+}
+
+///
+/// Builds the ustar header of a directory.
+///
+/// #### Arguments
+///
+/// * `name`: The name of the directory inside the archive, without a trailing slash.
+///
+/// #### Returns
+///
+/// The header block, or a message if the name does not fit.
+///
+pub fn directory_header(name: &str) -> Result<[u8; BLOCK_SIZE as usize], String> {
+    let mut header = header(&format!("{}/", name), 0)?;
+    header[100..108].copy_from_slice(b"0000755\0");
+    header[156] = b'5';
+    seal(&mut header);
+
+    Ok(header)
+}
+
+///
+/// Writes the checksum of a header into it.
+///
+/// #### Arguments
+///
+/// * `header`: The header block, complete but for its checksum.
+///
+fn seal(header: &mut [u8; BLOCK_SIZE as usize]) {
+    // The checksum is computed with its own field filled with spaces.
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+}
+
+///
+/// Returns the zero bytes that pad a file of a size to whole blocks.
+///
+pub fn padding(size: u64) -> Vec<u8> {
+    vec![0; (size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE - size) as usize]
+}
+
+///
+/// Writes the dump as the first file of a new bundle.
+///
+/// The dump streams straight into `<bundle>.partial`. Its header is written once the size is known, and the bundle
+/// stays partial until [`finish`] added the other files.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to dump.
+/// * `bundle_path`: The path of the bundle.
+/// * `dump_path`: The path the dump would have on its own, whose file name it gets inside the bundle.
+/// * `progress`: Reports the received bytes.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to dump.
+///
+/// #### Returns
+///
+/// The dump that was written, its SHA-256 and its size in the bundle, or an error.
+///
+pub async fn write_dump(
+    docker: &Docker,
+    instance: &Instance,
+    bundle_path: &Path,
+    dump_path: &Path,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<(DatabaseDump, String, u64), Error> {
+    let partial = crate::partial_path(bundle_path);
+    let write_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", bundle_path.display(), e),
+        )
+    };
+
+    let name = dump_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    // Checked before anything is dumped, rather than once the header is written.
+    header(&name, 0).map_err(|message| Error::new(ErrorKind::Filesystem, message))?;
+
+    let mut file = tokio::fs::File::create(&partial).await.map_err(write_error)?;
+
+    let result = async {
+        file.write_all(&[0; BLOCK_SIZE as usize]).await?;
+
+        let mut writer = HashingWriter::new(tokio::io::BufWriter::new(&mut file));
+        let dump = crate::stream_encrypted(docker, instance, progress, args, database, &mut writer).await;
+        writer.flush().await?;
+
+        let bytes = writer.bytes();
+        let (_, sha256) = writer.finish();
+
+        file.write_all(&padding(bytes)).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+        file.write_all(&header(&name, bytes).map_err(std::io::Error::other)?)
+            .await?;
+        file.flush().await?;
+
+        Ok::<_, std::io::Error>((dump, sha256, bytes))
+    }
+    .await;
+
+    match result {
+        Ok((Ok(dump), sha256, bytes)) => Ok((dump, sha256, bytes)),
+        Ok((Err(error), _, _)) => {
+            drop(file);
+            let _ = tokio::fs::remove_file(&partial).await;
+            Err(error)
+        }
+        Err(e) => {
+            drop(file);
+            let _ = tokio::fs::remove_file(&partial).await;
+            Err(write_error(e))
+        }
+    }
+}
+
+///
+/// Opens the dump inside a partial bundle for reading, e.g. to verify it.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle, whose `.partial` file is read.
+///
+/// #### Returns
+///
+/// A reader over exactly the bytes of the dump.
+///
+pub async fn open_dump(bundle_path: &Path) -> std::io::Result<impl AsyncRead + Unpin + Send + use<>> {
+    let mut file = tokio::fs::File::open(crate::partial_path(bundle_path)).await?;
+    let mut header = [0; BLOCK_SIZE as usize];
+    file.read_exact(&mut header).await?;
+
+    let size = archive::entry_size(&header).ok_or_else(|| std::io::Error::other("invalid bundle header"))?;
+
+    Ok(file.take(size))
+}
+
+///
+/// Adds the files next to the dump to its bundle and moves the finished bundle into place.
+///
+/// The loose files are removed once the bundle is complete.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle, written by [`write_dump`].
+/// * `dump_path`: The path the dump would have on its own, next to which the other files were written.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the bundle could not be completed.
+///
+pub fn finish(bundle_path: &Path, dump_path: &Path) -> Result<(), Error> {
+    let partial = crate::partial_path(bundle_path);
+    let write_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", bundle_path.display(), e),
+        )
+    };
+
+    let sidecars: Vec<PathBuf> = dumps::sidecars(dump_path)
+        .into_iter()
+        .filter(|sidecar| sidecar.is_file())
+        .collect();
+
+    let mut bundle = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&partial)
+        .map_err(write_error)?;
+
+    for sidecar in &sidecars {
+        let name = sidecar.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut file = File::open(sidecar).map_err(write_error)?;
+        let size = file.metadata().map_err(write_error)?.len();
+
+        output::debug(format!("Adding {} to {}", name, bundle_path.display()));
+
+        bundle
+            .write_all(&header(&name, size).map_err(|message| Error::new(ErrorKind::Filesystem, message))?)
+            .map_err(write_error)?;
+        std::io::copy(&mut (&mut file).take(size), &mut bundle).map_err(write_error)?;
+        bundle.write_all(&padding(size)).map_err(write_error)?;
+    }
+
+    // Two empty blocks end the archive.
+    bundle.write_all(&[0; 2 * BLOCK_SIZE as usize]).map_err(write_error)?;
+    bundle.sync_all().map_err(write_error)?;
+    drop(bundle);
+
+    std::fs::rename(&partial, bundle_path).map_err(write_error)?;
+
+    for sidecar in &sidecars {
+        let _ = std::fs::remove_file(sidecar);
+    }
+
+    Ok(())
+}
+
+///
+/// Lists the files of a bundle without reading their contents.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle.
+///
+/// #### Returns
+///
+/// The files in archive order, or a message if the bundle cannot be read.
+///
+pub fn members(bundle_path: &Path) -> Result<Vec<Member>, String> {
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", bundle_path.display(), e);
+    let mut file = File::open(bundle_path).map_err(read_error)?;
+    let mut members = Vec::new();
+
+    for_each_member(&mut file, |name, bytes, file| {
+        members.push(Member {
+            name: name.to_string(),
+            bytes,
+            offset: file.stream_position()?,
+        });
+        Ok(true)
+    })
+    .map_err(read_error)?;
+
+    Ok(members)
+}
+
+///
+/// Reads what a bundle holds for an import, checking the archive on the way.
+///
+/// Every header must be intact and every file must end within the archive, so a damaged or cut off bundle fails
+/// here, before anything is done to the target. The contents of the dump are checked against its checksum by
+/// [`crate::restore::verify`].
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle.
+///
+/// #### Returns
+///
+/// The files of the bundle, or a message if it is damaged or holds no dump.
+///
+pub fn contents(bundle_path: &Path) -> Result<Contents, String> {
+    let members = members(bundle_path)?;
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", bundle_path.display(), e);
+
+    let dump = members
+        .first()
+        .cloned()
+        .ok_or_else(|| format!("{} holds no dump.", bundle_path.display()))?;
+
+    let sidecar = |suffix: &str| {
+        let name = format!("{}{}", dump.name, suffix);
+        members.iter().find(|member| member.name == name).cloned()
+    };
+
+    if let Some(sidecar) = dumps::SIDECAR_SUFFIXES
+        .iter()
+        .find(|suffix| dump.name.ends_with(**suffix))
+    {
+        return Err(format!(
+            "{} starts with a {} file rather than a dump, it was not written by ndmig.",
+            bundle_path.display(),
+            sidecar
+        ));
+    }
+
+    let sha256 = match sidecar(checksum::SUFFIX) {
+        Some(member) => {
+            let text = read_member(bundle_path, &member).map_err(read_error)?;
+            Some(
+                checksum::parse(&text)
+                    .ok_or_else(|| format!("Invalid checksum file {} in {}", member.name, bundle_path.display()))?,
+            )
+        }
+        None => None,
+    };
+
+    Ok(Contents {
+        manifest: read_manifest(bundle_path)?,
+        sha256,
+        config: sidecar(bot::CONFIG_SUFFIX),
+        media: sidecar(media::SUFFIX),
+        dump,
+    })
+}
+
+///
+/// Reads a small file of a bundle as text, e.g. its checksum file.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle.
+/// * `member`: The file.
+///
+fn read_member(bundle_path: &Path, member: &Member) -> std::io::Result<String> {
+    let mut file = File::open(bundle_path)?;
+    file.seek(SeekFrom::Start(member.offset))?;
+
+    let mut text = String::new();
+    file.take(member.bytes).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+///
+/// Opens a file of a bundle for reading, e.g. the dump to stream it into psql.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle.
+/// * `member`: The file.
+///
+/// #### Returns
+///
+/// A reader over exactly the bytes of the file.
+///
+pub async fn open_member(bundle_path: &Path, member: &Member) -> std::io::Result<tokio::io::Take<tokio::fs::File>> {
+    let mut file = tokio::fs::File::open(bundle_path).await?;
+    file.seek(SeekFrom::Start(member.offset)).await?;
+
+    Ok(file.take(member.bytes))
+}
+
+///
+/// Copies the `config.yml` of a bundle into a container, replacing the one it has.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the bot of the target.
+/// * `bundle_path`: The path of the bundle.
+/// * `member`: The `config.yml` inside the bundle.
+/// * `destination`: The absolute path of the configuration inside the container, e.g. `/code/config.yml`.
+///
+/// #### Returns
+///
+/// Nothing, or an error if it could not be read or copied.
+///
+pub async fn restore_config(
+    docker: &Docker,
+    container_id: &str,
+    bundle_path: &Path,
+    member: &Member,
+    destination: &str,
+) -> Result<(), Error> {
+    let destination = Path::new(destination);
+    let (Some(directory), Some(name)) = (destination.parent(), destination.file_name()) else {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!("{} is not the path of a file.", destination.display()),
+        ));
+    };
+
+    let contents = read_member(bundle_path, member).map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", bundle_path.display(), e),
+        )
+    })?;
+
+    // The upload endpoint takes an archive, so the file gets one of its own.
+    let name = name.to_string_lossy();
+    let mut archive = header(&name, contents.len() as u64)
+        .map_err(|message| Error::new(ErrorKind::Config, message))?
+        .to_vec();
+    archive.extend_from_slice(contents.as_bytes());
+    archive.extend_from_slice(&padding(contents.len() as u64));
+    archive.extend_from_slice(&[0; 2 * BLOCK_SIZE as usize]);
+
+    archive::upload_archive(
+        docker,
+        container_id,
+        &directory.to_string_lossy(),
+        std::io::Cursor::new(archive),
+    )
+    .await
+}
+
+///
+/// Unpacks the media archive of a bundle into a container, next to the files it has.
+///
+/// The archive holds the media directory itself, as copied out of the source, so it is unpacked into the parent of
+/// the media directory of the target.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the admin panel or bot of the target.
+/// * `bundle_path`: The path of the bundle.
+/// * `member`: The media archive inside the bundle.
+/// * `directory`: The absolute path of the media directory inside the container.
+///
+/// #### Returns
+///
+/// Nothing, or an error if it could not be read or copied.
+///
+pub async fn restore_media(
+    docker: &Docker,
+    container_id: &str,
+    bundle_path: &Path,
+    member: &Member,
+    directory: &str,
+) -> Result<(), Error> {
+    let parent = Path::new(directory).parent().unwrap_or(Path::new("/"));
+    let archive = open_member(bundle_path, member).await.map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", bundle_path.display(), e),
+        )
+    })?;
+
+    archive::upload_archive(docker, container_id, &parent.to_string_lossy(), archive).await
+}
+
+///
+/// Reads the manifest inside a bundle.
+///
+/// #### Arguments
+///
+/// * `bundle_path`: The path of the bundle.
+///
+/// #### Returns
+///
+/// The manifest, `None` if the bundle has none, or a message if it cannot be read.
+///
+pub fn read_manifest(bundle_path: &Path) -> Result<Option<Manifest>, String> {
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {}", bundle_path.display(), e);
+    let mut file = File::open(bundle_path).map_err(read_error)?;
+    let mut contents = None;
+
+    for_each_member(&mut file, |name, bytes, file| {
+        if !name.ends_with(manifest::SUFFIX) {
+            return Ok(true);
+        }
+
+        let mut text = String::new();
+        file.take(bytes).read_to_string(&mut text)?;
+        contents = Some(text);
+        Ok(false)
+    })
+    .map_err(read_error)?;
+
+    contents
+        .map(|contents| {
+            serde_json::from_str(&contents).map_err(|e| format!("Invalid manifest in {}: {}", bundle_path.display(), e))
+        })
+        .transpose()
+}
+
+///
+/// Walks the regular files of a tar archive, skipping over their contents.
+///
+/// #### Arguments
+///
+/// * `file`: The archive, positioned at its start.
+/// * `visit`: Called with the name, the size and the archive positioned at the contents of every file. It may read
+///   them, and returns whether to go on.
+///
+fn for_each_member(
+    file: &mut File,
+    mut visit: impl FnMut(&str, u64, &mut File) -> std::io::Result<bool>,
+) -> std::io::Result<()> {
+    let invalid = || std::io::Error::other("not a valid bundle");
+    let length = file.metadata()?.len();
+    let mut position = 0;
+
+    loop {
+        let mut header = [0; BLOCK_SIZE as usize];
+        file.seek(SeekFrom::Start(position))?;
+
+        // An archive without its end blocks, such as one that is still being written, just ends.
+        if file.read_exact(&mut header).is_err() || header.iter().all(|byte| *byte == 0) {
+            return Ok(());
+        }
+
+        if !archive::valid_checksum(&header) {
+            return Err(std::io::Error::other(format!(
+                "the header at byte {} is damaged",
+                position
+            )));
+        }
+
+        let size = archive::entry_size(&header).ok_or_else(invalid)?;
+        let end = header[..100].iter().position(|byte| *byte == 0).unwrap_or(100);
+        let name = String::from_utf8_lossy(&header[..end]).into_owned();
+
+        if position + BLOCK_SIZE + size > length {
+            return Err(std::io::Error::other(format!("{} is cut off", name)));
+        }
+
+        if matches!(header[156], b'0' | 0) && !visit(&name, size, file)? {
+            return Ok(());
+        }
+
+        position += BLOCK_SIZE + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+    }
+}
@@ -0,0 +1,499 @@
+/*
+    The order the dumps of an import are loaded in.
+
+    The data file of a `--split` export is loaded after its schema file, which is picked up from next to it when it is
+    not passed as well. A schema file the target has applied already is not loaded again.
+
+    Every delta is loaded right after the dump it was exported on top of, going by the SHA-256 of its base. A chain
+    that starts with a delta has to build on the dump imported into the target last.
+*/
+
+use crate::container::Database;
+use crate::encrypt::Decryption;
+use crate::error::{Error, ErrorKind};
+use crate::history;
+use crate::output::{self, Glyph};
+use crate::restore::{self, Dump};
+use crate::split::{self, Split};
+use bollard::Docker;
+use colored::*;
+use std::collections::BTreeSet;
+
+///
+/// Returns the split a dump is a part of.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump.
+/// * `mode`: The part, `schema` or `data`.
+///
+/// #### Returns
+///
+/// The split, or `None` if the dump is not that part of a split export.
+///
+fn part<'a>(dump: &'a Dump, mode: &str) -> Option<&'a Split> {
+    let manifest = dump.manifest.as_ref()?;
+
+    match manifest.mode.as_deref() == Some(mode) {
+        true => manifest.split.as_ref(),
+        false => None,
+    }
+}
+
+///
+/// Returns whether a dump is a delta of `--incremental`.
+///
+fn is_delta(dump: &Dump) -> bool {
+    dump.manifest.as_ref().and_then(|manifest| manifest.mode.as_deref()) == Some("delta")
+}
+
+///
+/// Returns the SHA-256 of the dump a delta was exported on top of.
+///
+fn base_sha256(dump: &Dump) -> Option<&str> {
+    dump.manifest.as_ref()?.base_sha256.as_deref()
+}
+
+///
+/// Returns the file name of the dump a delta was exported on top of, for messages.
+///
+fn base_name(dump: &Dump) -> &str {
+    dump.manifest
+        .as_ref()
+        .and_then(|manifest| manifest.base.as_deref())
+        .unwrap_or("its base")
+}
+
+///
+/// Adds the schema file of every split export whose data file is imported without it, if it is next to the data file.
+///
+/// #### Arguments
+///
+/// * `dumps`: The dumps passed to the import, as opened by [`restore::open_with`].
+/// * `decryption`: How to decrypt a schema file that is encrypted.
+///
+/// #### Returns
+///
+/// The dumps in the order they are loaded, see [`order`] and [`link`], or an error if a schema file found is not the
+/// one the split recorded or the deltas do not form a chain.
+///
+pub async fn complete(dumps: Vec<Dump>, decryption: &Decryption) -> Result<Vec<Dump>, Error> {
+    let dumps = order(dumps);
+    let mut completed = Vec::with_capacity(dumps.len());
+
+    for (index, dump) in dumps.iter().enumerate() {
+        let Some(split) = part(dump, "data") else {
+            continue;
+        };
+
+        let paired = dumps[..index]
+            .iter()
+            .any(|other| part(other, "schema").is_some_and(|other| other.id == split.id));
+        let path = dump.path.with_file_name(&split.schema.file);
+
+        if paired || !path.is_file() {
+            continue;
+        }
+
+        let schema = restore::open_with(&path, Some(decryption)).await?;
+
+        if let (Some(expected), Some(actual)) = (&split.schema.sha256, &schema.sha256)
+            && expected != actual
+        {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} is not the schema file {} was split from, its SHA-256 is {} instead of {}. Pass the right one \
+                     along with the data file.",
+                    path.display(),
+                    dump.path.display(),
+                    actual,
+                    expected
+                ),
+            ));
+        }
+
+        output::status(
+            format!(
+                "{} Found {}, the schema file of {}, it is loaded first.",
+                Glyph::Ok,
+                path.display(),
+                dump.path.display()
+            )
+            .dimmed(),
+        );
+        completed.push((index, schema));
+    }
+
+    let mut found = completed.into_iter().peekable();
+    let mut ordered = Vec::new();
+
+    for (index, dump) in dumps.into_iter().enumerate() {
+        if let Some((_, schema)) = found.next_if(|(before, _)| *before == index) {
+            ordered.push(schema);
+        }
+
+        ordered.push(dump);
+    }
+
+    link(ordered)
+}
+
+///
+/// Moves the schema file of every split export in front of its data file, keeping the order of the other dumps.
+///
+/// #### Arguments
+///
+/// * `dumps`: The dumps of the import.
+///
+pub fn order(dumps: Vec<Dump>) -> Vec<Dump> {
+    let mut pending: Vec<Option<Dump>> = dumps.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(pending.len());
+
+    for index in 0..pending.len() {
+        let Some(dump) = pending[index].take() else {
+            continue;
+        };
+
+        if let Some(split) = part(&dump, "data") {
+            let schema = pending.iter().position(|other| {
+                other
+                    .as_ref()
+                    .and_then(|other| part(other, "schema"))
+                    .is_some_and(|other| other.id == split.id)
+            });
+
+            ordered.extend(schema.and_then(|schema| pending[schema].take()));
+        }
+
+        ordered.push(dump);
+    }
+
+    ordered
+}
+
+///
+/// Moves every delta right after the dump it was exported on top of, keeping the order of the other dumps.
+///
+/// #### Arguments
+///
+/// * `dumps`: The dumps of the import.
+///
+/// #### Returns
+///
+/// The dumps in the order they are loaded, or an error if a delta builds on none of them. Without other dumps, the
+/// deltas have to form a single chain, whose first delta builds on a dump imported before.
+///
+pub fn link(dumps: Vec<Dump>) -> Result<Vec<Dump>, Error> {
+    let (mut deltas, others): (Vec<Dump>, Vec<Dump>) = dumps.into_iter().partition(is_delta);
+    let mut ordered = Vec::with_capacity(others.len() + deltas.len());
+
+    if others.is_empty() && !deltas.is_empty() {
+        let heads: Vec<usize> = (0..deltas.len())
+            .filter(|&index| {
+                !deltas
+                    .iter()
+                    .any(|other| other.sha256.is_some() && other.sha256.as_deref() == base_sha256(&deltas[index]))
+            })
+            .collect();
+
+        match heads[..] {
+            [head] => ordered.push(deltas.remove(head)),
+            _ => {
+                let names: Vec<String> = deltas.iter().map(|delta| delta.path.display().to_string()).collect();
+
+                return Err(Error::new(
+                    ErrorKind::Config,
+                    format!(
+                        "The deltas {} do not form a single chain. Import the deltas of one chain, in any order.",
+                        names.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        follow(&mut ordered, &mut deltas);
+    }
+
+    for dump in others {
+        ordered.push(dump);
+        follow(&mut ordered, &mut deltas);
+    }
+
+    if let Some(delta) = deltas.first() {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} was exported on top of {} (SHA-256 {}), which is not among the dumps of this import. Pass the \
+                 base and the deltas in between as well, or import the deltas on their own.",
+                delta.path.display(),
+                base_name(delta),
+                base_sha256(delta).unwrap_or("unknown")
+            ),
+        ));
+    }
+
+    Ok(ordered)
+}
+
+///
+/// Moves the deltas that build on the last ordered dump after it, one after the other.
+///
+/// #### Arguments
+///
+/// * `ordered`: The dumps ordered so far.
+/// * `deltas`: The deltas not ordered yet.
+///
+fn follow(ordered: &mut Vec<Dump>, deltas: &mut Vec<Dump>) {
+    while let Some(sha256) = ordered.last().and_then(|dump| dump.sha256.as_deref())
+        && let Some(next) = deltas.iter().position(|delta| base_sha256(delta) == Some(sha256))
+    {
+        let next = deltas.remove(next);
+        ordered.push(next);
+    }
+}
+
+///
+/// Checks that a chain starting with a delta builds on the dump imported into the target last.
+///
+/// #### Arguments
+///
+/// * `instance`: The short name of the target.
+/// * `dumps`: The dumps of the import, in the order they are loaded.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the history records another dump as imported last. Without a record, only a warning is
+/// printed.
+///
+pub fn check_base(instance: &str, dumps: &[Dump]) -> Result<(), Error> {
+    let Some(delta) = dumps.first().filter(|dump| is_delta(dump)) else {
+        return Ok(());
+    };
+
+    let Some(expected) = base_sha256(delta) else {
+        return Ok(());
+    };
+
+    let last = history::last_import(instance);
+
+    match last.as_ref().and_then(|entry| entry.sha256.as_deref()) {
+        Some(sha256) if sha256 == expected => Ok(()),
+        Some(sha256) => Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} was exported on top of {} (SHA-256 {}), but the dump imported into {} last was {} (SHA-256 {}). \
+                 Import the dumps in between first, or pass its base along with the deltas.",
+                delta.path.display(),
+                base_name(delta),
+                expected,
+                instance,
+                last.as_ref()
+                    .and_then(|entry| entry.dump_path.as_ref())
+                    .map_or("another dump".to_string(), |path| path.display().to_string()),
+                sha256
+            ),
+        )),
+        None => {
+            output::status(
+                format!(
+                    "{} No import into {} is recorded with its checksum, so it cannot be checked that {} was imported \
+                     before {}.",
+                    Glyph::Warning,
+                    instance,
+                    base_name(delta),
+                    delta.path.display()
+                )
+                .yellow(),
+            );
+            Ok(())
+        }
+    }
+}
+
+///
+/// Finds the schema files the target has applied already, whose marker is still waiting for their data file.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running target.
+/// * `database`: The role and database the dumps are loaded into.
+/// * `dumps`: The dumps of the import, in the order they are loaded.
+///
+/// #### Returns
+///
+/// The positions of those schema files among the dumps.
+///
+pub async fn applied(docker: &Docker, container_id: &str, database: &Database, dumps: &[Dump]) -> BTreeSet<usize> {
+    let mut applied = BTreeSet::new();
+
+    for (index, dump) in dumps.iter().enumerate() {
+        if let Some(split) = part(dump, "schema")
+            && split::applied(docker, container_id, database, &split.id).await
+        {
+            applied.insert(index);
+        }
+    }
+
+    applied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::format::DumpFormat;
+    use crate::manifest::Manifest;
+    use crate::split::Part;
+    use std::path::PathBuf;
+
+    ///
+    /// Creates a dump with the given file name and manifest.
+    ///
+    fn dump(file: &str, manifest: Option<Manifest>) -> Dump {
+        Dump {
+            path: PathBuf::from("/dumps").join(file),
+            bytes: 0,
+            manifest,
+            sha256: None,
+            tables: None,
+            bundle: None,
+            verified: false,
+            format: DumpFormat::Plain,
+            compression: Compression::None,
+            decrypted: None,
+        }
+    }
+
+    ///
+    /// Creates a part of the split `id`, whose files are `<id>-schema.sql` and `<id>-data.sql`.
+    ///
+    fn split_part(id: &str, mode: &str) -> Dump {
+        let file = |mode: &str| Part {
+            file: format!("{}-{}.sql", id, mode),
+            sha256: None,
+        };
+
+        let manifest = Manifest {
+            mode: Some(mode.to_string()),
+            split: Some(Split {
+                id: id.to_string(),
+                schema: file("schema"),
+                data: file("data"),
+            }),
+            ..Manifest::default()
+        };
+
+        dump(&format!("{}-{}.sql", id, mode), Some(manifest))
+    }
+
+    ///
+    /// Returns the file names of the dumps, in order.
+    ///
+    fn names(dumps: &[Dump]) -> Vec<String> {
+        dumps
+            .iter()
+            .map(|dump| dump.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn order_moves_the_schema_before_its_data() {
+        let ordered = order(vec![split_part("a", "data"), split_part("a", "schema")]);
+
+        assert_eq!(names(&ordered), ["a-schema.sql", "a-data.sql"]);
+    }
+
+    #[test]
+    fn order_keeps_a_pair_in_the_right_order() {
+        let ordered = order(vec![split_part("a", "schema"), split_part("a", "data")]);
+
+        assert_eq!(names(&ordered), ["a-schema.sql", "a-data.sql"]);
+    }
+
+    #[test]
+    fn order_only_pairs_parts_of_the_same_split() {
+        let ordered = order(vec![
+            dump("full.sql", None),
+            split_part("a", "data"),
+            split_part("b", "data"),
+            split_part("b", "schema"),
+            split_part("a", "schema"),
+        ]);
+
+        assert_eq!(
+            names(&ordered),
+            ["full.sql", "a-schema.sql", "a-data.sql", "b-schema.sql", "b-data.sql"]
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_finds_the_schema_next_to_the_data() {
+        let directory = std::env::temp_dir().join(format!("ndmig-chain-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("a-schema.sql"), "CREATE TABLE t ();\n").unwrap();
+
+        let mut data = split_part("a", "data");
+        data.path = directory.join("a-data.sql");
+
+        let completed = complete(vec![data], &Decryption::Passphrase).await;
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(names(&completed.unwrap()), ["a-schema.sql", "a-data.sql"]);
+    }
+
+    ///
+    /// Creates a dump with the SHA-256 `sha256`, a delta on top of the dump with the SHA-256 `base` if there is one.
+    ///
+    fn chained(file: &str, sha256: &str, base: Option<&str>) -> Dump {
+        let manifest = Manifest {
+            mode: Some(if base.is_some() { "delta" } else { "full" }.to_string()),
+            base_sha256: base.map(str::to_string),
+            ..Manifest::default()
+        };
+
+        Dump {
+            sha256: Some(sha256.to_string()),
+            ..dump(file, Some(manifest))
+        }
+    }
+
+    #[test]
+    fn link_puts_each_delta_after_its_base() {
+        let linked = link(vec![
+            chained("d2.sql", "c", Some("b")),
+            chained("full.sql", "a", None),
+            chained("d1.sql", "b", Some("a")),
+        ]);
+
+        assert_eq!(names(&linked.unwrap()), ["full.sql", "d1.sql", "d2.sql"]);
+    }
+
+    #[test]
+    fn link_orders_a_chain_of_deltas_on_their_own() {
+        let linked = link(vec![
+            chained("d2.sql", "c", Some("b")),
+            chained("d1.sql", "b", Some("a")),
+        ]);
+
+        assert_eq!(names(&linked.unwrap()), ["d1.sql", "d2.sql"]);
+    }
+
+    #[test]
+    fn link_refuses_a_delta_on_top_of_another_dump() {
+        let linked = link(vec![chained("full.sql", "a", None), chained("d1.sql", "b", Some("x"))]);
+
+        assert!(linked.is_err_and(|error| error.message.contains("d1.sql was exported on top of")));
+    }
+
+    #[test]
+    fn link_refuses_deltas_of_two_chains() {
+        let linked = link(vec![
+            chained("d1.sql", "b", Some("a")),
+            chained("e1.sql", "y", Some("x")),
+        ]);
+
+        assert!(linked.is_err_and(|error| error.message.contains("do not form a single chain")));
+    }
+}
@@ -0,0 +1,187 @@
+/*
+    SHA-256 checksums of dumps, computed while they are written.
+*/
+
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// What is appended to the dump path to get the path of its checksum file.
+pub const SUFFIX: &str = ".sha256";
+
+///
+/// A writer that hashes every byte it passes on.
+///
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W> HashingWriter<W> {
+    ///
+    /// Wraps a writer.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The writer the bytes are passed on to.
+    ///
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    ///
+    /// Returns the number of bytes passed on so far.
+    ///
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    ///
+    /// Stops hashing.
+    ///
+    /// #### Returns
+    ///
+    /// The inner writer and the hex-encoded SHA-256 of everything written to it.
+    ///
+    pub fn finish(self) -> (W, String) {
+        (self.inner, format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        // Only hash what the inner writer accepted, the rest is offered again by the caller.
+        if let Poll::Ready(Ok(written)) = poll {
+            this.hasher.update(&buf[..written]);
+            this.bytes += written as u64;
+        }
+
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+///
+/// Returns the path of the checksum file that belongs to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// Writes the checksum file of a dump in the format of `sha256sum`, so `sha256sum -c` can verify it.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+/// * `sha256`: The hex-encoded SHA-256 of the dump.
+///
+/// #### Returns
+///
+/// Nothing, or a message if the file could not be written.
+///
+pub fn write(dump: &Path, sha256: &str) -> Result<(), String> {
+    let path = path(dump);
+    let file_name = dump.file_name().unwrap_or(dump.as_os_str()).to_string_lossy();
+
+    std::fs::write(&path, format!("{}  {}\n", sha256, file_name))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+///
+/// Reads the checksum file of a dump, as written by [`write`] or `sha256sum`.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+/// #### Returns
+///
+/// The hex-encoded SHA-256, `None` if the dump has no checksum file, or a message if it cannot be read.
+///
+pub fn read(dump: &Path) -> Result<Option<String>, String> {
+    let path = path(dump);
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    match parse(&contents) {
+        Some(sha256) => Ok(Some(sha256)),
+        None => Err(format!("Invalid checksum file {}", path.display())),
+    }
+}
+
+///
+/// Reads the SHA-256 out of the contents of a checksum file.
+///
+/// #### Arguments
+///
+/// * `contents`: The contents, e.g. `<sha256>  mydex.sql`.
+///
+/// #### Returns
+///
+/// The hex-encoded SHA-256 in lowercase, or `None` if the contents do not start with one.
+///
+pub fn parse(contents: &str) -> Option<String> {
+    contents
+        .split_whitespace()
+        .next()
+        .filter(|sha256| sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+}
+
+///
+/// Computes the SHA-256 of everything a reader yields.
+///
+/// #### Arguments
+///
+/// * `reader`: What to hash, e.g. a dump.
+///
+/// #### Returns
+///
+/// The hex-encoded SHA-256, or the error of reading.
+///
+pub async fn digest(mut reader: impl AsyncRead + Unpin) -> io::Result<String> {
+    let mut writer = HashingWriter::new(tokio::io::sink());
+    tokio::io::copy(&mut reader, &mut writer).await?;
+
+    Ok(writer.finish().1)
+}
+
+///
+/// Shortens a digest for display, like `git` shortens commit hashes.
+///
+/// #### Arguments
+///
+/// * `sha256`: The hex-encoded SHA-256.
+///
+pub fn short(sha256: &str) -> &str {
+    &sha256[..sha256.len().min(12)]
+}
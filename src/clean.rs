@@ -0,0 +1,225 @@
+/*
+    The `clean` subcommand, which deletes old dumps and leftovers of aborted exports.
+*/
+
+use crate::cli::CleanArgs;
+use crate::dumps::{self, DumpFile};
+use crate::error::{Error, ErrorKind};
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use crate::prompt::confirm;
+use colored::*;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+///
+/// What `clean` deleted, printed with `--json`.
+///
+#[derive(Serialize)]
+struct CleanReport {
+    deleted: Vec<PathBuf>,
+    kept: usize,
+    reclaimed_bytes: u64,
+    dry_run: bool,
+}
+
+///
+/// Parses an age such as `7d`, for `--older-than`.
+///
+/// #### Arguments
+///
+/// * `value`: A number followed by `s`, `m`, `h`, `d` or `w`.
+///
+/// #### Returns
+///
+/// The age, or the message to show when it is invalid.
+///
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid age '{}', expected e.g. 12h, 7d or 2w", value))?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid unit in '{}', expected one of s, m, h, d or w", value)),
+    };
+
+    Ok(Duration::from_secs(number * seconds))
+}
+
+///
+/// Formats an age with its largest unit, e.g. `3d`.
+///
+/// #### Arguments
+///
+/// * `age`: The age.
+///
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+///
+/// Deletes old dumps from the temporary directory and the configured output directory.
+///
+/// Partial dumps left behind by aborted exports are always deleted. Complete dumps are only deleted with `--all`, or
+/// with `--older-than` once they are old enough.
+///
+/// #### Arguments
+///
+/// * `args`: The clean arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the user cancelled or a file could not be deleted.
+///
+pub fn run(args: &CleanArgs, options: &Options) -> Result<(), Error> {
+    let files = dumps::find(options);
+
+    let (selected, kept): (Vec<DumpFile>, Vec<DumpFile>) = files
+        .into_iter()
+        .partition(|file| file.partial || args.all || args.older_than.is_some_and(|older_than| file.age >= older_than));
+
+    let reclaimed: u64 = selected.iter().map(|file| file.bytes).sum();
+
+    if !output::is_json() {
+        if selected.is_empty() {
+            output::info("Nothing to clean.".green().bold());
+        }
+
+        for file in &selected {
+            output::info(format!(
+                "  {} {:>10}  {:>4}  {}{}",
+                Glyph::Arrow.to_string().bright_yellow(),
+                crate::format_size(file.bytes),
+                format_age(file.age),
+                file.path.display(),
+                if file.partial {
+                    " (partial)".dimmed()
+                } else {
+                    "".normal()
+                }
+            ));
+        }
+
+        if !kept.is_empty() {
+            output::status(
+                format!(
+                    "{} dump(s) kept, pass --older-than or --all to delete them as well.",
+                    kept.len()
+                )
+                .dimmed(),
+            );
+        }
+    }
+
+    if selected.is_empty() {
+        if output::is_json() {
+            output::json(&CleanReport {
+                deleted: Vec::new(),
+                kept: kept.len(),
+                reclaimed_bytes: 0,
+                dry_run: options.dry_run,
+            });
+        }
+        return Ok(());
+    }
+
+    if options.dry_run {
+        if output::is_json() {
+            output::json(&CleanReport {
+                deleted: selected.into_iter().map(|file| file.path).collect(),
+                kept: kept.len(),
+                reclaimed_bytes: reclaimed,
+                dry_run: true,
+            });
+        } else {
+            output::info(
+                format!(
+                    "DRY RUN would delete {} file(s) and reclaim {}",
+                    selected.len(),
+                    crate::format_size(reclaimed)
+                )
+                .magenta(),
+            );
+        }
+        return Ok(());
+    }
+
+    let question = format!("Delete {} file(s), {}?", selected.len(), crate::format_size(reclaimed));
+
+    if !confirm(options, &question)? {
+        return Err(Error::new(ErrorKind::Aborted, "Clean cancelled."));
+    }
+
+    let mut deleted = Vec::new();
+    let mut reclaimed = 0;
+    let mut failed = 0;
+
+    for file in selected {
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                for sidecar in dumps::sidecars(&file.path).iter().filter(|sidecar| sidecar.exists()) {
+                    if let Err(e) = std::fs::remove_file(sidecar) {
+                        output::status(
+                            format!("{} Failed to delete {}: {}", Glyph::Warning, sidecar.display(), e).yellow(),
+                        );
+                    }
+                }
+
+                output::debug(format!("Deleted {}", file.path.display()));
+                reclaimed += file.bytes;
+                deleted.push(file.path);
+            }
+            Err(e) => {
+                output::status(format!("{} Failed to delete {}: {}", Glyph::Warning, file.path.display(), e).yellow());
+                failed += 1;
+            }
+        }
+    }
+
+    if output::is_json() {
+        output::json(&CleanReport {
+            deleted,
+            kept: kept.len(),
+            reclaimed_bytes: reclaimed,
+            dry_run: false,
+        });
+    } else {
+        output::info(
+            format!(
+                "{} Deleted {} file(s) and reclaimed {}.",
+                Glyph::Ok,
+                deleted.len(),
+                crate::format_size(reclaimed)
+            )
+            .green()
+            .bold(),
+        );
+    }
+
+    if failed > 0 {
+        return Err(Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to delete {} file(s).", failed),
+        ));
+    }
+
+    Ok(())
+}
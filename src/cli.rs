@@ -0,0 +1,621 @@
+/*
+    Command line interface definitions for ndmig.
+*/
+
+use crate::bundle::BundleFiles;
+use crate::compression::Compression;
+use crate::format::DumpFormat;
+use crate::incremental::Delta;
+use crate::merge::{DonationPolicy, DuplicatePlayers, Triggers};
+use crate::restore::Strategy;
+use crate::template::NameTemplate;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The exit codes table shown at the end of `--help`.
+pub(crate) const EXIT_CODES: &str = "Exit codes:
+  0    Success
+  1    General failure (e.g. a doctor check or the audit failed)
+  2    Docker is not reachable
+  3    No or unknown Ballsdex instance
+  4    Starting the container or running pg_dump failed
+  5    Reading or writing a local file failed
+  6    Aborted by the user
+  7    Invalid configuration
+  8    Input is needed but stdin is not a terminal
+  9    Loading the dump into the target failed
+  10   The dump was imported, but statements failed with --continue-on-error
+  130  Interrupted by Ctrl+C";
+
+///
+/// A CLI tool for migrating Ballsdex to NationDex.
+///
+/// Running `ndmig` without a subcommand starts the TUI, or the interactive menu on dumb terminals.
+///
+#[derive(Parser, Debug)]
+#[command(
+    name = "ndmig",
+    version,
+    about,
+    long_about = None,
+    after_help = EXIT_CODES,
+    disable_version_flag = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print the version, the git commit and the Docker server version, then exit.
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// Answer yes to every confirmation, e.g. starting a stopped container or overwriting a dump.
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Print every Docker API call, the commands being run and timings.
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    /// Only print the final result and errors.
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print a single JSON document on stdout and send everything else to stderr.
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Disable colors and use ASCII markers. Also enabled by NO_COLOR or when stdout is not a terminal.
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// How many invalid answers a prompt accepts before giving up [default: 3].
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: Option<u32>,
+
+    /// How often a dump is started again after a Docker error before any data arrived [default: 3].
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    pub exec_attempts: Option<u32>,
+
+    /// Show what export or import would do without starting containers, running commands or writing files.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Postgres role used to run pg_dump and psql inside the container [default: ballsdex].
+    #[arg(long, global = true)]
+    pub db_user: Option<String>,
+
+    /// Database to dump and query [default: the database named after the role].
+    #[arg(long, global = true)]
+    pub db_name: Option<String>,
+
+    /// Read the configuration from this file instead of ~/.config/ndmig/config.toml.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+///
+/// The operations `ndmig` can run without prompting.
+///
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Export the database of a Ballsdex instance.
+    Export(Box<ExportArgs>),
+
+    /// Import a database dump into a NationDex instance.
+    Import(ImportArgs),
+
+    /// List the discovered Ballsdex instances.
+    List(ListArgs),
+
+    /// Show the details of a Ballsdex instance.
+    Info(InfoArgs),
+
+    /// List past exports and imports.
+    History(HistoryArgs),
+
+    /// Delete partial dumps and, on request, old dumps.
+    Clean(CleanArgs),
+
+    /// List the dumps that are still on disk.
+    Exports(ExportsArgs),
+
+    /// Show what a dump or a bundle holds.
+    Inspect(InspectArgs),
+
+    /// Rewrite a plain Ballsdex dump into the NationDex schema without importing it.
+    Transform(TransformArgs),
+
+    /// Browse and export instances in a full-screen terminal UI.
+    Tui,
+
+    /// Check that the environment is ready for a migration.
+    Doctor(DoctorArgs),
+
+    /// Check that no row of an instance points at a row that does not exist, through any of its foreign keys.
+    Audit(AuditArgs),
+
+    /// Inspect the ndmig configuration.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+///
+/// The `config` subcommands.
+///
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration after merging the command line and the config file.
+    Show,
+}
+
+///
+/// Arguments for the `export` subcommand.
+///
+#[derive(Args, Debug, Clone, Default)]
+pub struct ExportArgs {
+    /// Name of the Ballsdex instance (compose project) to export.
+    #[arg(short, long)]
+    pub instance: Option<String>,
+
+    /// Export every discovered instance that is not ignored, one after another.
+    #[arg(long, conflicts_with = "instance")]
+    pub all: bool,
+
+    /// How many instances to export at once with --all or several selected instances [default: 2].
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel: Option<u16>,
+
+    /// File or directory to write the dump to, or `-` for stdout. Defaults to the ndmig temp directory.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Overwrite the output file without asking if it already exists, and export even if the dump may not fit.
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// File name of the dump, with {instance}, {container_id}, {date} and {time} placeholders [default: {instance}-{date}-{time}.sql].
+    #[arg(long)]
+    pub name_template: Option<NameTemplate>,
+
+    /// Output format of pg_dump. Custom dumps are restored with pg_restore and get a .dump extension.
+    #[arg(long, value_enum, default_value_t)]
+    pub format: DumpFormat,
+
+    /// Number of tables pg_dump dumps in parallel, only with --format directory.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Only dump these tables, e.g. --tables player,ball,ballinstance. The tables must exist.
+    #[arg(long, value_delimiter = ',')]
+    pub tables: Vec<String>,
+
+    /// Dump every table except these, e.g. --exclude-tables trade,tradeobject. The tables must exist.
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_tables: Vec<String>,
+
+    /// Only dump the schema, without any rows. Adds -schema to the file name.
+    #[arg(long, conflicts_with = "data_only")]
+    pub schema_only: bool,
+
+    /// Only dump the rows, without the schema. Adds -data to the file name.
+    #[arg(long)]
+    pub data_only: bool,
+
+    /// Only dump the rows added since the last export of the instance, as a delta on top of it. Adds -delta to the
+    /// file name.
+    #[arg(long, conflicts_with_all = ["schema_only", "data_only"])]
+    pub incremental: bool,
+
+    /// Only export the configuration, catches and trades of this Discord server, with the players, balls and specials
+    /// they need. Adds -guild to the file name.
+    #[arg(
+        long,
+        value_name = "ID",
+        conflicts_with_all = ["schema_only", "data_only", "incremental", "tables", "exclude_tables"]
+    )]
+    pub guild_id: Option<u64>,
+
+    /// Only export what is stored about this Discord user, with names instead of internal IDs, e.g. for a data access
+    /// request. Writes JSON, or one CSV file per section with --format csv. Adds -player to the file name.
+    #[arg(
+        long,
+        value_name = "DISCORD_ID",
+        conflicts_with_all = [
+            "schema_only", "data_only", "incremental", "guild_id", "tables", "exclude_tables",
+            "include_redis", "include_media", "include_globals", "anonymize", "stable",
+        ]
+    )]
+    pub player: Option<u64>,
+
+    /// Only export the catches from this date on, e.g. 2025-01-31 or 90d, leaving out the trades that involve older
+    /// ones. Adds -filtered to the file name.
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = crate::filter::parse_since,
+        conflicts_with_all = ["schema_only", "incremental", "guild_id", "player"]
+    )]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only export the players with at least this many catches, along with the rows that belong to them. Adds
+    /// -filtered to the file name.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["schema_only", "incremental", "guild_id", "player"]
+    )]
+    pub min_catches: Option<u64>,
+
+    /// Write the schema and the rows into two files, -schema and -data, so the schema can be applied and reviewed
+    /// first. The data file refuses to load until its schema file was applied.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "schema_only", "data_only", "incremental", "guild_id", "player", "since", "min_catches", "bundle",
+        ]
+    )]
+    pub split: bool,
+
+    /// The ID of the split export this export is a part of, set for each of the two.
+    #[arg(skip)]
+    pub split_id: Option<String>,
+
+    /// The export a delta builds on and the marks it ends at, filled in once the export starts.
+    #[arg(skip)]
+    pub delta: Option<Delta>,
+
+    /// Keep the ALTER ... OWNER TO statements, which fail when the target uses other role names.
+    #[arg(long)]
+    pub keep_owner: bool,
+
+    /// Keep the GRANT and REVOKE statements, which fail when the target uses other role names.
+    #[arg(long)]
+    pub keep_privileges: bool,
+
+    /// Pass an argument to pg_dump as is, after ndmig's own. Repeat for several, e.g. --pg-dump-arg=--no-comments.
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    pub pg_dump_arg: Vec<String>,
+
+    /// The `pg_dump_args` of the config file, which go before --pg-dump-arg.
+    #[arg(skip)]
+    pub config_pg_dump_args: Vec<String>,
+
+    /// Compress the dump, appending .gz or .zst to its name.
+    #[arg(long, value_enum, default_value_t)]
+    pub compress: Compression,
+
+    /// Compression level, 1 to 9 for gzip and 1 to 22 for zstd.
+    #[arg(long)]
+    pub compression_level: Option<u32>,
+
+    /// Replace Discord user, guild and channel IDs with pseudonyms, for sharing a dump. Plain format only.
+    #[arg(long)]
+    pub anonymize: bool,
+
+    /// Sort the rows of every table by primary key and drop the version comments, so dumps diff cleanly. Plain format only.
+    #[arg(long)]
+    pub stable: bool,
+
+    /// Encrypt the dump with age and a passphrase asked on the terminal, appending .age to its name.
+    #[arg(long)]
+    pub encrypt: bool,
+
+    /// Encrypt the dump with age to this recipient public key (age1...) instead of a passphrase.
+    #[arg(long, conflicts_with = "encrypt_key_file")]
+    pub encrypt_recipient: Option<String>,
+
+    /// Encrypt the dump with age to the public key of this identity file instead of a passphrase.
+    #[arg(long)]
+    pub encrypt_key_file: Option<PathBuf>,
+
+    /// Also save a snapshot of the project's Redis container and copy it next to the dump.
+    #[arg(long)]
+    pub include_redis: bool,
+
+    /// Also copy the files uploaded through the admin panel, such as ball artwork, next to the dump as a tarball.
+    #[arg(long)]
+    pub include_media: bool,
+
+    /// Also save the roles and their memberships with pg_dumpall --globals-only next to the dump, without the
+    /// superuser roles.
+    #[arg(long)]
+    pub include_globals: bool,
+
+    /// Write the dump and every file that belongs to it into a single <name>.ndmig.tar.
+    #[arg(long)]
+    pub bundle: bool,
+
+    /// Do not read the dump back after writing it to check that it is complete.
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Do not copy the bot's config.yml next to the dump.
+    #[arg(long, conflicts_with = "bot_config")]
+    pub no_config: bool,
+
+    /// Path of config.yml inside the bot container [default: /code/config.yml].
+    #[arg(long, value_name = "PATH")]
+    pub bot_config: Option<String>,
+
+    /// Let pg_dump write to a file inside the container and copy it out, for hosts that block attaching to commands.
+    #[arg(long)]
+    pub via_file: bool,
+
+    /// Leave a container ndmig started for the export running afterwards, instead of stopping it again.
+    #[arg(long)]
+    pub keep_running: bool,
+}
+
+///
+/// Arguments for the `import` subcommand.
+///
+#[derive(Args, Debug, Clone, Default)]
+pub struct ImportArgs {
+    /// Name of the instance (compose project) to import into.
+    #[arg(short, long, visible_alias = "target")]
+    pub instance: Option<String>,
+
+    /// The plain SQL dump to import, anywhere on disk. Without it, one of the dumps ndmig wrote is picked from a list.
+    ///
+    /// Repeat it with --strategy merge to merge several dumps into the target, one after the other in the given order.
+    #[arg(long, value_name = "PATH")]
+    pub file: Vec<PathBuf>,
+
+    /// Only import these tables, e.g. --tables ballinstance to re-import a single table. With --strategy replace,
+    /// only these tables are dropped.
+    #[arg(long, value_delimiter = ',')]
+    pub tables: Vec<String>,
+
+    /// Import a table of --tables even if it references a table that is not imported along, without checking its rows
+    /// against the rows the target holds.
+    #[arg(long)]
+    pub no_fk_check: bool,
+
+    /// Leave the blacklisted users and guilds and the blacklist history of the dump out of the import.
+    #[arg(long, conflicts_with_all = ["tables", "only_blacklists"])]
+    pub skip_blacklists: bool,
+
+    /// Only import the blacklisted users and guilds and the blacklist history of the dump, e.g. to bring the moderation
+    /// data over after the rest.
+    #[arg(long, conflicts_with = "tables")]
+    pub only_blacklists: bool,
+
+    /// Load the aerich migrations of the dump, which are left out by default as they belong to the bot of the source.
+    /// With --strategy replace, the target then loses its own.
+    #[arg(long)]
+    pub keep_source_aerich: bool,
+
+    /// What to do when the target already holds data: refuse, replace it with the dump or merge the dump into it.
+    #[arg(long, value_enum, default_value_t)]
+    pub strategy: Strategy,
+
+    /// Drop the whole public schema of the target and recreate it from the dump, whatever it holds. Asks to type the
+    /// name of the target even if it is empty, unless --force is passed.
+    #[arg(
+        long,
+        conflicts_with_all = ["strategy", "tables", "skip_blacklists", "only_blacklists"]
+    )]
+    pub wipe: bool,
+
+    /// Wipe the target even if it holds tables that are not part of the Ballsdex or NationDex schema, which are
+    /// dropped along.
+    #[arg(long, requires = "wipe")]
+    pub wipe_unknown_tables: bool,
+
+    /// What a merge does with a player of the dump whose Discord ID the target already has.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_duplicate_player: DuplicatePlayers,
+
+    /// Which donation policy a merged player keeps when the target and the dump disagree.
+    #[arg(long, value_enum, default_value_t)]
+    pub donation_policy: DonationPolicy,
+
+    /// Load the dump with bulk-load settings: no synchronous commit, more memory to build the indexes with, and the
+    /// indexes and constraints created once all rows are in.
+    #[arg(long)]
+    pub fast: bool,
+
+    /// Whether the triggers of the target fire while a dump is merged into it, only with --strategy merge. Disabling
+    /// them also skips checking the foreign keys row by row, and needs a superuser.
+    #[arg(long, value_enum, default_value_t)]
+    pub triggers: Triggers,
+
+    /// Replace or merge into a database that already holds data without typing its name to confirm.
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Leave a container ndmig started for the import running afterwards, instead of stopping it again.
+    #[arg(long)]
+    pub keep_running: bool,
+
+    /// Load the dump into a scratch database on the target and check it, without touching the real database.
+    #[arg(long)]
+    pub rehearse: bool,
+
+    /// Do not back up the target with pg_dump before importing, e.g. when it is known to be empty.
+    #[arg(long)]
+    pub no_backup: bool,
+
+    /// Load each dump statement by statement instead of in one transaction, for dumps too large for one. An error
+    /// then leaves the target partially modified.
+    #[arg(long)]
+    pub no_transaction: bool,
+
+    /// Keep loading the dump past the statements that fail, e.g. comments on columns the target does not have, and
+    /// write each of them with its error to a log next to the dump. Only the failing statements are rolled back.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Leave the sequences where the dump set them instead of moving them past the imported IDs. Only for those who
+    /// set them by hand, as the bot fails with duplicate keys on sequences that are behind.
+    #[arg(long)]
+    pub no_sequence_fix: bool,
+
+    /// Do not run ANALYZE on the target after the import. Until autovacuum gets to it, postgres plans the queries of
+    /// the bot without statistics, which makes it slow right after the migration.
+    #[arg(long, conflicts_with = "vacuum")]
+    pub no_analyze: bool,
+
+    /// Run VACUUM ANALYZE instead of ANALYZE after the import, which also cleans up what the import left behind, e.g.
+    /// the rows of tables a replace dropped. Slower, and only worth it on large targets.
+    #[arg(long)]
+    pub vacuum: bool,
+
+    /// What to do with the config.yml and media of a bundle once its dump is imported: ask for each, copy them into
+    /// the bot and admin panel containers of the target, or leave them in the bundle.
+    #[arg(long, value_enum, default_value_t)]
+    pub bundle_files: BundleFiles,
+
+    /// Also load the Redis snapshot saved next to the dump by export --include-redis into the project's Redis
+    /// container, which is stopped for it and started again.
+    #[arg(long)]
+    pub include_redis: bool,
+
+    /// Also create the roles saved next to the dump by export --include-globals on the server of the target, before
+    /// the dump is loaded. Roles the target has already are only a warning.
+    #[arg(long)]
+    pub include_globals: bool,
+
+    /// Decrypt an encrypted dump with this age identity file instead of a passphrase asked on the terminal.
+    #[arg(long)]
+    pub decrypt_key_file: Option<PathBuf>,
+
+    /// Do not check the dump against the SHA-256 in its checksum file or manifest before the import, e.g. for a dump
+    /// that was edited by hand. With --strict, this also allows importing a dump that has no checksum.
+    #[arg(long)]
+    pub no_checksum: bool,
+
+    /// Refuse to import a dump that has no checksum to verify it against, unless --no-checksum is passed.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Load a custom or directory dump as it is with `pg_restore -j`, restoring this many tables in parallel, instead
+    /// of converting it for psql. Needs --no-transform, --no-transaction and --keep-source-aerich.
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Import even if the postgres versions of the dump and the target are known not to work together, e.g. an
+    /// archive written by a newer pg_dump than the pg_restore of the target. The mismatch is still shown as a warning.
+    #[arg(long)]
+    pub allow_version_mismatch: bool,
+
+    /// Do not check the target after the import: its row counts against the manifest, and that no rows point nowhere.
+    #[arg(long)]
+    pub no_validate: bool,
+
+    /// Do not create the role and database of the target if they are missing, e.g. on a fresh postgres container
+    /// whose bot never started.
+    #[arg(long)]
+    pub no_create: bool,
+
+    /// Load the dump as is, for dumps already in the NationDex schema, instead of renaming the Ballsdex tables.
+    #[arg(long)]
+    pub no_transform: bool,
+
+    /// Also offer and accept targets that are not recognized as NationDex instances.
+    #[arg(long)]
+    pub allow_any_target: bool,
+}
+
+///
+/// Arguments for the `info` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// Name of the Ballsdex instance (compose project) to show.
+    pub instance: String,
+}
+
+///
+/// Arguments for the `audit` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+    /// Name of the instance (compose project) to audit.
+    #[arg(short, long, visible_alias = "target")]
+    pub instance: String,
+}
+
+///
+/// Arguments for the `list` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// Only list the instances whose name contains this text, ignoring case.
+    #[arg(short, long)]
+    pub filter: Option<String>,
+
+    /// Also list the instances hidden by the `ignore` list of the configuration.
+    #[arg(short, long)]
+    pub all: bool,
+}
+
+///
+/// Arguments for the `history` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Only show the runs of this instance.
+    #[arg(short, long)]
+    pub instance: Option<String>,
+}
+
+///
+/// Arguments for the `clean` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// Also delete the dumps older than this, e.g. `12h`, `7d` or `2w`.
+    #[arg(long, value_parser = crate::clean::parse_age, conflicts_with = "all")]
+    pub older_than: Option<Duration>,
+
+    /// Also delete every complete dump.
+    #[arg(long)]
+    pub all: bool,
+}
+
+///
+/// Arguments for the `exports` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct ExportsArgs {
+    /// Only list the dumps of this instance.
+    #[arg(short, long)]
+    pub instance: Option<String>,
+}
+
+///
+/// Arguments for the `inspect` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    /// The dump or the `.ndmig.tar` bundle to inspect.
+    pub path: PathBuf,
+}
+
+///
+/// Arguments for the `transform` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct TransformArgs {
+    /// The plain SQL dump to rewrite, compressed or in a bundle.
+    pub path: PathBuf,
+
+    /// Where to write the rewritten dump [default: `<dump>.nationdex.sql` next to it].
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+///
+/// Arguments for the `doctor` subcommand.
+///
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Only check this instance instead of every discovered one.
+    #[arg(short, long)]
+    pub instance: Option<String>,
+}
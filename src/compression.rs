@@ -0,0 +1,145 @@
+/*
+    Compression of dumps on the ndmig side, so it works with any postgres image.
+*/
+
+use async_compression::Level;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The first bytes of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// The first bytes of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+///
+/// How a dump is compressed.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    ///
+    /// Returns the name used on the command line, e.g. `gzip`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    ///
+    /// Returns the extension appended to compressed dumps, e.g. `gz` for `dump.sql.gz`.
+    ///
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    ///
+    /// Appends the extension of the compression to a dump path, unless it is already there.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: The path of the dump.
+    ///
+    pub fn apply_extension(self, path: PathBuf) -> PathBuf {
+        let Some(extension) = self.extension() else {
+            return path;
+        };
+
+        if path.extension().is_some_and(|current| current == extension) {
+            return path;
+        }
+
+        let mut path = path.into_os_string();
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    }
+
+    ///
+    /// Validates a `--compression-level`.
+    ///
+    /// #### Arguments
+    ///
+    /// * `level`: The requested level, or `None` for the default of the algorithm.
+    ///
+    /// #### Returns
+    ///
+    /// The level for the encoder, or the message to show when it is out of range.
+    ///
+    pub fn level(self, level: Option<u32>) -> Result<Level, String> {
+        let Some(level) = level else {
+            return Ok(Level::Default);
+        };
+
+        let range = match self {
+            Compression::None => return Err("--compression-level needs --compress gzip or zstd".to_string()),
+            Compression::Gzip => 1..=9,
+            Compression::Zstd => 1..=22,
+        };
+
+        if !range.contains(&level) {
+            return Err(format!(
+                "Invalid compression level {} for {}, expected {} to {}",
+                level,
+                self.name(),
+                range.start(),
+                range.end()
+            ));
+        }
+
+        Ok(Level::Precise(level as i32))
+    }
+
+    ///
+    /// Works out how an existing dump is compressed, from its first bytes or else its extension.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: The path of the dump.
+    ///
+    pub fn detect(path: &Path) -> Self {
+        let mut magic = [0; 4];
+        let read = std::fs::File::open(path)
+            .and_then(|mut file| file.read(&mut magic))
+            .unwrap_or(0);
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") if read == 0 => Compression::Gzip,
+            Some("zst") if read == 0 => Compression::Zstd,
+            _ => Self::from_magic(&magic[..read]),
+        }
+    }
+
+    ///
+    /// Works out how a stream is compressed from its first bytes, e.g. a dump inside a bundle.
+    ///
+    /// #### Arguments
+    ///
+    /// * `head`: The first bytes of the stream.
+    ///
+    pub fn from_magic(head: &[u8]) -> Self {
+        if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/*
+    Loading of the `config.toml` configuration file.
+*/
+
+use crate::error::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The keys `config.toml` understands. Anything else produces a warning.
+const KNOWN_KEYS: &[&str] = &[
+    "yes",
+    "verbose",
+    "quiet",
+    "json",
+    "no_color",
+    "max_attempts",
+    "exec_attempts",
+    "instance",
+    "output",
+    "name_template",
+    "ignore",
+    "db_user",
+    "db_name",
+    "bot_config",
+    "pg_dump_args",
+];
+
+///
+/// The contents of the configuration file. Every key mirrors the command line flag of the same name.
+///
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Config {
+    pub yes: Option<bool>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+    pub json: Option<bool>,
+    pub no_color: Option<bool>,
+    pub max_attempts: Option<u32>,
+    pub exec_attempts: Option<u32>,
+    /// The instance to export when none is given.
+    pub instance: Option<String>,
+    /// The file or directory to write dumps to when `--output` is not given.
+    pub output: Option<PathBuf>,
+    /// The dump file name template when `--name-template` is not given.
+    pub name_template: Option<String>,
+    /// Instance names or glob patterns that are never touched, e.g. `ci-*`.
+    pub ignore: Option<Vec<String>>,
+    /// The postgres role to connect as, `ballsdex` by default.
+    pub db_user: Option<String>,
+    /// The database to dump, the one named after the role by default.
+    pub db_name: Option<String>,
+    /// The path of `config.yml` inside the bot container, `/code/config.yml` by default.
+    pub bot_config: Option<String>,
+    /// Extra arguments passed to `pg_dump` on every export, before `--pg-dump-arg`.
+    pub pg_dump_args: Option<Vec<String>>,
+}
+
+///
+/// A configuration file that has been read from disk.
+///
+#[derive(Debug, Default)]
+pub struct LoadedConfig {
+    /// The parsed configuration.
+    pub config: Config,
+    /// The file it was read from, or `None` if there was no file.
+    pub path: Option<PathBuf>,
+    /// Problems that did not stop the file from loading, such as unknown keys.
+    pub warnings: Vec<String>,
+}
+
+///
+/// Returns the default location of the configuration file.
+///
+/// #### Returns
+///
+/// `$XDG_CONFIG_HOME/ndmig/config.toml`, falling back to `~/.config/ndmig/config.toml`.
+///
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join("ndmig").join("config.toml"))
+}
+
+///
+/// Loads the configuration file.
+///
+/// #### Arguments
+///
+/// * `path`: The `--config` path. When `None`, the default location is used if it exists.
+///
+/// #### Returns
+///
+/// The loaded configuration, or an error if the file cannot be read or parsed.
+///
+pub fn load(path: Option<&Path>) -> Result<LoadedConfig, Error> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(LoadedConfig::default()),
+        },
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| Error::new(ErrorKind::Config, format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let table: toml::Table = contents.parse().map_err(|e| {
+        Error::new(
+            ErrorKind::Config,
+            format!("Invalid config file {}: {}", path.display(), e),
+        )
+    })?;
+
+    let warnings = table
+        .keys()
+        .filter(|key| !KNOWN_KEYS.contains(&key.as_str()))
+        .map(|key| format!("Unknown key '{}' in {}", key, path.display()))
+        .collect();
+
+    let config: Config = table.try_into().map_err(|e| {
+        Error::new(
+            ErrorKind::Config,
+            format!("Invalid config file {}: {}", path.display(), e),
+        )
+    })?;
+
+    for (key, value) in [
+        ("max_attempts", config.max_attempts),
+        ("exec_attempts", config.exec_attempts),
+    ] {
+        if value == Some(0) {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!("Invalid config file {}: {} must be at least 1", path.display(), key),
+            ));
+        }
+    }
+
+    Ok(LoadedConfig {
+        config,
+        path: Some(path),
+        warnings,
+    })
+}
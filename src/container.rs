@@ -0,0 +1,1817 @@
+/*
+    Docker helpers for inspecting and running commands in Ballsdex containers.
+*/
+
+use crate::archive;
+use crate::cli::ExportArgs;
+use crate::error::{Error, ErrorKind};
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use crate::progress::Progress;
+use bollard::{
+    Docker,
+    exec::{CreateExecOptions, StartExecOptions, StartExecResults},
+    models::{ContainerSummary, ContainerSummaryStateEnum},
+    query_parameters::ListContainersOptions,
+};
+use colored::*;
+use futures_util::StreamExt;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// The delay before the first retry of a failed dump command, doubled for every further one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+static EXEC_ATTEMPTS: AtomicU32 = AtomicU32::new(3);
+
+/// The pg_dump flags ndmig sets on its own: the short and long flag, whether the short flag takes a value, and the
+/// ndmig option that controls it.
+const MANAGED_PG_DUMP_FLAGS: [(&str, &str, bool, &str); 11] = [
+    ("-F", "--format", true, "--format"),
+    ("-f", "--file", true, "--output"),
+    ("-j", "--jobs", true, "--jobs"),
+    ("-s", "--schema-only", false, "--schema-only"),
+    ("-a", "--data-only", false, "--data-only"),
+    ("-t", "--table", true, "--tables"),
+    ("-T", "--exclude-table", true, "--exclude-tables"),
+    ("-O", "--no-owner", false, "--keep-owner"),
+    ("-x", "--no-privileges", false, "--keep-privileges"),
+    ("-U", "--username", true, "--db-user"),
+    ("-d", "--dbname", true, "--db-name"),
+];
+
+/// How often a detached command is checked for whether it finished.
+const DETACHED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a freshly started postgres may take before it accepts connections.
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a starting postgres is asked whether it accepts connections.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+///
+/// Sets how often a dump command is started before a Docker error is given up on.
+///
+/// #### Arguments
+///
+/// * `attempts`: The number of attempts, at least 1.
+///
+pub fn set_exec_attempts(attempts: u32) {
+    EXEC_ATTEMPTS.store(attempts, Ordering::Relaxed);
+}
+
+///
+/// The collected output of a command run inside a container.
+///
+/// The output is kept as raw bytes, since commands such as `pg_dump -Fc` do not write UTF-8.
+///
+pub struct ExecOutput {
+    pub exit_code: Option<i64>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl ExecOutput {
+    ///
+    /// Returns stdout as text, with invalid UTF-8 replaced and surrounding whitespace trimmed.
+    ///
+    pub fn stdout_text(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).trim().to_string()
+    }
+
+    ///
+    /// Returns stderr as text, with invalid UTF-8 replaced and surrounding whitespace trimmed.
+    ///
+    pub fn stderr_text(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).trim().to_string()
+    }
+}
+
+///
+/// Which bot a compose project runs, told apart by the image and labels of its `bot` container.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flavor {
+    Ballsdex,
+    NationDex,
+    /// The project has no bot container, or neither name shows up on it.
+    Unknown,
+}
+
+impl Flavor {
+    ///
+    /// Returns the name shown by `list`, e.g. `NationDex`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Flavor::Ballsdex => "Ballsdex",
+            Flavor::NationDex => "NationDex",
+            Flavor::Unknown => "unknown",
+        }
+    }
+
+    ///
+    /// Classifies the bot container of a project.
+    ///
+    /// The image name decides, as it is the most specific. Images built by compose are named after the project, so
+    /// the labels follow, which include the source of the image and the directory of the compose file.
+    ///
+    /// #### Arguments
+    ///
+    /// * `bot`: The bot container, `None` if the project has none.
+    ///
+    fn of(bot: Option<&ContainerSummary>) -> Self {
+        let Some(bot) = bot else {
+            return Flavor::Unknown;
+        };
+
+        let classify = |text: &str| {
+            let text = text.to_lowercase();
+
+            // NationDex is a fork, so its sources may well mention Ballsdex too.
+            if text.contains("nationdex") {
+                Some(Flavor::NationDex)
+            } else if text.contains("ballsdex") {
+                Some(Flavor::Ballsdex)
+            } else {
+                None
+            }
+        };
+
+        bot.image
+            .as_deref()
+            .and_then(classify)
+            .or_else(|| bot.labels.iter().flatten().find_map(|(_, value)| classify(value)))
+            .unwrap_or(Flavor::Unknown)
+    }
+}
+
+///
+/// A Ballsdex instance, identified by the postgres container of its compose project.
+///
+#[derive(Debug, Clone)]
+pub struct Instance {
+    /// The full container name, e.g. `mydex-postgres-db-1`.
+    pub name: String,
+    /// The container ID.
+    pub container_id: String,
+    /// Whether the container was running when it was discovered.
+    pub running: bool,
+    /// Which bot the compose project runs, `Unknown` for the other containers of a project.
+    pub flavor: Flavor,
+}
+
+///
+/// The postgres role and database that commands inside a container connect with.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Database {
+    /// The role, passed as `-U`.
+    pub user: String,
+    /// The database, passed as `-d`. When `None`, postgres uses the database named after the role.
+    pub name: Option<String>,
+    /// The password of the role, passed as `PGPASSWORD`.
+    pub password: Option<String>,
+    /// Whether neither the options nor the container named a role, so the Ballsdex default is used.
+    pub default_user: bool,
+}
+
+/// The `application_name` of the sessions ndmig opens, which [`terminate_sessions`] finds them by.
+pub const APPLICATION_NAME: &str = "ndmig";
+
+impl Database {
+    ///
+    /// Returns the environment the commands connecting to this database run with.
+    ///
+    pub fn env(&self) -> Vec<String> {
+        let mut env = vec![format!("PGAPPNAME={}", APPLICATION_NAME)];
+        env.extend(self.password.iter().map(|password| format!("PGPASSWORD={}", password)));
+        env
+    }
+
+    ///
+    /// Returns the arguments that make `pg_dump` and `psql` connect to this database.
+    ///
+    pub fn connection_args(&self) -> Vec<String> {
+        let mut args = vec!["-U".to_string(), self.user.clone()];
+
+        if let Some(name) = &self.name {
+            args.push("-d".to_string());
+            args.push(name.clone());
+        }
+
+        args
+    }
+}
+
+///
+/// The result of a `pg_dump` run.
+///
+pub struct DatabaseDump {
+    /// How many bytes `pg_dump` wrote, before any compression.
+    pub bytes: u64,
+    /// The lines `pg_dump` wrote to stderr.
+    pub warnings: Vec<String>,
+}
+
+///
+/// Checks if a Docker container is a Ballsdex instance by inspecting its image name.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The ID of the container to inspect.
+///
+/// #### Returns
+///
+/// Whether the container is classified as a Ballsdex instance.
+///
+pub async fn is_ballsdex_instance(docker: &Docker, container_id: &str) -> bool {
+    output::debug(format!("inspect_container {}", container_id));
+    let info = match docker.inspect_container(container_id, None).await {
+        Ok(info) => info,
+        Err(_) => return false,
+    };
+
+    info.config
+        .and_then(|c| c.image)
+        .map(|img| img == "postgres")
+        .unwrap_or(false)
+}
+
+///
+/// Checks whether a container is currently running.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The ID of the container to inspect.
+///
+/// #### Returns
+///
+/// Whether the container is running.
+///
+pub async fn is_running(docker: &Docker, container_id: &str) -> Result<bool, bollard::errors::Error> {
+    output::debug(format!("inspect_container {}", container_id));
+    let info = docker.inspect_container(container_id, None).await?;
+    Ok(info.state.and_then(|s| s.running).unwrap_or(false))
+}
+
+///
+/// Waits until postgres in a freshly started container accepts connections.
+///
+/// A started container runs before the server inside it does, and a dump started too early fails with "the database
+/// system is starting up". `pg_isready` is asked until it reports the server ready, for up to a minute. Nothing is
+/// shown while waiting, which is up to the caller.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `name`: The container name, for the error message.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// Nothing once the server is ready, or an error if it did not get ready in time.
+///
+pub async fn wait_until_ready(
+    docker: &Docker,
+    container_id: &str,
+    name: &str,
+    database: &Database,
+) -> Result<(), Error> {
+    let started = Instant::now();
+    let connection = database.connection_args();
+
+    let result = loop {
+        let mut cmd = vec!["pg_isready", "-q"];
+        cmd.extend(connection.iter().map(String::as_str));
+
+        match exec(docker, container_id, cmd, database.env()).await {
+            Ok(output) if output.exit_code == Some(0) => break Ok(()),
+            Ok(output) => output::debug(format!("pg_isready exited with {:?}", output.exit_code)),
+            Err(e) => output::debug(format!("pg_isready failed: {}", e)),
+        }
+
+        if started.elapsed() >= READY_TIMEOUT {
+            break Err(Error::new(
+                ErrorKind::DumpFailed,
+                format!(
+                    "The database in {} did not accept connections within {} seconds of starting. Check its logs \
+                     with `docker logs {}`.",
+                    name,
+                    READY_TIMEOUT.as_secs(),
+                    name
+                ),
+            ));
+        }
+
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    };
+
+    output::debug(format!(
+        "{} was ready after {:.1}s",
+        name,
+        started.elapsed().as_secs_f64()
+    ));
+    result
+}
+
+///
+/// Works out the role and database to connect with, from the options and the environment of the container.
+///
+/// `--db-user` and `--db-name` win over the `POSTGRES_USER` and `POSTGRES_DB` variables of the postgres image, which
+/// win over the Ballsdex defaults. `POSTGRES_PASSWORD` is always taken from the container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The database. If the container cannot be inspected, only the options and defaults are used.
+///
+pub async fn database(docker: &Docker, container_id: &str, options: &Options) -> Database {
+    let env = environment(docker, container_id).await;
+    let variable = |name: &str| variable(&env, name);
+
+    let configured = options.database();
+    let user = options.db_user.clone().or_else(|| variable("POSTGRES_USER"));
+
+    Database {
+        default_user: user.is_none(),
+        user: user.unwrap_or(configured.user),
+        name: configured.name.or_else(|| variable("POSTGRES_DB")),
+        password: variable("POSTGRES_PASSWORD"),
+    }
+}
+
+///
+/// Reads the environment a container was created with.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+///
+/// #### Returns
+///
+/// The `KEY=value` entries, empty if the container cannot be inspected.
+///
+pub async fn environment(docker: &Docker, container_id: &str) -> Vec<String> {
+    output::debug(format!("inspect_container {}", container_id));
+
+    match docker.inspect_container(container_id, None).await {
+        Ok(info) => info.config.and_then(|config| config.env).unwrap_or_default(),
+        Err(e) => {
+            output::debug(format!(
+                "Failed to inspect {}, using the configured database: {}",
+                container_id, e
+            ));
+            Vec::new()
+        }
+    }
+}
+
+///
+/// Looks up a variable of an environment.
+///
+/// #### Arguments
+///
+/// * `env`: The `KEY=value` entries.
+/// * `name`: The name of the variable.
+///
+/// #### Returns
+///
+/// The value, or `None` if the variable is missing or empty.
+///
+pub fn variable(env: &[String], name: &str) -> Option<String> {
+    env.iter()
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(key, value)| *key == name && !value.is_empty())
+        .map(|(_, value)| value.to_string())
+}
+
+///
+/// Returns the image a container was created from.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+///
+/// #### Returns
+///
+/// The image reference, e.g. `postgres:16`, or `None` if the container cannot be inspected.
+///
+pub async fn image(docker: &Docker, container_id: &str) -> Option<String> {
+    output::debug(format!("inspect_container {}", container_id));
+    let info = docker.inspect_container(container_id, None).await.ok()?;
+    info.config?.image
+}
+
+///
+/// Runs a command inside a container and waits for it to finish.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command and its arguments.
+/// * `env`: Extra `KEY=value` environment variables for the command.
+///
+/// #### Returns
+///
+/// The exit code and output of the command or an error.
+///
+pub async fn exec(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<&str>,
+    env: Vec<String>,
+) -> Result<ExecOutput, bollard::errors::Error> {
+    output::debug(format!("create_exec {}: {}", container_id, cmd.join(" ")));
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd.into_iter().map(str::to_string).collect()),
+                env: Some(env),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    if let StartExecResults::Attached { output: mut stream, .. } = docker.start_exec(&exec.id, None).await? {
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                bollard::container::LogOutput::StdOut { message } => {
+                    stdout.extend_from_slice(&message);
+                }
+                bollard::container::LogOutput::StdErr { message } => {
+                    stderr.extend_from_slice(&message);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+    output::debug(format!("exec {} exited with {:?}", exec.id, exit_code));
+
+    Ok(ExecOutput {
+        exit_code,
+        stdout,
+        stderr,
+    })
+}
+
+///
+/// Runs a command inside a container with its stdin read from a reader, and waits for it to finish.
+///
+/// The command may exit before it read all of its input, e.g. `pg_restore --list` only needs the start of a dump.
+/// The rest of the input is not sent then.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command and its arguments.
+/// * `env`: Extra `KEY=value` environment variables for the command.
+/// * `input`: What the command reads from stdin.
+///
+/// #### Returns
+///
+/// The exit code and output of the command or an error.
+///
+pub async fn exec_with_input(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+    input: &mut (impl AsyncRead + Unpin),
+) -> Result<ExecOutput, bollard::errors::Error> {
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    let exit_code = exec_streaming(docker, container_id, cmd, env, input, |stream, chunk| match stream {
+        Stream::Stdout => stdout.extend_from_slice(chunk),
+        Stream::Stderr => stderr.extend_from_slice(chunk),
+    })
+    .await?;
+
+    Ok(ExecOutput {
+        exit_code,
+        stdout,
+        stderr,
+    })
+}
+
+///
+/// Which output of a command a chunk was written to.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// The standard output of the command.
+    Stdout,
+    /// The standard error of the command, e.g. the notices and errors of psql.
+    Stderr,
+}
+
+impl Stream {
+    ///
+    /// Returns the name of the output, e.g. `stderr`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+///
+/// Runs a command inside a container with its stdin read from a reader, handing its output over as it arrives rather
+/// than keeping it, for commands that print more than fits in memory.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command and its arguments.
+/// * `env`: Extra `KEY=value` environment variables for the command.
+/// * `input`: What the command reads from stdin.
+/// * `on_output`: Called with every chunk the command writes, in the order it was written.
+///
+/// #### Returns
+///
+/// The exit code of the command or an error.
+///
+pub async fn exec_streaming(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+    input: &mut (impl AsyncRead + Unpin),
+    mut on_output: impl FnMut(Stream, &[u8]),
+) -> Result<Option<i64>, bollard::errors::Error> {
+    output::debug(format!("create_exec {}: {}", container_id, cmd.join(" ")));
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                attach_stdin: Some(true),
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd),
+                env: Some(env),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    if let StartExecResults::Attached {
+        output: mut stream,
+        input: mut stdin,
+    } = docker.start_exec(&exec.id, None).await?
+    {
+        let send = async {
+            // A command that stops reading closes its stdin, which is not an error.
+            let _ = tokio::io::copy(input, &mut stdin).await;
+            let _ = stdin.shutdown().await;
+        };
+
+        let receive = async {
+            while let Some(chunk) = stream.next().await {
+                match chunk? {
+                    bollard::container::LogOutput::StdOut { message } => on_output(Stream::Stdout, &message),
+                    bollard::container::LogOutput::StdErr { message } => on_output(Stream::Stderr, &message),
+                    _ => {}
+                }
+            }
+
+            Ok::<_, bollard::errors::Error>(())
+        };
+
+        let mut send = std::pin::pin!(send);
+        let mut receive = std::pin::pin!(receive);
+
+        tokio::select! {
+            received = &mut receive => received?,
+            _ = &mut send => receive.await?,
+        }
+    }
+
+    let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+    output::debug(format!("exec {} exited with {:?}", exec.id, exit_code));
+
+    Ok(exit_code)
+}
+
+///
+/// Runs a command inside a container with its stdin read from a reader and its stdout written to a writer, for
+/// commands whose output is fed to another command, e.g. `pg_restore -f -` into psql.
+///
+/// The command only gets more output through while the writer takes it. Once the writer fails, e.g. because its
+/// reading end was dropped, the output is no longer read, which stops the command. The writer is left open, so the
+/// caller can still add to what the command wrote.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command and its arguments.
+/// * `env`: Extra `KEY=value` environment variables for the command.
+/// * `input`: What the command reads from stdin.
+/// * `stdout`: Where the standard output of the command is written to.
+/// * `on_stderr`: Called with every chunk the command writes to stderr.
+///
+/// #### Returns
+///
+/// The exit code of the command, `None` if it was stopped by the writer, or an error.
+///
+pub async fn exec_piped(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+    input: &mut (impl AsyncRead + Unpin),
+    stdout: &mut (impl AsyncWrite + Unpin),
+    mut on_stderr: impl FnMut(&[u8]),
+) -> Result<Option<i64>, bollard::errors::Error> {
+    output::debug(format!("create_exec {}: {}", container_id, cmd.join(" ")));
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                attach_stdin: Some(true),
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd),
+                env: Some(env),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut stopped = false;
+
+    if let StartExecResults::Attached {
+        output: mut stream,
+        input: mut stdin,
+    } = docker.start_exec(&exec.id, None).await?
+    {
+        let send = async {
+            let _ = tokio::io::copy(input, &mut stdin).await;
+            let _ = stdin.shutdown().await;
+        };
+
+        let receive = async {
+            while let Some(chunk) = stream.next().await {
+                let written = match chunk? {
+                    bollard::container::LogOutput::StdOut { message } => stdout.write_all(&message).await,
+                    bollard::container::LogOutput::StdErr { message } => {
+                        on_stderr(&message);
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                };
+
+                // The reader went away, e.g. psql stopped at a failing statement.
+                if written.is_err() {
+                    stopped = true;
+                    break;
+                }
+            }
+
+            Ok::<_, bollard::errors::Error>(())
+        };
+
+        let mut send = std::pin::pin!(send);
+        let mut receive = std::pin::pin!(receive);
+
+        tokio::select! {
+            received = &mut receive => received?,
+            _ = &mut send => receive.await?,
+        }
+    }
+
+    if stopped {
+        output::debug(format!("exec {} was stopped, its output was no longer read", exec.id));
+        return Ok(None);
+    }
+
+    let exit_code = docker.inspect_exec(&exec.id).await?.exit_code;
+    output::debug(format!("exec {} exited with {:?}", exec.id, exit_code));
+
+    Ok(exit_code)
+}
+
+///
+/// Builds the `pg_dump` command run inside the container.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to dump.
+/// * `args`: The export arguments, for the format, the mode, the included and excluded tables and the extra arguments.
+///
+/// #### Returns
+///
+/// The command and its arguments.
+///
+pub fn pg_dump_command(database: &Database, args: &ExportArgs) -> Vec<String> {
+    let mut cmd = vec!["pg_dump".to_string()]; // Ballsdex database dump command thingy
+    cmd.extend(database.connection_args());
+    cmd.extend(args.format.pg_dump_args().iter().map(|arg| arg.to_string()));
+
+    // The roles of the source rarely exist on the target, where restoring them would fail on every statement.
+    if !args.keep_owner {
+        cmd.push("--no-owner".to_string());
+    }
+
+    if !args.keep_privileges {
+        cmd.push("--no-privileges".to_string());
+    }
+
+    if let Some(jobs) = args.jobs {
+        cmd.push("-j".to_string());
+        cmd.push(jobs.to_string());
+    }
+
+    cmd.extend(args.config_pg_dump_args.iter().cloned());
+    cmd.extend(args.pg_dump_arg.iter().cloned());
+
+    if args.schema_only {
+        cmd.push("-s".to_string());
+    } else if args.data_only {
+        cmd.push("-a".to_string());
+    }
+
+    for table in &args.tables {
+        cmd.push("-t".to_string());
+        cmd.push(table.clone());
+    }
+
+    for table in &args.exclude_tables {
+        cmd.push("-T".to_string());
+        cmd.push(table.clone());
+    }
+
+    cmd
+}
+
+///
+/// Finds the ndmig option behind a pg_dump flag that ndmig sets on its own.
+///
+/// #### Arguments
+///
+/// * `arg`: An extra argument for `pg_dump`, e.g. `-Fc` or `--format=custom`.
+///
+/// #### Returns
+///
+/// The option that controls the flag, e.g. `--format`, or `None` if the argument is free to pass.
+///
+pub fn managed_pg_dump_option(arg: &str) -> Option<&'static str> {
+    MANAGED_PG_DUMP_FLAGS
+        .iter()
+        .find(|(short, long, takes_value, _)| {
+            arg == *short
+                || (*takes_value && arg.starts_with(short))
+                || arg == *long
+                || arg.strip_prefix(long).is_some_and(|rest| rest.starts_with('='))
+        })
+        .map(|(_, _, _, option)| *option)
+}
+
+///
+/// Creates a database dump by using `pg_dump` in the bot's postgres container.
+///
+/// The container must already be running. The output is written chunk by chunk as it arrives, so memory use does not
+/// grow with the size of the database. A non-zero exit code of `pg_dump` is an error, even if it wrote some output.
+///
+/// Docker errors before the first byte arrived, such as a failed attach, are retried with a growing delay, up to the
+/// attempts set with [`set_exec_attempts`].
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The `pg_dump` command, as built by [`pg_dump_command`], or another command that writes the dump to stdout.
+/// * `env`: The environment of the command, as returned by [`Database::env`].
+/// * `progress`: Reports the received bytes. It is not finished, so it can span several commands.
+/// * `writer`: Where to write the dump. The bytes are passed through unchanged, so any format works.
+///
+/// ### Returns
+///
+/// The size and warnings of the dump, or an error.
+///
+pub async fn create_database_dump(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+    progress: &Progress,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> Result<DatabaseDump, Error> {
+    let docker_error = |e: bollard::errors::Error| Error::new(ErrorKind::DumpFailed, format!("Export failed: {}", e));
+    let write_error = |e: std::io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    let program = cmd.first().cloned().unwrap_or_default();
+    let attempts = EXEC_ATTEMPTS.load(Ordering::Relaxed).max(1);
+    let mut attempt = 1;
+
+    let (exec_id, dump) = loop {
+        match attach_exec(docker, container_id, &cmd, &env, progress, writer).await {
+            Ok(attached) => break attached,
+            Err(ExecFailure::Transient(error)) if attempt < attempts => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt - 1);
+
+                output::status(
+                    format!(
+                        "{} {}, retrying in {:.1}s (attempt {}/{})",
+                        Glyph::Warning,
+                        error.message,
+                        delay.as_secs_f64(),
+                        attempt + 1,
+                        attempts
+                    )
+                    .yellow(),
+                );
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(ExecFailure::Transient(error) | ExecFailure::Fatal(error)) => return Err(error),
+        }
+    };
+
+    let DatabaseDump { bytes, warnings } = dump;
+
+    writer.flush().await.map_err(write_error)?;
+
+    // The stream also ends when pg_dump fails halfway, so only the exit code tells a complete dump apart.
+    let exit_code = docker.inspect_exec(&exec_id).await.map_err(docker_error)?.exit_code;
+    output::debug(format!("exec {} exited with {:?}", exec_id, exit_code));
+
+    check_exit(&program, exit_code, &warnings)?;
+
+    Ok(DatabaseDump { bytes, warnings })
+}
+
+///
+/// Tells from the exit code of the dump command whether the dump is complete.
+///
+/// #### Arguments
+///
+/// * `program`: The name of the command, e.g. `pg_dump`.
+/// * `exit_code`: Its exit code, `None` if Docker does not know it.
+/// * `stderr`: The lines it wrote to stderr.
+///
+/// #### Returns
+///
+/// Nothing, or an error with what the command printed and a hint for a wrong role or database.
+///
+fn check_exit(program: &str, exit_code: Option<i64>, stderr: &[String]) -> Result<(), Error> {
+    if exit_code == Some(0) {
+        return Ok(());
+    }
+
+    let status = exit_code.map_or("an unknown status".to_string(), |code| format!("status {}", code));
+
+    let mut message = if stderr.is_empty() {
+        format!("{} exited with {}", program, status)
+    } else {
+        format!("{} exited with {}: {}", program, status, stderr.join("\n"))
+    };
+
+    if let Some(hint) = connection_hint(stderr) {
+        message = format!("{}\n{}", message, hint);
+    }
+
+    Err(Error::new(ErrorKind::DumpFailed, message))
+}
+
+///
+/// Runs a command inside a container without attaching to it, and waits for it to finish.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command and its arguments.
+/// * `env`: Extra `KEY=value` environment variables for the command.
+///
+/// #### Returns
+///
+/// The exit code of the command, or an error.
+///
+async fn run_detached(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+) -> Result<Option<i64>, Error> {
+    output::debug(format!("create_exec {} (detached): {}", container_id, cmd.join(" ")));
+
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                cmd: Some(cmd),
+                env: Some(env),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(exec_error)?;
+
+    docker
+        .start_exec(
+            &exec.id,
+            Some(StartExecOptions {
+                detach: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .map_err(exec_error)?;
+
+    loop {
+        let inspected = docker.inspect_exec(&exec.id).await.map_err(exec_error)?;
+
+        if inspected.running != Some(true) {
+            output::debug(format!("exec {} exited with {:?}", exec.id, inspected.exit_code));
+            return Ok(inspected.exit_code);
+        }
+
+        tokio::time::sleep(DETACHED_POLL_INTERVAL).await;
+    }
+}
+
+///
+/// Runs `pg_dump` inside a container without attaching to it, for dumps it writes to a file with `-f`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The `pg_dump` command, including `-f`.
+/// * `env`: The environment of the command, as returned by [`Database::env`].
+///
+/// #### Returns
+///
+/// Nothing, or an error if `pg_dump` failed. Its error output is not available.
+///
+pub async fn run_pg_dump_detached(
+    docker: &Docker,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+) -> Result<(), Error> {
+    let program = cmd.first().cloned().unwrap_or_default();
+    let exit_code = run_detached(docker, container_id, cmd, env).await?;
+
+    if exit_code == Some(0) {
+        return Ok(());
+    }
+
+    let status = exit_code.map_or("an unknown status".to_string(), |code| format!("status {}", code));
+
+    Err(Error::new(
+        ErrorKind::DumpFailed,
+        format!(
+            "{} exited with {}. Its error output is not available with --via-file, see the postgres logs.",
+            program, status
+        ),
+    ))
+}
+
+///
+/// Returns where ndmig stages a dump inside a container, for formats that `pg_dump` writes to a file or directory.
+///
+/// The path only depends on the process, so an interrupted export can clean it up without knowing how far it got.
+///
+pub fn staging_path() -> String {
+    format!("/tmp/ndmig-{}", std::process::id())
+}
+
+///
+/// Removes a staged dump from a container, and warns if that fails.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `path`: The file or directory to remove, from [`staging_path`].
+///
+pub async fn remove_staged(docker: &Docker, container_id: &str, path: &str) {
+    let cmd = vec!["rm".to_string(), "-rf".to_string(), path.to_string()];
+
+    if !matches!(run_detached(docker, container_id, cmd, Vec::new()).await, Ok(Some(0))) {
+        output::status(
+            format!(
+                "{} Failed to remove {} from the container, remove it by hand to free the space.",
+                Glyph::Warning,
+                path
+            )
+            .yellow(),
+        );
+    }
+}
+
+///
+/// Creates a database dump by letting `pg_dump` write it to a file inside the container, then copying that file out.
+///
+/// This is the `--via-file` fallback for hosts that allow running commands but not attaching to their output. The
+/// dump briefly takes up space inside the container, and the file is removed again whether the dump succeeded or not.
+/// As nothing is attached, what `pg_dump` writes to stderr is not available.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The `pg_dump` command, as built by [`pg_dump_command`].
+/// * `env`: The environment of the command, as returned by [`Database::env`].
+/// * `progress`: Reports the bytes copied out of the container.
+/// * `writer`: Where to write the dump.
+///
+/// #### Returns
+///
+/// The size of the dump, or an error.
+///
+pub async fn create_database_dump_via_file(
+    docker: &Docker,
+    container_id: &str,
+    mut cmd: Vec<String>,
+    env: Vec<String>,
+    progress: &Progress,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> Result<DatabaseDump, Error> {
+    let staged = staging_path();
+
+    cmd.push("-f".to_string());
+    cmd.push(staged.clone());
+
+    let program = cmd.first().cloned().unwrap_or_default();
+    let result = async {
+        run_pg_dump_detached(docker, container_id, cmd, env).await?;
+
+        archive::stream_file(docker, container_id, &staged, writer, Some(progress))
+            .await?
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("{} succeeded, but {} does not exist in the container.", program, staged),
+                )
+            })
+    }
+    .await;
+
+    remove_staged(docker, container_id, &staged).await;
+
+    let bytes = result?;
+
+    writer
+        .flush()
+        .await
+        .map_err(|e| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e)))?;
+
+    Ok(DatabaseDump {
+        bytes,
+        warnings: Vec::new(),
+    })
+}
+
+///
+/// Checks whether Docker refused to run or attach to a command because an authorization plugin forbids it.
+///
+/// #### Arguments
+///
+/// * `error`: The error returned by Docker.
+///
+fn is_exec_denied(error: &bollard::errors::Error) -> bool {
+    matches!(
+        error,
+        bollard::errors::Error::DockerResponseServerError { status_code: 403, message }
+            if message.contains("authorization denied")
+    )
+}
+
+///
+/// Explains an error of a dump command, with a way out if an authorization plugin blocks `docker exec`.
+///
+/// #### Arguments
+///
+/// * `error`: The error returned by Docker.
+///
+fn exec_error(error: bollard::errors::Error) -> Error {
+    if is_exec_denied(&error) {
+        return Error::new(
+            ErrorKind::DumpFailed,
+            format!(
+                "Docker refused to run the dump command: {}\n\
+                 An authorization plugin on this host blocks docker exec or attaching to it. If only attaching is \
+                 blocked, --via-file runs pg_dump detached and copies the dump out of the container instead.",
+                error
+            ),
+        );
+    }
+
+    Error::new(ErrorKind::DumpFailed, format!("Export failed: {}", error))
+}
+
+///
+/// Why a single attempt to run the dump command failed.
+///
+enum ExecFailure {
+    /// Docker failed before any output arrived, so the command can safely run again.
+    Transient(Error),
+    /// The failure happened after output reached the writer, running again would duplicate it.
+    Fatal(Error),
+}
+
+///
+/// Runs the dump command once and streams its stdout into a writer until it ends.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `cmd`: The command to run.
+/// * `env`: The environment of the command.
+/// * `progress`: Reports the received bytes.
+/// * `writer`: Where to write the output.
+///
+/// #### Returns
+///
+/// The ID of the exec, to read its exit code from, and what was received, or why the attempt failed.
+///
+async fn attach_exec(
+    docker: &Docker,
+    container_id: &str,
+    cmd: &[String],
+    env: &[String],
+    progress: &Progress,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> Result<(String, DatabaseDump), ExecFailure> {
+    let write_error = |e: std::io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    // Running again does not help when the host forbids it.
+    let retryable = |e: bollard::errors::Error| match is_exec_denied(&e) {
+        true => ExecFailure::Fatal(exec_error(e)),
+        false => ExecFailure::Transient(exec_error(e)),
+    };
+
+    output::debug(format!("create_exec {}: {}", container_id, cmd.join(" ")));
+
+    let exec = docker
+        .create_exec(
+            container_id,
+            CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(cmd.to_vec()),
+                env: Some(env.to_vec()),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(retryable)?;
+
+    let mut bytes = 0;
+    let mut warnings = Vec::new();
+    let mut chunks = 0;
+    let started = Instant::now();
+
+    output::debug(format!("start_exec {}", exec.id));
+
+    let attached = docker.start_exec(&exec.id, None).await.map_err(retryable)?;
+
+    if let StartExecResults::Attached { output: mut stream, .. } = attached {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| match bytes {
+                0 => retryable(e),
+                _ => ExecFailure::Fatal(exec_error(e)),
+            })?;
+
+            match chunk {
+                bollard::container::LogOutput::StdOut { message } => {
+                    chunks += 1;
+                    bytes += message.len() as u64;
+                    progress.inc(message.len() as u64);
+                    writer
+                        .write_all(&message)
+                        .await
+                        .map_err(|e| ExecFailure::Fatal(write_error(e)))?;
+                }
+                bollard::container::LogOutput::StdErr { message } => {
+                    let message = String::from_utf8_lossy(&message).trim_end().to_string();
+
+                    if output::level() >= output::Level::Verbose {
+                        progress.suspend(|| eprintln!("{} {}", "pg_dump stderr:".yellow().bold(), message));
+                    }
+
+                    warnings.push(message);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    output::debug(format!(
+        "received {} chunks ({} bytes) in {:.2}s",
+        chunks,
+        bytes,
+        started.elapsed().as_secs_f64()
+    ));
+
+    Ok((exec.id, DatabaseDump { bytes, warnings }))
+}
+
+///
+/// Suggests the flag to fix a failed connection, from the lines `pg_dump` or `psql` wrote to stderr.
+///
+/// #### Arguments
+///
+/// * `stderr`: The lines written to stderr.
+///
+/// #### Returns
+///
+/// The hint, or `None` if the error is not about the role or the database.
+///
+fn connection_hint(stderr: &[String]) -> Option<&'static str> {
+    if stderr
+        .iter()
+        .any(|line| line.contains("role") && line.contains("does not exist"))
+    {
+        Some("Pass --db-user (or set db_user in the config file) to connect with another role.")
+    } else if stderr
+        .iter()
+        .any(|line| line.contains("database") && line.contains("does not exist"))
+    {
+        Some("Pass --db-name (or set db_name in the config file) to dump another database.")
+    } else {
+        None
+    }
+}
+
+///
+/// Runs a single SQL query with `psql` inside the container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+/// * `sql`: The query to run.
+///
+/// #### Returns
+///
+/// The unaligned, header-less output of the query, or `None` if it failed.
+///
+pub async fn query(docker: &Docker, container_id: &str, database: &Database, sql: &str) -> Option<String> {
+    let connection = database.connection_args();
+    let mut cmd = vec!["psql"];
+    cmd.extend(connection.iter().map(String::as_str));
+    cmd.extend(["-tAc", sql]);
+
+    let output = exec(docker, container_id, cmd, database.env()).await.ok()?;
+
+    if output.exit_code != Some(0) {
+        return None;
+    }
+
+    Some(output.stdout_text())
+}
+
+///
+/// Terminates the other sessions ndmig has open on the database, which rolls back whatever they had not committed.
+///
+/// An interrupted import ends its psql or pg_restore this way instead of closing its stdin, which psql would take
+/// as the end of the dump and commit.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// Whether the sessions could be terminated.
+///
+pub async fn terminate_sessions(docker: &Docker, container_id: &str, database: &Database) -> bool {
+    let sql = format!(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+         WHERE application_name = '{}' AND datname = current_database() AND pid <> pg_backend_pid()",
+        APPLICATION_NAME
+    );
+
+    query(docker, container_id, database, &sql).await.is_some()
+}
+
+///
+/// Runs a single SQL query with `psql` inside the container and splits its output into fields.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+/// * `sql`: The query to run.
+/// * `kind`: The kind of the error if the query fails.
+/// * `what`: What the query is for, e.g. `read the tables to merge`, which the error starts with.
+///
+/// #### Returns
+///
+/// The fields of every row, or an error with what psql printed if the query failed.
+///
+pub async fn rows(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    sql: &str,
+    kind: ErrorKind,
+    what: &str,
+) -> Result<Vec<Vec<String>>, Error> {
+    let connection = database.connection_args();
+    let mut cmd = vec!["psql"];
+    cmd.extend(connection.iter().map(String::as_str));
+    cmd.extend(["-tAc", sql]);
+
+    let output = exec(docker, container_id, cmd, database.env())
+        .await
+        .map_err(|e| Error::new(kind, format!("Failed to {}: {}", what, e)))?;
+
+    if output.exit_code != Some(0) {
+        return Err(Error::new(
+            kind,
+            format!("Failed to {}: {}", what, output.stderr_text()),
+        ));
+    }
+
+    Ok(output
+        .stdout_text()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split('|').map(str::to_string).collect())
+        .collect())
+}
+
+///
+/// Lists the tables of the Ballsdex database.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// The names of the tables in the `public` schema sorted by name, or `None` if the query failed.
+///
+pub async fn tables(docker: &Docker, container_id: &str, database: &Database) -> Option<Vec<String>> {
+    let output = query(
+        docker,
+        container_id,
+        database,
+        "SELECT tablename FROM pg_tables WHERE schemaname = 'public' ORDER BY tablename",
+    )
+    .await?;
+
+    Some(output.lines().map(str::to_string).collect())
+}
+
+///
+/// Reads the primary key columns of the tables of the Ballsdex database.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// The key columns in key order per table of the `public` schema that has a primary key, or `None` if the query
+/// failed.
+///
+pub async fn primary_keys(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+) -> Option<BTreeMap<String, Vec<String>>> {
+    let output = query(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, string_agg(a.attname, ',' ORDER BY array_position(i.indkey::int2[], a.attnum)) \
+         FROM pg_index i JOIN pg_class c ON c.oid = i.indrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = ANY(i.indkey) \
+         WHERE i.indisprimary AND n.nspname = 'public' GROUP BY c.relname",
+    )
+    .await?;
+
+    Some(
+        output
+            .lines()
+            .filter_map(|line| line.split_once('|'))
+            .map(|(table, columns)| (table.to_string(), columns.split(',').map(str::to_string).collect()))
+            .collect(),
+    )
+}
+
+///
+/// Lists the columns of a table for the header of its `COPY` section.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+/// * `table`: The table.
+///
+/// #### Returns
+///
+/// The quoted table name and its quoted columns in table order, or an error if they could not be queried.
+///
+pub async fn columns(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    table: &str,
+) -> Result<(String, String), Error> {
+    let output = query(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT quote_ident(table_name), string_agg(quote_ident(column_name), ', ' ORDER BY ordinal_position) \
+             FROM information_schema.columns WHERE table_schema = 'public' AND table_name = '{}' \
+             GROUP BY table_name",
+            table.replace('\'', "''")
+        ),
+    )
+    .await;
+
+    output
+        .as_deref()
+        .and_then(|output| output.trim_end().split_once('|'))
+        .map(|(table, columns)| (table.to_string(), columns.to_string()))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::DumpFailed,
+                format!("Failed to list the columns of {}.", table),
+            )
+        })
+}
+
+/// The Ballsdex tables that hold the actual game data.
+pub const CORE_TABLES: [&str; 7] = [
+    "player",
+    "ball",
+    "ballinstance",
+    "guildconfig",
+    "special",
+    "trade",
+    "blacklistedid",
+];
+
+/// Every table of the Ballsdex schema by its Ballsdex name, including the migrations of aerich.
+pub const BALLSDEX_TABLES: [&str; 15] = [
+    "aerich",
+    "ball",
+    "ballinstance",
+    "blacklistedguild",
+    "blacklistedid",
+    "blacklisthistory",
+    "block",
+    "economy",
+    "friendship",
+    "guildconfig",
+    "player",
+    "regime",
+    "special",
+    "trade",
+    "tradeobject",
+];
+
+/// The Ballsdex tables that hold the moderation data, which `--skip-blacklists` and `--only-blacklists` pick out.
+pub const BLACKLIST_TABLES: [&str; 3] = ["blacklistedid", "blacklistedguild", "blacklisthistory"];
+
+///
+/// Counts the rows of some tables exactly.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+/// * `tables`: The tables to count. Tables that do not exist are left out.
+///
+/// #### Returns
+///
+/// The number of rows per existing table, or `None` if the query failed.
+///
+pub async fn row_counts(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    tables: &[&str],
+) -> Option<BTreeMap<String, u64>> {
+    let tables = tables
+        .iter()
+        .map(|table| format!("'{}'", table))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // query_to_xml runs an exact count(*) per table while skipping tables this Ballsdex version lacks.
+    let output = query(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT table_name, (xpath('/row/c/text()', query_to_xml(format('SELECT count(*) AS c FROM %I', table_name), false, true, '')))[1]::text \
+             FROM information_schema.tables WHERE table_schema = 'public' AND table_name IN ({})",
+            tables
+        ),
+    )
+    .await?;
+
+    let mut counts = BTreeMap::new();
+
+    for line in output.lines() {
+        if let Some((table, count)) = line.split_once('|')
+            && let Ok(count) = count.parse()
+        {
+            counts.insert(table.to_string(), count);
+        }
+    }
+
+    Some(counts)
+}
+
+///
+/// Queries the on-disk size of the Ballsdex database.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// The size in bytes, or `None` if it could not be queried.
+///
+pub async fn database_size(docker: &Docker, container_id: &str, database: &Database) -> Option<u64> {
+    query(
+        docker,
+        container_id,
+        database,
+        "SELECT pg_database_size(current_database())",
+    )
+    .await?
+    .parse()
+    .ok()
+}
+
+///
+/// Queries the database size of several instances concurrently.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The instances to query.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The size of every instance in the same order, `None` for stopped instances and failed queries.
+///
+pub async fn database_sizes(docker: &Docker, instances: &[Instance], options: &Options) -> Vec<Option<u64>> {
+    join_all(instances.iter().map(|instance| async {
+        if instance.running {
+            let database = database(docker, &instance.container_id, options).await;
+            database_size(docker, &instance.container_id, &database).await
+        } else {
+            None
+        }
+    }))
+    .await
+}
+
+///
+/// Returns the compose project of an instance, e.g. `mydex` for `mydex-postgres-db-1`.
+///
+/// #### Arguments
+///
+/// * `instance`: The full container name of the instance.
+///
+pub fn project(instance: &str) -> &str {
+    instance.strip_suffix("-postgres-db-1").unwrap_or(instance)
+}
+
+///
+/// Finds another container of the compose project of an instance, such as its Redis.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+/// * `service`: The compose service, e.g. `redis`.
+///
+/// #### Returns
+///
+/// The `<project>-<service>-1` container, `None` if the project has none, or an error.
+///
+pub async fn find_service(
+    docker: &Docker,
+    instance: &str,
+    service: &str,
+) -> Result<Option<Instance>, bollard::errors::Error> {
+    let name = format!("{}-{}-1", project(instance), service);
+    let options = Some(ListContainersOptions {
+        all: true,
+        ..Default::default()
+    });
+
+    output::debug(format!("list_containers all=true, looking for {}", name));
+
+    let found = docker.list_containers(options).await?.into_iter().find(|container| {
+        container
+            .names
+            .iter()
+            .flatten()
+            .any(|candidate| candidate.trim_start_matches('/') == name)
+    });
+
+    Ok(found.and_then(|container| {
+        Some(Instance {
+            name: name.clone(),
+            container_id: container.id?,
+            running: container.state == Some(ContainerSummaryStateEnum::RUNNING),
+            flavor: Flavor::Unknown,
+        })
+    }))
+}
+
+///
+/// Lists the Ballsdex instances known to the Docker daemon.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+///
+/// #### Returns
+///
+/// The Ballsdex instances sorted by name, or an error.
+///
+pub async fn discover_instances(docker: &Docker) -> Result<Vec<Instance>, bollard::errors::Error> {
+    let options = Some(ListContainersOptions {
+        all: true,
+        ..Default::default()
+    });
+
+    output::debug("list_containers all=true");
+
+    let all = docker.list_containers(options).await?;
+
+    let mut instances = Vec::new();
+
+    for container in &all {
+        let id = match container.id.as_deref() {
+            Some(id) => id,
+            None => continue,
+        };
+        if is_ballsdex_instance(docker, id).await {
+            let project_name = container
+                .names
+                .iter()
+                .flatten()
+                .next()
+                .map(|name| name.trim_start_matches("/").to_string())
+                .unwrap_or_else(|| id.to_string());
+
+            if project_name.ends_with("postgres-db-1") {
+                let bot_name = format!("{}-bot-1", project(&project_name));
+                let bot = all.iter().find(|candidate| {
+                    candidate
+                        .names
+                        .iter()
+                        .flatten()
+                        .any(|name| name.trim_start_matches('/') == bot_name)
+                });
+
+                instances.push(Instance {
+                    flavor: Flavor::of(bot),
+                    name: project_name,
+                    container_id: id.to_string(),
+                    running: container.state == Some(ContainerSummaryStateEnum::RUNNING),
+                });
+            }
+        }
+    }
+
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(instances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// Turns the lines pg_dump wrote to stderr into the form [`connection_hint`] reads.
+    ///
+    fn stderr(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn hints_at_the_role_for_a_wrong_user() {
+        let lines = stderr(&[
+            "pg_dump: error: connection to server on socket \"/var/run/postgresql/.s.PGSQL.5432\" failed: \
+             FATAL:  role \"ballsdex\" does not exist",
+        ]);
+
+        assert_eq!(
+            connection_hint(&lines),
+            Some("Pass --db-user (or set db_user in the config file) to connect with another role.")
+        );
+    }
+
+    #[test]
+    fn hints_at_the_database_for_a_wrong_name() {
+        let lines = stderr(&[
+            "pg_dump: error: connection to server on socket \"/var/run/postgresql/.s.PGSQL.5432\" failed: \
+             FATAL:  database \"nationdex\" does not exist",
+        ]);
+
+        assert_eq!(
+            connection_hint(&lines),
+            Some("Pass --db-name (or set db_name in the config file) to dump another database.")
+        );
+    }
+
+    #[test]
+    fn gives_no_hint_for_other_errors() {
+        let lines = stderr(&[
+            "pg_dump: error: query failed: ERROR:  permission denied for table player",
+            "pg_dump: detail: Query was: LOCK TABLE public.player IN ACCESS SHARE MODE",
+        ]);
+
+        assert_eq!(connection_hint(&lines), None);
+        assert_eq!(connection_hint(&[]), None);
+    }
+
+    #[test]
+    fn accepts_a_dump_that_exited_with_zero() {
+        let lines = stderr(&["pg_dump: warning: there are circular foreign-key constraints on this table"]);
+
+        assert!(check_exit("pg_dump", Some(0), &lines).is_ok());
+    }
+
+    #[test]
+    fn fails_a_dump_of_a_wrong_user() {
+        let lines = stderr(&[
+            "pg_dump: error: connection to server on socket \"/var/run/postgresql/.s.PGSQL.5432\" failed: \
+             FATAL:  role \"ballsdex\" does not exist",
+        ]);
+
+        let error = check_exit("pg_dump", Some(1), &lines).unwrap_err();
+
+        assert_eq!(error.kind, ErrorKind::DumpFailed);
+        assert_eq!(
+            error.message,
+            format!(
+                "pg_dump exited with status 1: {}\nPass --db-user (or set db_user in the config file) to connect with \
+                 another role.",
+                lines[0]
+            )
+        );
+    }
+
+    #[test]
+    fn fails_a_dump_without_an_exit_code() {
+        let error = check_exit("pg_dump", None, &[]).unwrap_err();
+
+        assert_eq!(error.kind, ErrorKind::DumpFailed);
+        assert_eq!(error.message, "pg_dump exited with an unknown status");
+    }
+}
@@ -0,0 +1,248 @@
+/*
+    Per-table CSV exports, for `--format csv`.
+*/
+
+use crate::cli::ExportArgs;
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use serde::Serialize;
+use std::path::Path;
+use tokio::io::BufWriter;
+
+/// The name of the index written next to the CSV files.
+pub const INDEX: &str = "tables.json";
+
+///
+/// The contents of `tables.json`.
+///
+#[derive(Serialize)]
+struct Index<'a> {
+    instance: &'a str,
+    tables: Vec<IndexEntry>,
+}
+
+///
+/// A single exported table in `tables.json`.
+///
+#[derive(Serialize)]
+struct IndexEntry {
+    table: String,
+    file: String,
+    /// The number of rows when the export started, `None` if they could not be counted.
+    rows: Option<u64>,
+    bytes: u64,
+}
+
+///
+/// Builds the `psql` command that writes a table as CSV to stdout.
+///
+/// `-q` keeps the `COPY n` status line out of the CSV, `-X` ignores a `.psqlrc` in the container.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to connect with.
+/// * `table`: The table to export.
+///
+pub fn copy_command(database: &Database, table: &str) -> Vec<String> {
+    let mut cmd = vec![
+        "psql".to_string(),
+        "-X".to_string(),
+        "-q".to_string(),
+        "-v".to_string(),
+        "ON_ERROR_STOP=1".to_string(),
+    ];
+    cmd.extend(database.connection_args());
+    cmd.push("-c".to_string());
+    cmd.push(format!(
+        "\\copy \"{}\" TO STDOUT WITH CSV HEADER",
+        table.replace('"', "\"\"")
+    ));
+    cmd
+}
+
+///
+/// Works out which tables a CSV export writes.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// Every table of the public schema, limited by `--tables` and `--exclude-tables`, or an error if they could not be
+/// listed.
+///
+async fn select_tables(
+    docker: &Docker,
+    container_id: &str,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<Vec<String>, Error> {
+    let Some(available) = container::tables(docker, container_id, database).await else {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            "Failed to list the tables of the database.",
+        ));
+    };
+
+    Ok(available
+        .into_iter()
+        .filter(|table| args.tables.is_empty() || args.tables.contains(table))
+        .filter(|table| !args.exclude_tables.contains(table))
+        .collect())
+}
+
+///
+/// Writes one CSV file per table into a directory, with a `tables.json` index next to them.
+///
+/// The files are written into `<directory>.partial` first and only moved into place once every table succeeded.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `instance`: The short instance name, recorded in the index.
+/// * `directory`: The directory to write.
+/// * `progress`: Reports the received bytes of all tables together.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to export.
+///
+/// #### Returns
+///
+/// The total size and the warnings of all tables, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    instance: &str,
+    directory: &Path,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<DatabaseDump, Error> {
+    let tables = select_tables(docker, container_id, args, database).await?;
+    let names: Vec<&str> = tables.iter().map(String::as_str).collect();
+    let counts = container::row_counts(docker, container_id, database, &names).await;
+
+    let partial = crate::partial_path(directory);
+    let write_error = |path: &Path, e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    };
+
+    // A leftover of an earlier interrupted export would otherwise end up in the new directory.
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+    }
+
+    std::fs::create_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+
+    let result = write_tables(docker, container_id, &partial, progress, database, &tables).await;
+    progress.finish();
+
+    let (dump, mut entries) = match result {
+        Ok(written) => written,
+        Err(error) => {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Err(error);
+        }
+    };
+
+    for entry in &mut entries {
+        entry.rows = counts.as_ref().and_then(|counts| counts.get(&entry.table).copied());
+    }
+
+    let index = Index {
+        instance,
+        tables: entries,
+    };
+    let index_path = partial.join(INDEX);
+    let contents = serde_json::to_string_pretty(&index).expect("Failed to serialize the table index");
+    std::fs::write(&index_path, contents + "\n").map_err(|e| write_error(&index_path, e))?;
+
+    // The overwrite was confirmed before the export started, and a directory cannot be renamed onto a full one.
+    if directory.exists() {
+        std::fs::remove_dir_all(directory).map_err(|e| write_error(directory, e))?;
+    }
+
+    std::fs::rename(&partial, directory).map_err(|e| write_error(directory, e))?;
+
+    Ok(dump)
+}
+
+///
+/// Writes the CSV file of every table.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `directory`: The directory to write the files into.
+/// * `progress`: Reports the received bytes.
+/// * `database`: The role and database to export.
+/// * `tables`: The tables to export.
+///
+/// #### Returns
+///
+/// The total size and the warnings, and the index entries without row counts, or the first error.
+///
+async fn write_tables(
+    docker: &Docker,
+    container_id: &str,
+    directory: &Path,
+    progress: &Progress,
+    database: &Database,
+    tables: &[String],
+) -> Result<(DatabaseDump, Vec<IndexEntry>), Error> {
+    let mut dump = DatabaseDump {
+        bytes: 0,
+        warnings: Vec::new(),
+    };
+    let mut entries = Vec::new();
+
+    for table in tables {
+        let file = format!("{}.{}", table, DumpFormat::Csv.extension());
+        let path = directory.join(&file);
+        let write_error = |e: std::io::Error| {
+            Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to write {}: {}", path.display(), e),
+            )
+        };
+
+        let mut writer = BufWriter::new(tokio::fs::File::create(&path).await.map_err(write_error)?);
+
+        let written = container::create_database_dump(
+            docker,
+            container_id,
+            copy_command(database, table),
+            database.env(),
+            progress,
+            &mut writer,
+        )
+        .await?;
+
+        writer.into_inner().sync_all().await.map_err(write_error)?;
+        output::debug(format!("Wrote {} ({} bytes)", path.display(), written.bytes));
+
+        dump.bytes += written.bytes;
+        dump.warnings.extend(written.warnings);
+        entries.push(IndexEntry {
+            table: table.clone(),
+            file,
+            rows: None,
+            bytes: written.bytes,
+        });
+    }
+
+    Ok((dump, entries))
+}
@@ -0,0 +1,141 @@
+/*
+    Directory-format dumps, for `--format directory`, which pg_dump can write with several jobs in parallel.
+*/
+
+use crate::archive;
+use crate::cli::ExportArgs;
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use crate::progress::Progress;
+use bollard::Docker;
+use colored::*;
+use std::path::Path;
+
+///
+/// Writes a directory-format dump.
+///
+/// `pg_dump` cannot stream a directory archive, so it writes one inside the container first, under
+/// [`container::staging_path`]. The directory is then copied out through the archive API into
+/// `<directory>.partial`, which is only moved into place once it is complete. The staged copy is removed whether the
+/// dump succeeded or not.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `directory`: The directory to write.
+/// * `progress`: Reports the bytes copied out of the container.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to export.
+///
+/// #### Returns
+///
+/// The total size of the files and the warnings of `pg_dump`, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    directory: &Path,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<DatabaseDump, Error> {
+    let staged = container::staging_path();
+    let partial = crate::partial_path(directory);
+    let write_error = |path: &Path, e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    };
+
+    // A leftover of an earlier interrupted export would otherwise end up in the new directory.
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+    }
+
+    std::fs::create_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+
+    let result = stage_and_copy(docker, container_id, &staged, &partial, progress, args, database).await;
+    progress.finish();
+    container::remove_staged(docker, container_id, &staged).await;
+
+    let dump = match result {
+        Ok(dump) => dump,
+        Err(error) => {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Err(error);
+        }
+    };
+
+    // The overwrite was confirmed before the export started, and a directory cannot be renamed onto a full one.
+    if directory.exists() {
+        std::fs::remove_dir_all(directory).map_err(|e| write_error(directory, e))?;
+    }
+
+    std::fs::rename(&partial, directory).map_err(|e| write_error(directory, e))?;
+
+    Ok(dump)
+}
+
+///
+/// Runs `pg_dump` into the staging directory and copies the result out of the container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `staged`: The staging directory inside the container. It must not exist yet.
+/// * `destination`: The existing directory on the host to copy the files into.
+/// * `progress`: Reports the bytes copied out of the container.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to export.
+///
+/// #### Returns
+///
+/// The total size of the files and the warnings of `pg_dump`, or an error.
+///
+async fn stage_and_copy(
+    docker: &Docker,
+    container_id: &str,
+    staged: &str,
+    destination: &Path,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<DatabaseDump, Error> {
+    let mut cmd = container::pg_dump_command(database, args);
+    cmd.push("-f".to_string());
+    cmd.push(staged.to_string());
+
+    // pg_dump refuses to write into a directory that already exists.
+    container::remove_staged(docker, container_id, staged).await;
+
+    // Nothing arrives on stdout while pg_dump writes the directory, only its warnings on stderr.
+    let warnings = if args.via_file {
+        container::run_pg_dump_detached(docker, container_id, cmd, database.env()).await?;
+        Vec::new()
+    } else {
+        let mut sink = tokio::io::sink();
+        container::create_database_dump(docker, container_id, cmd, database.env(), progress, &mut sink)
+            .await?
+            .warnings
+    };
+
+    output::status(format!("{} Copying {} out of the container...", Glyph::Pending, staged).yellow());
+
+    let contents = archive::extract_archive(docker, container_id, staged, destination, progress).await?;
+
+    output::debug(format!(
+        "Wrote {} ({} files, {} bytes)",
+        destination.display(),
+        contents.files,
+        contents.bytes
+    ));
+
+    Ok(DatabaseDump {
+        bytes: contents.bytes,
+        warnings,
+    })
+}
@@ -0,0 +1,262 @@
+/*
+    Environment checks for the `doctor` subcommand.
+*/
+
+use crate::cli::DoctorArgs;
+use crate::container::{self, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use bollard::Docker;
+use colored::*;
+
+/// Minimum free space in the ndmig temp directory before the check fails.
+const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+///
+/// Collects the results of the doctor checks.
+///
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    ///
+    /// Prints a passed check.
+    ///
+    /// #### Arguments
+    ///
+    /// * `name`: The name of the check.
+    /// * `detail`: What was found.
+    ///
+    fn pass(&mut self, name: &str, detail: &str) {
+        output::info(format!(
+            "  {} {} {}",
+            Glyph::Ok.to_string().green().bold(),
+            name.bold(),
+            detail.dimmed()
+        ));
+    }
+
+    ///
+    /// Prints a failed check along with a hint on how to fix it.
+    ///
+    /// #### Arguments
+    ///
+    /// * `name`: The name of the check.
+    /// * `detail`: What went wrong.
+    /// * `hint`: How to fix it.
+    ///
+    fn fail(&mut self, name: &str, detail: &str, hint: &str) {
+        self.failed += 1;
+        output::info(format!(
+            "  {} {} {}",
+            Glyph::Error.to_string().red().bold(),
+            name.bold(),
+            detail.bright_red()
+        ));
+        output::info(format!("      {} {}", "hint:".yellow(), hint));
+    }
+}
+
+///
+/// Checks the containers of a single instance.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `report`: The report to add the results to.
+/// * `instance`: The instance to check.
+///
+async fn check_instance(docker: &Docker, report: &mut Report, instance: &Instance) {
+    let container_id = instance.container_id.as_str();
+    let instance = instance.name.as_str();
+    let name = crate::format_name(instance);
+    let check = format!("{} container", name);
+
+    let started = match container::is_running(docker, container_id).await {
+        Ok(true) => {
+            report.pass(&check, "running");
+            false
+        }
+        Ok(false) => match docker.start_container(container_id, None).await {
+            Ok(()) => {
+                report.pass(&check, "stopped, but can be started");
+                true
+            }
+            Err(e) => {
+                report.fail(
+                    &check,
+                    &format!("stopped and cannot be started: {}", e),
+                    &format!("Check the container logs with `docker logs {}`.", instance),
+                );
+                return;
+            }
+        },
+        Err(e) => {
+            report.fail(
+                &check,
+                &format!("cannot be inspected: {}", e),
+                "Make sure the container still exists with `docker ps -a`.",
+            );
+            return;
+        }
+    };
+
+    let check = format!("{} pg_dump", name);
+
+    match container::exec(docker, container_id, vec!["pg_dump", "--version"], Vec::new()).await {
+        Ok(output) if output.exit_code == Some(0) => report.pass(&check, &output.stdout_text()),
+        Ok(output) => report.fail(
+            &check,
+            &output.stderr_text(),
+            "The postgres image must ship the PostgreSQL client tools (pg_dump).",
+        ),
+        Err(e) => report.fail(
+            &check,
+            &e.to_string(),
+            "Make sure `docker exec` is allowed on this host.",
+        ),
+    }
+
+    if started && let Err(e) = docker.stop_container(container_id, None).await {
+        report.fail(
+            &check,
+            &format!("failed to stop the container again: {}", e),
+            &format!("Stop it manually with `docker stop {}`.", instance),
+        );
+    }
+}
+
+///
+/// Checks the free space in the ndmig temp directory.
+///
+/// #### Arguments
+///
+/// * `report`: The report to add the result to.
+///
+fn check_free_space(report: &mut Report) {
+    let temp_dir = crate::temp_dir();
+    let _ = std::fs::create_dir_all(&temp_dir);
+
+    match fs4::available_space(&temp_dir) {
+        Ok(free) if free >= MIN_FREE_SPACE => report.pass(
+            "Free space",
+            &format!("{} free in {}", crate::format_size(free), temp_dir.display()),
+        ),
+        Ok(free) => report.fail(
+            "Free space",
+            &format!("only {} free in {}", crate::format_size(free), temp_dir.display()),
+            "Free up some disk space or export to another disk with --output.",
+        ),
+        Err(e) => report.fail(
+            "Free space",
+            &format!("cannot read free space of {}: {}", temp_dir.display(), e),
+            "Make sure the temp directory exists and is writable.",
+        ),
+    }
+}
+
+///
+/// Checks the Docker connection and every discovered instance.
+///
+/// #### Arguments
+///
+/// * `report`: The report to add the results to.
+/// * `args`: The doctor arguments.
+///
+async fn check_docker(report: &mut Report, args: &DoctorArgs) {
+    let docker = match Docker::connect_with_local_defaults() {
+        Ok(docker) => docker,
+        Err(e) => {
+            report.fail(
+                "Docker connection",
+                &e.to_string(),
+                "Make sure Docker is installed and DOCKER_HOST is set correctly.",
+            );
+            return;
+        }
+    };
+
+    match docker.version().await {
+        Ok(version) => report.pass(
+            "Docker connection",
+            &format!(
+                "Docker {} (API {})",
+                version.version.unwrap_or_default(),
+                version.api_version.unwrap_or_default()
+            ),
+        ),
+        Err(e) => {
+            report.fail(
+                "Docker connection",
+                &e.to_string(),
+                "Make sure the Docker daemon is running and your user can access its socket (e.g. is in the `docker` group).",
+            );
+            return;
+        }
+    }
+
+    let mut instances = match container::discover_instances(&docker).await {
+        Ok(instances) => instances,
+        Err(e) => {
+            report.fail(
+                "Ballsdex instances",
+                &format!("cannot list containers: {}", e),
+                "Make sure your user is allowed to list containers.",
+            );
+            return;
+        }
+    };
+
+    if let Some(name) = &args.instance {
+        instances = crate::resolve_instance(&instances, name).into_iter().cloned().collect();
+    }
+
+    if instances.is_empty() {
+        report.fail(
+            "Ballsdex instances",
+            "none found",
+            "ndmig looks for `<project>-postgres-db-1` containers running the `postgres` image.",
+        );
+    } else {
+        report.pass("Ballsdex instances", &format!("{} found", instances.len()));
+    }
+
+    for instance in &instances {
+        check_instance(&docker, report, instance).await;
+    }
+}
+
+///
+/// Runs every environment check and prints the results.
+///
+/// #### Arguments
+///
+/// * `args`: The doctor arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error if any check failed.
+///
+pub async fn run(args: &DoctorArgs) -> Result<(), Error> {
+    let mut report = Report::default();
+
+    output::status("Checking the ndmig environment...\n".bold().bright_white());
+
+    check_docker(&mut report, args).await;
+    check_free_space(&mut report);
+
+    output::info("");
+
+    if report.failed > 0 {
+        return Err(Error::new(
+            ErrorKind::ChecksFailed,
+            format!("{} check(s) failed.", report.failed),
+        ));
+    }
+
+    output::info(format!("{} All checks passed!", Glyph::Ok).green().bold());
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+/*
+    Finding the dumps ndmig has written, for `clean` and `exports`.
+*/
+
+use crate::bot;
+use crate::checksum;
+use crate::encrypt;
+use crate::globals;
+use crate::manifest;
+use crate::media;
+use crate::options::Options;
+use crate::output;
+use crate::redis;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Suffixes of the dumps ndmig writes, in every format and compression.
+const DUMP_SUFFIXES: &[&str] = &[
+    ".sql",
+    ".sql.gz",
+    ".sql.zst",
+    ".dump",
+    ".dump.gz",
+    ".dump.zst",
+    ".json",
+    ".json.gz",
+    ".json.zst",
+    ".ndmig.tar",
+];
+
+/// Suffixes of the files that belong to a dump, rather than being one.
+pub const SIDECAR_SUFFIXES: &[&str] = &[
+    manifest::SUFFIX,
+    checksum::SUFFIX,
+    redis::SUFFIX,
+    globals::SUFFIX,
+    bot::CONFIG_SUFFIX,
+    media::SUFFIX,
+];
+
+///
+/// A dump, or a partial dump, found in one of the ndmig directories.
+///
+pub struct DumpFile {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub modified: SystemTime,
+    pub age: Duration,
+    pub partial: bool,
+}
+
+///
+/// Returns the files that belong to a dump, such as its manifest.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+/// #### Returns
+///
+/// The paths the sidecars would have, whether they exist or not.
+///
+pub fn sidecars(dump: &Path) -> Vec<PathBuf> {
+    SIDECAR_SUFFIXES
+        .iter()
+        .map(|suffix| {
+            let mut path = dump.as_os_str().to_owned();
+            path.push(suffix);
+            PathBuf::from(path)
+        })
+        .collect()
+}
+
+///
+/// Lists the dumps in a directory.
+///
+/// #### Arguments
+///
+/// * `dir`: The directory to look in. A missing directory holds no dumps.
+/// * `owned`: Whether ndmig owns the directory. Otherwise only dumps with a known extension, such as
+///   `.sql` or `.dump.gz`, and `.partial` files are considered.
+///
+/// #### Returns
+///
+/// The dumps found directly in the directory, without their sidecars.
+///
+pub fn scan(dir: &Path, owned: bool) -> Vec<DumpFile> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        output::debug(format!("Skipping {}, it cannot be read", dir.display()));
+        return Vec::new();
+    };
+
+    let now = SystemTime::now();
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            let partial = extension == Some("partial");
+
+            if SIDECAR_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+                return None;
+            }
+
+            let plaintext_name = name.strip_suffix(encrypt::SUFFIX).unwrap_or(&name);
+            let dump = DUMP_SUFFIXES.iter().any(|suffix| plaintext_name.ends_with(suffix));
+
+            if !owned && !partial && !dump {
+                return None;
+            }
+
+            let modified = metadata.modified().unwrap_or(now);
+
+            Some(DumpFile {
+                path,
+                bytes: metadata.len(),
+                modified,
+                age: now.duration_since(modified).unwrap_or_default(),
+                partial,
+            })
+        })
+        .collect()
+}
+
+///
+/// Lists the dumps in the temporary directory and the configured output directory.
+///
+/// #### Arguments
+///
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Every dump found, oldest first.
+///
+pub fn find(options: &Options) -> Vec<DumpFile> {
+    let temp_dir = crate::temp_dir();
+    let mut files = scan(&temp_dir, true);
+
+    if let Some(output) = &options.output
+        && crate::is_output_dir(output)
+        && *output != temp_dir
+    {
+        files.extend(scan(output, false));
+    }
+
+    files.sort_by_key(|file| file.modified);
+    files
+}
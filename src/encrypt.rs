@@ -0,0 +1,324 @@
+/*
+    Encryption of dumps at rest with age, for `--encrypt`, and their decryption on import.
+*/
+
+use crate::cli::{ExportArgs, ImportArgs};
+use crate::error::{Error, ErrorKind};
+use crate::output;
+use crate::prompt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::process::{Child, Command};
+
+/// Numbers the decrypted copies of one run, as an import can decrypt several dumps.
+static DECRYPTED: AtomicU32 = AtomicU32::new(0);
+
+/// What is appended to the name of an encrypted dump.
+pub const SUFFIX: &str = ".age";
+
+/// What the binary and the armored files written by age start with.
+pub const MAGIC: [&[u8]; 2] = [b"age-encryption.org/", b"-----BEGIN AGE ENCRYPTED FILE-----"];
+
+///
+/// How a dump is encrypted.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encryption {
+    /// A passphrase that age asks for on the terminal.
+    Passphrase,
+    /// An age recipient public key, e.g. `age1...`.
+    Recipient(String),
+    /// An age identity file. The dump is encrypted to its public key, so the same file decrypts it.
+    KeyFile(PathBuf),
+}
+
+impl Encryption {
+    ///
+    /// Works out the encryption from the export arguments.
+    ///
+    /// #### Arguments
+    ///
+    /// * `args`: The export arguments.
+    ///
+    /// #### Returns
+    ///
+    /// The encryption, or `None` if the dump is not encrypted.
+    ///
+    pub fn from_args(args: &ExportArgs) -> Option<Self> {
+        if let Some(recipient) = &args.encrypt_recipient {
+            Some(Encryption::Recipient(recipient.clone()))
+        } else if let Some(key_file) = &args.encrypt_key_file {
+            Some(Encryption::KeyFile(key_file.clone()))
+        } else {
+            args.encrypt.then_some(Encryption::Passphrase)
+        }
+    }
+
+    ///
+    /// Returns how the dump is encrypted, for the summary and the manifest. Never contains key material.
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encryption::Passphrase => "age (passphrase)",
+            Encryption::Recipient(_) => "age (recipient)",
+            Encryption::KeyFile(_) => "age (key file)",
+        }
+    }
+
+    ///
+    /// Builds the arguments of `age` that encrypt with this method.
+    ///
+    fn age_args(&self) -> Vec<String> {
+        let mut args = vec!["--encrypt".to_string()];
+
+        match self {
+            Encryption::Passphrase => args.push("--passphrase".to_string()),
+            Encryption::Recipient(recipient) => {
+                args.push("--recipient".to_string());
+                args.push(recipient.clone());
+            }
+            Encryption::KeyFile(key_file) => {
+                args.push("--identity".to_string());
+                args.push(key_file.display().to_string());
+            }
+        }
+
+        args
+    }
+
+    ///
+    /// Starts `age`, reading the plaintext from its stdin and writing the ciphertext to its stdout.
+    ///
+    /// With a passphrase, age asks for it on the terminal itself, so it never passes through ndmig.
+    ///
+    /// #### Returns
+    ///
+    /// The running process, or an error if age is not installed.
+    ///
+    pub fn spawn(&self) -> Result<Child, Error> {
+        let args = self.age_args();
+        output::debug(format!("age {}", args.join(" ")));
+
+        Command::new("age")
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Error::new(
+                    ErrorKind::Config,
+                    "--encrypt needs the age command, see https://age-encryption.org to install it",
+                ),
+                _ => Error::new(ErrorKind::DumpFailed, format!("Failed to start age: {}", e)),
+            })
+    }
+}
+
+///
+/// How an encrypted dump is decrypted on import.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decryption {
+    /// A passphrase that age asks for on the terminal.
+    Passphrase,
+    /// An age identity file holding the key the dump was encrypted to.
+    KeyFile(PathBuf),
+}
+
+impl Decryption {
+    ///
+    /// Works out the decryption from the import arguments.
+    ///
+    /// #### Arguments
+    ///
+    /// * `args`: The import arguments.
+    ///
+    pub fn from_args(args: &ImportArgs) -> Self {
+        match &args.decrypt_key_file {
+            Some(key_file) => Decryption::KeyFile(key_file.clone()),
+            None => Decryption::Passphrase,
+        }
+    }
+
+    ///
+    /// Decrypts a dump with `age --decrypt` into a temporary file only the current user can read.
+    ///
+    /// The whole dump is decrypted up front, so age asks for a passphrase once, however often the dump is read.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: The encrypted dump.
+    ///
+    /// #### Returns
+    ///
+    /// The decrypted copy, which is removed again when it is dropped, or an error if age is missing or failed.
+    ///
+    pub async fn decrypt(&self, path: &Path) -> Result<Decrypted, Error> {
+        let mut args = vec!["--decrypt".to_string()];
+
+        match self {
+            // age asks for the passphrase on the terminal, which only works when someone is there to type it.
+            Decryption::Passphrase => prompt::require_interactive(
+                &format!("ask for the passphrase of {}", path.display()),
+                "--decrypt-key-file",
+            )?,
+            Decryption::KeyFile(key_file) => {
+                args.push("--identity".to_string());
+                args.push(key_file.display().to_string());
+            }
+        }
+
+        let decrypted = Decrypted {
+            path: std::env::temp_dir().join(format!(
+                "ndmig-decrypted-{}-{}",
+                std::process::id(),
+                DECRYPTED.fetch_add(1, Ordering::Relaxed)
+            )),
+        };
+
+        let decrypt_error = |message: String| {
+            Error::new(
+                ErrorKind::Config,
+                format!("Failed to decrypt {}: {}", path.display(), message),
+            )
+        };
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut plaintext = options.open(&decrypted.path).await.map_err(|e| {
+            Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to create {}: {}", decrypted.path.display(), e),
+            )
+        })?;
+
+        args.push(path.display().to_string());
+        output::debug(format!("age {}", args.join(" ")));
+
+        let mut age = Command::new("age")
+            .args(&args)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Error::new(
+                    ErrorKind::Config,
+                    format!(
+                        "{} is encrypted, which needs the age command to import, see https://age-encryption.org to \
+                         install it",
+                        path.display()
+                    ),
+                ),
+                _ => decrypt_error(format!("Failed to start age: {}", e)),
+            })?;
+
+        let mut stdout = age.stdout.take().expect("age stdout is piped");
+        let copied = tokio::io::copy(&mut stdout, &mut plaintext).await;
+        let status = age.wait().await.map_err(|e| decrypt_error(e.to_string()))?;
+
+        if !status.success() {
+            return Err(decrypt_error(format!("age exited with {}", status)));
+        }
+
+        copied.map_err(|e| decrypt_error(e.to_string()))?;
+        Ok(decrypted)
+    }
+}
+
+///
+/// The decrypted copy of an encrypted dump, removed again when it is dropped.
+///
+#[derive(Debug)]
+pub struct Decrypted {
+    /// The path of the copy.
+    pub path: PathBuf,
+}
+
+impl Drop for Decrypted {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+///
+/// Checks whether a file is encrypted with age, by its name or its first bytes.
+///
+/// #### Arguments
+///
+/// * `path`: The file.
+///
+pub fn is_encrypted(path: &Path) -> bool {
+    if path.to_string_lossy().ends_with(SUFFIX) {
+        return true;
+    }
+
+    let longest = MAGIC.iter().map(|magic| magic.len()).max().unwrap_or_default();
+    let mut head = Vec::with_capacity(longest);
+
+    std::fs::File::open(path)
+        .and_then(|file| file.take(longest as u64).read_to_end(&mut head))
+        .is_ok_and(|_| MAGIC.iter().any(|magic| head.starts_with(magic)))
+}
+
+///
+/// Appends the extension of encrypted dumps, e.g. `mydex.sql` becomes `mydex.sql.age`.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the dump.
+///
+pub fn apply_extension(path: PathBuf) -> PathBuf {
+    let mut path = path.into_os_string();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// Writes a file into the temporary directory of the tests.
+    ///
+    fn write(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ndmig-encrypt-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn is_encrypted_reads_the_first_bytes() {
+        let binary = write("binary", b"age-encryption.org/v1\n-> X25519 ...\n");
+        let armored = write("armored", b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n");
+        let plain = write("plain", b"-- PostgreSQL database dump\n");
+
+        let found = [&binary, &armored, &plain].map(|path| is_encrypted(path));
+        for path in [binary, armored, plain] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(found, [true, true, false]);
+    }
+
+    #[test]
+    fn is_encrypted_goes_by_the_suffix() {
+        assert!(is_encrypted(Path::new("/nowhere/mydex.sql.age")));
+    }
+
+    #[test]
+    fn a_decrypted_copy_is_removed_when_dropped() {
+        let path = write("decrypted", b"SELECT 1;\n");
+        drop(Decrypted { path: path.clone() });
+
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,155 @@
+/*
+    Errors that end an ndmig run.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+///
+/// The category of an error, reported as `error.kind` in JSON output and mapped to the exit code.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The Docker daemon could not be reached.
+    DockerConnection,
+    /// There is no (matching) Ballsdex instance.
+    InstanceNotFound,
+    /// Starting the container or running `pg_dump` failed.
+    DumpFailed,
+    /// Reading or writing a local file failed.
+    Filesystem,
+    /// The user cancelled the operation.
+    Aborted,
+    /// One or more `doctor` checks failed, or the audit found rows that point nowhere.
+    ChecksFailed,
+    /// The configuration file is invalid.
+    Config,
+    /// The user pressed Ctrl+C.
+    Interrupted,
+    /// Input is needed, but stdin is not a terminal.
+    NonInteractive,
+    /// Loading a dump into the target failed.
+    ImportFailed,
+    /// A dump was imported with `--continue-on-error`, but some of its statements failed.
+    PartialImport,
+}
+
+impl ErrorKind {
+    ///
+    /// Returns the process exit code for this kind of error.
+    ///
+    /// Keep this in sync with the table in the `--help` output.
+    ///
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::ChecksFailed => 1,
+            ErrorKind::DockerConnection => 2,
+            ErrorKind::InstanceNotFound => 3,
+            ErrorKind::DumpFailed => 4,
+            ErrorKind::Filesystem => 5,
+            ErrorKind::Aborted => 6,
+            ErrorKind::Config => 7,
+            ErrorKind::NonInteractive => 8,
+            ErrorKind::ImportFailed => 9,
+            ErrorKind::PartialImport => 10,
+            // 128 + SIGINT, like a shell reports a process killed by Ctrl+C.
+            ErrorKind::Interrupted => 130,
+        }
+    }
+}
+
+///
+/// An error that ends the current operation.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Error {
+    ///
+    /// Creates a new error.
+    ///
+    /// #### Arguments
+    ///
+    /// * `kind`: The category of the error.
+    /// * `message`: A human-readable description.
+    ///
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every kind of error with the exit code it ends ndmig with.
+    const KINDS: [(ErrorKind, i32); 11] = [
+        (ErrorKind::DockerConnection, 2),
+        (ErrorKind::InstanceNotFound, 3),
+        (ErrorKind::DumpFailed, 4),
+        (ErrorKind::Filesystem, 5),
+        (ErrorKind::Aborted, 6),
+        (ErrorKind::ChecksFailed, 1),
+        (ErrorKind::Config, 7),
+        (ErrorKind::Interrupted, 130),
+        (ErrorKind::NonInteractive, 8),
+        (ErrorKind::ImportFailed, 9),
+        (ErrorKind::PartialImport, 10),
+    ];
+
+    ///
+    /// Fails to compile when a kind is added, until it is listed in [`KINDS`] as well.
+    ///
+    fn listed(kind: ErrorKind) {
+        match kind {
+            ErrorKind::DockerConnection
+            | ErrorKind::InstanceNotFound
+            | ErrorKind::DumpFailed
+            | ErrorKind::Filesystem
+            | ErrorKind::Aborted
+            | ErrorKind::ChecksFailed
+            | ErrorKind::Config
+            | ErrorKind::Interrupted
+            | ErrorKind::NonInteractive
+            | ErrorKind::ImportFailed
+            | ErrorKind::PartialImport => {}
+        }
+    }
+
+    #[test]
+    fn maps_every_kind_to_its_exit_code() {
+        for (kind, code) in KINDS {
+            listed(kind);
+            assert_eq!(kind.exit_code(), code, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn help_lists_every_exit_code() {
+        let listed: Vec<i32> = crate::cli::EXIT_CODES
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+
+        let mut codes: Vec<i32> = KINDS.iter().map(|(kind, _)| kind.exit_code()).collect();
+        codes.push(0);
+        codes.sort();
+        codes.dedup();
+
+        assert_eq!(listed, codes);
+    }
+}
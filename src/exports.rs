@@ -0,0 +1,222 @@
+/*
+    The `exports` subcommand, which lists the dumps that are still on disk.
+*/
+
+use crate::bundle;
+use crate::cli::ExportsArgs;
+use crate::compression::Compression;
+use crate::dumps;
+use crate::encrypt;
+use crate::error::Error;
+use crate::format::DumpFormat;
+use crate::manifest;
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use colored::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+///
+/// A dump as listed by `exports`.
+///
+#[derive(Serialize)]
+struct ListedExport {
+    instance: Option<String>,
+    timestamp: String,
+    bytes: u64,
+    format: String,
+    path: PathBuf,
+    /// Why the manifest could not be used, if it exists but is broken.
+    warning: Option<String>,
+}
+
+///
+/// Recovers the instance and the time of an export from a file name written with the default template.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the dump, e.g. `mydex-20250101-120000.sql` or `mydex-20250101-120000-schema.sql`.
+///
+/// #### Returns
+///
+/// The instance and the local time of the export, or `None` if the name does not follow the default template.
+///
+fn parse_file_name(path: &Path) -> Option<(String, DateTime<Local>)> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.split_once('.').map_or(name, |(stem, _)| stem);
+    let stem = ["-schema", "-data", "-delta", "-guild", "-player", "-filtered"]
+        .iter()
+        .find_map(|mode| stem.strip_suffix(mode))
+        .unwrap_or(stem);
+
+    let mut parts = stem.rsplitn(3, '-');
+    let (time, date, instance) = (parts.next()?, parts.next()?, parts.next()?);
+
+    if instance.is_empty() || date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+
+    let naive = NaiveDateTime::parse_from_str(&format!("{}{}", date, time), "%Y%m%d%H%M%S").ok()?;
+    let local = Local.from_local_datetime(&naive).earliest()?;
+
+    Some((instance.to_string(), local))
+}
+
+///
+/// Guesses the format of a dump from its first bytes, or from its extension for compressed dumps.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the dump, e.g. `mydex-20250101-120000.sql.gz`.
+///
+/// #### Returns
+///
+/// The format, followed by the compression if there is one, e.g. `plain+gzip`.
+///
+fn format_from_extension(path: &Path) -> String {
+    if bundle::is_bundle(path) {
+        return "bundle".to_string();
+    }
+
+    if path.to_string_lossy().ends_with(encrypt::SUFFIX) {
+        // The ciphertext cannot be inspected, so the format comes from the extensions under `.age`.
+        return format!("{}+age", format_from_extension(&path.with_extension("")));
+    }
+
+    let compression = Compression::detect(path);
+    let path = match compression {
+        Compression::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    };
+
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+        _ if compression == Compression::None
+            && let Some(format) = DumpFormat::detect(&path) =>
+        {
+            format.name()
+        }
+        Some("sql") => DumpFormat::Plain.name(),
+        Some("dump") => DumpFormat::Custom.name(),
+        Some("json") => DumpFormat::Json.name(),
+        _ => "unknown",
+    };
+
+    match compression {
+        Compression::None => format.to_string(),
+        compression => format!("{}+{}", format, compression.name()),
+    }
+}
+
+///
+/// Lists the dumps in the temporary directory and the configured output directory, newest first.
+///
+/// The details come from the manifest next to each dump, or from its file name if it has none.
+///
+/// #### Arguments
+///
+/// * `args`: The exports arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing. Broken manifests are reported as warnings, not errors.
+///
+pub fn run(args: &ExportsArgs, options: &Options) -> Result<(), Error> {
+    let mut listed: Vec<(DateTime<Local>, ListedExport)> = dumps::find(options)
+        .into_iter()
+        .filter(|file| !file.partial)
+        .map(|file| {
+            // The manifest of a bundle is one of its files rather than next to it.
+            let manifest = match bundle::is_bundle(&file.path) {
+                true => bundle::read_manifest(&file.path),
+                false => manifest::read(&file.path),
+            };
+
+            let (manifest, warning) = match manifest {
+                Ok(manifest) => {
+                    let manifest = manifest.unwrap_or_default();
+                    let warning = manifest.check_compatible().err();
+                    (manifest, warning)
+                }
+                Err(message) => (manifest::Manifest::default(), Some(message)),
+            };
+
+            let parsed = parse_file_name(&file.path);
+
+            let timestamp = manifest
+                .created_at
+                .as_deref()
+                .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+                .map(|created_at| created_at.with_timezone(&Local))
+                .or(parsed.as_ref().map(|(_, timestamp)| *timestamp))
+                .unwrap_or_else(|| file.modified.into());
+
+            let export = ListedExport {
+                instance: manifest.instance.or(parsed.map(|(instance, _)| instance)),
+                timestamp: timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                bytes: file.bytes,
+                format: match (manifest.format, manifest.compression.as_deref()) {
+                    (Some(format), None | Some("none")) => format,
+                    (Some(format), Some(compression)) => format!("{}+{}", format, compression),
+                    (None, _) => format_from_extension(&file.path),
+                },
+                path: file.path,
+                warning,
+            };
+
+            (timestamp, export)
+        })
+        .collect();
+
+    if let Some(instance) = &args.instance {
+        let instance = instance.trim().to_lowercase();
+
+        listed.retain(|(_, export)| {
+            export
+                .instance
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase() == instance)
+        });
+    }
+
+    listed.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    if output::is_json() {
+        output::json(&listed.iter().map(|(_, export)| export).collect::<Vec<_>>());
+        return Ok(());
+    }
+
+    if listed.is_empty() {
+        output::info("No dumps found.".yellow().bold());
+        return Ok(());
+    }
+
+    let width = listed
+        .iter()
+        .map(|(_, export)| export.instance.as_deref().map_or(7, str::len))
+        .max()
+        .unwrap_or(0);
+
+    for (timestamp, export) in &listed {
+        if let Some(warning) = &export.warning {
+            output::status(format!("{} {}", Glyph::Warning, warning).yellow());
+        }
+
+        let instance = match &export.instance {
+            Some(instance) => format!("{:width$}", instance).bright_cyan(),
+            None => format!("{:width$}", "unknown").dimmed(),
+        };
+
+        output::info(format!(
+            "  {}  {}  {:>10}  {:12}  {}",
+            timestamp.format("%Y-%m-%d %H:%M:%S").to_string().dimmed(),
+            instance,
+            crate::format_size(export.bytes),
+            export.format,
+            export.path.display()
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,406 @@
+/*
+    Row-filtered exports, for `--since` and `--min-catches`, e.g. to fill a staging instance with recent data only.
+
+    The schema comes from pg_dump as usual, but the rows are copied out with `\copy (SELECT ...) TO STDOUT`. A filter
+    picks the rows of one table, and every row that points at a row left out is left out as well, following the
+    foreign keys, so the slimmed dump restores without a dangling reference. Tables no filter reaches stay complete.
+*/
+
+use crate::cli::ExportArgs;
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::incremental;
+use crate::json;
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use std::collections::BTreeMap;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+///
+/// Parses the start of `--since`.
+///
+/// #### Arguments
+///
+/// * `value`: A date such as `2025-01-31`, an RFC 3339 timestamp or an age such as `90d`.
+///
+/// #### Returns
+///
+/// The moment from which on catches are kept, or the message to show when it is invalid.
+///
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("Midnight is a valid time").and_utc());
+    }
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let age = crate::clean::parse_age(value)
+        .map_err(|_| format!("Invalid start '{}', expected e.g. 2025-01-31 or 90d", value))?;
+
+    chrono::Duration::from_std(age)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| format!("The start '{}' is too far in the past", value))
+}
+
+///
+/// Checks whether an export only holds the rows that pass a filter.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+///
+pub fn is_filtered(args: &ExportArgs) -> bool {
+    args.since.is_some() || args.min_catches.is_some()
+}
+
+///
+/// Returns the conditions the filters put on the rows of their tables, as recorded in the manifest.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+///
+/// #### Returns
+///
+/// The condition per filtered table, before the foreign keys carry them over to other tables.
+///
+pub fn expressions(args: &ExportArgs) -> BTreeMap<String, String> {
+    let mut expressions = BTreeMap::new();
+
+    if let Some(since) = args.since {
+        expressions.insert(
+            "ballinstance".to_string(),
+            format!("catch_date >= '{}'", since.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        );
+    }
+
+    if let Some(min_catches) = args.min_catches {
+        expressions.insert(
+            "player".to_string(),
+            format!(
+                "id IN (SELECT player_id FROM ballinstance GROUP BY player_id HAVING count(*) >= {})",
+                min_catches
+            ),
+        );
+    }
+
+    expressions
+}
+
+///
+/// A single-column foreign key.
+///
+struct Reference {
+    table: String,
+    column: String,
+    referenced: String,
+    referenced_column: String,
+}
+
+///
+/// Lists the single-column foreign keys of the `public` schema.
+///
+/// Keys over several columns are left out. Ballsdex has none, and a filter does not reach through them.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+///
+async fn references(docker: &Docker, container_id: &str, database: &Database) -> Result<Vec<Reference>, Error> {
+    let output = container::query(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, a.attname, r.relname, ra.attname FROM pg_constraint k \
+         JOIN pg_class c ON c.oid = k.conrelid JOIN pg_class r ON r.oid = k.confrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = k.conrelid AND a.attnum = k.conkey[1] \
+         JOIN pg_attribute ra ON ra.attrelid = k.confrelid AND ra.attnum = k.confkey[1] \
+         WHERE k.contype = 'f' AND n.nspname = 'public' AND cardinality(k.conkey) = 1",
+    )
+    .await
+    .ok_or_else(|| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            "Failed to read the foreign keys for the filters.",
+        )
+    })?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('|');
+            Some(Reference {
+                table: fields.next()?.to_string(),
+                column: fields.next()?.to_string(),
+                referenced: fields.next()?.to_string(),
+                referenced_column: fields.next()?.to_string(),
+            })
+        })
+        .filter(|reference| reference.table != reference.referenced)
+        .collect())
+}
+
+///
+/// Carries the conditions of the filtered tables over to every table whose foreign keys point at them.
+///
+/// #### Arguments
+///
+/// * `order`: The tables, each after the tables it references.
+/// * `references`: The foreign keys between them.
+/// * `roots`: The conditions of the filtered tables.
+///
+/// #### Returns
+///
+/// The condition of every table that loses rows. Tables missing from it are copied completely.
+///
+fn closure(order: &[String], references: &[Reference], roots: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut conditions: BTreeMap<String, String> = BTreeMap::new();
+
+    for table in order {
+        let mut parts: Vec<String> = roots.get(table).map(|root| format!("({})", root)).into_iter().collect();
+
+        for reference in references.iter().filter(|reference| &reference.table == table) {
+            if let Some(condition) = conditions.get(&reference.referenced) {
+                let column = json::quote(&reference.column);
+                parts.push(format!(
+                    "({} IS NULL OR {} IN (SELECT {} FROM {} WHERE {}))",
+                    column,
+                    column,
+                    json::quote(&reference.referenced_column),
+                    json::quote(&reference.referenced),
+                    condition
+                ));
+            }
+        }
+
+        if !parts.is_empty() {
+            conditions.insert(table.clone(), parts.join(" AND "));
+        }
+    }
+
+    conditions
+}
+
+///
+/// Works out which rows of every table an export keeps.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+/// * `args`: The export arguments with the filters.
+/// * `tables`: The tables to export.
+///
+/// #### Returns
+///
+/// The tables in restore order and the conditions of those that lose rows, or an error if a filter does not fit
+/// the database.
+///
+async fn conditions(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    args: &ExportArgs,
+    tables: Vec<String>,
+) -> Result<(Vec<String>, BTreeMap<String, String>), Error> {
+    let mut roots = expressions(args);
+
+    if let Some(table) = ["ballinstance", "player"]
+        .into_iter()
+        .find(|table| !tables.iter().any(|name| name == table))
+    {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--since and --min-catches need the {} table, which is not part of the export.",
+                table
+            ),
+        ));
+    }
+
+    let references = references(docker, container_id, database).await?;
+    let order = incremental::dependency_order(docker, container_id, database, tables).await;
+    let mut conditions = closure(&order, &references, &roots);
+
+    // A trade only references its players, so it would survive with some of its balls missing. It is left out as a
+    // whole instead, and its remaining objects go with it.
+    if let Some(balls) = conditions.get("ballinstance")
+        && order.iter().any(|table| table == "trade")
+        && order.iter().any(|table| table == "tradeobject")
+    {
+        let complete = format!(
+            "NOT EXISTS (SELECT 1 FROM tradeobject o WHERE o.trade_id = trade.id \
+             AND o.ballinstance_id NOT IN (SELECT id FROM ballinstance WHERE {}))",
+            balls
+        );
+        roots.insert("trade".to_string(), complete);
+        conditions = closure(&order, &references, &roots);
+    }
+
+    Ok((order, conditions))
+}
+
+///
+/// Builds the `psql` command that writes the kept rows of every table as the `COPY` sections of a plain dump.
+///
+/// All tables are copied in the same read-only transaction, so the conditions see one snapshot. The sequences are set
+/// to where they stand on the source, so the target does not hand out the IDs of rows that were left out either.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to connect with.
+/// * `sections`: The quoted name, the quoted columns and the condition of every table, in restore order.
+///
+fn copy_command(database: &Database, sections: &[(String, String, Option<String>)]) -> Vec<String> {
+    let mut cmd = vec![
+        "psql".to_string(),
+        "-X".to_string(),
+        "-q".to_string(),
+        "-v".to_string(),
+        "ON_ERROR_STOP=1".to_string(),
+    ];
+    cmd.extend(database.connection_args());
+
+    let mut statements = vec!["BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY".to_string()];
+
+    for (table, columns, condition) in sections {
+        let condition = condition
+            .as_ref()
+            .map(|condition| format!(" WHERE {}", condition))
+            .unwrap_or_default();
+
+        // Within the single quotes of \echo, psql turns '' into a quote and \\ into a backslash.
+        statements.push(format!(
+            "\\echo 'COPY public.{} ({}) FROM stdin;'",
+            table.replace('\'', "''"),
+            columns.replace('\'', "''")
+        ));
+        statements.push(format!(
+            "\\copy (SELECT {} FROM {}{}) TO STDOUT",
+            columns, table, condition
+        ));
+        statements.push("\\echo '\\\\.'".to_string());
+        statements.push("\\echo".to_string());
+    }
+
+    statements.push(
+        "\\copy (SELECT format('SELECT pg_catalog.setval(%L, %s, %s);', \
+         quote_ident(schemaname) || '.' || quote_ident(sequencename), coalesce(last_value, start_value), \
+         (last_value IS NOT NULL)::text) FROM pg_sequences WHERE schemaname = 'public' ORDER BY sequencename) TO STDOUT"
+            .to_string(),
+    );
+    statements.push("\\echo".to_string());
+    statements.push("COMMIT".to_string());
+
+    for statement in statements {
+        cmd.push("-c".to_string());
+        cmd.push(statement);
+    }
+
+    cmd
+}
+
+///
+/// Writes a filtered dump: the schema of pg_dump around the kept rows of every table.
+///
+/// With `--data-only` only the rows are written, to be restored into a database that already has the schema.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `progress`: Reports the received bytes of all parts together.
+/// * `args`: The export arguments with the filters.
+/// * `database`: The role and database to export.
+/// * `writer`: Where the dump is written.
+///
+/// #### Returns
+///
+/// The size of the dump and the warnings of pg_dump and psql, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let write_error = |e: io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    let tables: Vec<String> = container::tables(docker, container_id, database)
+        .await
+        .ok_or_else(|| Error::new(ErrorKind::DumpFailed, "Failed to list the tables for the filters."))?
+        .into_iter()
+        .filter(|table| {
+            (args.tables.is_empty() || args.tables.iter().any(|name| name == table))
+                && !args.exclude_tables.iter().any(|name| name == table)
+        })
+        .collect();
+
+    let (order, conditions) = conditions(docker, container_id, database, args, tables).await?;
+
+    let mut sections = Vec::new();
+
+    for table in order {
+        let (quoted, columns) = container::columns(docker, container_id, database, &table).await?;
+        output::debug(match conditions.get(&table) {
+            Some(condition) => format!("Filtering {}: {}", table, condition),
+            None => format!("Keeping all rows of {}", table),
+        });
+        sections.push((quoted, columns, conditions.get(&table).cloned()));
+    }
+
+    let mut dump = DatabaseDump {
+        bytes: 0,
+        warnings: Vec::new(),
+    };
+
+    let mut parts = Vec::new();
+
+    if !args.data_only {
+        let mut cmd = container::pg_dump_command(database, args);
+        cmd.push("--section=pre-data".to_string());
+        parts.push((cmd, None));
+    }
+
+    let header = expressions(args)
+        .iter()
+        .map(|(table, expression)| format!("-- {}: {}\n", table, expression))
+        .collect::<String>();
+    parts.push((
+        copy_command(database, &sections),
+        Some(format!("--\n-- ndmig filtered data\n{}--\n\n", header)),
+    ));
+
+    if !args.data_only {
+        let mut cmd = container::pg_dump_command(database, args);
+        cmd.push("--section=post-data".to_string());
+        parts.push((cmd, None));
+    }
+
+    for (cmd, header) in parts {
+        if let Some(header) = header {
+            writer.write_all(header.as_bytes()).await.map_err(write_error)?;
+        }
+
+        let written =
+            container::create_database_dump(docker, container_id, cmd, database.env(), progress, writer).await?;
+        dump.bytes += written.bytes;
+        dump.warnings.extend(written.warnings);
+    }
+
+    Ok(dump)
+}
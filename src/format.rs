@@ -0,0 +1,154 @@
+/*
+    The pg_dump output formats ndmig can write.
+*/
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The first bytes of a pg_dump custom format archive.
+const CUSTOM_MAGIC: &[u8] = b"PGDMP";
+
+///
+/// The output format of `pg_dump`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpFormat {
+    /// A plain SQL script, restored with `psql`.
+    #[default]
+    Plain,
+    /// A pg_dump archive (`-Fc`), restored with `pg_restore`.
+    Custom,
+    /// A pg_dump directory archive (`-Fd`) with one file per table, which pg_dump can write with several jobs at once.
+    Directory,
+    /// A directory with one CSV file per table, written with `psql`'s `\copy` instead of `pg_dump`.
+    Csv,
+    /// A JSON document with the rows of the core Ballsdex tables, written with `psql` instead of `pg_dump`.
+    Json,
+}
+
+impl DumpFormat {
+    ///
+    /// Returns the name used on the command line, e.g. `custom`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            DumpFormat::Plain => "plain",
+            DumpFormat::Custom => "custom",
+            DumpFormat::Directory => "directory",
+            DumpFormat::Csv => "csv",
+            DumpFormat::Json => "json",
+        }
+    }
+
+    ///
+    /// Returns the extension of dumps in this format, e.g. `dump` for `mydex.dump`. For `directory` and `csv` it is the
+    /// extension of the files inside the directory.
+    ///
+    pub fn extension(self) -> &'static str {
+        match self {
+            DumpFormat::Plain => "sql",
+            DumpFormat::Custom => "dump",
+            DumpFormat::Directory => "dat.gz",
+            DumpFormat::Csv => "csv",
+            DumpFormat::Json => "json",
+        }
+    }
+
+    ///
+    /// Returns the arguments that make `pg_dump` write this format. Nothing for `csv` and `json`, which do not use
+    /// `pg_dump`.
+    ///
+    pub fn pg_dump_args(self) -> &'static [&'static str] {
+        match self {
+            DumpFormat::Plain | DumpFormat::Csv | DumpFormat::Json => &[],
+            DumpFormat::Custom => &["-Fc"],
+            DumpFormat::Directory => &["-Fd"],
+        }
+    }
+
+    ///
+    /// Gives a dump path the extension of the format.
+    ///
+    /// A `.sql` extension, as written by the default name template, is replaced. Any other extension is kept and the
+    /// extension of the format is appended. `directory` and `csv` exports are directories, so they only lose the `.sql`
+    /// extension.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: The path of the dump.
+    ///
+    pub fn apply_extension(self, path: PathBuf) -> PathBuf {
+        if self.is_directory() {
+            return match path.extension() {
+                Some(extension) if extension == "sql" => path.with_extension(""),
+                _ => path,
+            };
+        }
+
+        let extension = self.extension();
+
+        match path.extension().and_then(|current| current.to_str()) {
+            Some(current) if current == extension => path,
+            Some("sql") => path.with_extension(extension),
+            _ => {
+                let mut path = path.into_os_string();
+                path.push(".");
+                path.push(extension);
+                PathBuf::from(path)
+            }
+        }
+    }
+
+    ///
+    /// Returns whether the format writes a directory rather than a single file.
+    ///
+    pub fn is_directory(self) -> bool {
+        matches!(self, DumpFormat::Directory | DumpFormat::Csv)
+    }
+
+    ///
+    /// Works out the format of an uncompressed dump from its first bytes.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: The path of the dump.
+    ///
+    /// #### Returns
+    ///
+    /// The format, or `None` if the file cannot be read or is empty.
+    ///
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut magic = [0; CUSTOM_MAGIC.len()];
+        let read = std::fs::File::open(path)
+            .and_then(|mut file| file.read(&mut magic))
+            .ok()?;
+
+        Self::from_magic(&magic[..read])
+    }
+
+    ///
+    /// Works out the format of an uncompressed dump from its first bytes, e.g. of a dump inside a bundle.
+    ///
+    /// #### Arguments
+    ///
+    /// * `head`: The first bytes of the dump.
+    ///
+    /// #### Returns
+    ///
+    /// The format, or `None` if the dump is empty.
+    ///
+    pub fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(CUSTOM_MAGIC) {
+            Some(DumpFormat::Custom)
+        } else if head.first() == Some(&b'{') {
+            Some(DumpFormat::Json)
+        } else if head.is_empty() {
+            None
+        } else {
+            Some(DumpFormat::Plain)
+        }
+    }
+}
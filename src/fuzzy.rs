@@ -0,0 +1,72 @@
+/*
+    Fuzzy matching for mistyped instance names.
+*/
+
+/// How many suggestions are offered at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+///
+/// Computes the Levenshtein distance between two strings.
+///
+/// #### Arguments
+///
+/// * `a`: The first string.
+/// * `b`: The second string.
+///
+/// #### Returns
+///
+/// The number of single-character insertions, deletions and substitutions needed to turn `a` into `b`.
+///
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+///
+/// Finds the candidates that look like a typo of the input.
+///
+/// Case is ignored. A candidate is close if it is within a third of the input's length in edit
+/// distance, or if it contains the input (for inputs of at least three characters).
+///
+/// #### Arguments
+///
+/// * `input`: What the user typed.
+/// * `candidates`: The names to match against.
+///
+/// #### Returns
+///
+/// Up to three close candidates, best match first.
+///
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let input = input.trim().to_lowercase();
+    let threshold = (input.chars().count() / 3).max(1);
+
+    let mut matches: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let folded = candidate.to_lowercase();
+            let distance = distance(&input, &folded);
+            let contains = input.chars().count() >= 3 && folded.contains(&input);
+
+            (distance <= threshold || contains).then_some((distance, candidate))
+        })
+        .collect();
+
+    matches.sort();
+    matches.truncate(MAX_SUGGESTIONS);
+    matches.into_iter().map(|(_, candidate)| candidate).collect()
+}
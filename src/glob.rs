@@ -0,0 +1,54 @@
+/*
+    Shell-style glob patterns, used for the instance ignore list.
+*/
+
+///
+/// Checks whether a text matches a glob pattern.
+///
+/// `*` matches any run of characters, including none, and `?` matches exactly one character. Everything else
+/// matches itself.
+///
+/// #### Arguments
+///
+/// * `pattern`: The pattern, e.g. `ci-*`.
+/// * `text`: The text to match.
+///
+/// #### Returns
+///
+/// Whether the whole text matches the pattern.
+///
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: the pattern position after it and the text position it matched up to.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, t));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
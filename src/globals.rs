@@ -0,0 +1,310 @@
+/*
+    Exports the roles and role memberships of the postgres server, for `--include-globals`, and creates them on the
+    server of the target on import.
+
+    pg_dump only covers a single database, so roles created by hand, e.g. read-only roles for a dashboard, are lost on
+    a migration. `pg_dumpall --globals-only` writes them, and ndmig keeps the result next to the dump after leaving
+    out the superuser roles, which the target has its own of.
+*/
+
+use crate::container::{self, Database};
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use crate::restore;
+use bollard::Docker;
+use colored::*;
+use std::path::{Path, PathBuf};
+
+/// What is appended to the dump path to get the path of the globals.
+pub const SUFFIX: &str = ".globals.sql";
+
+///
+/// Returns the path of the globals that belong to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// Returns the role a statement of pg_dumpall starts with, e.g. `dashboard` for `CREATE ROLE dashboard;`.
+///
+/// #### Arguments
+///
+/// * `rest`: The statement after its keywords.
+///
+fn role(rest: &str) -> &str {
+    rest.split([' ', ';']).next().unwrap_or_default()
+}
+
+///
+/// Leaves the superuser roles out of the output of `pg_dumpall --globals-only`.
+///
+/// Restoring them would change the superuser of the target, or fail on it. Memberships granted to or by them go as
+/// well, as the target may not have a role of the same name.
+///
+/// #### Arguments
+///
+/// * `contents`: The output of `pg_dumpall`.
+///
+/// #### Returns
+///
+/// The remaining statements and the names of the roles that were left out.
+///
+pub fn filter(contents: &str) -> (String, Vec<String>) {
+    let mut superusers = vec!["postgres".to_string()];
+
+    for line in contents.lines() {
+        if let Some((name, options)) = line
+            .strip_prefix("ALTER ROLE ")
+            .and_then(|rest| rest.split_once(" WITH "))
+            && options
+                .trim_end_matches(';')
+                .split(' ')
+                .any(|option| option == "SUPERUSER")
+            && !superusers.iter().any(|superuser| superuser == name)
+        {
+            superusers.push(name.to_string());
+        }
+    }
+
+    let is_superuser = |name: &str| superusers.iter().any(|superuser| superuser == name);
+    let mut kept = String::new();
+
+    for line in contents.lines() {
+        let about_superuser = ["CREATE ROLE ", "ALTER ROLE ", "COMMENT ON ROLE "]
+            .iter()
+            .any(|prefix| line.strip_prefix(prefix).is_some_and(|rest| is_superuser(role(rest))));
+
+        if about_superuser {
+            continue;
+        }
+
+        let mut line = line.to_string();
+
+        if let Some((granted, rest)) = line.strip_prefix("GRANT ").and_then(|rest| rest.split_once(" TO ")) {
+            if is_superuser(granted) || is_superuser(role(rest)) {
+                continue;
+            }
+
+            if let Some(grantor) = superusers
+                .iter()
+                .find(|superuser| line.ends_with(&format!(" GRANTED BY {};", superuser)))
+            {
+                line = format!("{};", line.trim_end_matches(&format!(" GRANTED BY {};", grantor)));
+            }
+        }
+
+        kept.push_str(&line);
+        kept.push('\n');
+    }
+
+    (kept, superusers)
+}
+
+///
+/// Saves the roles of the postgres server next to a dump.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role to connect with, which has to be a superuser to read the role passwords.
+/// * `dump_path`: The path of the dump the globals belong to.
+///
+/// #### Returns
+///
+/// The size of the saved file, or an error.
+///
+pub async fn export(docker: &Docker, container_id: &str, database: &Database, dump_path: &Path) -> Result<u64, Error> {
+    output::status(format!("{} Saving the roles of the server...", Glyph::Pending).yellow());
+
+    let mut cmd = vec!["pg_dumpall", "--globals-only", "-U", database.user.as_str()];
+
+    if let Some(name) = &database.name {
+        cmd.extend(["-l", name.as_str()]);
+    }
+
+    let failed = |message: String| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("pg_dumpall --globals-only failed: {}", message),
+        )
+    };
+
+    let result = container::exec(docker, container_id, cmd, database.env())
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+
+    if result.exit_code != Some(0) {
+        return Err(failed(result.stderr_text()));
+    }
+
+    let (contents, left_out) = filter(&String::from_utf8_lossy(&result.stdout));
+    output::debug(format!("Left out the superuser roles {}", left_out.join(", ")));
+
+    let destination = path(dump_path);
+    std::fs::write(&destination, &contents).map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", destination.display(), e),
+        )
+    })?;
+
+    Ok(contents.len() as u64)
+}
+
+///
+/// Finds the globals an import creates, for `--include-globals`.
+///
+/// #### Arguments
+///
+/// * `dumps`: The paths of the dumps of the import, in the order they are loaded.
+///
+/// #### Returns
+///
+/// The globals next to the first dump that has them, or an error if none has.
+///
+pub fn find(dumps: &[&Path]) -> Result<PathBuf, Error> {
+    dumps
+        .iter()
+        .map(|dump| path(dump))
+        .find(|globals| globals.is_file())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Config,
+                format!(
+                    "--include-globals creates the roles saved by `export --include-globals`, but there is no {}.",
+                    path(dumps[0]).display()
+                ),
+            )
+        })
+}
+
+///
+/// Sorts the errors psql printed for the globals into roles the target has already and real failures.
+///
+/// #### Arguments
+///
+/// * `stderr`: What psql printed to stderr.
+///
+/// #### Returns
+///
+/// The roles that exist already and the other errors.
+///
+fn conflicts(stderr: &str) -> (Vec<String>, Vec<String>) {
+    let mut existing = Vec::new();
+    let mut errors = Vec::new();
+
+    for line in stderr.lines() {
+        let Some((_, error)) = line.split_once("ERROR:") else {
+            continue;
+        };
+
+        let error = error.trim();
+
+        match error
+            .strip_prefix("role \"")
+            .and_then(|rest| rest.strip_suffix("\" already exists"))
+        {
+            Some(role) => existing.push(role.to_string()),
+            None => errors.push(error.to_string()),
+        }
+    }
+
+    (existing, errors)
+}
+
+///
+/// Creates the saved roles on the server of the target, before the dumps are loaded so that their grants find them.
+///
+/// psql runs without `ON_ERROR_STOP`, so a role the target has already only skips its `CREATE ROLE` and is reported
+/// as a warning, while its attributes and memberships are still applied.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running target.
+/// * `database`: The role to connect with, which has to be allowed to create roles.
+/// * `globals`: The globals, as found by [`find`].
+///
+/// #### Returns
+///
+/// Nothing, or an error naming the statements that failed for another reason.
+///
+pub async fn import(docker: &Docker, container_id: &str, database: &Database, globals: &Path) -> Result<(), Error> {
+    output::status(format!("{} Creating the roles of {}...", Glyph::Pending, globals.display()).yellow());
+
+    let contents = std::fs::read(globals).map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", globals.display(), e),
+        )
+    })?;
+
+    let failed = |message: String| {
+        Error::new(
+            ErrorKind::ImportFailed,
+            format!("Failed to create the roles of {}: {}", globals.display(), message),
+        )
+    };
+
+    let cmd = restore::psql_command(database, false, true);
+    let result = container::exec_with_input(docker, container_id, cmd, database.env(), &mut contents.as_slice())
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+
+    let stderr = result.stderr_text();
+    let (existing, errors) = conflicts(&stderr);
+
+    if result.exit_code != Some(0) {
+        return Err(failed(stderr));
+    }
+
+    if !errors.is_empty() {
+        return Err(failed(errors.join("\n")));
+    }
+
+    if !existing.is_empty() {
+        output::status(
+            format!(
+                "{} The target has the roles {} already, they were updated instead.",
+                Glyph::Warning,
+                existing.join(", ")
+            )
+            .yellow(),
+        );
+    }
+
+    output::status(format!("{} Created the roles of {}.", Glyph::Ok, globals.display()).green());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicts_turns_existing_roles_into_warnings() {
+        let stderr = "psql:<stdin>:4: ERROR:  role \"dashboard\" already exists\n\
+                      psql:<stdin>:9: ERROR:  permission denied to create role\n";
+
+        let (existing, errors) = conflicts(stderr);
+
+        assert_eq!(existing, ["dashboard"]);
+        assert_eq!(errors, ["permission denied to create role"]);
+    }
+
+    #[test]
+    fn conflicts_ignores_the_other_output() {
+        let (existing, errors) = conflicts("NOTICE:  role \"reader\" is already a member of role \"dashboard\"\n");
+
+        assert!(existing.is_empty() && errors.is_empty());
+    }
+}
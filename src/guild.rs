@@ -0,0 +1,201 @@
+/*
+    Exports reduced to the data of a single Discord server, for `--guild-id`.
+
+    Ballsdex does not tie players to a server, only the catches are. A guild export holds the server's configuration,
+    the balls caught in it and the trades they were part of, together with every row those point at, so the reduced
+    set can be restored into an empty database without breaking a foreign key.
+*/
+
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+///
+/// Returns the tables of a guild export and the condition that picks the rows of the guild from each.
+///
+/// #### Arguments
+///
+/// * `guild_id`: The Discord ID of the server.
+///
+/// #### Returns
+///
+/// The tables in restore order, each after the tables it references.
+///
+fn tables(guild_id: u64) -> [(&'static str, String); 9] {
+    let catches = format!("SELECT id FROM ballinstance WHERE server_id = {}", guild_id);
+    let balls = format!("SELECT ball_id FROM ballinstance WHERE server_id = {}", guild_id);
+    let trades = format!(
+        "SELECT trade_id FROM tradeobject WHERE ballinstance_id IN ({})",
+        catches
+    );
+
+    [
+        (
+            "regime",
+            format!("id IN (SELECT regime_id FROM ball WHERE id IN ({}))", balls),
+        ),
+        (
+            "economy",
+            format!("id IN (SELECT economy_id FROM ball WHERE id IN ({}))", balls),
+        ),
+        ("ball", format!("id IN ({})", balls)),
+        (
+            "special",
+            format!(
+                "id IN (SELECT special_id FROM ballinstance WHERE server_id = {})",
+                guild_id
+            ),
+        ),
+        (
+            "player",
+            format!(
+                "id IN (SELECT player_id FROM ballinstance WHERE server_id = {0} \
+                 UNION SELECT trade_player_id FROM ballinstance WHERE server_id = {0} \
+                 UNION SELECT player1_id FROM trade WHERE id IN ({1}) \
+                 UNION SELECT player2_id FROM trade WHERE id IN ({1}))",
+                guild_id, trades
+            ),
+        ),
+        ("guildconfig", format!("guild_id = {}", guild_id)),
+        ("ballinstance", format!("server_id = {}", guild_id)),
+        ("trade", format!("id IN ({})", trades)),
+        ("tradeobject", format!("ballinstance_id IN ({})", catches)),
+    ]
+}
+
+///
+/// Builds the `psql` command that writes the rows of a guild as the `COPY` sections of a plain dump.
+///
+/// Every table is copied in the same read-only transaction, so the rows all come from one snapshot and a ball caught
+/// while the export runs cannot point at a player that was copied before it.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to connect with.
+/// * `sections`: The quoted name, the quoted columns and the condition of every table, in restore order.
+///
+fn copy_command(database: &Database, sections: &[(String, String, String)]) -> Vec<String> {
+    let mut cmd = vec![
+        "psql".to_string(),
+        "-X".to_string(),
+        "-q".to_string(),
+        "-v".to_string(),
+        "ON_ERROR_STOP=1".to_string(),
+    ];
+    cmd.extend(database.connection_args());
+
+    let mut statements = vec!["BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY".to_string()];
+
+    for (table, columns, condition) in sections {
+        let setval = format!(
+            "SELECT pg_catalog.setval(pg_catalog.pg_get_serial_sequence('public.{}', 'id'), \
+             (SELECT max(id) FROM public.{}), true);",
+            table, table
+        );
+
+        // Within the single quotes of \echo, psql turns '' into a quote and \\ into a backslash.
+        statements.push(format!(
+            "\\echo 'COPY public.{} ({}) FROM stdin;'",
+            table.replace('\'', "''"),
+            columns.replace('\'', "''")
+        ));
+        statements.push(format!(
+            "\\copy (SELECT {} FROM {} WHERE {}) TO STDOUT",
+            columns, table, condition
+        ));
+        statements.push("\\echo '\\\\.'".to_string());
+        statements.push("\\echo".to_string());
+        statements.push(format!("\\echo '{}'", setval.replace('\'', "''")));
+        statements.push("\\echo".to_string());
+    }
+
+    statements.push("COMMIT".to_string());
+
+    for statement in statements {
+        cmd.push("-c".to_string());
+        cmd.push(statement);
+    }
+
+    cmd
+}
+
+///
+/// Writes the rows that belong to a guild.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `progress`: Reports the received bytes.
+/// * `guild_id`: The Discord ID of the server.
+/// * `database`: The role and database to export.
+/// * `writer`: Where the dump is written.
+///
+/// #### Returns
+///
+/// The size of the rows and the warnings of psql, or an error if the guild is unknown to the instance.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    progress: &Progress,
+    guild_id: u64,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let write_error = |e: io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    let known = container::query(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT EXISTS (SELECT 1 FROM guildconfig WHERE guild_id = {0}) \
+             OR EXISTS (SELECT 1 FROM ballinstance WHERE server_id = {0})",
+            guild_id
+        ),
+    )
+    .await;
+
+    match known.as_deref().map(str::trim) {
+        Some("t") => {}
+        Some(_) => {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "Guild {} has no configuration and no catches in this instance.",
+                    guild_id
+                ),
+            ));
+        }
+        None => {
+            return Err(Error::new(
+                ErrorKind::DumpFailed,
+                format!("Failed to look up guild {}. Is this a Ballsdex database?", guild_id),
+            ));
+        }
+    }
+
+    let mut sections = Vec::new();
+
+    for (table, condition) in tables(guild_id) {
+        let (quoted, columns) = container::columns(docker, container_id, database, table).await?;
+        sections.push((quoted, columns, condition));
+    }
+
+    writer
+        .write_all(format!("--\n-- ndmig export of guild {}\n--\n\n", guild_id).as_bytes())
+        .await
+        .map_err(write_error)?;
+
+    let cmd = copy_command(database, &sections);
+    let dump = container::create_database_dump(docker, container_id, cmd, database.env(), progress, writer).await?;
+
+    output::debug(format!("Wrote guild {} ({} bytes)", guild_id, dump.bytes));
+
+    Ok(dump)
+}
@@ -0,0 +1,328 @@
+/*
+    The append-only history of exports and imports, and the `history` subcommand.
+*/
+
+use crate::cli::HistoryArgs;
+use crate::container::Instance;
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use crate::summary::{OperationKind, Summary};
+use chrono::{DateTime, Local, SecondsFormat};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+///
+/// How an operation ended.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failed,
+}
+
+///
+/// A single line of the history file.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the operation finished, as RFC 3339.
+    pub timestamp: String,
+    pub operation: OperationKind,
+    pub instance: String,
+    pub container_id: String,
+    pub dump_path: Option<PathBuf>,
+    pub bytes: Option<u64>,
+    pub outcome: Outcome,
+    pub error_kind: Option<ErrorKind>,
+    pub duration_ms: u128,
+    /// How long an import took to load its dumps, `None` for exports and entries written before it was recorded.
+    #[serde(default)]
+    pub load_ms: Option<u128>,
+    /// Whether an import loaded its dumps with `--fast`.
+    #[serde(default)]
+    pub fast: bool,
+    /// The SHA-256 of the dump written, or of the dump an import loaded last, which the next delta has to build on.
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl Entry {
+    ///
+    /// Creates the entry of a successful operation.
+    ///
+    /// #### Arguments
+    ///
+    /// * `summary`: The summary of the operation.
+    ///
+    pub fn success(summary: &Summary) -> Self {
+        Entry {
+            timestamp: now(),
+            operation: summary.operation,
+            instance: summary.instance.clone(),
+            container_id: summary.container_id.clone(),
+            dump_path: Some(summary.dump_path.clone()),
+            bytes: Some(summary.bytes),
+            outcome: Outcome::Success,
+            error_kind: None,
+            duration_ms: summary.duration_ms,
+            load_ms: summary.load_ms,
+            fast: summary.fast,
+            sha256: summary.checksum.clone(),
+        }
+    }
+
+    ///
+    /// Creates the entry of a failed operation.
+    ///
+    /// #### Arguments
+    ///
+    /// * `operation`: The operation that failed.
+    /// * `instance`: The instance it ran on.
+    /// * `error`: The error it failed with.
+    /// * `duration`: How long it ran.
+    ///
+    pub fn failure(operation: OperationKind, instance: &Instance, error: &Error, duration: Duration) -> Self {
+        Entry {
+            timestamp: now(),
+            operation,
+            instance: crate::format_name(&instance.name),
+            container_id: instance.container_id.clone(),
+            dump_path: None,
+            bytes: None,
+            outcome: Outcome::Failed,
+            error_kind: Some(error.kind),
+            duration_ms: duration.as_millis(),
+            load_ms: None,
+            fast: false,
+            sha256: None,
+        }
+    }
+}
+
+///
+/// Returns the current local time as RFC 3339.
+///
+fn now() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+///
+/// Returns the location of the history file.
+///
+/// #### Returns
+///
+/// `$XDG_DATA_HOME/ndmig/history.jsonl`, falling back to `~/.local/share/ndmig/history.jsonl`.
+///
+pub fn path() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))?;
+
+    Some(data_home.join("ndmig").join("history.jsonl"))
+}
+
+///
+/// Appends an entry to the history file.
+///
+/// Failing to write the history never fails the operation itself, it only prints a warning.
+///
+/// #### Arguments
+///
+/// * `entry`: The entry to append.
+///
+pub fn record(entry: &Entry) {
+    let Some(path) = path() else {
+        output::debug("No history file location, skipping history");
+        return;
+    };
+
+    let line = serde_json::to_string(entry).expect("Failed to serialize history entry");
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|mut file| writeln!(file, "{}", line));
+
+    match result {
+        Ok(()) => output::debug(format!("Recorded {:?} in {}", entry.outcome, path.display())),
+        Err(e) => output::status(
+            format!(
+                "{} Failed to record the history in {}: {}",
+                Glyph::Warning,
+                path.display(),
+                e
+            )
+            .yellow(),
+        ),
+    }
+}
+
+///
+/// Reads every entry of the history file.
+///
+/// #### Returns
+///
+/// The entries, oldest first. A missing file is an empty history, unreadable lines are skipped.
+///
+pub fn read() -> Result<Vec<Entry>, Error> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to read {}: {}", path.display(), e),
+            ));
+        }
+    };
+
+    let mut entries = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => output::debug(format!("Skipping line {} of {}: {}", number + 1, path.display(), e)),
+        }
+    }
+
+    Ok(entries)
+}
+
+///
+/// Formats an RFC 3339 timestamp for the history table.
+///
+/// #### Arguments
+///
+/// * `timestamp`: The timestamp from the history file.
+///
+fn format_timestamp(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+///
+/// Estimates how long loading a dump takes, at the rate of the last successful import with or without `--fast`.
+///
+/// #### Arguments
+///
+/// * `bytes`: The size of the dump.
+/// * `fast`: Whether to go by the imports with `--fast`.
+///
+/// #### Returns
+///
+/// The estimated time in milliseconds, or `None` if no such import was recorded.
+///
+pub fn estimate_load(bytes: u64, fast: bool) -> Option<u128> {
+    read()
+        .ok()?
+        .iter()
+        .rev()
+        .filter(|entry| entry.operation == OperationKind::Import && entry.outcome == Outcome::Success)
+        .filter(|entry| entry.fast == fast)
+        .find_map(|entry| match (entry.bytes, entry.load_ms) {
+            (Some(loaded), Some(ms)) if loaded > 0 && ms > 0 => Some(u128::from(bytes) * ms / u128::from(loaded)),
+            _ => None,
+        })
+}
+
+///
+/// Finds the last successful import into an instance.
+///
+/// #### Arguments
+///
+/// * `instance`: The short name of the instance.
+///
+/// #### Returns
+///
+/// The entry of the import, or `None` if none was recorded.
+///
+pub fn last_import(instance: &str) -> Option<Entry> {
+    read().ok()?.into_iter().rev().find(|entry| {
+        entry.operation == OperationKind::Import && entry.outcome == Outcome::Success && entry.instance == instance
+    })
+}
+
+///
+/// Lists past exports and imports.
+///
+/// #### Arguments
+///
+/// * `args`: The history arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the history could not be read.
+///
+pub fn run(args: &HistoryArgs) -> Result<(), Error> {
+    let mut entries = read()?;
+
+    if let Some(instance) = &args.instance {
+        let instance = instance.trim().to_lowercase();
+        entries.retain(|entry| entry.instance.to_lowercase() == instance);
+    }
+
+    if output::is_json() {
+        output::json(&entries);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        output::info("No exports or imports recorded yet.".yellow().bold());
+        return Ok(());
+    }
+
+    let width = entries.iter().map(|entry| entry.instance.len()).max().unwrap_or(0);
+
+    // Newest first, like `git log`.
+    for entry in entries.iter().rev() {
+        let operation = match entry.operation {
+            OperationKind::Export => "export",
+            OperationKind::Import => "import",
+        };
+
+        let outcome = match (entry.outcome, entry.error_kind) {
+            (Outcome::Success, _) => format!("{:20}", format!("{} success", Glyph::Ok)).green(),
+            (Outcome::Failed, kind) => {
+                let kind = kind
+                    .and_then(|kind| serde_json::to_value(kind).ok())
+                    .and_then(|kind| kind.as_str().map(str::to_string))
+                    .unwrap_or_else(|| "failed".to_string());
+
+                format!("{:20}", format!("{} {}", Glyph::Error, kind)).red()
+            }
+        };
+
+        let details = match (&entry.dump_path, entry.bytes) {
+            (Some(path), Some(bytes)) => format!("{}  {}", crate::format_size(bytes), path.display()),
+            _ => String::new(),
+        };
+
+        output::info(format!(
+            "  {}  {:6}  {}  {:>6.1}s  {}  {}",
+            format_timestamp(&entry.timestamp).dimmed(),
+            operation,
+            format!("{:width$}", entry.instance).bright_cyan(),
+            Duration::from_millis(entry.duration_ms as u64).as_secs_f64(),
+            outcome,
+            details
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,418 @@
+/*
+    Incremental exports, for `--incremental`, which only dump the rows added since an earlier export.
+
+    Every export of the rows records the highest primary key of each table with a single integer key in its manifest.
+    A delta copies the rows above those marks out with `\copy (SELECT ...) TO STDOUT` and wraps them in the `COPY`
+    sections of a plain dump, so it can be applied on top of its base with psql.
+*/
+
+use crate::bundle;
+use crate::cli::ExportArgs;
+use crate::container::{self, Database, DatabaseDump};
+use crate::dumps;
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::json;
+use crate::manifest;
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use crate::progress::Progress;
+use bollard::Docker;
+use chrono::DateTime;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+///
+/// The highest primary key of a table when it was exported.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mark {
+    /// The integer primary key column.
+    pub column: String,
+    /// The highest value of the column, 0 for an empty table.
+    pub value: i64,
+}
+
+///
+/// The earlier export a delta builds on.
+///
+#[derive(Debug, Clone)]
+pub struct Base {
+    pub path: PathBuf,
+    /// The SHA-256 of the base, which the delta records to refuse being applied on top of another dump.
+    pub sha256: String,
+    pub marks: BTreeMap<String, Mark>,
+}
+
+///
+/// The rows a delta holds: those above the marks of its base, up to the marks read when it started.
+///
+#[derive(Debug, Clone)]
+pub struct Delta {
+    pub base: Base,
+    pub marks: BTreeMap<String, Mark>,
+}
+
+///
+/// Checks whether an export records the marks a later delta can build on.
+///
+/// Only the pg_dump formats hold every row of the tables they export. A schema-only dump holds none, and a guild or
+/// filtered export only some.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+///
+pub fn records_marks(args: &ExportArgs) -> bool {
+    matches!(
+        args.format,
+        DumpFormat::Plain | DumpFormat::Custom | DumpFormat::Directory
+    ) && !args.schema_only
+        && args.guild_id.is_none()
+        && !crate::filter::is_filtered(args)
+}
+
+///
+/// Reads the highest primary key of every table an export holds.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+/// * `args`: The export arguments, for `--tables` and `--exclude-tables`.
+///
+/// #### Returns
+///
+/// The marks per table with a single integer primary key, or `None` if they could not be queried.
+///
+pub async fn high_water_marks(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    args: &ExportArgs,
+) -> Option<BTreeMap<String, Mark>> {
+    let output = container::query(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, a.attname FROM pg_index i JOIN pg_class c ON c.oid = i.indrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = i.indkey[0] \
+         WHERE i.indisprimary AND i.indnatts = 1 AND n.nspname = 'public' \
+         AND a.atttypid = ANY ('{int2,int4,int8}'::regtype[])",
+    )
+    .await?;
+
+    let keys: BTreeMap<String, String> = output
+        .lines()
+        .filter_map(|line| line.split_once('|'))
+        .filter(|(table, _)| {
+            (args.tables.is_empty() || args.tables.iter().any(|name| name == table))
+                && !args.exclude_tables.iter().any(|name| name == table)
+        })
+        .map(|(table, column)| (table.to_string(), column.to_string()))
+        .collect();
+
+    if keys.is_empty() {
+        return Some(BTreeMap::new());
+    }
+
+    let sql = keys
+        .iter()
+        .map(|(table, column)| {
+            format!(
+                "SELECT '{}', coalesce(max({}), 0) FROM {}",
+                table.replace('\'', "''"),
+                json::quote(column),
+                json::quote(table)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+
+    let output = container::query(docker, container_id, database, &sql).await?;
+
+    Some(
+        output
+            .lines()
+            .filter_map(|line| line.split_once('|'))
+            .filter_map(|(table, value)| {
+                let mark = Mark {
+                    column: keys.get(table)?.clone(),
+                    value: value.parse().ok()?,
+                };
+                Some((table.to_string(), mark))
+            })
+            .collect(),
+    )
+}
+
+///
+/// Finds the newest export of an instance that a delta can build on.
+///
+/// A delta records its own marks, so the base of the next delta is the previous delta, and the deltas form a chain.
+///
+/// #### Arguments
+///
+/// * `instance`: The short instance name.
+/// * `directory`: The directory the delta is written to, searched on top of the ndmig directories.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The base, or an error if no export of the instance recorded its marks.
+///
+pub fn find_base(instance: &str, directory: Option<&Path>, options: &Options) -> Result<Base, Error> {
+    let mut files = dumps::find(options);
+
+    if let Some(directory) = directory {
+        let directory = match directory.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => directory,
+        };
+
+        files.extend(dumps::scan(directory, false));
+    }
+
+    let mut seen = BTreeSet::new();
+
+    files
+        .into_iter()
+        .filter(|file| !file.partial && seen.insert(file.path.canonicalize().unwrap_or(file.path.clone())))
+        .filter_map(|file| {
+            let manifest = match bundle::is_bundle(&file.path) {
+                true => bundle::read_manifest(&file.path),
+                false => manifest::read(&file.path),
+            };
+
+            let manifest = manifest.ok().flatten()?;
+
+            if manifest.instance.as_deref() != Some(instance) {
+                return None;
+            }
+
+            let created_at = manifest
+                .created_at
+                .as_deref()
+                .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())?;
+
+            let base = Base {
+                path: file.path,
+                sha256: manifest.sha256?,
+                marks: manifest.high_water_marks?,
+            };
+
+            Some((created_at, base))
+        })
+        .max_by_key(|(created_at, _)| *created_at)
+        .map(|(_, base)| base)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Config,
+                format!(
+                    "No earlier export of {} records where a delta would start. Run a full export first.",
+                    crate::format_name(instance)
+                ),
+            )
+        })
+}
+
+///
+/// Orders tables so that every table comes after the tables its foreign keys point at.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+/// * `tables`: The tables to order.
+///
+/// #### Returns
+///
+/// The tables with the referenced ones first. Tables in a cycle keep their alphabetical order at the end.
+///
+pub async fn dependency_order(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    tables: Vec<String>,
+) -> Vec<String> {
+    let output = container::query(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, r.relname FROM pg_constraint k JOIN pg_class c ON c.oid = k.conrelid \
+         JOIN pg_class r ON r.oid = k.confrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE k.contype = 'f' AND n.nspname = 'public'",
+    )
+    .await
+    .unwrap_or_default();
+
+    let references: Vec<(&str, &str)> = output
+        .lines()
+        .filter_map(|line| line.split_once('|'))
+        .filter(|(table, referenced)| table != referenced)
+        .collect();
+
+    let mut remaining = tables;
+    let mut ordered = Vec::new();
+
+    while !remaining.is_empty() {
+        let blocked = |table: &String| {
+            references
+                .iter()
+                .any(|(from, to)| from == table && remaining.iter().any(|other| other == to))
+        };
+        let (waiting, ready): (Vec<String>, Vec<String>) = remaining.iter().cloned().partition(blocked);
+
+        // Nothing in a cycle can go first, so the rest keeps its order.
+        if ready.is_empty() {
+            ordered.extend(waiting);
+            break;
+        }
+
+        ordered.extend(ready);
+        remaining = waiting;
+    }
+
+    ordered
+}
+
+///
+/// Builds the `psql` command that writes the rows of a table between two marks as `COPY` data.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to connect with.
+/// * `table`: The table to export, quoted.
+/// * `columns`: The quoted columns of the table.
+/// * `from`: The mark of the base, whose rows are left out.
+/// * `to`: The mark of the delta.
+///
+fn copy_command(database: &Database, table: &str, columns: &str, from: &Mark, to: &Mark) -> Vec<String> {
+    let column = json::quote(&to.column);
+
+    let mut cmd = vec![
+        "psql".to_string(),
+        "-X".to_string(),
+        "-q".to_string(),
+        "-v".to_string(),
+        "ON_ERROR_STOP=1".to_string(),
+    ];
+    cmd.extend(database.connection_args());
+    cmd.push("-c".to_string());
+    cmd.push(format!(
+        "\\copy (SELECT {} FROM {} WHERE {} > {} AND {} <= {} ORDER BY {}) TO STDOUT",
+        columns, table, column, from.value, column, to.value, column
+    ));
+    cmd
+}
+
+///
+/// Writes a delta: the rows each table gained since the base, as the `COPY` sections of a plain dump.
+///
+/// Every section is followed by a `setval` that moves the sequence of the key to the highest key after the new rows,
+/// so the target does not hand out their IDs again.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `progress`: Reports the received bytes of all tables together.
+/// * `delta`: The base and the marks of the delta.
+/// * `database`: The role and database to export.
+/// * `writer`: Where the delta is written.
+///
+/// #### Returns
+///
+/// The size of the rows and the warnings of all tables, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    progress: &Progress,
+    delta: &Delta,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let write_error = |e: io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    let base_name = delta.base.path.file_name().unwrap_or_default().to_string_lossy();
+    writer
+        .write_all(
+            format!(
+                "--\n-- ndmig delta, the rows added since {}\n-- Base SHA-256: {}\n--\n\n",
+                base_name, delta.base.sha256
+            )
+            .as_bytes(),
+        )
+        .await
+        .map_err(write_error)?;
+
+    // A table the base has no mark for may not even exist in the restored base, so its rows cannot be added.
+    let (tables, unmarked): (Vec<String>, Vec<String>) =
+        delta.marks.keys().cloned().partition(|table| {
+            delta.base.marks.get(table).map(|mark| &mark.column) == Some(&delta.marks[table].column)
+        });
+
+    if !unmarked.is_empty() {
+        output::status(
+            format!(
+                "{} Leaving out {}, the base has no mark for them",
+                Glyph::Warning,
+                unmarked.join(", ")
+            )
+            .yellow(),
+        );
+    }
+
+    let mut dump = DatabaseDump {
+        bytes: 0,
+        warnings: Vec::new(),
+    };
+
+    for table in dependency_order(docker, container_id, database, tables).await {
+        let (from, to) = (&delta.base.marks[&table], &delta.marks[&table]);
+
+        if to.value <= from.value {
+            output::debug(format!("No new rows in {}", table));
+            continue;
+        }
+
+        let (quoted, columns) = container::columns(docker, container_id, database, &table).await?;
+        writer
+            .write_all(format!("COPY public.{} ({}) FROM stdin;\n", quoted, columns).as_bytes())
+            .await
+            .map_err(write_error)?;
+
+        let cmd = copy_command(database, &quoted, &columns, from, to);
+        let written =
+            container::create_database_dump(docker, container_id, cmd, database.env(), progress, writer).await?;
+
+        writer
+            .write_all(
+                format!(
+                    "\\.\n\nSELECT pg_catalog.setval(pg_catalog.pg_get_serial_sequence('public.{}', '{}'), \
+                     (SELECT max({}) FROM public.{}), true);\n\n",
+                    quoted.replace('\'', "''"),
+                    to.column.replace('\'', "''"),
+                    json::quote(&to.column),
+                    quoted
+                )
+                .as_bytes(),
+            )
+            .await
+            .map_err(write_error)?;
+
+        output::debug(format!("Wrote {} ({} bytes)", table, written.bytes));
+        dump.bytes += written.bytes;
+        dump.warnings.extend(written.warnings);
+    }
+
+    Ok(dump)
+}
@@ -0,0 +1,253 @@
+/*
+    The `info` subcommand, which shows the details of a single Ballsdex instance.
+*/
+
+use crate::cli::InfoArgs;
+use crate::container::{self, CORE_TABLES, Database, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use crate::prompt::{self, confirm};
+use bollard::Docker;
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The label compose puts on every container of a project.
+const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+
+///
+/// The details of an instance, printed as a table or as JSON.
+///
+#[derive(Serialize, Default)]
+struct InstanceInfo {
+    name: String,
+    container_id: String,
+    image: Option<String>,
+    running: bool,
+    uptime: Option<String>,
+    compose_project: Option<String>,
+    database_size: Option<u64>,
+    server_version: Option<String>,
+    row_counts: BTreeMap<String, u64>,
+}
+
+///
+/// Splits an image reference into its name and tag.
+///
+/// #### Arguments
+///
+/// * `image`: The image reference, e.g. `postgres:16`.
+///
+/// #### Returns
+///
+/// The image with an explicit tag, e.g. `postgres:latest` for `postgres`.
+///
+fn with_tag(image: &str) -> String {
+    let name = image.rsplit('/').next().unwrap_or(image);
+
+    if name.contains(':') || name.contains('@') {
+        image.to_string()
+    } else {
+        format!("{}:latest", image)
+    }
+}
+
+///
+/// Reads the details that need a running postgres server.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `info`: The details to fill in.
+/// * `database`: The role and database to connect with.
+///
+async fn query_database(docker: &Docker, info: &mut InstanceInfo, database: &Database) {
+    let container_id = info.container_id.clone();
+
+    info.database_size = container::database_size(docker, &container_id, database).await;
+
+    if let Some(server) = container::query(
+        docker,
+        &container_id,
+        database,
+        "SELECT current_setting('server_version'), date_trunc('second', now() - pg_postmaster_start_time())",
+    )
+    .await
+        && let Some((version, uptime)) = server.split_once('|')
+    {
+        info.server_version = Some(version.to_string());
+        info.uptime = Some(uptime.to_string());
+    }
+
+    if let Some(counts) = container::row_counts(docker, &container_id, database, &CORE_TABLES).await {
+        info.row_counts = counts;
+    }
+}
+
+///
+/// Prints the details of an instance as an aligned table.
+///
+/// #### Arguments
+///
+/// * `info`: The details to print.
+///
+fn print(info: &InstanceInfo) {
+    let unknown = || "unknown".dimmed().to_string();
+
+    let state = match (&info.running, &info.uptime) {
+        (true, Some(uptime)) => format!("{} (up {})", "running".green(), uptime),
+        (true, None) => "running".green().to_string(),
+        (false, _) => "stopped".dimmed().to_string(),
+    };
+
+    let rows = [
+        ("Container ID", info.container_id.clone()),
+        ("Image", info.image.clone().unwrap_or_else(unknown)),
+        ("State", state),
+        ("Compose project", info.compose_project.clone().unwrap_or_else(unknown)),
+        (
+            "Database size",
+            info.database_size.map(crate::format_size).unwrap_or_else(unknown),
+        ),
+        ("PostgreSQL", info.server_version.clone().unwrap_or_else(unknown)),
+    ];
+
+    output::info(format!("{}", info.name.bold().bright_cyan()));
+
+    for (label, value) in rows {
+        output::info(format!("  {}  {}", format!("{:15}", label).bold(), value));
+    }
+
+    // Only print the counts if the database could be queried at all.
+    if info.server_version.is_none() {
+        return;
+    }
+
+    output::info(format!("\n{}", "Rows".bold().yellow()));
+
+    for table in CORE_TABLES {
+        let count = match info.row_counts.get(table) {
+            Some(count) => count.to_string(),
+            None => "missing".dimmed().to_string(),
+        };
+
+        output::info(format!(
+            "  {} {:12}  {}",
+            Glyph::Arrow.to_string().bright_yellow(),
+            table,
+            count
+        ));
+    }
+}
+
+///
+/// Shows the details of an instance.
+///
+/// A stopped container is only started (and stopped again afterwards) if the user agrees,
+/// otherwise only what `docker inspect` knows is shown.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered Ballsdex instances.
+/// * `args`: The info arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the instance could not be found or inspected.
+///
+pub async fn run(docker: &Docker, instances: &[Instance], args: &InfoArgs, options: &Options) -> Result<(), Error> {
+    let instance = crate::match_instance(instances, &args.instance, options)
+        .map_err(|message| Error::new(ErrorKind::InstanceNotFound, message))?;
+
+    output::debug(format!("inspect_container {}", instance.container_id));
+
+    let inspected = docker
+        .inspect_container(&instance.container_id, None)
+        .await
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::DockerConnection,
+                format!("Failed to inspect container: {}", e),
+            )
+        })?;
+
+    let config = inspected.config.unwrap_or_default();
+
+    let mut info = InstanceInfo {
+        name: crate::format_name(&instance.name),
+        container_id: instance.container_id.clone(),
+        image: config.image.as_deref().map(with_tag),
+        running: inspected.state.and_then(|state| state.running).unwrap_or(false),
+        compose_project: config
+            .labels
+            .and_then(|labels| labels.get(COMPOSE_PROJECT_LABEL).cloned()),
+        ..Default::default()
+    };
+
+    let mut started = false;
+
+    // Without a terminal the container is left alone unless --yes was passed.
+    if !info.running
+        && (options.assume_yes || prompt::is_interactive())
+        && confirm(
+            options,
+            &format!("{} is stopped. Start it to read the database details?", info.name),
+        )?
+    {
+        output::debug(format!("start_container {}", info.container_id));
+
+        match docker.start_container(&info.container_id, None).await {
+            Ok(()) => {
+                info.running = true;
+                started = true;
+            }
+            Err(e) => output::status(format!("{} Failed to start container: {}", Glyph::Warning, e).yellow()),
+        }
+    }
+
+    if info.running {
+        let database = container::database(docker, &instance.container_id, options).await;
+
+        let ready = match started {
+            true => {
+                let spinner = crate::progress::spinner("Waiting for the database to start...");
+                let ready = container::wait_until_ready(docker, &info.container_id, &info.name, &database).await;
+
+                if let Some(spinner) = spinner {
+                    spinner.finish_and_clear();
+                }
+
+                ready
+            }
+            false => Ok(()),
+        };
+
+        match ready {
+            Ok(()) => query_database(docker, &mut info, &database).await,
+            Err(error) => output::status(format!("{} {}", Glyph::Warning, error.message).yellow()),
+        }
+    }
+
+    if started {
+        output::debug(format!("stop_container {}", info.container_id));
+
+        match docker.stop_container(&info.container_id, None).await {
+            Ok(()) => {
+                info.running = false;
+                info.uptime = None;
+            }
+            Err(e) => output::status(format!("{} Failed to stop the container again: {}", Glyph::Warning, e).yellow()),
+        }
+    }
+
+    if output::is_json() {
+        output::json(&info);
+    } else {
+        print(&info);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+/*
+    The `inspect` subcommand, which shows what a dump or a bundle holds without importing it.
+*/
+
+use crate::bundle::{self, Member};
+use crate::cli::InspectArgs;
+use crate::dumps;
+use crate::error::{Error, ErrorKind};
+use crate::manifest::{self, Manifest};
+use crate::output::{self, Glyph};
+use colored::*;
+use serde::Serialize;
+use std::path::PathBuf;
+
+///
+/// The contents of a dump, printed as a table or as JSON.
+///
+#[derive(Serialize)]
+struct Inspection {
+    path: PathBuf,
+    bundle: bool,
+    /// The dump and the files that belong to it, inside the bundle or next to the dump.
+    files: Vec<Member>,
+    manifest: Option<Manifest>,
+}
+
+///
+/// Prints the contents of a dump as an aligned table.
+///
+/// #### Arguments
+///
+/// * `inspection`: The contents to print.
+///
+fn print(inspection: &Inspection) {
+    output::info(format!(
+        "{}",
+        inspection.path.display().to_string().bold().bright_cyan()
+    ));
+
+    if let Some(manifest) = &inspection.manifest {
+        let format = match (&manifest.format, manifest.compression.as_deref()) {
+            (Some(format), None | Some("none")) => Some(format.clone()),
+            (Some(format), Some(compression)) => Some(format!("{}+{}", format, compression)),
+            (None, _) => None,
+        };
+
+        let rows = [
+            ("Instance", manifest.instance.clone()),
+            ("Created", manifest.created_at.clone()),
+            ("Format", format),
+            ("Mode", manifest.mode.clone()),
+            ("PostgreSQL", manifest.server_version.clone()),
+            ("pg_dump", manifest.pg_dump_version.clone()),
+            ("ndmig", manifest.ndmig_version.clone()),
+            ("SHA-256", manifest.sha256.clone()),
+        ];
+
+        for (label, value) in rows {
+            if let Some(value) = value {
+                output::info(format!("  {}  {}", format!("{:12}", label).bold(), value));
+            }
+        }
+    } else {
+        output::info(format!("  {}", "No manifest".dimmed()));
+    }
+
+    output::info(format!("\n{}", "Files".bold().yellow()));
+
+    for file in &inspection.files {
+        output::info(format!(
+            "  {} {:>10}  {}",
+            Glyph::Arrow.to_string().bright_yellow(),
+            crate::format_size(file.bytes),
+            file.name
+        ));
+    }
+}
+
+///
+/// Shows the files and the manifest of a dump or a bundle.
+///
+/// #### Arguments
+///
+/// * `args`: The inspect arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the path cannot be read.
+///
+pub fn run(args: &InspectArgs) -> Result<(), Error> {
+    let path = &args.path;
+    let read_error = |message: String| Error::new(ErrorKind::Filesystem, message);
+
+    let metadata =
+        std::fs::metadata(path).map_err(|e| read_error(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let inspection = if bundle::is_bundle(path) {
+        Inspection {
+            path: path.clone(),
+            bundle: true,
+            files: bundle::members(path).map_err(read_error)?,
+            manifest: bundle::read_manifest(path).map_err(read_error)?,
+        }
+    } else {
+        let member = |path: &PathBuf, bytes: u64| Member {
+            name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            bytes,
+            offset: 0,
+        };
+
+        let sidecars = dumps::sidecars(path).into_iter().filter_map(|sidecar| {
+            let bytes = std::fs::metadata(&sidecar).ok()?.len();
+            Some(member(&sidecar, bytes))
+        });
+
+        Inspection {
+            path: path.clone(),
+            bundle: false,
+            files: std::iter::once(member(path, metadata.len())).chain(sidecars).collect(),
+            manifest: manifest::read(path).map_err(read_error)?,
+        }
+    };
+
+    if output::is_json() {
+        output::json(&inspection);
+    } else {
+        print(&inspection);
+    }
+
+    Ok(())
+}
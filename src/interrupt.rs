@@ -0,0 +1,76 @@
+/*
+    Ctrl+C handling, so interrupted operations can clean up after themselves.
+*/
+
+use crate::error::ErrorKind;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+static SCOPES: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+///
+/// Installs the Ctrl+C handler for the rest of the run.
+///
+/// Outside of a [`Scope`] Ctrl+C exits immediately. Inside one, the first Ctrl+C asks the scope to
+/// cancel and clean up, and a second one force-exits.
+///
+pub fn install() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if SCOPES.load(Ordering::SeqCst) == 0 || INTERRUPTED.swap(true, Ordering::SeqCst) {
+                eprintln!();
+                process::exit(ErrorKind::Interrupted.exit_code());
+            }
+
+            NOTIFY.notify_waiters();
+        }
+    });
+}
+
+///
+/// Returns whether the user pressed Ctrl+C, so queued work can be skipped.
+///
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+///
+/// A section of work that wants to clean up instead of dying when the user presses Ctrl+C.
+///
+pub struct Scope {
+    _private: (),
+}
+
+impl Scope {
+    ///
+    /// Enters a new scope. Ctrl+C is handed to the scope until it is dropped.
+    ///
+    pub fn enter() -> Self {
+        SCOPES.fetch_add(1, Ordering::SeqCst);
+        Scope { _private: () }
+    }
+
+    ///
+    /// Waits until the user presses Ctrl+C.
+    ///
+    pub async fn interrupted(&self) {
+        loop {
+            let notified = NOTIFY.notified();
+
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        SCOPES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
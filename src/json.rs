@@ -0,0 +1,332 @@
+/*
+    JSON exports of the core Ballsdex entities, for `--format json`.
+*/
+
+use crate::cli::ExportArgs;
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::output;
+use crate::progress::Progress;
+use bollard::Docker;
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The tables exported unless `--tables` picks others.
+pub const TABLES: [&str; 6] = ["ball", "special", "economy", "regime", "player", "ballinstance"];
+
+/// How many rows psql fetches at once, so large tables are never held in memory as a whole.
+const FETCH_COUNT: &str = "1000";
+
+///
+/// The `metadata` block at the top of the document.
+///
+#[derive(Serialize)]
+struct Metadata<'a> {
+    instance: &'a str,
+    ndmig_version: &'static str,
+    created_at: String,
+    server_version: Option<String>,
+    /// The number of rows per exported table when the export started.
+    row_counts: BTreeMap<String, u64>,
+    /// Tables that were asked for but do not exist in this Ballsdex version.
+    missing_tables: Vec<String>,
+}
+
+///
+/// Builds the `psql` command that writes every row of a table as one JSON object per line.
+///
+/// `bytea` columns are base64-encoded, as `row_to_json` would otherwise write them as hex escapes.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to connect with.
+/// * `table`: The table to export.
+/// * `binary_columns`: The `bytea` columns of the table.
+///
+pub fn select_command(database: &Database, table: &str, binary_columns: &[String]) -> Vec<String> {
+    let row = if binary_columns.is_empty() {
+        "row_to_json(t)".to_string()
+    } else {
+        let encoded = binary_columns
+            .iter()
+            .map(|column| {
+                format!(
+                    "'{}', encode(t.{}, 'base64')",
+                    column.replace('\'', "''"),
+                    quote(column)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("to_jsonb(t) || jsonb_build_object({})", encoded)
+    };
+
+    let mut cmd = vec![
+        "psql".to_string(),
+        "-X".to_string(),
+        "-q".to_string(),
+        "-tA".to_string(),
+        "-v".to_string(),
+        "ON_ERROR_STOP=1".to_string(),
+        "-v".to_string(),
+        format!("FETCH_COUNT={}", FETCH_COUNT),
+    ];
+    cmd.extend(database.connection_args());
+    cmd.push("-c".to_string());
+    cmd.push(format!("SELECT {} FROM {} t", row, quote(table)));
+    cmd
+}
+
+///
+/// Quotes an SQL identifier.
+///
+/// #### Arguments
+///
+/// * `name`: The table or column name.
+///
+pub fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+///
+/// Lists the `bytea` columns of a table.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to connect with.
+/// * `table`: The table.
+///
+/// #### Returns
+///
+/// The column names, or an error if they could not be queried.
+///
+async fn binary_columns(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    table: &str,
+) -> Result<Vec<String>, Error> {
+    let output = container::query(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT column_name FROM information_schema.columns \
+             WHERE table_schema = 'public' AND table_name = '{}' AND data_type = 'bytea'",
+            table.replace('\'', "''")
+        ),
+    )
+    .await
+    .ok_or_else(|| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("Failed to list the columns of {}.", table),
+        )
+    })?;
+
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+///
+/// Writes the JSON document of an instance.
+///
+/// The document has a `metadata` block, followed by a `tables` object with an array of rows per table. Rows are
+/// streamed from psql as they arrive.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `instance`: The short instance name, recorded in the metadata.
+/// * `progress`: Reports the received bytes of all tables together.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to export.
+/// * `writer`: Where the document is written.
+///
+/// #### Returns
+///
+/// The size of the rows and the warnings of all tables, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    instance: &str,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let write_error = |e: io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e));
+
+    let Some(available) = container::tables(docker, container_id, database).await else {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            "Failed to list the tables of the database.",
+        ));
+    };
+
+    let requested: Vec<String> = if args.tables.is_empty() {
+        TABLES.iter().map(|table| table.to_string()).collect()
+    } else {
+        args.tables.clone()
+    };
+
+    let (tables, missing_tables): (Vec<String>, Vec<String>) = requested
+        .into_iter()
+        .filter(|table| !args.exclude_tables.contains(table))
+        .partition(|table| available.contains(table));
+
+    let names: Vec<&str> = tables.iter().map(String::as_str).collect();
+
+    let metadata = Metadata {
+        instance,
+        ndmig_version: env!("CARGO_PKG_VERSION"),
+        created_at: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        server_version: container::query(docker, container_id, database, "SHOW server_version").await,
+        row_counts: container::row_counts(docker, container_id, database, &names)
+            .await
+            .unwrap_or_default(),
+        missing_tables,
+    };
+
+    let metadata = serde_json::to_string(&metadata).expect("Failed to serialize the JSON metadata");
+    writer
+        .write_all(format!("{{\n\"metadata\": {},\n\"tables\": {{", metadata).as_bytes())
+        .await
+        .map_err(write_error)?;
+
+    let mut dump = DatabaseDump {
+        bytes: 0,
+        warnings: Vec::new(),
+    };
+
+    for (index, table) in tables.iter().enumerate() {
+        let separator = if index == 0 { "" } else { "," };
+        writer
+            .write_all(format!("{}\n{}: [\n", separator, serde_json::json!(table)).as_bytes())
+            .await
+            .map_err(write_error)?;
+
+        let cmd = select_command(
+            database,
+            table,
+            &binary_columns(docker, container_id, database, table).await?,
+        );
+        let mut rows = RowWriter::new(&mut *writer);
+        let written =
+            container::create_database_dump(docker, container_id, cmd, database.env(), progress, &mut rows).await?;
+        rows.shutdown().await.map_err(write_error)?;
+
+        output::debug(format!("Wrote {} ({} bytes)", table, written.bytes));
+        dump.bytes += written.bytes;
+        dump.warnings.extend(written.warnings);
+
+        writer.write_all(b"]").await.map_err(write_error)?;
+    }
+
+    writer.write_all(b"\n}\n}\n").await.map_err(write_error)?;
+
+    Ok(dump)
+}
+
+///
+/// A writer that turns the one-object-per-line output of psql into the elements of a JSON array.
+///
+/// JSON escapes newlines inside strings, so every newline psql writes ends a row. Each one but the last becomes a
+/// comma and a newline. Shutting it down writes the last newline but leaves the inner writer open.
+///
+struct RowWriter<W> {
+    inner: W,
+    /// Whether a row ended and the next one, if any, needs a comma in front.
+    row_ended: bool,
+    /// Bytes the inner writer has not accepted yet.
+    output: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> RowWriter<W> {
+    ///
+    /// Wraps a writer.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The writer the array elements are passed on to.
+    ///
+    fn new(inner: W) -> Self {
+        RowWriter {
+            inner,
+            row_ended: false,
+            output: Vec::new(),
+            written: 0,
+        }
+    }
+
+    ///
+    /// Passes the pending bytes on to the inner writer.
+    ///
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.output.len() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.written += written;
+        }
+
+        self.output.clear();
+        self.written = 0;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for RowWriter<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+
+        for byte in buf {
+            if this.row_ended {
+                this.output.extend_from_slice(b",\n");
+                this.row_ended = false;
+            }
+
+            if *byte == b'\n' {
+                this.row_ended = true;
+            } else {
+                this.output.push(*byte);
+            }
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        if this.row_ended {
+            this.output.push(b'\n');
+            this.row_ended = false;
+        }
+
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+}
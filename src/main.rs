@@ -3,218 +3,5255 @@
     By @Cayla
 */
 
-use bollard::{
-    Docker,
-    exec::{CreateExecOptions, StartExecResults},
-    query_parameters::ListContainersOptions,
-};
+mod anonymize;
+mod archive;
+mod audit;
+mod bot;
+mod bundle;
+mod chain;
+mod checksum;
+mod clean;
+mod cli;
+mod compression;
+mod config;
+mod container;
+mod csv;
+mod directory;
+mod doctor;
+mod dumps;
+mod encrypt;
+mod error;
+mod exports;
+mod filter;
+mod format;
+mod fuzzy;
+mod glob;
+mod globals;
+mod guild;
+mod history;
+mod incremental;
+mod info;
+mod inspect;
+mod interrupt;
+mod json;
+mod manifest;
+mod media;
+mod merge;
+mod options;
+mod output;
+mod player;
+mod postgres;
+mod progress;
+mod prompt;
+mod provision;
+mod redis;
+mod restore;
+mod sequence;
+mod space;
+mod split;
+mod stable;
+mod summary;
+mod template;
+mod transcript;
+mod transform;
+mod tui;
+mod validate;
+mod verify;
+mod version;
+
+use anonymize::Anonymizer;
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use bollard::Docker;
+use bundle::BundleFiles;
+use checksum::HashingWriter;
+use chrono::Local;
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, ExportArgs, ImportArgs, ListArgs};
 use colored::*;
-use futures_util::StreamExt;
-use std::collections::HashMap;
-use std::io::{self, Write};
+use compression::Compression;
+use container::{
+    Database, DatabaseDump, Instance, create_database_dump, database_size, database_sizes, discover_instances,
+};
+use dialoguer::{MultiSelect, Select};
+use encrypt::{Decryption, Encryption};
+use error::{Error, ErrorKind};
+use format::DumpFormat;
+use futures_util::future::join_all;
+use incremental::{Base, Delta};
+use manifest::Manifest;
+use options::Options;
+use output::Glyph;
+use progress::Progress;
+use prompt::{confirm, confirm_typed};
+use restore::Strategy;
+use serde::Serialize;
+use stable::Stabilizer;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use summary::{OperationKind, Summary};
+use template::NameTemplate;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use transcript::Transcript;
+
+/// How many instances are exported at once when several are exported and `--parallel` is not given.
+const DEFAULT_PARALLEL: usize = 2;
+
+///
+/// Formats a container name by removing the suffix.
+///
+/// #### Arguments
+///
+/// * `name`: The full container name.
+///
+/// #### Returns
+///
+/// The formatted name without the suffix.
+///
+fn format_name(name: &str) -> String {
+    name.split("-").next().unwrap_or("unknown").to_string()
+}
+
+///
+/// Formats a size in bytes using binary units.
+///
+/// #### Arguments
+///
+/// * `bytes`: The size in bytes.
+///
+/// #### Returns
+///
+/// The human-readable size, e.g. `1.5 GiB`.
+///
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+///
+/// Returns the directory ndmig stores its dumps in by default.
+///
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join("ndmig")
+}
+
+///
+/// Shortens a container ID the same way the Docker CLI does.
+///
+/// #### Arguments
+///
+/// * `id`: The full container ID.
+///
+/// #### Returns
+///
+/// The first 12 characters of the ID.
+///
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(12)]
+}
+
+/// How many instances the selection menus show at once, the rest is reached by scrolling.
+const PAGE_SIZE: usize = 15;
+
+/// The container name suffix compose gives the postgres service of a Ballsdex instance.
+const INSTANCE_SUFFIX: &str = "-postgres-db-1";
+
+///
+/// Strips the postgres container suffix from an instance name.
+///
+/// #### Arguments
+///
+/// * `name`: The full instance name.
+///
+/// #### Returns
+///
+/// The compose project name, which is what users type to select the instance.
+///
+fn project_name(name: &str) -> &str {
+    name.strip_suffix(INSTANCE_SUFFIX).unwrap_or(name)
+}
+
+///
+/// Finds the instances whose short name matches regardless of case and surrounding whitespace.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances, sorted by name.
+/// * `name`: The short instance name the user typed.
+///
+/// #### Returns
+///
+/// The matching instances.
+///
+fn case_insensitive_matches<'a>(instances: &'a [Instance], name: &str) -> Vec<&'a Instance> {
+    let name = name.trim().to_lowercase();
+
+    instances
+        .iter()
+        .filter(|instance| project_name(&instance.name).to_lowercase() == name)
+        .collect()
+}
+
+///
+/// Looks up an instance by its short name.
+///
+/// An exact match wins, otherwise the name is compared case-insensitively as long as that only
+/// matches a single instance.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances.
+/// * `name`: The short instance name, as shown in the selection menu.
+///
+/// #### Returns
+///
+/// The instance, or `None` if there is no such instance.
+///
+fn resolve_instance<'a>(instances: &'a [Instance], name: &str) -> Option<&'a Instance> {
+    let full_name = name.trim().to_string() + INSTANCE_SUFFIX;
+
+    if let Some(instance) = instances.iter().find(|instance| instance.name == full_name) {
+        return Some(instance);
+    }
+
+    match case_insensitive_matches(instances, name).as_slice() {
+        [only] => Some(only),
+        _ => None,
+    }
+}
+
+///
+/// Looks up an instance by its short name, falling back to fuzzy matching for typos.
+///
+/// A single close match is only used after the user confirms it, so a typo never silently selects
+/// the wrong instance. With `--yes` or several close matches, the suggestions end up in the error.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances.
+/// * `name`: The short instance name the user typed.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The instance, or a message explaining why there is no match.
+///
+fn match_instance<'a>(instances: &'a [Instance], name: &str, options: &Options) -> Result<&'a Instance, String> {
+    if let Some(instance) = resolve_instance(instances, name) {
+        return Ok(instance);
+    }
+
+    let name = name.trim();
+    let ambiguous = case_insensitive_matches(instances, name);
+
+    if !ambiguous.is_empty() {
+        let items: Vec<&str> = ambiguous.iter().map(|instance| project_name(&instance.name)).collect();
+
+        if options.assume_yes || !prompt::is_interactive() {
+            return Err(format!(
+                "'{}' matches several instances that only differ by case: {}",
+                name,
+                items.join(", ")
+            ));
+        }
+
+        let selection = Select::with_theme(output::theme().as_ref())
+            .with_prompt(format!("'{}' matches several instances", name))
+            .items(&items)
+            .interact_opt()
+            .map_err(|e| format!("Failed to read input: {}", e))?;
+
+        return selection
+            .map(|index| ambiguous[index])
+            .ok_or_else(|| "Selection cancelled.".to_string());
+    }
+
+    let suggestions = fuzzy::closest(name, instances.iter().map(|instance| project_name(&instance.name)));
+
+    match suggestions.as_slice() {
+        [] => Err(format!("Instance not found: '{}'", name)),
+        [only] if !options.assume_yes && prompt::is_interactive() => {
+            let question = format!("Instance '{}' not found. Did you mean '{}'?", name, only);
+
+            if confirm(options, &question).map_err(|error| error.message)? {
+                resolve_instance(instances, only).ok_or_else(|| format!("Instance not found: '{}'", only))
+            } else {
+                Err(format!("Instance not found: '{}'", name))
+            }
+        }
+        _ => Err(format!(
+            "Instance not found: '{}'. Did you mean: {}?",
+            name,
+            suggestions.join(", ")
+        )),
+    }
+}
+
+///
+/// Checks whether an instance matches a filter typed by the user.
+///
+/// #### Arguments
+///
+/// * `instance`: The instance to check.
+/// * `filter`: The text to look for, case-insensitive. An empty filter matches everything.
+///
+fn matches_filter(instance: &Instance, filter: &str) -> bool {
+    format_name(&instance.name)
+        .to_lowercase()
+        .contains(&filter.trim().to_lowercase())
+}
+
+///
+/// Checks whether an `--output` path names a directory rather than a file.
+///
+/// #### Arguments
+///
+/// * `output`: The `--output` path.
+///
+/// #### Returns
+///
+/// Whether the path is an existing directory or ends with a path separator.
+///
+fn is_output_dir(output: &Path) -> bool {
+    output.is_dir()
+        || output
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::MAIN_SEPARATOR)
+}
+
+///
+/// Checks whether an `--output` path is `-`, which writes the dump to stdout.
+///
+/// #### Arguments
+///
+/// * `output`: The `--output` path, if one was given.
+///
+fn is_stdout(output: Option<&Path>) -> bool {
+    output == Some(Path::new("-"))
+}
+
+///
+/// Works out where a dump should be written.
+///
+/// #### Arguments
+///
+/// * `output`: The `--output` path, if one was given.
+/// * `template`: The file name template used unless `output` names a file.
+/// * `instance`: The full instance name.
+/// * `container_id`: The container ID of the instance.
+///
+/// #### Returns
+///
+/// The path of the dump file.
+///
+fn dump_path(output: Option<&Path>, template: &NameTemplate, instance: &str, container_id: &str) -> PathBuf {
+    let file_name = template.render(&format_name(instance), container_id, Local::now());
+
+    let output = match output {
+        Some(output) => output,
+        None => {
+            return temp_dir().join(file_name);
+        }
+    };
+
+    if is_output_dir(output) {
+        output.join(file_name)
+    } else {
+        output.to_path_buf()
+    }
+}
+
+///
+/// Returns what an export dumps.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+///
+/// #### Returns
+///
+/// `schema` for `--schema-only`, `data` for `--data-only` and `full` otherwise.
+///
+fn export_mode(args: &ExportArgs) -> &'static str {
+    if args.schema_only {
+        "schema"
+    } else if args.data_only {
+        "data"
+    } else if args.incremental {
+        "delta"
+    } else if args.guild_id.is_some() {
+        "guild"
+    } else if args.player.is_some() {
+        "player"
+    } else if filter::is_filtered(args) {
+        "filtered"
+    } else {
+        "full"
+    }
+}
+
+///
+/// Adds the export mode to a dump file name, e.g. `mydex-20250101-120000-schema.sql`.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the dump.
+/// * `mode`: The mode, as returned by [`export_mode`].
+///
+fn with_mode_suffix(path: PathBuf, mode: &str) -> PathBuf {
+    let Some(stem) = path.file_stem() else {
+        return path;
+    };
+
+    let mut file_name = stem.to_owned();
+    file_name.push("-");
+    file_name.push(mode);
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+///
+/// Formats one line per instance for the selection menus.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances.
+/// * `sizes`: The database size of every instance, in the same order.
+///
+/// #### Returns
+///
+/// The name, short container ID, database size and state of every instance, aligned.
+///
+fn instance_items(instances: &[Instance], sizes: &[Option<u64>]) -> Vec<String> {
+    let width = instances
+        .iter()
+        .map(|instance| format_name(&instance.name).len())
+        .max()
+        .unwrap_or(0);
+
+    instances
+        .iter()
+        .zip(sizes)
+        .map(|(instance, size)| {
+            let size = size.map(format_size).unwrap_or_else(|| "unknown".to_string());
+            let state = if instance.running {
+                "running".green()
+            } else {
+                "stopped (will be started)".dimmed()
+            };
+
+            format!(
+                "{}  {}  {:>10}  {}",
+                format!("{:width$}", format_name(&instance.name)).bright_cyan(),
+                short_id(&instance.container_id).dimmed(),
+                size,
+                state
+            )
+        })
+        .collect()
+}
+
+///
+/// Returns the position of the instance from the configuration, if it was discovered.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances.
+/// * `options`: The options for this run.
+///
+fn preferred_index(instances: &[Instance], options: &Options) -> Option<usize> {
+    options
+        .instance
+        .as_deref()
+        .and_then(|name| resolve_instance(instances, name))
+        .and_then(|preferred| instances.iter().position(|instance| instance.name == preferred.name))
+}
+
+///
+/// Displays the instance selection menu and handles user input.
+///
+/// Uses an arrow-key picker when stdin is a terminal and falls back to numbered input otherwise.
+/// The database size of every running instance is queried concurrently and shown next to its name.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered Ballsdex instances, sorted by name.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The selected instance, `None` if the user cancelled, or an error.
+///
+async fn select_instance<'a>(
+    docker: &Docker,
+    instances: &'a [Instance],
+    options: &Options,
+) -> Result<Option<&'a Instance>, Error> {
+    if instances.is_empty() {
+        return Err(Error::new(ErrorKind::InstanceNotFound, "No Ballsdex instances found."));
+    }
+
+    prompt::require_interactive("ask which instance to use", "--instance <name>")?;
+
+    let sizes = database_sizes(docker, instances, options).await;
+    let items = instance_items(instances, &sizes);
+
+    // The picker draws on stderr, so fall back to numbered input if that is redirected.
+    if io::stderr().is_terminal() {
+        let selection = Select::with_theme(output::theme().as_ref())
+            .with_prompt("Select instance")
+            .items(&items)
+            .max_length(PAGE_SIZE)
+            .default(preferred_index(instances, options).unwrap_or(0))
+            .interact_opt()
+            .map_err(|e| Error::new(ErrorKind::Aborted, format!("Failed to read input: {}", e)))?;
+
+        return Ok(selection.map(|index| &instances[index]));
+    }
+
+    output::info(format!("\n{}", "Detected Ballsdex instances:".bold().yellow()));
+
+    for (i, item) in items.iter().enumerate() {
+        output::info(format!("  {}. {}", i + 1, item));
+    }
+
+    prompt::ask(
+        options,
+        "Select instance (number or name, q to go back):",
+        |answer| match answer.parse::<usize>() {
+            Ok(n) if (1..=instances.len()).contains(&n) => Ok(&instances[n - 1]),
+            _ => match_instance(instances, answer, options),
+        },
+    )
+}
+
+///
+/// Displays the instance selection menu with several instances selectable at once.
+///
+/// In a terminal, space toggles an instance and `a` toggles all of them. Otherwise the numbers or names are typed,
+/// separated by commas, or `all`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered Ballsdex instances, sorted by name.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The selected instances in list order, `None` if the user cancelled, or an error.
+///
+async fn select_instances<'a>(
+    docker: &Docker,
+    instances: &'a [Instance],
+    options: &Options,
+) -> Result<Option<Vec<&'a Instance>>, Error> {
+    if instances.is_empty() {
+        return Err(Error::new(ErrorKind::InstanceNotFound, "No Ballsdex instances found."));
+    }
+
+    prompt::require_interactive("ask which instance to use", "--instance <name>")?;
+
+    let sizes = database_sizes(docker, instances, options).await;
+    let items = instance_items(instances, &sizes);
+
+    // The picker draws on stderr, so fall back to numbered input if that is redirected.
+    if io::stderr().is_terminal() {
+        let preferred = preferred_index(instances, options);
+        let defaults: Vec<bool> = (0..instances.len()).map(|index| Some(index) == preferred).collect();
+
+        loop {
+            let selection = MultiSelect::with_theme(output::theme().as_ref())
+                .with_prompt("Select instances (space to toggle, a for all)")
+                .items(&items)
+                .max_length(PAGE_SIZE)
+                .defaults(&defaults)
+                .interact_opt()
+                .map_err(|e| Error::new(ErrorKind::Aborted, format!("Failed to read input: {}", e)))?;
+
+            match selection {
+                Some(indices) if indices.is_empty() => {
+                    output::info(format!("{} Select at least one instance.", Glyph::Error).red().bold());
+                }
+                Some(indices) => return Ok(Some(indices.into_iter().map(|index| &instances[index]).collect())),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    output::info(format!("\n{}", "Detected Ballsdex instances:".bold().yellow()));
+
+    for (i, item) in items.iter().enumerate() {
+        output::info(format!("  {}. {}", i + 1, item));
+    }
+
+    prompt::ask(
+        options,
+        "Select instances (numbers or names separated by commas, all, q to go back):",
+        |answer| {
+            if answer.eq_ignore_ascii_case("all") || answer.eq_ignore_ascii_case("a") {
+                return Ok(instances.iter().collect());
+            }
+
+            let mut selected: Vec<&Instance> = Vec::new();
+
+            for part in answer.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+                let instance = match part.parse::<usize>() {
+                    Ok(n) if (1..=instances.len()).contains(&n) => &instances[n - 1],
+                    _ => match_instance(instances, part, options)?,
+                };
+
+                if !selected.iter().any(|selected| selected.name == instance.name) {
+                    selected.push(instance);
+                }
+            }
+
+            if selected.is_empty() {
+                return Err("Select at least one instance.".to_string());
+            }
+
+            Ok(selected)
+        },
+    )
+}
+
+///
+/// Starts the export setup process.
+///
+/// Every export except dry runs is recorded in the history, whether it succeeded or not. A container that ndmig
+/// started for the export is stopped again afterwards, unless `--keep-running` was passed.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to export.
+/// * `args`: The export arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The summary of the export, `None` for a dry run, or an error if the export failed.
+///
+async fn export(
+    docker: &Docker,
+    instance: &Instance,
+    args: &ExportArgs,
+    options: &Options,
+) -> Result<Option<Summary>, Error> {
+    // Checked here rather than where the container is started, so it is stopped again whichever way the export ends.
+    // The parts of a split export leave that to the split, which would otherwise start the container twice.
+    let stop = !args.keep_running
+        && args.split_id.is_none()
+        && !container::is_running(docker, &instance.container_id)
+            .await
+            .unwrap_or(true);
+
+    let result = match args.split {
+        true => export_split(docker, instance, args, options).await,
+        false => export_recorded(docker, instance, args, options).await,
+    };
+
+    if stop {
+        match stop_started_container(docker, instance).await {
+            Ok(true) => {
+                output::status(format!("{} Stopped {} again.", Glyph::Ok, format_name(&instance.name)).dimmed())
+            }
+            Ok(false) => {}
+            Err(message) => output::status(format!("{} {}", Glyph::Warning, message).yellow()),
+        }
+    }
+
+    result
+}
+
+///
+/// Stops a container that ndmig started for an export, once the export is over.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance whose container to stop.
+///
+/// #### Returns
+///
+/// Whether the container was stopped, `false` if it was not running (anymore), or a message if stopping it failed.
+/// The export is complete either way.
+///
+async fn stop_started_container(docker: &Docker, instance: &Instance) -> Result<bool, String> {
+    let container_id = instance.container_id.as_str();
+
+    // An export cancelled before it started the container, or aborted with Ctrl+C, leaves nothing to stop.
+    if !container::is_running(docker, container_id).await.unwrap_or(false) {
+        return Ok(false);
+    }
+
+    output::debug(format!("stop_container {}", container_id));
+
+    docker
+        .stop_container(container_id, None)
+        .await
+        .map(|_| true)
+        .map_err(|e| format!("Failed to stop {} again: {}", format_name(&instance.name), e))
+}
+
+///
+/// Runs a single export, records it in the history and prints its summary.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to export.
+/// * `args`: The export arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The summary of the export, `None` for a dry run, or an error if the export failed.
+///
+async fn export_recorded(
+    docker: &Docker,
+    instance: &Instance,
+    args: &ExportArgs,
+    options: &Options,
+) -> Result<Option<Summary>, Error> {
+    let started = Instant::now();
+
+    let summary = match run_export(docker, instance, args, options).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => return Ok(None),
+        Err(error) => {
+            history::record(&history::Entry::failure(
+                OperationKind::Export,
+                instance,
+                &error,
+                started.elapsed(),
+            ));
+            return Err(error);
+        }
+    };
+
+    history::record(&history::Entry::success(&summary));
+    summary.print();
+
+    if summary.warnings > 0 && !output::is_json() && output::level() < output::Level::Verbose {
+        output::status(
+            format!(
+                "{} pg_dump printed {} line(s) to stderr, run with --verbose to see them.",
+                Glyph::Warning,
+                summary.warnings
+            )
+            .yellow(),
+        );
+    }
+
+    Ok(Some(summary))
+}
+
+///
+/// Exports the schema and the rows of an instance into two linked files, for `--split`.
+///
+/// The schema is exported first and carries the bot configuration, Redis snapshot and media of the export, so the
+/// data file only holds the rows.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to export.
+/// * `args`: The export arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The summary of the data export, `None` for a dry run, or an error if either export failed.
+///
+async fn export_split(
+    docker: &Docker,
+    instance: &Instance,
+    args: &ExportArgs,
+    options: &Options,
+) -> Result<Option<Summary>, Error> {
+    let unsupported = if args.format != DumpFormat::Plain {
+        Some(format!("--format {}", args.format.name()))
+    } else if args.output.as_deref() == Some(Path::new("-")) {
+        Some("--output -".to_string())
+    } else if args.output.as_deref().is_some_and(|output| !is_output_dir(output)) {
+        Some("an --output file, pass a directory for the two files".to_string())
+    } else {
+        None
+    };
+
+    if let Some(unsupported) = unsupported {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!("--split does not work with {}", unsupported),
+        ));
+    }
+
+    // Named once, so both parts share the time of the schema export.
+    let template = args.name_template.as_ref().unwrap_or(&options.name_template);
+    let path = dump_path(args.output.as_deref(), template, &instance.name, &instance.container_id);
+
+    let id = split::new_id();
+    let schema_args = ExportArgs {
+        output: Some(with_mode_suffix(path.clone(), "schema")),
+        split: false,
+        split_id: Some(id.clone()),
+        schema_only: true,
+        ..args.clone()
+    };
+    let data_args = ExportArgs {
+        output: Some(with_mode_suffix(path, "data")),
+        split: false,
+        split_id: Some(id.clone()),
+        data_only: true,
+        no_config: true,
+        include_redis: false,
+        include_globals: false,
+        include_media: false,
+        ..args.clone()
+    };
+
+    // Boxed, as the two parts go through export like any other export.
+    let schema = Box::pin(export(docker, instance, &schema_args, options)).await?;
+    let data = Box::pin(export(docker, instance, &data_args, options)).await?;
+
+    if let (Some(schema), Some(data)) = (&schema, &data)
+        && let Err(message) = split::link(&id, schema, data)
+    {
+        output::status(format!("{} {}", Glyph::Warning, message).yellow());
+    }
+
+    Ok(data)
+}
+
+///
+/// Exports several instances, up to `--parallel` of them at once.
+///
+/// Every instance is written to its own file. A failed export does not stop the remaining ones, only an interrupt
+/// does. Once all of them ran, a table with the result of every instance is printed.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The instances to export.
+/// * `args`: The export arguments, shared by every export.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if any of the exports failed.
+///
+async fn export_batch(
+    docker: &Docker,
+    instances: &[&Instance],
+    args: &ExportArgs,
+    options: &Options,
+) -> Result<(), Error> {
+    if let [instance] = instances {
+        return export(docker, instance, args, options).await.map(|_| ());
+    }
+
+    if let Some(output) = &args.output
+        && !is_output_dir(output)
+    {
+        return Err(Error::new(
+            ErrorKind::Filesystem,
+            format!(
+                "{} is a file, the output must be a directory when exporting several instances",
+                output.display()
+            ),
+        ));
+    }
+
+    let parallel = args.parallel.map_or(DEFAULT_PARALLEL, usize::from).min(instances.len());
+    let semaphore = Semaphore::new(parallel);
+    let started = AtomicUsize::new(0);
+
+    progress::set_concurrent(parallel > 1);
+
+    let results = join_all(instances.iter().map(|instance| async {
+        let name = format_name(&instance.name);
+        let _permit = semaphore.acquire().await.expect("The export semaphore is never closed");
+
+        // Exports still waiting for a slot are skipped once the user pressed Ctrl+C.
+        if interrupt::requested() {
+            return (name, Err(Error::new(ErrorKind::Interrupted, "Export cancelled.")));
+        }
+
+        let i = started.fetch_add(1, Ordering::Relaxed) + 1;
+        output::status(format!("\n[{}/{}] {}", i, instances.len(), name).bold());
+
+        let result = export(docker, instance, args, options).await;
+
+        if let Err(error) = &result
+            && error.kind != ErrorKind::Interrupted
+        {
+            output::status(format!("{} {}: {}", Glyph::Error, name, error.message).red().bold());
+        }
+
+        (name, result)
+    }))
+    .await;
+
+    progress::set_concurrent(false);
+
+    if let Some((_, Err(error))) = results
+        .iter()
+        .find(|(_, result)| result.as_ref().is_err_and(|error| error.kind == ErrorKind::Interrupted))
+    {
+        return Err(error.clone());
+    }
+
+    print_batch_results(&results);
+
+    let failed: Vec<(&str, ErrorKind)> = results
+        .iter()
+        .filter_map(|(name, result)| result.as_ref().err().map(|error| (name.as_str(), error.kind)))
+        .collect();
+
+    let succeeded = instances.len() - failed.len();
+    let line = format!("{} of {} export(s) succeeded.", succeeded, instances.len());
+
+    let Some((_, kind)) = failed.first() else {
+        output::status(format!("\n{} {}", Glyph::Ok, line).green().bold());
+        return Ok(());
+    };
+
+    let names: Vec<&str> = failed.iter().map(|(name, _)| *name).collect();
+
+    Err(Error::new(*kind, format!("{} Failed: {}", line, names.join(", "))))
+}
+
+///
+/// Prints the result of every export of a batch as a table.
+///
+/// #### Arguments
+///
+/// * `results`: The short instance name and the result of every export, in the order they ran.
+///
+fn print_batch_results(results: &[(String, Result<Option<Summary>, Error>)]) {
+    if output::is_json() {
+        return;
+    }
+
+    let width = results.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    output::status("");
+
+    for (name, result) in results {
+        let line = match result {
+            Ok(Some(summary)) => format!(
+                "{} {}  {:>10}  {:>6.1}s",
+                Glyph::Ok,
+                format!("{:width$}", name).bright_cyan(),
+                format_size(summary.bytes),
+                Duration::from_millis(summary.duration_ms as u64).as_secs_f64()
+            ),
+            Ok(None) => format!(
+                "{} {}  {}",
+                Glyph::Ok,
+                format!("{:width$}", name).bright_cyan(),
+                "dry run"
+            ),
+            Err(error) => format!(
+                "{} {}  {}",
+                Glyph::Error,
+                format!("{:width$}", name).bright_cyan(),
+                error.message.red()
+            ),
+        };
+
+        output::status(format!("  {}", line));
+    }
+}
+
+///
+/// The files an export writes next to the dump before the dump itself, removed again unless the export completes.
+///
+/// A snapshot without the dump it belongs to would only be mistaken for part of a complete export.
+///
+#[derive(Default)]
+struct Sidecars {
+    paths: Vec<PathBuf>,
+}
+
+impl Sidecars {
+    ///
+    /// Keeps the files for good, once the export is complete.
+    ///
+    fn keep(mut self) {
+        self.paths.clear();
+    }
+}
+
+impl Drop for Sidecars {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+///
+/// Runs an export without printing its result.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to export.
+/// * `args`: The export arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The summary of the export, `None` for a dry run, or an error if the export failed.
+///
+async fn run_export(
+    docker: &Docker,
+    instance: &Instance,
+    args: &ExportArgs,
+    options: &Options,
+) -> Result<Option<Summary>, Error> {
+    // The pg_dump formats cannot hold the resolved names of a player export, which is JSON unless CSV was asked for.
+    let player_args;
+    let args = match (args.player, args.format) {
+        (Some(_), DumpFormat::Plain) => {
+            player_args = ExportArgs {
+                format: DumpFormat::Json,
+                ..args.clone()
+            };
+            &player_args
+        }
+        _ => args,
+    };
+
+    if args.player.is_some() && !matches!(args.format, DumpFormat::Json | DumpFormat::Csv) {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--player writes JSON or CSV, it does not work with --format {}",
+                args.format.name()
+            ),
+        ));
+    }
+
+    args.compress
+        .level(args.compression_level)
+        .map_err(|message| Error::new(ErrorKind::Config, message))?;
+
+    if args.anonymize && args.format != DumpFormat::Plain {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "--anonymize only works with --format plain, the IDs in other formats cannot be rewritten",
+        ));
+    }
+
+    if args.stable && args.format != DumpFormat::Plain {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "--stable only works with --format plain, the other formats cannot be reordered",
+        ));
+    }
+
+    if args.schema_only && args.format == DumpFormat::Json {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "--schema-only does not work with --format json, which only holds rows",
+        ));
+    }
+
+    if args.via_file && matches!(args.format, DumpFormat::Csv | DumpFormat::Json) {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "--via-file only works with --format plain, custom or directory, which pg_dump writes",
+        ));
+    }
+
+    if (args.keep_owner || args.keep_privileges) && matches!(args.format, DumpFormat::Csv | DumpFormat::Json) {
+        let flag = if args.keep_owner {
+            "--keep-owner"
+        } else {
+            "--keep-privileges"
+        };
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} does not work with --format {}, which holds no owners or privileges",
+                flag,
+                args.format.name()
+            ),
+        ));
+    }
+
+    // All of them copy rows out with psql and wrap them in the COPY sections of a plain dump.
+    let row_copy = if args.incremental {
+        Some("--incremental")
+    } else if args.guild_id.is_some() {
+        Some("--guild-id")
+    } else if filter::is_filtered(args) {
+        Some("--since and --min-catches")
+    } else {
+        None
+    };
+
+    // A filtered dump still takes its schema from pg_dump, so the options for pg_dump apply to it.
+    let schema_from_pg_dump = filter::is_filtered(args);
+
+    if let Some(option) = row_copy {
+        let unsupported = if args.format != DumpFormat::Plain {
+            Some(format!("--format {}", args.format.name()))
+        } else if args.via_file {
+            Some("--via-file".to_string())
+        } else if !schema_from_pg_dump && (args.keep_owner || args.keep_privileges) {
+            Some("--keep-owner and --keep-privileges".to_string())
+        } else if !schema_from_pg_dump && !args.pg_dump_arg.is_empty() {
+            Some("--pg-dump-arg".to_string())
+        } else {
+            None
+        };
+
+        if let Some(flag) = unsupported {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} does not work with {}, which copies the rows out with psql instead of running pg_dump",
+                    flag, option
+                ),
+            ));
+        }
+    }
+
+    check_pg_dump_args(args, options)?;
+
+    if args.jobs.is_some() && args.format != DumpFormat::Directory {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "--jobs only works with --format directory, pg_dump cannot write other formats in parallel",
+        ));
+    }
+
+    let encryption = Encryption::from_args(args);
+
+    if args.format == DumpFormat::Directory {
+        let unsupported = if args.compress != Compression::None {
+            Some("--compress")
+        } else if is_stdout(args.output.as_deref()) {
+            Some("--output -")
+        } else if encryption.is_some() {
+            Some("--encrypt")
+        } else {
+            None
+        };
+
+        if let Some(flag) = unsupported {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} does not work with --format directory, which writes a directory of compressed files",
+                    flag
+                ),
+            ));
+        }
+    }
+
+    if args.format == DumpFormat::Csv {
+        let unsupported = if args.compress != Compression::None {
+            Some("--compress")
+        } else if args.schema_only {
+            Some("--schema-only")
+        } else if is_stdout(args.output.as_deref()) {
+            Some("--output -")
+        } else if encryption.is_some() {
+            Some("--encrypt")
+        } else {
+            None
+        };
+
+        if let Some(flag) = unsupported {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} does not work with --format csv, which writes a directory of CSV files",
+                    flag
+                ),
+            ));
+        }
+    }
+
+    if args.bundle {
+        let unsupported = if is_stdout(args.output.as_deref()) {
+            Some("--output -")
+        } else if matches!(args.format, DumpFormat::Csv | DumpFormat::Directory) {
+            Some("--format csv and --format directory")
+        } else {
+            None
+        };
+
+        if let Some(flag) = unsupported {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "--bundle does not work with {}, a bundle holds a single dump file",
+                    flag
+                ),
+            ));
+        }
+    }
+
+    if let Some(table) = args.tables.iter().find(|table| args.exclude_tables.contains(table)) {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!("Table '{}' is both in --tables and --exclude-tables", table),
+        ));
+    }
+
+    let to_stdout = is_stdout(args.output.as_deref());
+
+    let sidecar_flag = if args.include_redis {
+        Some("--include-redis")
+    } else if args.include_globals {
+        Some("--include-globals")
+    } else if args.include_media {
+        Some("--include-media")
+    } else {
+        None
+    };
+
+    if to_stdout && let Some(flag) = sidecar_flag {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} does not work with --output -, as it writes a file next to the dump",
+                flag
+            ),
+        ));
+    }
+
+    if to_stdout && !options.dry_run && io::stdout().is_terminal() {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "Refusing to write the dump to a terminal, pipe or redirect stdout when using --output -",
+        ));
+    }
+
+    let dump_path = if to_stdout {
+        PathBuf::from("-")
+    } else {
+        let template = args.name_template.as_ref().unwrap_or(&options.name_template);
+        let mut dump_path = dump_path(args.output.as_deref(), template, &instance.name, &instance.container_id);
+
+        // An explicit --output file is used as is, only names from the template get the mode.
+        if export_mode(args) != "full" && args.output.as_deref().is_none_or(is_output_dir) {
+            dump_path = with_mode_suffix(dump_path, export_mode(args));
+        }
+
+        let dump_path = args.compress.apply_extension(args.format.apply_extension(dump_path));
+
+        match encryption {
+            Some(_) => encrypt::apply_extension(dump_path),
+            None => dump_path,
+        }
+    };
+
+    // With --bundle the dump and its files are written into one archive instead, under their usual names.
+    let target = match args.bundle {
+        true => bundle::path(&dump_path),
+        false => dump_path.clone(),
+    };
+
+    // Looked up before anything runs, as an export with nothing to build on cannot be incremental.
+    let base = match args.incremental {
+        true => {
+            let directory = (!to_stdout).then(|| dump_path.parent()).flatten();
+            Some(incremental::find_base(&instance.name, directory, options)?)
+        }
+        false => None,
+    };
+
+    let database = container::database(docker, &instance.container_id, options).await;
+
+    if options.dry_run {
+        let mut actions = plan_export(instance, &dump_path, args, base.as_ref(), &database, options);
+
+        // A stopped container cannot report its size without being started, which a dry run never does.
+        if !to_stdout
+            && instance.running
+            && let Some(bytes) = database_size(docker, &instance.container_id, &database).await
+            && let Some(estimate) = space::estimate(bytes, args, &dump_path)
+        {
+            let verdict = if estimate.fits() { "fits" } else { "does not fit" };
+            actions.insert(
+                0,
+                format!("check the free space: {}, so it {}", estimate.describe(), verdict),
+            );
+        }
+
+        print_dry_run(instance, &target, &actions);
+        return Ok(None);
+    }
+
+    if database.default_user {
+        output::status(
+            format!(
+                "{} {} does not set POSTGRES_USER, connecting as {}. Pass --db-user to use another role.",
+                Glyph::Warning,
+                format_name(&instance.name),
+                database.user
+            )
+            .yellow(),
+        );
+    }
+
+    let source = instance;
+    let container_id = instance.container_id.as_str();
+    let running = instance.running;
+    let instance = instance.name.as_str();
+
+    if !to_stdout
+        && let Some(parent) = dump_path.parent()
+        && let Err(e) = std::fs::create_dir_all(parent)
+    {
+        return Err(Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to create {}: {}", parent.display(), e),
+        ));
+    }
+
+    if !to_stdout
+        && target.exists()
+        && !args.force
+        && !confirm(options, &format!("{} already exists. Overwrite?", target.display()))?
+    {
+        return Err(Error::new(ErrorKind::Aborted, "Export cancelled."));
+    }
+
+    // age asks for the passphrase on the terminal, which only works when someone is there to type it.
+    if encryption == Some(Encryption::Passphrase) {
+        prompt::require_interactive(
+            "ask for the encryption passphrase",
+            "--encrypt-recipient or --encrypt-key-file",
+        )?;
+    }
+
+    let mut started_container = false;
+
+    if !running {
+        let question = match args.keep_running {
+            true => format!("{} is stopped. Start it?", format_name(instance)),
+            false => format!(
+                "{} is stopped. Start it temporarily? It is stopped again after the export.",
+                format_name(instance)
+            ),
+        };
+
+        if !confirm(options, &question)? {
+            return Err(Error::new(ErrorKind::Aborted, "Export cancelled."));
+        }
+
+        output::debug(format!("start_container {}", container_id));
+
+        if let Err(e) = docker.start_container(container_id, None).await {
+            return Err(Error::new(
+                ErrorKind::DumpFailed,
+                format!("Failed to start container: {}", e),
+            ));
+        }
+
+        started_container = true;
+
+        let spinner = progress::spinner("Waiting for the database to start...");
+        let ready = container::wait_until_ready(docker, container_id, instance, &database).await;
+
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+
+        ready?;
+    }
+
+    check_tables(docker, container_id, args, &database).await?;
+
+    // Without a size the export runs as before, just without the estimate. The size of the database says nothing
+    // about that of a delta.
+    let database_bytes =
+        match args.incremental || args.guild_id.is_some() || args.player.is_some() || filter::is_filtered(args) {
+            true => None,
+            false => database_size(docker, container_id, &database).await,
+        };
+
+    if let Some(bytes) = database_bytes {
+        output::status(progress::estimate(bytes, args.format == DumpFormat::Plain).dimmed());
+    }
+
+    if !to_stdout {
+        check_space(database_bytes, args, &dump_path)?;
+    }
+
+    // Saved before the dump starts rather than after, so a missing or failing Redis leaves no orphaned dump behind.
+    // If anything fails from here on, the files are removed again.
+    let mut sidecars = Sidecars::default();
+
+    let redis_bytes = if args.include_redis {
+        let redis = redis::find(docker, instance).await?;
+        let bytes = redis::export(docker, &redis, &dump_path).await?;
+        sidecars.paths.push(redis::path(&dump_path));
+        Some(bytes)
+    } else {
+        None
+    };
+
+    let globals_bytes = if args.include_globals {
+        let bytes = globals::export(docker, container_id, &database, &dump_path).await?;
+        sidecars.paths.push(globals::path(&dump_path));
+        Some(bytes)
+    } else {
+        None
+    };
+
+    let media = if args.include_media {
+        let media = media::export(docker, instance, &dump_path).await?;
+        sidecars.paths.push(media::path(&dump_path));
+        Some(media)
+    } else {
+        None
+    };
+
+    // Counted before the dump starts, so the manifest describes the same state as the dump.
+    let row_counts = container::row_counts(docker, container_id, &database, &container::CORE_TABLES).await;
+    print_row_counts(instance, row_counts.as_ref());
+    let row_counts = row_counts.unwrap_or_default();
+
+    // Read before the dump starts too. Rows added while pg_dump runs end up in both the dump and the next delta, but
+    // a delta reads them in the same pass, so the deltas of a chain never overlap.
+    let high_water_marks = match incremental::records_marks(args) {
+        true => incremental::high_water_marks(docker, container_id, &database, args).await,
+        false => None,
+    };
+
+    let delta_args;
+    let args = match base {
+        Some(base) => {
+            let marks = high_water_marks.clone().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::DumpFailed,
+                    "Failed to read the highest primary keys for --incremental.",
+                )
+            })?;
+
+            delta_args = ExportArgs {
+                delta: Some(Delta { base, marks }),
+                ..args.clone()
+            };
+            &delta_args
+        }
+        None => args,
+    };
+
+    output::status(
+        format!("{} Exporting {}...", Glyph::Pending, format_name(instance))
+            .yellow()
+            .bold(),
+    );
+
+    let started = Instant::now();
+    let progress = Progress::new(database_bytes).with_label(&format_name(instance));
+    let interrupt = interrupt::Scope::enter();
+
+    let write = async {
+        if to_stdout {
+            write_dump_to_stdout(docker, source, &progress, args, &database)
+                .await
+                .map(|(dump, sha256, bytes)| (dump, Some(sha256), Some(bytes)))
+        } else if let Some(discord_id) = args.player
+            && args.format == DumpFormat::Csv
+        {
+            player::export_csv(docker, container_id, &dump_path, discord_id, &database)
+                .await
+                .map(|dump| (dump, None, None))
+        } else if args.format == DumpFormat::Csv {
+            csv::export(
+                docker,
+                container_id,
+                &format_name(instance),
+                &dump_path,
+                &progress,
+                args,
+                &database,
+            )
+            .await
+            .map(|dump| (dump, None, None))
+        } else if args.format == DumpFormat::Directory {
+            directory::export(docker, container_id, &dump_path, &progress, args, &database)
+                .await
+                .map(|dump| (dump, None, None))
+        } else if args.bundle {
+            bundle::write_dump(docker, source, &target, &dump_path, &progress, args, &database)
+                .await
+                .map(|(dump, sha256, bytes)| (dump, Some(sha256), Some(bytes)))
+        } else {
+            write_dump(docker, source, &dump_path, &progress, args, &database)
+                .await
+                .map(|(dump, sha256)| (dump, Some(sha256), None))
+        }
+    };
+
+    let (dump, sha256, written_bytes) = tokio::select! {
+        written = write => written?,
+        _ = interrupt.interrupted() => {
+            progress.finish();
+
+            let staged = args.via_file || args.format == DumpFormat::Directory;
+            let stop = started_container && !args.keep_running;
+            return Err(abort_export(docker, container_id, &partial_path(&target), staged, stop).await);
+        }
+    };
+
+    drop(interrupt);
+
+    let verified = if args.no_verify || to_stdout {
+        false
+    } else {
+        output::status(format!("{} Verifying {}...", Glyph::Pending, dump_path.display()).yellow());
+
+        let expected = verify::expected_tables(args, &row_counts);
+        verify::run(docker, container_id, &dump_path, args, &expected).await?
+    };
+
+    let mut summary = export_summary(instance, container_id, dump_path, args, &database, &dump, sha256);
+    summary.duration_ms = started.elapsed().as_millis();
+    summary.row_counts = row_counts;
+    summary.high_water_marks = high_water_marks;
+    summary.redis_bytes = redis_bytes;
+    summary.globals_bytes = globals_bytes;
+    summary.media = media;
+    summary.verified = verified;
+    describe_source(docker, container_id, &database, &mut summary).await;
+
+    // The configuration holds the bot token, which has no place in the data of a single user.
+    if !args.no_config && !to_stdout && args.player.is_none() {
+        let source = bot_config_source(args, options);
+
+        match bot::export_config(docker, instance, source, &summary.dump_path).await {
+            Ok(_) => summary.bot_config = true,
+            Err(message) => output::status(format!("{} {}", Glyph::Warning, message).yellow()),
+        }
+    }
+
+    // The dump inside a bundle and on stdout has no file of its own to take the size from.
+    if let Some(bytes) = written_bytes {
+        summary.bytes = bytes;
+    }
+
+    // A dump on stdout has no file to put the checksum and the manifest next to.
+    if !to_stdout && let Err(message) = write_sidecars(&summary) {
+        output::status(format!("{} {}", Glyph::Warning, message).yellow());
+    }
+
+    if args.bundle {
+        bundle::finish(&target, &summary.dump_path)?;
+        summary.dump_path = target;
+    }
+
+    sidecars.keep();
+    Ok(Some(summary))
+}
+
+///
+/// Checks the extra `pg_dump` arguments of `--pg-dump-arg` and the `pg_dump_args` setting.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+/// * `options`: The options for this run, for the path of the configuration file.
+///
+/// #### Returns
+///
+/// Nothing, or an error naming the argument and the ndmig option it conflicts with.
+///
+fn check_pg_dump_args(args: &ExportArgs, options: &Options) -> Result<(), Error> {
+    let config_source = match &options.config_file {
+        Some(path) => format!("pg_dump_args in {}", path.display()),
+        None => "pg_dump_args".to_string(),
+    };
+
+    // The setting applies to every export, so it is only refused for CSV and JSON when given on the command line.
+    if !args.pg_dump_arg.is_empty() && matches!(args.format, DumpFormat::Csv | DumpFormat::Json) {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--pg-dump-arg does not work with --format {}, which does not use pg_dump",
+                args.format.name()
+            ),
+        ));
+    }
+
+    let extra = args
+        .config_pg_dump_args
+        .iter()
+        .map(|arg| (arg, config_source.as_str()))
+        .chain(args.pg_dump_arg.iter().map(|arg| (arg, "--pg-dump-arg")));
+
+    for (arg, source) in extra {
+        if let Some(option) = container::managed_pg_dump_option(arg) {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "'{}' from {} conflicts with {}, which sets that pg_dump flag. Use {} instead.",
+                    arg, source, option, option
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// Refuses to start an export whose dump is not expected to fit on the destination.
+///
+/// #### Arguments
+///
+/// * `database_bytes`: The size of the database, `None` if it could not be queried.
+/// * `args`: The export arguments.
+/// * `dump_path`: Where the dump is written.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the dump does not fit and `--force` was not passed. Without a size or free space to
+/// compare, the export goes ahead.
+///
+fn check_space(database_bytes: Option<u64>, args: &ExportArgs, dump_path: &Path) -> Result<(), Error> {
+    let Some(estimate) = database_bytes.and_then(|bytes| space::estimate(bytes, args, dump_path)) else {
+        output::debug("Could not estimate the dump size, skipping the free space check");
+        return Ok(());
+    };
+
+    output::debug(format!("Free space: {}", estimate.describe()));
+
+    if estimate.fits() {
+        return Ok(());
+    }
+
+    if args.force {
+        output::status(
+            format!(
+                "{} The dump may not fit: {}. Continuing because of --force.",
+                Glyph::Warning,
+                estimate.describe()
+            )
+            .yellow(),
+        );
+        return Ok(());
+    }
+
+    Err(Error::new(
+        ErrorKind::Filesystem,
+        format!(
+            "Not enough free space for the dump: {}. Free up space, export elsewhere with --output, or pass --force to try anyway.",
+            estimate.describe()
+        ),
+    ))
+}
+
+///
+/// Returns the path of `config.yml` inside the bot container.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// `--bot-config`, the `bot_config` setting, or the path of the Ballsdex image.
+///
+fn bot_config_source<'a>(args: &'a ExportArgs, options: &'a Options) -> &'a str {
+    args.bot_config
+        .as_deref()
+        .or(options.bot_config.as_deref())
+        .unwrap_or(bot::DEFAULT_CONFIG_PATH)
+}
+
+///
+/// Prints the number of rows of the core Ballsdex tables before an export.
+///
+/// #### Arguments
+///
+/// * `instance`: The full container name of the instance.
+/// * `counts`: The counts, or `None` if they could not be queried. Tables missing from the counts are shown as `n/a`.
+///
+fn print_row_counts(instance: &str, counts: Option<&BTreeMap<String, u64>>) {
+    if output::is_json() {
+        return;
+    }
+
+    let Some(counts) = counts else {
+        output::status(
+            format!(
+                "{} Could not count the rows of {}, continuing anyway.",
+                Glyph::Warning,
+                format_name(instance)
+            )
+            .yellow(),
+        );
+        return;
+    };
+
+    // Built as one block so the tables of parallel exports do not interleave.
+    let mut table = format!("{}", format!("Rows in {}", format_name(instance)).bold());
+
+    for name in container::CORE_TABLES {
+        let count = match counts.get(name) {
+            Some(count) => format!("{:>10}", count).normal(),
+            None => format!("{:>10}", "n/a").dimmed(),
+        };
+
+        table.push_str(&format!(
+            "\n  {} {:14}{}",
+            Glyph::Arrow.to_string().bright_yellow(),
+            name,
+            count
+        ));
+    }
+
+    output::status(table);
+}
+
+///
+/// Builds the summary of a finished export.
+///
+/// #### Arguments
+///
+/// * `instance`: The full container name of the exported instance.
+/// * `container_id`: The container ID of the instance.
+/// * `dump_path`: The path of the dump.
+/// * `args`: The export arguments.
+/// * `database`: The role and database that were dumped.
+/// * `dump`: The dump, as returned by [`write_dump`].
+/// * `sha256`: The SHA-256 of the dump, `None` for a directory of CSV files.
+///
+/// #### Returns
+///
+/// The summary, without the duration and the details of the source.
+///
+fn export_summary(
+    instance: &str,
+    container_id: &str,
+    dump_path: PathBuf,
+    args: &ExportArgs,
+    database: &Database,
+    dump: &DatabaseDump,
+    sha256: Option<String>,
+) -> Summary {
+    let mut summary = Summary::new(
+        OperationKind::Export,
+        format_name(instance),
+        container_id.to_string(),
+        dump_path,
+    );
+    // The files of a CSV export are not compressed, so their total is the size on disk.
+    summary.bytes = match args.format {
+        DumpFormat::Csv | DumpFormat::Directory => dump.bytes,
+        _ => std::fs::metadata(&summary.dump_path).map_or(dump.bytes, |metadata| metadata.len()),
+    };
+    summary.uncompressed_bytes = dump.bytes;
+    summary.compression = args.compress;
+    summary.format = args.format;
+    summary.mode = export_mode(args);
+    // pg_dump ignores --no-owner for archives, it is up to pg_restore to leave the owners out, as the import does.
+    summary.owner = args.keep_owner || matches!(args.format, DumpFormat::Custom | DumpFormat::Directory);
+    summary.privileges = args.keep_privileges;
+    summary.anonymized = args.anonymize;
+    summary.stable = args.stable;
+    summary.encryption = Encryption::from_args(args).map(|encryption| encryption.name());
+    summary.jobs = args.jobs;
+    if matches!(
+        args.format,
+        DumpFormat::Plain | DumpFormat::Custom | DumpFormat::Directory
+    ) && !args.incremental
+        && args.guild_id.is_none()
+    {
+        summary.pg_dump_args = container::pg_dump_command(database, args);
+    }
+    summary.guild_id = args.guild_id;
+    summary.player = args.player;
+    summary.filters = Some(filter::expressions(args)).filter(|filters| !filters.is_empty());
+    summary.base = args.delta.as_ref().map(|delta| delta.base.path.clone());
+    summary.base_sha256 = args.delta.as_ref().map(|delta| delta.base.sha256.clone());
+    summary.tables = args.tables.clone();
+    summary.excluded_tables = args.exclude_tables.clone();
+    summary.checksum = sha256;
+    summary.warnings = dump.warnings.len();
+    summary
+}
+
+///
+/// Adds the image, the postgres version and the pg_dump version of the exported container to a summary.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the instance.
+/// * `database`: The role and database to connect with.
+/// * `summary`: The summary to fill in. Details that cannot be read are left empty.
+///
+async fn describe_source(docker: &Docker, container_id: &str, database: &Database, summary: &mut Summary) {
+    summary.image = container::image(docker, container_id).await;
+    summary.server_version = container::query(docker, container_id, database, "SHOW server_version").await;
+
+    // The dumps psql copies the rows out for were not written by pg_dump.
+    if !summary.pg_dump_args.is_empty() {
+        summary.pg_dump_version = postgres::tool_version(docker, container_id, "pg_dump")
+            .await
+            .map(|version| version.to_string());
+    }
+}
+
+///
+/// Checks that the tables passed to `--tables` and `--exclude-tables` exist, so a typo fails instead of producing an
+/// empty or unexpectedly large dump.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to connect with.
+///
+/// #### Returns
+///
+/// Nothing, or an error naming the first missing table and the available ones.
+///
+async fn check_tables(
+    docker: &Docker,
+    container_id: &str,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<(), Error> {
+    if args.tables.is_empty() && args.exclude_tables.is_empty() {
+        return Ok(());
+    }
+
+    let Some(available) = container::tables(docker, container_id, database).await else {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            "Failed to list the tables of the database.",
+        ));
+    };
+
+    let mut tables = args.tables.iter().chain(&args.exclude_tables);
+
+    if let Some(missing) = tables.find(|table| !available.contains(table)) {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            format!(
+                "Table '{}' not found, available tables are {}",
+                missing,
+                available.join(", ")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+///
+/// Lists what an export would do, for `--dry-run`.
+///
+/// #### Arguments
+///
+/// * `instance`: The instance to export.
+/// * `dump_path`: Where the dump would be written.
+/// * `args`: The export arguments.
+/// * `database`: The role and database that would be dumped.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The actions in the order they would happen, each completing "would ...".
+///
+fn plan_export(
+    instance: &Instance,
+    dump_path: &Path,
+    args: &ExportArgs,
+    base: Option<&Base>,
+    database: &Database,
+    options: &Options,
+) -> Vec<String> {
+    let mut actions = Vec::new();
+    let container_id = short_id(&instance.container_id);
+    let to_stdout = is_stdout(Some(dump_path));
+    let target = match args.bundle {
+        true => bundle::path(dump_path),
+        false => dump_path.to_path_buf(),
+    };
+
+    if !to_stdout
+        && let Some(parent) = dump_path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        actions.push(format!("create the directory {}", parent.display()));
+    }
+
+    if !to_stdout && target.exists() {
+        let confirmation = if args.force || options.assume_yes {
+            ""
+        } else {
+            " after confirmation"
+        };
+        actions.push(format!("overwrite {}{}", target.display(), confirmation));
+    }
+
+    if !instance.running {
+        actions.push(format!(
+            "start the stopped container {} ({}){}",
+            instance.name,
+            container_id,
+            match args.keep_running {
+                true => "",
+                false => " and stop it again afterwards",
+            }
+        ));
+    }
+
+    if let Some(base) = base {
+        actions.push(format!(
+            "run `\\copy (SELECT ... WHERE <key> > <mark>) TO STDOUT` in container {} for every table with rows added since {}",
+            container_id,
+            base.path.display()
+        ));
+    } else if let Some(guild_id) = args.guild_id {
+        actions.push(format!(
+            "run psql in container {} to copy the rows of guild {} and the rows they reference, in one transaction",
+            container_id, guild_id
+        ));
+    } else if filter::is_filtered(args) {
+        actions.push(format!(
+            "run pg_dump for the schema and psql for the rows in container {}, keeping {} and the rows that depend on them",
+            container_id,
+            filter::expressions(args)
+                .iter()
+                .map(|(table, expression)| format!("{} WHERE {}", table, expression))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        ));
+    } else if let Some(discord_id) = args.player {
+        actions.push(format!(
+            "run one read-only query in container {} for everything stored about Discord user {}",
+            container_id, discord_id
+        ));
+    } else if args.format == DumpFormat::Csv {
+        actions.push(format!(
+            "run `{}` in container {} for every table",
+            csv::copy_command(database, "<table>").join(" "),
+            container_id
+        ));
+    } else if args.format == DumpFormat::Json {
+        actions.push(format!(
+            "run `{}` in container {} for every table",
+            json::select_command(database, "<table>", &[]).join(" "),
+            container_id
+        ));
+    } else if args.format == DumpFormat::Directory {
+        actions.push(format!(
+            "run `{} -f {}` in container {}, then copy the directory out and remove it",
+            container::pg_dump_command(database, args).join(" "),
+            container::staging_path(),
+            container_id
+        ));
+    } else if args.via_file {
+        actions.push(format!(
+            "run `{} -f <file>` detached in container {} and copy the file out",
+            container::pg_dump_command(database, args).join(" "),
+            container_id
+        ));
+    } else {
+        actions.push(format!(
+            "run `{}` in container {}",
+            container::pg_dump_command(database, args).join(" "),
+            container_id
+        ));
+    }
+
+    if args.stable {
+        actions.push("sort the rows of every table by primary key and drop the version comments".to_string());
+    }
+
+    if args.anonymize {
+        actions.push("replace the Discord IDs in the dump with pseudonyms".to_string());
+    }
+
+    if args.compress != Compression::None {
+        actions.push(format!("compress the dump with {}", args.compress.name()));
+    }
+
+    if let Some(encryption) = Encryption::from_args(args) {
+        actions.push(format!("encrypt the dump with {}", encryption.name()));
+    }
+
+    if !args.no_config && !to_stdout && args.player.is_none() {
+        actions.push(format!(
+            "copy {} from {}-bot-1 to {}",
+            bot_config_source(args, options),
+            container::project(&instance.name),
+            bot::config_path(dump_path).display()
+        ));
+    }
+
+    if args.include_redis {
+        actions.push(format!(
+            "save a Redis snapshot with BGSAVE in {}-redis-1 and copy it to {}",
+            container::project(&instance.name),
+            redis::path(dump_path).display()
+        ));
+    }
+
+    if args.include_globals {
+        actions.push(format!(
+            "save the roles with pg_dumpall --globals-only in container {}, without the superusers, to {}",
+            container_id,
+            globals::path(dump_path).display()
+        ));
+    }
+
+    if args.include_media {
+        actions.push(format!(
+            "copy the media directory of {0}-admin-panel-1 or {0}-bot-1 to {1}",
+            container::project(&instance.name),
+            media::path(dump_path).display()
+        ));
+    }
+
+    if to_stdout {
+        actions.push("write the dump to stdout".to_string());
+    } else if args.player.is_some() && args.format == DumpFormat::Csv {
+        actions.push(format!("write one CSV file per section to {}", dump_path.display()));
+    } else if args.format == DumpFormat::Csv {
+        actions.push(format!(
+            "write one CSV file per table and {} to {}",
+            csv::INDEX,
+            dump_path.display()
+        ));
+    } else if args.bundle {
+        actions.push(format!(
+            "write the dump into {}, followed by the files above, and remove their loose copies",
+            target.display()
+        ));
+    } else {
+        actions.push(format!("write the dump to {}", dump_path.display()));
+    }
+
+    if !args.no_verify
+        && !to_stdout
+        && Encryption::from_args(args).is_none()
+        && (args.format == DumpFormat::Plain || args.format == DumpFormat::Custom && !args.via_file)
+        && !args.incremental
+        && args.guild_id.is_none()
+    {
+        actions.push("read the dump back to verify that it is complete".to_string());
+    }
+
+    actions
+}
+
+///
+/// The JSON document printed by `--dry-run`.
+///
+#[derive(Serialize)]
+struct DryRunReport<'a> {
+    instance: String,
+    container_id: &'a str,
+    dump_path: &'a Path,
+    actions: &'a [String],
+    status: &'static str,
+}
+
+///
+/// Prints the actions of a dry run.
+///
+/// #### Arguments
+///
+/// * `instance`: The instance the actions apply to.
+/// * `dump_path`: Where the dump would be written.
+/// * `actions`: The actions, as returned by [`plan_export`].
+///
+fn print_dry_run(instance: &Instance, dump_path: &Path, actions: &[String]) {
+    if output::is_json() {
+        output::json(&DryRunReport {
+            instance: format_name(&instance.name),
+            container_id: &instance.container_id,
+            dump_path,
+            actions,
+            status: "dry_run",
+        });
+        return;
+    }
+
+    for action in actions {
+        output::info(format!("{} would {}", "DRY RUN".magenta().bold(), action));
+    }
+}
+
+///
+/// Runs `pg_dump` in a running container and streams the dump to disk as it arrives.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to dump.
+/// * `dump_path`: Where to write the dump.
+/// * `progress`: Reports the received bytes.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to dump.
+///
+/// #### Returns
+///
+/// The dump that was written and the SHA-256 of the file on disk, or an error.
+///
+async fn write_dump(
+    docker: &Docker,
+    instance: &Instance,
+    dump_path: &Path,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<(DatabaseDump, String), Error> {
+    let partial_path = partial_path(dump_path);
+    let write_error = |e: io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", dump_path.display(), e),
+        )
+    };
+
+    // Write next to the destination first so an interrupted write never looks like a finished dump.
+    let file = tokio::fs::File::create(&partial_path).await.map_err(write_error)?;
+    // Hashed in front of the buffer, so the checksum covers exactly the bytes that end up on disk.
+    let mut writer = HashingWriter::new(tokio::io::BufWriter::new(file));
+
+    let dump = match stream_encrypted(docker, instance, progress, args, database, &mut writer).await {
+        Ok(dump) => dump,
+        Err(error) => {
+            drop(writer);
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(error);
+        }
+    };
+
+    let (writer, sha256) = writer.finish();
+    writer.into_inner().sync_all().await.map_err(write_error)?;
+    tokio::fs::rename(&partial_path, dump_path).await.map_err(write_error)?;
+
+    Ok((dump, sha256))
+}
+
+///
+/// Runs `pg_dump` in a running container and streams the dump to stdout, for `--output -`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to dump.
+/// * `progress`: Reports the received bytes.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to dump.
+///
+/// #### Returns
+///
+/// The dump that was written, the SHA-256 of the bytes on stdout and their number, or an error.
+///
+async fn write_dump_to_stdout(
+    docker: &Docker,
+    instance: &Instance,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+) -> Result<(DatabaseDump, String, u64), Error> {
+    let mut writer = HashingWriter::new(tokio::io::stdout());
+
+    let dump = stream_encrypted(docker, instance, progress, args, database, &mut writer).await?;
+
+    writer
+        .flush()
+        .await
+        .map_err(|e| Error::new(ErrorKind::Filesystem, format!("Failed to write to stdout: {}", e)))?;
+
+    let bytes = writer.bytes();
+    let (_, sha256) = writer.finish();
+
+    Ok((dump, sha256, bytes))
+}
+
+///
+/// Writes the dump to a writer, through `age` if it is encrypted.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to dump.
+/// * `progress`: Reports the received bytes.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to dump.
+/// * `writer`: Where the dump, or its ciphertext, is written.
+///
+/// #### Returns
+///
+/// The dump that was written, or an error.
+///
+async fn stream_encrypted(
+    docker: &Docker,
+    instance: &Instance,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let Some(encryption) = Encryption::from_args(args) else {
+        return stream_dump(docker, instance, progress, args, database, writer).await;
+    };
+
+    let mut age = encryption.spawn()?;
+    let mut stdin = age.stdin.take().expect("age stdin is piped");
+    let mut stdout = age.stdout.take().expect("age stdout is piped");
+
+    let (dump, copied) = tokio::join!(
+        async {
+            let dump = stream_dump(docker, instance, progress, args, database, &mut stdin).await;
+            // Closing stdin tells age the plaintext ended, so it writes the last block.
+            drop(stdin);
+            dump
+        },
+        tokio::io::copy(&mut stdout, writer)
+    );
+
+    let dump = dump?;
+    let age_error = |message: String| Error::new(ErrorKind::DumpFailed, format!("Encryption failed: {}", message));
+
+    copied.map_err(|e| age_error(e.to_string()))?;
+
+    let status = age.wait().await.map_err(|e| age_error(e.to_string()))?;
+    if !status.success() {
+        return Err(age_error(format!("age exited with {}", status)));
+    }
+
+    Ok(dump)
+}
+
+///
+/// Runs `pg_dump` in a running container and writes the dump, compressed if requested, to a writer.
+///
+/// The JSON format runs `psql` once per table instead of `pg_dump`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to dump.
+/// * `progress`: Reports the received bytes.
+/// * `args`: The export arguments.
+/// * `database`: The role and database to dump.
+/// * `writer`: Where the dump is written.
+///
+/// #### Returns
+///
+/// The dump that was written, or an error.
+///
+async fn stream_dump(
+    docker: &Docker,
+    instance: &Instance,
+    progress: &Progress,
+    args: &ExportArgs,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let level = args
+        .compress
+        .level(args.compression_level)
+        .map_err(|message| Error::new(ErrorKind::Config, message))?;
+
+    let mut sink: Box<dyn AsyncWrite + Unpin + Send + '_> = match args.compress {
+        Compression::None => Box::new(writer),
+        Compression::Gzip => Box::new(GzipEncoder::with_quality(writer, level)),
+        Compression::Zstd => Box::new(ZstdEncoder::with_quality(writer, level)),
+    };
+
+    // In front of the encoder, as the COPY sections can only be rewritten before they are compressed.
+    if args.stable {
+        let primary_keys = container::primary_keys(docker, &instance.container_id, database)
+            .await
+            .ok_or_else(|| Error::new(ErrorKind::DumpFailed, "Failed to read the primary keys for --stable."))?;
+        sink = Box::new(Stabilizer::new(sink, primary_keys));
+    }
+
+    // The pseudonyms are written before the rows are sorted, which keeps the order of --stable independent of them.
+    if args.anonymize {
+        sink = Box::new(Anonymizer::new(sink).map_err(|message| Error::new(ErrorKind::DumpFailed, message))?);
+    }
+
+    let container_id = instance.container_id.as_str();
+
+    if let Some(id) = &args.split_id
+        && args.data_only
+    {
+        sink.write_all(split::guard(id).as_bytes())
+            .await
+            .map_err(|e| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e)))?;
+    }
+
+    let result = if let Some(delta) = &args.delta {
+        incremental::export(docker, container_id, progress, delta, database, &mut sink).await
+    } else if let Some(guild_id) = args.guild_id {
+        guild::export(docker, container_id, progress, guild_id, database, &mut sink).await
+    } else if let Some(discord_id) = args.player {
+        let name = format_name(&instance.name);
+        player::export(docker, container_id, &name, discord_id, database, &mut sink).await
+    } else if filter::is_filtered(args) {
+        filter::export(docker, container_id, progress, args, database, &mut sink).await
+    } else {
+        match args.format {
+            DumpFormat::Json => {
+                let name = format_name(&instance.name);
+                json::export(docker, container_id, &name, progress, args, database, &mut sink).await
+            }
+            _ => {
+                let cmd = container::pg_dump_command(database, args);
+
+                match args.via_file {
+                    true => {
+                        container::create_database_dump_via_file(
+                            docker,
+                            container_id,
+                            cmd,
+                            database.env(),
+                            progress,
+                            &mut sink,
+                        )
+                        .await
+                    }
+                    false => create_database_dump(docker, container_id, cmd, database.env(), progress, &mut sink).await,
+                }
+            }
+        }
+    };
+    progress.finish();
+
+    // The marker goes after the trailer of pg_dump, so it is only left behind once the whole schema was applied.
+    let result = match (&args.split_id, result) {
+        (Some(id), Ok(dump)) => {
+            let statement = match args.schema_only {
+                true => split::marker(id),
+                false => split::release(),
+            };
+
+            sink.write_all(format!("\n{}", statement).as_bytes())
+                .await
+                .map(|_| dump)
+                .map_err(|e| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e)))
+        }
+        (_, result) => result,
+    };
+
+    finish_stream(result, &mut sink).await
+}
+
+///
+/// Writes the checksum file and the manifest next to a finished dump.
+///
+/// #### Arguments
+///
+/// * `summary`: The summary of the export.
+///
+/// #### Returns
+///
+/// Nothing, or a message if a file could not be written. The dump itself is still usable then.
+///
+fn write_sidecars(summary: &Summary) -> Result<(), String> {
+    let dump_path = &summary.dump_path;
+
+    if let Some(sha256) = &summary.checksum {
+        checksum::write(dump_path, sha256)?;
+        output::debug(format!("Wrote {}", checksum::path(dump_path).display()));
+    }
+
+    manifest::write(dump_path, &Manifest::new(summary))?;
+    output::debug(format!("Wrote {}", manifest::path(dump_path).display()));
+
+    Ok(())
+}
+
+///
+/// Writes the end of the stream once the dump succeeded, such as the trailer of a compressed dump or the last line
+/// held back by the anonymizer.
+///
+/// #### Arguments
+///
+/// * `result`: The result of writing the dump into the stream.
+/// * `stream`: The stream to finish.
+///
+/// #### Returns
+///
+/// The dump, or the first error.
+///
+async fn finish_stream(
+    result: Result<DatabaseDump, Error>,
+    stream: &mut (impl AsyncWrite + Unpin),
+) -> Result<DatabaseDump, Error> {
+    let dump = result?;
+
+    stream
+        .shutdown()
+        .await
+        .map_err(|e| Error::new(ErrorKind::Filesystem, format!("Failed to finish the dump: {}", e)))?;
+
+    Ok(dump)
+}
+
+///
+/// Returns the path a dump is written to before it is complete.
+///
+/// #### Arguments
+///
+/// * `dump_path`: The final path of the dump.
+///
+/// #### Returns
+///
+/// The dump path with `.partial` appended.
+///
+fn partial_path(dump_path: &Path) -> PathBuf {
+    let mut path = dump_path.as_os_str().to_owned();
+    path.push(".partial");
+    PathBuf::from(path)
+}
+
+///
+/// Cleans up after an export the user interrupted with Ctrl+C.
+///
+/// Removes the incomplete dump and stops the container again if ndmig started it.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the instance.
+/// * `partial_path`: The path of the incomplete dump.
+/// * `staged`: Whether the dump was staged inside the container, see [`container::staging_path`].
+/// * `started_container`: Whether ndmig started the container for this export and has to stop it again.
+///
+/// #### Returns
+///
+/// The "aborted by user" error to end the run with.
+///
+async fn abort_export(
+    docker: &Docker,
+    container_id: &str,
+    partial_path: &Path,
+    staged: bool,
+    started_container: bool,
+) -> Error {
+    output::status(
+        format!(
+            "{} Interrupted, cleaning up (press Ctrl+C again to force quit)...",
+            Glyph::Warning
+        )
+        .yellow(),
+    );
+
+    if partial_path.exists() {
+        output::debug(format!("remove {}", partial_path.display()));
+
+        // CSV and directory exports write a directory.
+        let removed = if partial_path.is_dir() {
+            std::fs::remove_dir_all(partial_path)
+        } else {
+            std::fs::remove_file(partial_path)
+        };
+
+        if let Err(e) = removed {
+            output::status(format!("{} Failed to remove {}: {}", Glyph::Warning, partial_path.display(), e).yellow());
+        }
+    }
+
+    if staged {
+        container::remove_staged(docker, container_id, &container::staging_path()).await;
+    }
+
+    if started_container {
+        output::debug(format!("stop_container {}", container_id));
+
+        if let Err(e) = docker.stop_container(container_id, None).await {
+            output::status(format!("{} Failed to stop the container again: {}", Glyph::Warning, e).yellow());
+        }
+    }
+
+    Error::new(ErrorKind::Interrupted, "Export aborted by user.")
+}
+
+///
+/// An instance as printed by the `list` subcommand.
+///
+#[derive(Serialize)]
+struct ListedInstance {
+    name: String,
+    container_id: String,
+    flavor: container::Flavor,
+    running: bool,
+    ignored: bool,
+}
+
+///
+/// Prints the discovered instances.
+///
+/// #### Arguments
+///
+/// * `instances`: The discovered Ballsdex instances.
+/// * `ignored`: The discovered instances hidden by the ignore list, only listed with `--all`.
+/// * `args`: The list arguments.
+///
+fn list(instances: &[Instance], ignored: &[Instance], args: &ListArgs) {
+    let filter = args.filter.as_deref().unwrap_or("");
+    let ignored = if args.all { ignored } else { &[] };
+
+    let mut listed: Vec<ListedInstance> = instances
+        .iter()
+        .map(|instance| (instance, false))
+        .chain(ignored.iter().map(|instance| (instance, true)))
+        .filter(|(instance, _)| matches_filter(instance, filter))
+        .map(|(instance, ignored)| ListedInstance {
+            name: format_name(&instance.name),
+            container_id: instance.container_id.clone(),
+            flavor: instance.flavor,
+            running: instance.running,
+            ignored,
+        })
+        .collect();
+
+    listed.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if output::is_json() {
+        output::json(&listed);
+        return;
+    }
+
+    if listed.is_empty() {
+        match &args.filter {
+            Some(filter) => output::info(format!("No Ballsdex instances match '{}'.", filter).yellow().bold()),
+            None => output::info("No Ballsdex instances found.".yellow().bold()),
+        }
+        return;
+    }
+
+    let width = listed.iter().map(|i| i.name.len()).max().unwrap_or(0);
+
+    for instance in &listed {
+        if instance.ignored {
+            let line = format!(
+                "  {} {:width$}  {}  {:9}  ignored",
+                Glyph::Arrow,
+                instance.name,
+                short_id(&instance.container_id),
+                instance.flavor.name(),
+            );
+            output::info(line.dimmed());
+            continue;
+        }
+
+        let state = if instance.running {
+            "running".green()
+        } else {
+            "stopped".dimmed()
+        };
+
+        let flavor = format!("{:9}", instance.flavor.name());
+        let flavor = match instance.flavor {
+            container::Flavor::Unknown => flavor.dimmed(),
+            _ => flavor.normal(),
+        };
+
+        output::info(format!(
+            "  {} {}  {}  {}  {}",
+            Glyph::Arrow.to_string().bright_yellow(),
+            format!("{:width$}", instance.name).bright_cyan(),
+            short_id(&instance.container_id),
+            flavor,
+            state,
+        ));
+    }
+}
+
+///
+/// Makes sure the user really wants to import into a database that already holds data.
+///
+/// The target counts as non-empty if any core Ballsdex table has rows, or if that cannot be
+/// checked. With the default strategy the import then stops. Otherwise the user has to type the
+/// instance name back; `--yes` does not skip this, only `--force` does.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The instance that would be overwritten.
+/// * `args`: The import arguments, with the strategy and `--force`.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing if the import may go ahead, or an "aborted" error.
+///
+async fn guard_existing_data(
+    docker: &Docker,
+    target: &Instance,
+    args: &ImportArgs,
+    options: &Options,
+) -> Result<(), Error> {
+    let name = format_name(&target.name);
+
+    // A NationDex target holds the core tables under their renamed names.
+    let mut tables = container::CORE_TABLES.to_vec();
+    tables.extend(
+        container::CORE_TABLES
+            .iter()
+            .map(|table| transform::target_name(table))
+            .filter(|renamed| !container::CORE_TABLES.contains(renamed)),
+    );
+
+    let counts = if target.running {
+        container::row_counts(
+            docker,
+            &target.container_id,
+            &container::database(docker, &target.container_id, options).await,
+            &tables,
+        )
+        .await
+    } else {
+        None
+    };
+
+    let reason = match counts {
+        Some(counts) if counts.values().all(|count| *count == 0) => return Ok(()),
+        Some(counts) => {
+            let rows: u64 = counts.values().sum();
+            format!("{} already holds {} row(s) in its core tables.", name, rows)
+        }
+        None => format!("Could not check whether {} already holds data.", name),
+    };
+
+    let consequence = match args.strategy {
+        Strategy::Abort => {
+            return Err(Error::new(
+                ErrorKind::Aborted,
+                format!(
+                    "{} Pass --strategy replace to replace its data with the dump, or --strategy merge to add the \
+                     dump to it.",
+                    reason
+                ),
+            ));
+        }
+        Strategy::Replace if !args.tables.is_empty() => {
+            "Strategy replace: the tables of --tables are dropped and replaced with those of the dump."
+        }
+        Strategy::Replace if args.skip_blacklists => {
+            "Strategy replace: every table but the blacklists is dropped and replaced with those of the dump."
+        }
+        Strategy::Replace if args.only_blacklists => {
+            "Strategy replace: the blacklist tables are dropped and replaced with those of the dump."
+        }
+        Strategy::Replace => "Strategy replace: everything in its public schema is dropped and replaced with the dump.",
+        Strategy::Merge => "Strategy merge: the rows of the dump are added to its rows, which are kept.",
+    };
+
+    if args.force {
+        return Ok(());
+    }
+
+    output::info(format!("{} {} {}", Glyph::Warning, reason, consequence).yellow().bold());
+
+    prompt::require_interactive("ask to confirm the overwrite", "--force")?;
+
+    if confirm_typed(&format!("Type '{}' to continue:", name), &name)? {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Aborted,
+            "Import cancelled. Pass --force to skip this check.",
+        ))
+    }
+}
+
+///
+/// Refuses to load a dump that only holds rows into a target without the tables they go into, which would otherwise
+/// fail halfway through the dump on the first missing relation.
+///
+/// A dump that creates the schema earlier in the same import, such as the schema file of a split export, counts as
+/// having applied it. With `--wipe`, the target has no schema left once its tables are dropped.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The running target.
+/// * `dumps`: The dumps to import, in the order they are loaded.
+/// * `args`: The import arguments.
+/// * `database`: The role and database of the target.
+///
+/// #### Returns
+///
+/// Nothing, or an error saying to apply the schema first.
+///
+async fn guard_schema(
+    docker: &Docker,
+    target: &Instance,
+    dumps: &[restore::Dump],
+    args: &ImportArgs,
+    database: &Database,
+) -> Result<(), Error> {
+    if !dumps[0].is_data_only() {
+        return Ok(());
+    }
+
+    if !args.wipe {
+        // Without the list of tables, psql still stops at the first missing one.
+        let Some(tables) = container::tables(docker, &target.container_id, database).await else {
+            return Ok(());
+        };
+
+        let has_schema = container::CORE_TABLES.iter().any(|table| {
+            tables
+                .iter()
+                .any(|existing| existing == table || existing == transform::target_name(table))
+        });
+
+        if has_schema {
+            return Ok(());
+        }
+    }
+
+    let reason = match args.wipe {
+        true => "--wipe drops the tables".to_string(),
+        false => format!(
+            "{} has none of the Ballsdex or NationDex tables",
+            format_name(&target.name)
+        ),
+    };
+
+    Err(Error::new(
+        ErrorKind::Config,
+        format!(
+            "{} only holds data, but {} to load it into. Apply the schema first, e.g. by importing the -schema.sql \
+             file of the same export or a --schema-only dump, then import this one.",
+            dumps[0].path.display(),
+            reason
+        ),
+    ))
+}
+
+///
+/// Checks that the target looks like a Ballsdex or NationDex database before `--wipe` drops its schema, and asks to
+/// confirm by typing its name unless `--force` is passed, even if it holds no rows.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The running target.
+/// * `args`: The import arguments.
+/// * `database`: The role and database of the target.
+///
+/// #### Returns
+///
+/// The tables that will be dropped, or an error if the target holds unknown tables or the user declined.
+///
+async fn guard_wipe(
+    docker: &Docker,
+    target: &Instance,
+    args: &ImportArgs,
+    database: &Database,
+) -> Result<Vec<String>, Error> {
+    let name = format_name(&target.name);
+    let tables = container::tables(docker, &target.container_id, database)
+        .await
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::ImportFailed,
+                format!("Could not list the tables of {}, nothing was wiped.", name),
+            )
+        })?;
+
+    let unknown: Vec<&str> = tables
+        .iter()
+        .map(String::as_str)
+        .filter(|table| {
+            !container::BALLSDEX_TABLES
+                .iter()
+                .any(|known| known == table || transform::target_name(known) == *table)
+        })
+        .collect();
+
+    if !unknown.is_empty() && !args.wipe_unknown_tables {
+        return Err(Error::new(
+            ErrorKind::Aborted,
+            format!(
+                "{} holds tables that are not part of the Ballsdex or NationDex schema: {}. Nothing was wiped. Pass \
+                 --wipe-unknown-tables to drop them along.",
+                name,
+                unknown.join(", ")
+            ),
+        ));
+    }
+
+    let dropped = match tables.is_empty() {
+        true => "which holds no tables".to_string(),
+        false => format!("with its {} table(s) {}", tables.len(), tables.join(", ")),
+    };
+
+    output::info(
+        format!(
+            "{} --wipe drops the public schema of {} {}, and recreates it from the dump.",
+            Glyph::Warning,
+            name,
+            dropped
+        )
+        .yellow()
+        .bold(),
+    );
+
+    if args.force {
+        return Ok(tables);
+    }
+
+    prompt::require_interactive("ask to confirm the wipe", "--force")?;
+
+    if confirm_typed(&format!("Type '{}' to wipe it:", name), &name)? {
+        Ok(tables)
+    } else {
+        Err(Error::new(
+            ErrorKind::Aborted,
+            "Import cancelled, nothing was wiped. Pass --force to skip this check.",
+        ))
+    }
+}
+
+///
+/// Displays the dumps ndmig wrote that can be imported, and lets the user pick one.
+///
+/// #### Arguments
+///
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The path of the selected dump, `None` if the user cancelled, or an error if there is none to pick.
+///
+async fn select_dump(options: &Options) -> Result<Option<PathBuf>, Error> {
+    let mut files: Vec<dumps::DumpFile> = Vec::new();
+
+    for file in dumps::find(options).into_iter().rev() {
+        if !file.partial && restore::open(&file.path).await.is_ok() {
+            files.push(file);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "No dumps ndmig can import found in the ndmig directories. Pass --file <path> to import another one.",
+        ));
+    }
+
+    prompt::require_interactive("ask which dump to import", "--file <path>")?;
+
+    let items: Vec<String> = files
+        .iter()
+        .map(|file| {
+            format!(
+                "{}  ({}, {} old)",
+                file.path.display(),
+                format_size(file.bytes),
+                clean::format_age(file.age)
+            )
+        })
+        .collect();
+
+    if io::stderr().is_terminal() {
+        let selection = Select::with_theme(output::theme().as_ref())
+            .with_prompt("Select dump")
+            .items(&items)
+            .max_length(PAGE_SIZE)
+            .default(0)
+            .interact_opt()
+            .map_err(|e| Error::new(ErrorKind::Aborted, format!("Failed to read input: {}", e)))?;
+
+        return Ok(selection.map(|index| files[index].path.clone()));
+    }
+
+    output::info(format!("\n{}", "Dumps, newest first:".bold().yellow()));
+
+    for (i, item) in items.iter().enumerate() {
+        output::info(format!("  {}. {}", i + 1, item));
+    }
+
+    prompt::ask(options, "Select dump (number, q to go back):", |answer| {
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=files.len()).contains(&n) => Ok(files[n - 1].path.clone()),
+            _ => Err(format!("Invalid dump (1 to {}).", files.len())),
+        }
+    })
+}
+
+///
+/// Tells where a dump about to be imported came from, according to its manifest, or warns that there is no telling.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump to import.
+///
+fn print_dump_origin(dump: &restore::Dump) {
+    let Some(manifest) = &dump.manifest else {
+        let missing = match dump.bundle {
+            Some(_) => "the bundle holds none".to_string(),
+            None => format!("{} not found", manifest::path(&dump.path).display()),
+        };
+
+        output::status(
+            format!(
+                "{} {} has no manifest ({}). ndmig cannot tell which instance it came from, what it holds or how \
+                 many rows it should load, so check it before going on.",
+                Glyph::Warning,
+                dump.path.display(),
+                missing
+            )
+            .yellow()
+            .bold(),
+        );
+        return;
+    };
+
+    let mut origin = format!(
+        "{} Dump of {}",
+        Glyph::Arrow,
+        manifest
+            .instance
+            .as_deref()
+            .map_or_else(|| "an unknown instance".to_string(), format_name)
+    );
+
+    if let Some(created_at) = &manifest.created_at {
+        origin.push_str(&format!(" from {}", created_at));
+    }
+
+    origin.push_str(&format!(", {}", format_size(dump.bytes)));
+
+    if let Some(mode) = manifest.mode.as_deref().filter(|mode| *mode != "full") {
+        origin.push_str(&format!(", {} export", mode));
+    }
+
+    if let Some(sha256) = &dump.sha256 {
+        origin.push_str(&format!(", SHA-256 {}", checksum::short(sha256)));
+    }
+
+    output::status(origin.dimmed());
+}
+
+///
+/// Resolves the instance to import into.
+///
+/// Only NationDex instances are offered and accepted, as importing into a Ballsdex source is almost always a mistake,
+/// unless `--allow-any-target` is passed.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered instances.
+/// * `args`: The import arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The target, or an error if it is not a NationDex instance or the user cancelled.
+///
+async fn select_target(
+    docker: &Docker,
+    instances: &[Instance],
+    args: &ImportArgs,
+    options: &Options,
+) -> Result<Instance, Error> {
+    if let Some(name) = args.instance.as_deref().or(options.instance.as_deref()) {
+        let target = match_instance(instances, name, options)
+            .map_err(|message| Error::new(ErrorKind::InstanceNotFound, message))?;
+
+        if target.flavor != container::Flavor::NationDex && !args.allow_any_target {
+            let flavor = match target.flavor {
+                container::Flavor::Ballsdex => "a Ballsdex instance",
+                _ => "not recognized as a NationDex instance",
+            };
+
+            return Err(Error::new(
+                ErrorKind::InstanceNotFound,
+                format!(
+                    "{} is {}. Pass --allow-any-target to import into it anyway.",
+                    format_name(&target.name),
+                    flavor
+                ),
+            ));
+        }
+
+        return Ok(target.clone());
+    }
+
+    let candidates: Vec<Instance> = instances
+        .iter()
+        .filter(|instance| args.allow_any_target || instance.flavor == container::Flavor::NationDex)
+        .cloned()
+        .collect();
+
+    if candidates.is_empty() && !instances.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InstanceNotFound,
+            "No NationDex instances found. Pass --allow-any-target to pick from every instance.",
+        ));
+    }
+
+    select_instance(docker, &candidates, options)
+        .await?
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::Aborted, "Import cancelled."))
+}
+
+///
+/// Returns the actions an import would take, for `--dry-run`.
+///
+/// #### Arguments
+///
+/// * `target`: The instance to import into.
+/// * `dumps`: The dumps to import, in order.
+/// * `args`: The import arguments.
+/// * `database`: The role and database to load the dump into.
+/// * `options`: The global options.
+///
+fn plan_import(
+    target: &Instance,
+    dumps: &[restore::Dump],
+    args: &ImportArgs,
+    database: &Database,
+    options: &Options,
+) -> Vec<String> {
+    let checks: Vec<String> = dumps
+        .iter()
+        .filter(|_| !args.no_checksum)
+        .map(|dump| match &dump.sha256 {
+            Some(sha256) => format!(
+                "verify {} against its SHA-256 {}",
+                dump.path.display(),
+                checksum::short(sha256)
+            ),
+            None if args.strict => format!("refuse {}, which has no checksum", dump.path.display()),
+            None => format!("warn that {} has no checksum to verify it against", dump.path.display()),
+        })
+        .collect();
+    let mut actions = checks.clone();
+    let dump_path = dumps[0].path.as_path();
+
+    if args.rehearse {
+        let mut actions = checks;
+
+        if !target.running {
+            actions.push(format!("start container {}", target.container_id));
+        }
+
+        for dump in dumps {
+            actions.push("create the scratch database ndmig_rehearsal_<id>".to_string());
+            actions.push(format!("feed {} to psql in the scratch database", dump.path.display()));
+            actions.push("compare the rows of the core tables with the manifest".to_string());
+            actions.push("drop the scratch database".to_string());
+        }
+        return actions;
+    }
+
+    if !target.running {
+        actions.push(match args.keep_running {
+            true => format!("start container {}", target.container_id),
+            false => format!("start container {} and stop it again afterwards", target.container_id),
+        });
+    }
+
+    if !args.no_create {
+        actions.push(format!(
+            "create the role {} and the database {} if the target does not have them yet",
+            database.user,
+            database.name.as_deref().unwrap_or(&database.user)
+        ));
+    }
+
+    actions.push(match (args.strategy, args.force) {
+        _ if args.wipe => format!(
+            "check that the target only holds Ballsdex or NationDex tables{}, and {}",
+            match args.wipe_unknown_tables {
+                true => " (or drop the others along)",
+                false => "",
+            },
+            match args.force {
+                true => "wipe it without asking",
+                false => "ask to type its name to confirm the wipe",
+            }
+        ),
+        (Strategy::Abort, _) => "check whether the target already holds data, and stop if it does".to_string(),
+        (strategy, false) => format!(
+            "check whether the target already holds data, and ask to confirm the {} if it does",
+            strategy.name()
+        ),
+        (strategy, true) => format!("{} the data of the target without asking", strategy.name()),
+    });
+
+    if !args.no_backup {
+        actions.push(format!("back up the target to {}", backup_path(dump_path).display()));
+    }
+
+    let join = |tables: &BTreeSet<String>| tables.iter().cloned().collect::<Vec<_>>().join(", ");
+
+    match (args.strategy, imported_tables(dumps)) {
+        (Strategy::Replace, None) => actions.push("drop and recreate the public schema of the target".to_string()),
+        (Strategy::Replace, Some(tables)) => actions.push(format!("drop the tables {} of the target", join(&tables))),
+        _ => {}
+    }
+
+    if !args.keep_source_aerich {
+        actions.push(match args.strategy {
+            Strategy::Replace => {
+                "keep the aerich migrations of the target aside, to put them back in place of those of \
+                                  the dump"
+                    .to_string()
+            }
+            _ => "leave the aerich migrations of the dump out".to_string(),
+        });
+    }
+
+    if args.include_globals {
+        actions.push(format!(
+            "create the roles of the {} next to the first dump that has one with `{}`, warning about those the target \
+             has already",
+            globals::SUFFIX,
+            restore::psql_command(database, false, true).join(" ")
+        ));
+    }
+
+    for dump in dumps {
+        if let Some(jobs) = args.jobs {
+            let staged = container::staging_path();
+            actions.push(format!(
+                "copy {} into the container as {} and load it as it is with `{}`, then remove the copy",
+                dump.path.display(),
+                staged,
+                restore::parallel_command(database, jobs, args.continue_on_error, &staged).join(" ")
+            ));
+            continue;
+        }
+
+        let converted = match (dump.format, dump.compression) {
+            (DumpFormat::Plain, Compression::None) => String::new(),
+            (DumpFormat::Plain, compression) => format!(", decompressed with {},", compression.name()),
+            (DumpFormat::Directory, _) => format!(
+                ", copied into the container and converted by `{}`,",
+                restore::convert_command(Some(&container::staging_path())).join(" ")
+            ),
+            (format, Compression::None) => format!(
+                ", a {} dump converted by `{}`,",
+                format.name(),
+                restore::convert_command(None).join(" ")
+            ),
+            (format, compression) => format!(
+                ", a {} dump decompressed with {} and converted by `{}`,",
+                format.name(),
+                compression.name(),
+                restore::convert_command(None).join(" ")
+            ),
+        };
+
+        actions.push(format!(
+            "feed {}{}{}{} to `{}`{}",
+            dump.path.display(),
+            converted,
+            match &dump.tables {
+                None => String::new(),
+                Some(tables) => format!(", limited to the tables {},", join(tables)),
+            },
+            match args.no_transform {
+                true => "",
+                false => ", rewritten into the NationDex schema,",
+            },
+            restore::psql_command(database, !args.no_transaction, args.continue_on_error).join(" "),
+            match args.strategy {
+                Strategy::Merge => format!(" with its tables in the schema {}", merge::SCHEMA),
+                _ => String::new(),
+            }
+        ));
+
+        if args.fast {
+            actions.push(
+                "load it with synchronous_commit off and a larger maintenance_work_mem, creating the indexes and \
+                 constraints after the rows"
+                    .to_string(),
+            );
+        }
+
+        if args.strategy == Strategy::Merge {
+            actions.push(format!(
+                "insert the rows of {} into the tables of the target, remapping taken IDs and handling players it \
+                 already has by Discord ID with --on-duplicate-player {} and --triggers {}, and drop it",
+                merge::SCHEMA,
+                args.on_duplicate_player.name(),
+                args.triggers.name()
+            ));
+        }
+    }
+
+    if !args.no_sequence_fix {
+        actions.push("move every sequence that is behind past the highest ID of its table".to_string());
+    }
+
+    if !args.no_analyze {
+        actions.push(match args.vacuum {
+            true => "run VACUUM ANALYZE on the target".to_string(),
+            false => "run ANALYZE on the target".to_string(),
+        });
+    }
+
+    if !args.no_validate {
+        actions.push(
+            "check the row counts against the manifest, that no rows point at missing ones and that aerich is at the \
+             migration the bot of the target expects"
+                .to_string(),
+        );
+    }
+
+    if let Some(contents) = dumps.iter().find_map(|dump| dump.bundle.as_ref())
+        && args.bundle_files != BundleFiles::Skip
+    {
+        let verb = match args.bundle_files {
+            BundleFiles::Restore => "copy",
+            _ => "offer to copy",
+        };
+
+        if contents.config.is_some() {
+            actions.push(format!(
+                "{} the config.yml of the bundle to {} in the bot container",
+                verb,
+                bundle_config_destination(options)
+            ));
+        }
+
+        if contents.media.is_some() {
+            actions.push(format!(
+                "{} the media of the bundle into the media directory of the target",
+                verb
+            ));
+        }
+    }
+
+    if args.include_redis {
+        actions.push(format!(
+            "stop the Redis container of the project, replace its snapshot with the {} next to the last dump that \
+             has one, and start it again",
+            redis::SUFFIX
+        ));
+    }
+
+    actions
+}
+
+///
+/// Returns the tables an import is limited to by their Ballsdex name, which `--tables` may give in either schema.
+///
+/// #### Arguments
+///
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// The tables, or `None` without `--tables`.
+///
+fn selected_tables(args: &ImportArgs) -> Option<BTreeSet<String>> {
+    (!args.tables.is_empty()).then(|| {
+        args.tables
+            .iter()
+            .map(|table| transform::source_name(&table.to_lowercase()).to_string())
+            .collect()
+    })
+}
+
+///
+/// Returns every table the dumps of an import are limited to, by its Ballsdex name.
+///
+/// #### Arguments
+///
+/// * `dumps`: The dumps to import.
+///
+/// #### Returns
+///
+/// The tables, or `None` if the dumps are imported whole.
+///
+fn imported_tables(dumps: &[restore::Dump]) -> Option<BTreeSet<String>> {
+    dumps
+        .iter()
+        .filter_map(|dump| dump.tables.clone())
+        .reduce(|mut tables, more| {
+            tables.extend(more);
+            tables
+        })
+}
+
+///
+/// Compares the postgres versions the dumps were written with and those of the target, before anything is done to it.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The instance to import into.
+/// * `dumps`: The dumps to import.
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// The versions of the target and those of the first dump, or an error naming both if they are known not to work
+/// together unless `--allow-version-mismatch` is passed.
+///
+async fn check_versions(
+    docker: &Docker,
+    target: &Instance,
+    dumps: &[restore::Dump],
+    args: &ImportArgs,
+) -> Result<(postgres::Target, postgres::Source), Error> {
+    let versions = postgres::of_target(docker, &target.container_id).await;
+    let mut first = None;
+
+    for dump in dumps {
+        let source = postgres::of_dump(dump).await;
+        let mismatches = postgres::compare(dump.format, &source, &versions);
+        first.get_or_insert(source);
+
+        if mismatches.is_empty() {
+            if source.server.is_some() && versions.server.is_some() {
+                output::status(
+                    format!(
+                        "{} {} comes from {}, the target runs {}.",
+                        Glyph::Ok,
+                        dump.path.display(),
+                        source.describe(),
+                        versions.describe()
+                    )
+                    .dimmed(),
+                );
+            }
+            continue;
+        }
+
+        for mismatch in &mismatches {
+            if mismatch.fatal && !args.allow_version_mismatch {
+                return Err(Error::new(
+                    ErrorKind::Config,
+                    format!(
+                        "{} comes from {}, and the target runs {}. {} Pass --allow-version-mismatch to import it \
+                         anyway.",
+                        dump.path.display(),
+                        source.describe(),
+                        versions.describe(),
+                        mismatch.message
+                    ),
+                ));
+            }
+
+            output::status(format!("{} {}", Glyph::Warning, mismatch.message).yellow());
+        }
+    }
+
+    Ok((versions, first.unwrap_or_default()))
+}
+
+///
+/// Adds the postgres versions of the dump and the target to an error of the import, when they differ, as a newer
+/// dump is the most common reason for an error on a statement that looks fine.
+///
+/// #### Arguments
+///
+/// * `error`: The error.
+/// * `versions`: The versions of the target and those of the first dump, from [`check_versions`].
+///
+fn explain_versions(mut error: Error, versions: &(postgres::Target, postgres::Source)) -> Error {
+    let (target, source) = versions;
+
+    if error.kind == ErrorKind::ImportFailed && postgres::differ(source, target) {
+        error.message.push_str(&format!(
+            "\nThe dump comes from {}, and the target runs {}. If the error is about syntax or an unknown parameter, \
+             the difference is the likely cause.",
+            source.describe(),
+            target.describe()
+        ));
+    }
+
+    error
+}
+
+///
+/// Checks a dump against the checksum it was written with, from the checksum file next to it, its manifest or its
+/// bundle.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump, as opened by [`restore::open`].
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the dump is corrupted, or has no checksum with `--strict`.
+///
+async fn verify_checksum(dump: &mut restore::Dump, args: &ImportArgs) -> Result<(), Error> {
+    if args.no_checksum {
+        output::debug(format!(
+            "Not verifying {}, as --no-checksum was passed",
+            dump.path.display()
+        ));
+        return Ok(());
+    }
+
+    if dump.sha256.is_none() {
+        let missing = match dump.bundle {
+            Some(_) => "the bundle holds none".to_string(),
+            None => format!("{} not found", checksum::path(&dump.path).display()),
+        };
+
+        if args.strict {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} has no checksum ({}), so it cannot be verified. Pass --no-checksum to import it anyway.",
+                    dump.path.display(),
+                    missing
+                ),
+            ));
+        }
+
+        output::status(
+            format!(
+                "{} {} has no checksum ({}), so it cannot be verified.",
+                Glyph::Warning,
+                dump.path.display(),
+                missing
+            )
+            .yellow(),
+        );
+        return Ok(());
+    }
+
+    output::status(format!("{} Verifying {}...", Glyph::Pending, dump.path.display()).yellow());
+    restore::verify(dump).await?;
+    output::status(format!("{} The checksum of {} matches.", Glyph::Ok, dump.path.display()).green());
+
+    Ok(())
+}
+
+///
+/// Checks that a dump can be loaded with `pg_restore -j` as it is, when `--jobs` is passed. Nothing of the dump is
+/// rewritten then, so every option that needs psql to see the SQL is refused.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump to import.
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error naming what does not work with `--jobs`.
+///
+fn check_jobs(dump: &restore::Dump, args: &ImportArgs) -> Result<(), Error> {
+    if args.jobs.is_none() {
+        return Ok(());
+    }
+
+    let missing = if !args.no_transform {
+        Some("--no-transform")
+    } else if !args.no_transaction {
+        Some("--no-transaction")
+    } else if !args.keep_source_aerich {
+        Some("--keep-source-aerich")
+    } else {
+        None
+    };
+
+    if let Some(flag) = missing {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--jobs needs {}, pg_restore loads the dump as it is and in several transactions",
+                flag
+            ),
+        ));
+    }
+
+    let unsupported = if !args.tables.is_empty() {
+        Some("--tables")
+    } else if args.skip_blacklists || args.only_blacklists {
+        Some("--skip-blacklists and --only-blacklists")
+    } else if args.fast {
+        Some("--fast")
+    } else if args.strategy == Strategy::Merge {
+        Some("--strategy merge")
+    } else {
+        None
+    };
+
+    if let Some(flag) = unsupported {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--jobs does not work with {}, which needs psql to read the SQL of the dump",
+                flag
+            ),
+        ));
+    }
+
+    if !matches!(dump.format, DumpFormat::Custom | DumpFormat::Directory) || dump.compression != Compression::None {
+        let kind = match dump.compression {
+            Compression::None => dump.format.name().to_string(),
+            compression => format!("{} compressed {}", compression.name(), dump.format.name()),
+        };
+
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "--jobs only works with uncompressed custom and directory dumps, which pg_restore reads in parallel, \
+                 but {} is a {} dump",
+                dump.path.display(),
+                kind
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+///
+/// Picks the tables of a dump to import, from `--tables` or the blacklist options, and checks that the dump holds
+/// them and the tables they reference unless `--no-fk-check` is passed.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump to import, whose tables are set.
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error naming the tables that are missing.
+///
+async fn select_tables(dump: &mut restore::Dump, args: &ImportArgs) -> Result<(), Error> {
+    if !args.skip_blacklists && !args.only_blacklists && args.tables.is_empty() {
+        return Ok(());
+    }
+
+    if dump.format != DumpFormat::Plain {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} is a {} dump, only plain SQL dumps can be limited to some tables with --tables, \
+                 --skip-blacklists or --only-blacklists",
+                dump.path.display(),
+                dump.format.name()
+            ),
+        ));
+    }
+
+    let stats = async { transform::scan(dump.decompressed().await?).await }
+        .await
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to read {}: {}", dump.path.display(), e),
+            )
+        })?;
+
+    let blacklist = |table: &String| container::BLACKLIST_TABLES.contains(&table.as_str());
+    let selected: BTreeSet<String> = match selected_tables(args) {
+        Some(selected) => selected,
+        None => stats
+            .created
+            .iter()
+            .filter(|table| blacklist(table) == args.only_blacklists)
+            .cloned()
+            .collect(),
+    };
+
+    if args.only_blacklists && selected.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} holds none of the blacklist tables {}.",
+                dump.path.display(),
+                container::BLACKLIST_TABLES.join(", ")
+            ),
+        ));
+    }
+
+    let unknown: Vec<&str> = selected
+        .iter()
+        .filter(|table| !stats.created.contains(*table))
+        .map(String::as_str)
+        .collect();
+
+    if !unknown.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} has no table {}. Its tables are {}.",
+                dump.path.display(),
+                unknown.join(", "),
+                stats.created.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        ));
+    }
+
+    let missing: Vec<String> = stats
+        .references
+        .iter()
+        .filter(|(table, referenced)| selected.contains(table) && !selected.contains(referenced) && table != referenced)
+        .map(|(table, referenced)| format!("{} references {}", table, referenced))
+        .collect();
+
+    if !missing.is_empty() {
+        match args.no_fk_check {
+            true => output::status(
+                format!(
+                    "{} {}, which is not imported. These foreign keys are added without checking the rows.",
+                    Glyph::Warning,
+                    missing.join(", ")
+                )
+                .yellow(),
+            ),
+            false => {
+                return Err(Error::new(
+                    ErrorKind::Config,
+                    format!(
+                        "{}, which is not among --tables. Add it, or pass --no-fk-check to import anyway, without \
+                         checking the rows against the tables that are already in the target.",
+                        missing.join(", ")
+                    ),
+                ));
+            }
+        }
+    }
+
+    dump.tables = Some(selected);
+    Ok(())
+}
+
+///
+/// Warns about plain dumps that set the owners of their objects to a role the target does not use.
+///
+/// Archives hold the owners whether `--keep-owner` was passed or not, but they are loaded with `pg_restore --no-owner`,
+/// so only a plain dump actually runs its `OWNER TO` statements. Those fail for a role the target lacks.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The instance to import into.
+/// * `dumps`: The dumps to import.
+///
+async fn warn_owner(docker: &Docker, target: &Instance, dumps: &[restore::Dump]) {
+    let env = container::environment(docker, &target.container_id).await;
+    let Some(role) = container::variable(&env, "POSTGRES_USER") else {
+        return;
+    };
+
+    for dump in dumps {
+        let Some(manifest) = &dump.manifest else {
+            continue;
+        };
+
+        if dump.format != DumpFormat::Plain || manifest.owner != Some(true) {
+            continue;
+        }
+
+        if let Some(user) = manifest.user()
+            && user != role
+        {
+            output::status(
+                format!(
+                    "{} {} was exported with --keep-owner and sets its objects to be owned by {}, but the role of {} \
+                     is {}. Its OWNER TO statements fail unless the role {} exists on the target.",
+                    Glyph::Warning,
+                    dump.path.display(),
+                    user,
+                    format_name(&target.name),
+                    role,
+                    user
+                )
+                .yellow(),
+            );
+        }
+    }
+}
+
+///
+/// Starts the import process.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered Ballsdex instances.
+/// * `args`: The import arguments.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the import was cancelled or failed.
+///
+async fn import(docker: &Docker, instances: &[Instance], args: &ImportArgs, options: &Options) -> Result<(), Error> {
+    // --wipe replaces the whole schema, it only guards the target more closely than --strategy replace.
+    let wiped;
+    let args = match args.wipe {
+        true => {
+            wiped = ImportArgs {
+                strategy: Strategy::Replace,
+                ..args.clone()
+            };
+            &wiped
+        }
+        false => args,
+    };
+
+    // Opened before the target is even picked, so a wrong path never starts a container.
+    let decryption = Decryption::from_args(args);
+    let mut dumps = Vec::new();
+
+    for path in &args.file {
+        dumps.push(restore::open_with(path, Some(&decryption)).await?);
+    }
+
+    let target = &select_target(docker, instances, args, options).await?;
+
+    if dumps.is_empty() {
+        let path = select_dump(options)
+            .await?
+            .ok_or_else(|| Error::new(ErrorKind::Aborted, "Import cancelled."))?;
+        dumps.push(restore::open_with(&path, Some(&decryption)).await?);
+    }
+
+    let mut dumps = chain::complete(dumps, &decryption).await?;
+
+    for dump in &mut dumps {
+        print_dump_origin(dump);
+        check_jobs(dump, args)?;
+        select_tables(dump, args).await?;
+    }
+
+    let database = container::database(docker, &target.container_id, options).await;
+    let versions = check_versions(docker, target, &dumps, args).await?;
+    warn_owner(docker, target, &dumps).await;
+    chain::check_base(&format_name(&target.name), &dumps)?;
+
+    if options.dry_run {
+        print_dry_run(
+            target,
+            &dumps[0].path,
+            &plan_import(target, &dumps, args, &database, options),
+        );
+        return Ok(());
+    }
+
+    // A damaged dump fails here, before anything is done to the target.
+    for dump in &mut dumps {
+        verify_checksum(dump, args).await?;
+    }
+
+    let stop = !args.keep_running
+        && !container::is_running(docker, &target.container_id)
+            .await
+            .unwrap_or(true);
+
+    if args.rehearse {
+        let mut result = Ok(());
+
+        // Each dump gets a scratch database of its own, as loading one on top of another says nothing.
+        for dump in &dumps {
+            result = rehearse(docker, target, dump, args, &database, options)
+                .await
+                .map_err(|e| explain_versions(e, &versions));
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if stop {
+            match stop_started_container(docker, target).await {
+                Ok(true) => {
+                    output::status(format!("{} Stopped {} again.", Glyph::Ok, format_name(&target.name)).dimmed())
+                }
+                Ok(false) => {}
+                Err(message) => output::status(format!("{} {}", Glyph::Warning, message).yellow()),
+            }
+        }
+
+        return result;
+    }
+
+    let started = Instant::now();
+    let result = run_import(docker, target, &dumps, args, &database, options)
+        .await
+        .map(|mut summary| {
+            summary.server_version = versions.0.server.map(|version| version.to_string());
+            summary.source_server_version = versions.1.server.map(|version| version.to_string());
+            summary
+        })
+        .map_err(|e| explain_versions(e, &versions));
+
+    if stop {
+        match stop_started_container(docker, target).await {
+            Ok(true) => output::status(format!("{} Stopped {} again.", Glyph::Ok, format_name(&target.name)).dimmed()),
+            Ok(false) => {}
+            Err(message) => output::status(format!("{} {}", Glyph::Warning, message).yellow()),
+        }
+    }
+
+    match result {
+        Ok(summary) => {
+            // A delta imported next builds on the last dump of the chain.
+            let mut entry = history::Entry::success(&summary);
+            entry.sha256 = dumps.last().and_then(|dump| dump.sha256.clone());
+            history::record(&entry);
+            summary.print();
+
+            let failed = summary
+                .validation
+                .iter()
+                .flatten()
+                .filter(|check| !check.passed)
+                .count();
+
+            if summary.warnings > 0 && !output::is_json() && output::level() < output::Level::Verbose {
+                output::status(
+                    format!(
+                        "{} psql printed {} line(s) to stderr, run with --verbose to see them.",
+                        Glyph::Warning,
+                        summary.warnings
+                    )
+                    .yellow(),
+                );
+            }
+
+            if failed > 0 {
+                let mut message = format!(
+                    "The dump was imported, but {} check(s) of the validation failed.",
+                    failed
+                );
+
+                if let Some(backup) = &summary.backup {
+                    message.push_str(&format!(
+                        " Restore the state before the import from {}.",
+                        backup.display()
+                    ));
+                }
+
+                if let Some(log) = &summary.error_log {
+                    message.push_str(&format!(
+                        " {} statement(s) of the dump failed as well, see {}.",
+                        summary.errors,
+                        log.display()
+                    ));
+                }
+
+                return Err(Error::new(ErrorKind::ImportFailed, message));
+            }
+
+            if let Some(log) = &summary.error_log {
+                return Err(Error::new(
+                    ErrorKind::PartialImport,
+                    format!(
+                        "The dump was imported, but {} statement(s) of it failed and were skipped. Each is in {} with \
+                         its error.",
+                        summary.errors,
+                        log.display()
+                    ),
+                ));
+            }
+
+            Ok(())
+        }
+        Err(error) => {
+            history::record(&history::Entry::failure(
+                OperationKind::Import,
+                target,
+                &error,
+                started.elapsed(),
+            ));
+            Err(error)
+        }
+    }
+}
+
+///
+/// Loads one dump into the target and, with `--strategy merge`, merges its rows into the tables of the target.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running target.
+/// * `database`: The role and database to load the dump into.
+/// * `dump`: The dump to import.
+/// * `args`: The import arguments.
+/// * `transcript`: The log of the import, which everything psql prints is written to.
+///
+/// #### Returns
+///
+/// The result of loading the dump and of the merge, or an error.
+///
+async fn load_dump(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    dump: &restore::Dump,
+    args: &ImportArgs,
+    transcript: &mut Transcript,
+) -> Result<(restore::Restore, Option<merge::Merge>), Error> {
+    let loaded = async {
+        let restored = restore::restore(
+            docker,
+            container_id,
+            database,
+            dump,
+            restore::Load {
+                transform: !args.no_transform,
+                strategy: args.strategy,
+                single_transaction: !args.no_transaction,
+                tables: dump.tables.as_ref(),
+                no_fk_check: args.no_fk_check,
+                source_aerich: args.keep_source_aerich,
+                fast: args.fast,
+                continue_on_error: args.continue_on_error,
+                jobs: args.jobs,
+            },
+            transcript,
+        )
+        .await?;
+
+        let merged = match args.strategy {
+            Strategy::Merge => {
+                output::status(format!("{} Merging the rows of {}...", Glyph::Pending, dump.path.display()).yellow());
+                let rules = merge::Rules {
+                    duplicate_players: args.on_duplicate_player,
+                    donation_policy: args.donation_policy,
+                    triggers: args.triggers,
+                };
+
+                Some(merge::run(docker, container_id, database, &dump.path, rules, !args.no_transaction).await?)
+            }
+            _ => None,
+        };
+
+        Ok((restored, merged))
+    }
+    .await;
+
+    // The copy of the dump goes whether the merge worked or not.
+    if args.strategy == Strategy::Merge {
+        merge::clean_up(docker, container_id, database).await;
+    }
+
+    loaded
+}
+
+///
+/// Cleans up after an import the user interrupted with Ctrl+C.
+///
+/// Terminates the sessions loading the dump, so the server rolls back what they had not committed, and removes what
+/// the load left inside the container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database the dump was loaded into.
+/// * `dump`: The dump that was being loaded.
+/// * `index`: The position of the dump among the dumps of the import.
+/// * `backup`: The backup taken before the import, if any.
+/// * `args`: The import arguments.
+///
+/// #### Returns
+///
+/// The "aborted by user" error to end the run with.
+///
+async fn abort_import(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    dump: &restore::Dump,
+    index: usize,
+    backup: Option<&Path>,
+    args: &ImportArgs,
+) -> Error {
+    output::status(
+        format!(
+            "{} Interrupted, cleaning up (press Ctrl+C again to force quit)...",
+            Glyph::Warning
+        )
+        .yellow(),
+    );
+
+    let terminated = container::terminate_sessions(docker, container_id, database).await;
+
+    if !terminated {
+        output::status(
+            format!(
+                "{} Failed to end the sessions loading {}.",
+                Glyph::Warning,
+                dump.path.display()
+            )
+            .yellow(),
+        );
+    }
+
+    if args.strategy == Strategy::Merge {
+        merge::clean_up(docker, container_id, database).await;
+    }
+
+    if dump.format == DumpFormat::Directory {
+        container::remove_staged(docker, container_id, &container::staging_path()).await;
+    }
+
+    let mut message = match (args.no_transaction, terminated) {
+        (false, true) => format!(
+            "Import aborted by user. The load of {} was rolled back.",
+            dump.path.display()
+        ),
+        _ => format!(
+            "Import aborted by user. The target may hold part of {}.",
+            dump.path.display()
+        ),
+    };
+
+    if index > 0 {
+        message.push_str(&format!(
+            "\nThe {} dump(s) before {} were loaded already.",
+            index,
+            dump.path.display()
+        ));
+    }
+
+    if let Some(backup) = backup {
+        message.push_str(&format!(
+            "\nRestore the state before the import from {}.",
+            backup.display()
+        ));
+    }
+
+    Error::new(ErrorKind::Interrupted, message)
+}
+
+///
+/// Starts the target if needed and loads the dumps into it.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The instance to import into.
+/// * `dumps`: The dumps to import, as opened by [`restore::open`], in the order they are loaded.
+/// * `args`: The import arguments.
+/// * `database`: The role and database to load the dumps into.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The summary of the import, or an error.
+///
+async fn run_import(
+    docker: &Docker,
+    target: &Instance,
+    dumps: &[restore::Dump],
+    args: &ImportArgs,
+    database: &Database,
+    options: &Options,
+) -> Result<Summary, Error> {
+    let started = Instant::now();
+    let first = dumps[0].path.as_path();
+    let name = format_name(&target.name);
+    let container_id = target.container_id.as_str();
+
+    // Looked for before anything is done to the target, so a missing file stops the import right away.
+    let paths: Vec<&Path> = dumps.iter().map(|dump| dump.path.as_path()).collect();
+
+    let snapshot = match args.include_redis {
+        true => Some(redis::find_snapshot(docker, &target.name, &paths).await?),
+        false => None,
+    };
+
+    let globals = match args.include_globals {
+        true => Some(globals::find(&paths)?),
+        false => None,
+    };
+
+    let target = &start_target(docker, target, args, database, options).await?;
+
+    if !args.no_create {
+        let provisioned = provision::ensure(docker, container_id, database).await?;
+
+        for (kind, created) in [("role", &provisioned.role), ("database", &provisioned.database)] {
+            if let Some(created) = created {
+                output::status(format!("{} Created the {} {} on {}.", Glyph::Ok, kind, created, name).green());
+            }
+        }
+    }
+
+    guard_schema(docker, target, dumps, args, database).await?;
+
+    // A schema file the target holds already is not loaded again, unless a replace drops it first.
+    let applied = match args.strategy {
+        Strategy::Replace => BTreeSet::new(),
+        _ => chain::applied(docker, container_id, database, dumps).await,
+    };
+
+    let wiped = match args.wipe {
+        true => Some(guard_wipe(docker, target, args, database).await?),
+        false => {
+            guard_existing_data(docker, target, args, options).await?;
+            None
+        }
+    };
+
+    let backup = match args.no_backup {
+        true => None,
+        false => Some(backup_target(docker, target, first, options).await?),
+    };
+
+    // A merge keeps the rows of the target, and so does --tables for the other tables, which the validation expects.
+    let before = match (args.strategy == Strategy::Merge || imported_tables(dumps).is_some()) && !args.no_validate {
+        true => validate::count_core_tables(docker, container_id, database, !args.no_transform).await,
+        false => None,
+    };
+
+    // What the bot of the target expects, from the migrations its image ships or else those it applied already.
+    let aerich = match args.no_validate {
+        true => None,
+        false => match bot::latest_migration(docker, &target.name).await {
+            Some(migration) => Some(migration),
+            None => validate::aerich_version(docker, container_id, database).await,
+        },
+    };
+
+    // Created before the dumps are loaded, so their grants find the roles.
+    if let Some(globals) = &globals {
+        globals::import(docker, container_id, database, globals).await?;
+    }
+
+    let mut transcript = Transcript::create(&log_path(first, &name, options))?;
+    let mut restores = Vec::new();
+    let mut merges = Vec::new();
+    let loading = Instant::now();
+    let interrupt = interrupt::Scope::enter();
+
+    for (index, dump) in dumps.iter().enumerate() {
+        if applied.contains(&index) {
+            output::status(
+                format!(
+                    "{} Skipping {}, {} has that schema already.",
+                    Glyph::Ok,
+                    dump.path.display(),
+                    name
+                )
+                .dimmed(),
+            );
+            continue;
+        }
+
+        let step = match dumps.len() {
+            1 => String::new(),
+            count => format!(", {} of {}", index + 1, count),
+        };
+
+        output::status(
+            format!(
+                "{} Importing {} into {} (strategy {}{})...",
+                Glyph::Pending,
+                dump.path.display(),
+                name,
+                args.strategy.name(),
+                step
+            )
+            .yellow(),
+        );
+
+        // A data dump goes on top of the dumps before it, replacing would drop the schema they created.
+        let chained;
+        let args = match index > 0 && dump.is_data_only() && args.strategy == Strategy::Replace {
+            true => {
+                chained = ImportArgs {
+                    strategy: Strategy::Abort,
+                    ..args.clone()
+                };
+                &chained
+            }
+            false => args,
+        };
+
+        let load = load_dump(docker, container_id, database, dump, args, &mut transcript);
+        tokio::pin!(load);
+
+        // The load is only dropped once its sessions are gone, closing its stdin first would commit a cut-off dump.
+        let loaded = tokio::select! {
+            loaded = &mut load => loaded,
+            _ = interrupt.interrupted() => {
+                return Err(abort_import(docker, container_id, database, dump, index, backup.as_deref(), args).await);
+            }
+        };
+
+        let (restored, merged) = loaded.map_err(|error| {
+            let mut message = error.message;
+
+            if index > 0 {
+                message.push_str(&format!(
+                    "\nThe {} dump(s) before {} were merged already.",
+                    index,
+                    dump.path.display()
+                ));
+            }
+
+            match (&backup, args.no_transaction) {
+                (Some(backup), _) => message.push_str(&format!(
+                    "\nRestore the state before the import from {}.",
+                    backup.display()
+                )),
+                (None, true) => message.push_str("\nNo backup was taken, as --no-backup was passed."),
+                (None, false) => {}
+            }
+
+            Error::new(error.kind, message)
+        })?;
+
+        restores.push(restored);
+        merges.extend(merged);
+    }
+
+    drop(interrupt);
+
+    let load_ms = loading.elapsed().as_millis();
+    let errors: usize = restores.iter().map(|restored| restored.failures.len()).sum();
+
+    let error_log = match errors {
+        0 => None,
+        _ => {
+            let failed: Vec<(&Path, &[restore::Failure])> = dumps
+                .iter()
+                .zip(&restores)
+                .map(|(dump, restored)| (dump.path.as_path(), restored.failures.as_slice()))
+                .collect();
+            let log = write_error_log(first, &failed)?;
+
+            output::status(
+                format!(
+                    "{} {} statement(s) failed and were skipped, each is in {} with its error.",
+                    Glyph::Warning,
+                    errors,
+                    log.display()
+                )
+                .yellow(),
+            );
+
+            Some(log)
+        }
+    };
+
+    if let Some(tables) = &wiped {
+        output::status(
+            format!(
+                "{} Wiped the public schema of {}: dropped {} table(s){} and recreated it from the dump.",
+                Glyph::Ok,
+                name,
+                tables.len(),
+                match tables.is_empty() {
+                    true => String::new(),
+                    false => format!(" ({})", tables.join(", ")),
+                }
+            )
+            .green(),
+        );
+    }
+
+    if !args.keep_source_aerich && validate::aerich_version(docker, container_id, database).await.is_none() {
+        output::status(
+            format!(
+                "{} The aerich table of {} records no migrations, as those of the dump were left out and the target \
+                 had none to keep. Run `aerich upgrade` in its bot container before starting the bot.",
+                Glyph::Warning,
+                name
+            )
+            .yellow(),
+        );
+    }
+
+    let sequences = match args.no_sequence_fix {
+        true => None,
+        false => Some(sequence::reset(docker, container_id, database).await.map_err(|error| {
+            Error::new(
+                error.kind,
+                format!(
+                    "{} The data was imported, but new rows may fail with duplicate keys until the sequences \
+                             are set past the highest IDs.",
+                    error.message
+                ),
+            )
+        })?),
+    };
+
+    let analyze_ms = match args.no_analyze {
+        true => None,
+        false => analyze(docker, container_id, database, args.vacuum).await,
+    };
+
+    let validation = match args.no_validate {
+        true => None,
+        false => {
+            output::status(format!("{} Validating the import...", Glyph::Pending).yellow());
+            let tables = imported_tables(dumps);
+            let imported = validate::Imported {
+                manifests: dumps.iter().map(|dump| dump.manifest.as_ref()).collect(),
+                strategy: args.strategy,
+                merged: &merges,
+                before: before.as_ref(),
+                tables: tables.as_ref(),
+                aerich: aerich.as_deref(),
+            };
+
+            let mut checks = validate::import(docker, container_id, database, &imported, !args.no_transform).await;
+
+            // A merge inserts the rows itself, with the triggers of the target off if asked to.
+            if args.strategy == Strategy::Merge {
+                output::status(format!("{} Checking the foreign keys of the target...", Glyph::Pending).yellow());
+
+                match audit::run(docker, container_id, database).await {
+                    Ok(audit) => checks.extend(audit.checks()),
+                    Err(error) => checks.push(validate::Check {
+                        name: "rows pointing nowhere".to_string(),
+                        expected: Some("0".to_string()),
+                        actual: error.message,
+                        passed: false,
+                    }),
+                }
+            }
+
+            Some(checks)
+        }
+    };
+
+    let bytes = restores.iter().map(|restored| restored.bytes).sum();
+    let uncompressed_bytes = restores.iter().map(|restored| restored.uncompressed_bytes).sum();
+
+    let mut summary = Summary::new(
+        OperationKind::Import,
+        name,
+        target.container_id.clone(),
+        first.to_path_buf(),
+    );
+    summary.bytes = bytes;
+    summary.uncompressed_bytes = uncompressed_bytes;
+    summary.compression = dumps[0].compression;
+    summary.format = dumps[0].format;
+    summary.jobs = args.jobs;
+    summary.warnings = restores.iter().map(|restored| restored.warnings).sum();
+    summary.renamed = restores.iter().map(|restored| restored.renamed).sum::<Option<u64>>();
+    summary.tables = restores
+        .iter()
+        .flat_map(|restored| restored.tables.iter().flatten().cloned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    summary.blacklists = match (args.skip_blacklists, args.only_blacklists) {
+        (true, _) => Some("skipped"),
+        (_, true) => Some("only"),
+        _ => None,
+    };
+
+    for restored in &restores {
+        for (table, rows) in &restored.left_out {
+            *summary.left_out.entry(table.clone()).or_default() += rows;
+        }
+    }
+
+    summary.analyze_ms = analyze_ms;
+    summary.vacuumed = args.vacuum;
+    summary.load_ms = Some(load_ms);
+    summary.load_estimate_ms = history::estimate_load(bytes, !args.fast);
+    summary.fast = args.fast;
+    summary.deferred = restores.iter().map(|restored| restored.deferred).sum();
+    summary.errors = errors;
+    summary.error_log = error_log;
+    summary.backup = backup;
+    summary.log = Some(transcript.path().to_path_buf());
+    summary.checksum = dumps[0].sha256.clone();
+    summary.checksum_check = Some(match args.no_checksum {
+        true => "skipped",
+        false if dumps.iter().all(|dump| dump.verified) => "verified",
+        false => "missing",
+    });
+    summary.wiped = wiped;
+    summary.strategy = Some(args.strategy);
+    summary.merged = merges;
+    summary.sequences = sequences;
+    if validation.iter().flatten().any(|check| !check.passed) {
+        summary.status = "failed";
+    } else if errors > 0 {
+        summary.status = "partial";
+    }
+    summary.validation = validation;
+
+    // The config and media go with the first bundle, the dumps after it are only merged into the same target.
+    if let Some(dump) = dumps.iter().find(|dump| dump.bundle.is_some()) {
+        summary.restored = restore_bundle_files(docker, target, dump, args, options).await?;
+    }
+
+    if let Some(snapshot) = &snapshot {
+        redis::import(docker, snapshot).await.map_err(|error| {
+            Error::new(
+                error.kind,
+                format!(
+                    "{}\nThe dumps were imported into {} already.",
+                    error.message, summary.instance
+                ),
+            )
+        })?;
+        summary.restored.push("Redis snapshot");
+    }
+
+    summary.duration_ms = started.elapsed().as_millis();
+
+    Ok(summary)
+}
 
 ///
-/// Checks if a Docker container is a Ballsdex instance by inspecting its image name.
+/// Returns where the `config.yml` of a bundle is copied to in the bot container: the `bot_config` setting, or the
+/// path of the Ballsdex image.
 ///
 /// #### Arguments
 ///
-/// * `docker`: The Docker client.
-/// * `container_id`: The ID of the container to inspect.
-///
-/// #### Returns
-///
-/// Whether the container is classified as a Ballsdex instance.
+/// * `options`: The global options.
 ///
-async fn is_ballsdex_instance(docker: &Docker, container_id: &str) -> bool {
-    let info = match docker.inspect_container(container_id, None).await {
-        Ok(info) => info,
-        Err(_) => return false,
-    };
-
-    info.config
-        .and_then(|c| c.image)
-        .map(|img| img == "postgres")
-        .unwrap_or(false)
+fn bundle_config_destination(options: &Options) -> &str {
+    options.bot_config.as_deref().unwrap_or(bot::DEFAULT_CONFIG_PATH)
 }
 
 ///
-/// Creates a database dump by using `pg_dump` in the bot's postgres container.
+/// Copies the `config.yml` and media of a bundle into the containers of the target once its dump is imported, as
+/// `--bundle-files` says.
+///
+/// The data is imported by then, so a file that fails to copy is only a warning.
 ///
 /// #### Arguments
 ///
 /// * `docker`: The Docker client.
-/// * `container_id`: The container ID.
+/// * `target`: The instance the dump was imported into.
+/// * `dump`: The dump, as opened from a bundle by [`restore::open`].
+/// * `args`: The import arguments.
+/// * `options`: The global options.
 ///
-/// ### Returns
+/// #### Returns
 ///
-/// The SQL dump or an error.
+/// The files that were copied, or an error if the user could not be asked.
 ///
-async fn create_database_dump(docker: &Docker, container_id: &str) -> Result<String, bollard::errors::Error> {
-    let info = docker.inspect_container(container_id, None).await?;
-    let is_running = info.state.and_then(|s| s.running).unwrap_or(false);
+async fn restore_bundle_files(
+    docker: &Docker,
+    target: &Instance,
+    dump: &restore::Dump,
+    args: &ImportArgs,
+    options: &Options,
+) -> Result<Vec<&'static str>, Error> {
+    let Some(contents) = &dump.bundle else {
+        return Ok(Vec::new());
+    };
 
-    if !is_running {
-        docker.start_container(container_id, None).await?;
+    let mut restored = Vec::new();
+
+    if args.bundle_files == BundleFiles::Skip || (contents.config.is_none() && contents.media.is_none()) {
+        return Ok(restored);
     }
 
-    let exec = docker
-        .create_exec(
-            container_id,
-            CreateExecOptions {
-                attach_stdout: Some(true),
-                attach_stderr: Some(true),
-                cmd: Some(vec!["pg_dump", "-U", "ballsdex"]), // Ballsdex database dump command thingy
-                ..Default::default()
-            },
-        )
-        .await?;
+    // Without a terminal the files stay in the bundle, as overwriting the config of the target is not what an
+    // import is expected to do.
+    if args.bundle_files == BundleFiles::Ask && !options.assume_yes && !prompt::is_interactive() {
+        output::status(
+            format!(
+                "{} {} also holds {}, which were left in it. Pass --bundle-files restore to copy them into {}.",
+                Glyph::Arrow,
+                dump.path.display(),
+                [
+                    contents.config.as_ref().map(|_| "config.yml"),
+                    contents.media.as_ref().map(|_| "media")
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" and "),
+                format_name(&target.name)
+            )
+            .dimmed(),
+        );
+        return Ok(restored);
+    }
 
-    let mut output = String::new();
+    let wanted = |question: &str| match args.bundle_files {
+        BundleFiles::Restore => Ok(true),
+        _ => prompt::confirm(options, question),
+    };
 
-    if let StartExecResults::Attached { output: mut stream, .. } = docker.start_exec(&exec.id, None).await? {
-        while let Some(chunk) = stream.next().await {
-            match chunk? {
-                bollard::container::LogOutput::StdOut { message } => {
-                    output.push_str(&String::from_utf8_lossy(&message));
-                }
-                bollard::container::LogOutput::StdErr { message } => {
-                    eprintln!(
-                        "{} {}",
-                        "pg_dump stderr:".yellow().bold(),
-                        String::from_utf8_lossy(&message)
-                    );
+    if let Some(member) = &contents.config {
+        let destination = bundle_config_destination(options);
+
+        if wanted(&format!(
+            "Copy the config.yml of the bundle to {} in the bot container of {}?",
+            destination,
+            format_name(&target.name)
+        ))? {
+            let copied = match container::find_service(docker, &target.name, "bot").await {
+                Ok(Some(bot)) => bundle::restore_config(docker, &bot.container_id, &dump.path, member, destination)
+                    .await
+                    .map_err(|error| error.message),
+                Ok(None) => Err(format!(
+                    "{}-bot-1 does not exist, the config.yml was left in the bundle.",
+                    container::project(&target.name)
+                )),
+                Err(e) => Err(format!("Failed to look for the bot container: {}", e)),
+            };
+
+            match copied {
+                Ok(()) => {
+                    output::status(format!("{} Copied config.yml to {}.", Glyph::Ok, destination).green());
+                    restored.push("config.yml");
                 }
-                _ => {}
+                Err(message) => output::status(format!("{} {}", Glyph::Warning, message).yellow()),
+            }
+        }
+    }
+
+    if let Some(member) = &contents.media
+        && wanted(&format!(
+            "Copy the media of the bundle into {}?",
+            format_name(&target.name)
+        ))?
+    {
+        let copied = match media::find(docker, &target.name).await {
+            Ok((service, directory)) => {
+                bundle::restore_media(docker, &service.container_id, &dump.path, member, &directory)
+                    .await
+                    .map(|()| directory)
             }
+            Err(error) => Err(error),
+        };
+
+        match copied {
+            Ok(directory) => {
+                output::status(format!("{} Copied the media into {}.", Glyph::Ok, directory).green());
+                restored.push("media");
+            }
+            Err(error) => output::status(format!("{} {}", Glyph::Warning, error.message).yellow()),
         }
     }
 
-    Ok(output)
+    Ok(restored)
 }
 
 ///
-/// Formats a container name by removing the suffix.
+/// Gathers the statistics of the target after an import, so postgres plans the queries of the bot well from the start
+/// rather than once autovacuum got to it.
+///
+/// The data is loaded by then, so a failure is only a warning.
 ///
 /// #### Arguments
 ///
-/// * `name`: The full container name.
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running target.
+/// * `database`: The role and database the dumps were imported into.
+/// * `vacuum`: Whether to run VACUUM ANALYZE rather than ANALYZE.
 ///
 /// #### Returns
 ///
-/// The formatted name without the suffix.
+/// How long it took, or `None` if it failed.
 ///
-fn format_name(name: &str) -> String {
-    name.split("-").next().unwrap_or("unknown").to_string()
+async fn analyze(docker: &Docker, container_id: &str, database: &Database, vacuum: bool) -> Option<u128> {
+    let command = match vacuum {
+        true => "VACUUM ANALYZE",
+        false => "ANALYZE",
+    };
+
+    output::status(format!("{} Running {} on the target...", Glyph::Pending, command).yellow());
+    let started = Instant::now();
+
+    // VACUUM cannot run inside a transaction.
+    match restore::run_script(docker, container_id, database, &format!("{};\n", command), false).await {
+        Ok(()) => Some(started.elapsed().as_millis()),
+        Err(error) => {
+            output::status(
+                format!(
+                    "{} {} failed, the data is imported but queries may be slow until autovacuum analyzes the \
+                     tables: {}",
+                    Glyph::Warning,
+                    command,
+                    error.message
+                )
+                .yellow(),
+            );
+            None
+        }
+    }
 }
 
 ///
-/// Displays the export selection menu and handles user input.
+/// Starts the target of an import if it is stopped, after asking, and waits until postgres accepts connections.
 ///
 /// #### Arguments
 ///
-/// * `instances`: A HashMap of Ballsdex instances names.
+/// * `docker`: The Docker client.
+/// * `target`: The instance to import into.
+/// * `args`: The import arguments.
+/// * `database`: The role and database the import connects with.
+/// * `options`: The options for this run.
 ///
 /// #### Returns
 ///
-/// A tuple containing the instance name and its container ID.
+/// The running target, or an error if the user declined or it did not start.
 ///
-fn export_setup(instances: &HashMap<String, String>) -> (String, String) {
-    println!("\n{}", "Detected Ballsdex instances:".bold().yellow());
+async fn start_target(
+    docker: &Docker,
+    target: &Instance,
+    args: &ImportArgs,
+    database: &Database,
+    options: &Options,
+) -> Result<Instance, Error> {
+    let name = format_name(&target.name);
+    let container_id = target.container_id.as_str();
 
-    for name in instances.keys() {
-        println!("  {} {}", "›".bright_yellow(), format_name(name).bright_cyan());
+    if container::is_running(docker, container_id)
+        .await
+        .unwrap_or(target.running)
+    {
+        return Ok(target.clone());
     }
 
-    print!("\n{}", "Select instance: ".bold().white());
-    let _ = io::stdout().flush();
+    let question = match args.keep_running {
+        true => format!("{} is stopped. Start it?", name),
+        false => format!(
+            "{} is stopped. Start it temporarily? It is stopped again after the import.",
+            name
+        ),
+    };
+
+    if !confirm(options, &question)? {
+        return Err(Error::new(ErrorKind::Aborted, "Import cancelled."));
+    }
 
-    let mut instance = String::new();
-    io::stdin().read_line(&mut instance).expect("Failed to read input");
+    output::debug(format!("start_container {}", container_id));
 
-    let instance = instance.trim().to_string() + "-postgres-db-1";
+    if let Err(e) = docker.start_container(container_id, None).await {
+        return Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!("Failed to start container: {}", e),
+        ));
+    }
 
-    if !instances.contains_key(&instance) {
-        clearscreen::clear().expect("Failed to clear screen");
-        eprintln!(
-            "{} {}",
-            "✗ Instance not found:".red().bold(),
-            format!("'{}'", instance).bright_red()
-        );
-        process::exit(1);
+    let spinner = progress::spinner("Waiting for the database to start...");
+    let ready = container::wait_until_ready(docker, container_id, &target.name, database).await;
+
+    if let Some(spinner) = spinner {
+        spinner.finish_and_clear();
     }
 
-    let container_id = instances[&instance].clone();
-    (instance, container_id)
+    ready?;
+
+    Ok(Instance {
+        running: true,
+        ..target.clone()
+    })
 }
 
 ///
-/// Starts the export setup process.
+/// The outcome of `--rehearse`, printed as the JSON document.
+///
+#[derive(Serialize)]
+struct RehearsalReport<'a> {
+    instance: String,
+    container_id: &'a str,
+    dump_path: &'a Path,
+    /// The scratch database the dump was loaded into, which is dropped again.
+    database: &'a str,
+    renamed: Option<u64>,
+    /// The statements that failed and were skipped, with `--continue-on-error`.
+    failures: &'a [restore::Failure],
+    /// The log of everything psql printed while loading the dump.
+    log: &'a Path,
+    checks: &'a [validate::Check],
+    duration_ms: u128,
+    status: &'static str,
+}
+
+///
+/// Loads a dump into a scratch database on the target and checks the result, without touching the real database.
 ///
 /// #### Arguments
 ///
 /// * `docker`: The Docker client.
-/// * `instances`: A HashMap of Ballsdex instances names.
+/// * `target`: The instance to rehearse the import on.
+/// * `dump`: The dump to import, as opened by [`restore::open`].
+/// * `args`: The import arguments.
+/// * `database`: The role and database the real import would load the dump into.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the dump did not load or a check failed.
 ///
-async fn export(docker: &Docker, instances: &HashMap<String, String>) {
-    let (instance, container_id) = export_setup(instances);
+async fn rehearse(
+    docker: &Docker,
+    target: &Instance,
+    dump: &restore::Dump,
+    args: &ImportArgs,
+    database: &Database,
+    options: &Options,
+) -> Result<(), Error> {
+    let started = Instant::now();
+    let dump_path = dump.path.as_path();
+    let target = &start_target(docker, target, args, database, options).await?;
+    let container_id = target.container_id.as_str();
 
-    let temp_dir = std::env::temp_dir().join("ndmig");
-    std::fs::create_dir_all(&temp_dir).expect("Failed to create ndmig temp directory");
+    let id = split::new_id();
+    let scratch = Database {
+        name: Some(format!("ndmig_rehearsal_{}", id)),
+        ..database.clone()
+    };
+    let scratch_name = scratch.name.as_deref().unwrap_or_default();
+    let mut transcript = Transcript::create(&log_path(dump_path, &format_name(&target.name), options))?;
 
-    let dump_path = temp_dir.join(format!("{}-ndmig.sql", container_id));
+    output::status(format!("{} Creating the scratch database {}...", Glyph::Pending, scratch_name).yellow());
 
-    println!("{}", "⧗ Exporting...".yellow().bold());
+    let created = container::exec(
+        docker,
+        container_id,
+        vec!["createdb", "-U", database.user.as_str(), scratch_name],
+        database.env(),
+    )
+    .await;
 
-    match create_database_dump(docker, &container_id).await {
-        Ok(sql) => {
-            std::fs::write(&dump_path, sql).expect("Failed to create database dump");
-            println!(
-                "{}",
-                format!("✓ {} has been successfully exported!", format_name(&instance),)
-                    .green()
-                    .bold()
-            );
+    match created {
+        Ok(output) if output.exit_code == Some(0) => {}
+        Ok(output) => {
+            return Err(Error::new(
+                ErrorKind::ImportFailed,
+                format!("Failed to create the scratch database: {}", output.stderr_text()),
+            ));
         }
         Err(e) => {
-            eprintln!("{} {}", "✗ Export failed:".red().bold(), e);
-            process::exit(1);
+            return Err(Error::new(
+                ErrorKind::ImportFailed,
+                format!("Failed to create the scratch database: {}", e),
+            ));
+        }
+    }
+
+    output::status(format!("{} Rehearsing the import of {}...", Glyph::Pending, dump_path.display()).yellow());
+
+    // The scratch database starts out empty, so the dump loads as it is whatever the strategy.
+    let restored = restore::restore(
+        docker,
+        container_id,
+        &scratch,
+        dump,
+        restore::Load {
+            transform: !args.no_transform,
+            strategy: Strategy::Abort,
+            single_transaction: !args.no_transaction,
+            tables: dump.tables.as_ref(),
+            no_fk_check: args.no_fk_check,
+            source_aerich: args.keep_source_aerich,
+            fast: args.fast,
+            continue_on_error: args.continue_on_error,
+            jobs: args.jobs,
+        },
+        &mut transcript,
+    )
+    .await;
+
+    let checks = match &restored {
+        Ok(_) => {
+            validate::row_counts(
+                docker,
+                container_id,
+                &scratch,
+                // The counts of a dump describe all of its tables, not those of --tables.
+                dump.manifest.as_ref().filter(|_| dump.tables.is_none()),
+                !args.no_transform,
+            )
+            .await
+        }
+        Err(_) => Vec::new(),
+    };
+
+    // Dropped whatever happened, the scratch database is of no use afterwards.
+    let dropped = container::exec(
+        docker,
+        container_id,
+        vec!["dropdb", "-U", database.user.as_str(), "--if-exists", scratch_name],
+        database.env(),
+    )
+    .await;
+
+    if !dropped.as_ref().is_ok_and(|output| output.exit_code == Some(0)) {
+        output::status(
+            format!(
+                "{} Failed to drop the scratch database {}, drop it by hand.",
+                Glyph::Warning,
+                scratch_name
+            )
+            .yellow(),
+        );
+    }
+
+    let restored = restored.map_err(|error| {
+        Error::new(
+            error.kind,
+            format!(
+                "The rehearsal failed, the real database was not touched: {}",
+                error.message
+            ),
+        )
+    })?;
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+
+    if output::is_json() {
+        output::json(&RehearsalReport {
+            instance: format_name(&target.name),
+            container_id,
+            dump_path,
+            database: scratch_name,
+            renamed: restored.renamed,
+            failures: &restored.failures,
+            log: transcript.path(),
+            checks: &checks,
+            duration_ms: started.elapsed().as_millis(),
+            status: match (failed, restored.failures.len()) {
+                (0, 0) => "success",
+                (0, _) => "partial",
+                _ => "failed",
+            },
+        });
+    } else {
+        let outcome = format!(
+            "{} The dump loads into {} in {:.1}s.",
+            Glyph::Ok,
+            format_name(&target.name),
+            started.elapsed().as_secs_f64()
+        );
+        output::info(outcome.green().bold());
+
+        for failure in &restored.failures {
+            output::status(format!("{} {}", Glyph::Warning, failure.describe(Some(1))).yellow());
+        }
+
+        validate::print(&checks);
+    }
+
+    match (failed, restored.failures.len()) {
+        (0, 0) => Ok(()),
+        (0, failures) => Err(Error::new(
+            ErrorKind::PartialImport,
+            format!(
+                "The dump loaded, but {} statement(s) of it failed and were skipped. The real database was not \
+                 touched.",
+                failures
+            ),
+        )),
+        (failed, _) => Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "The dump loaded, but {} check(s) of the rehearsal failed. The real database was not touched.",
+                failed
+            ),
+        )),
+    }
+}
+
+///
+/// Returns where the backup of the target is written before an import: next to the dump, named after the time.
+///
+/// #### Arguments
+///
+/// * `dump_path`: The dump that is about to be imported.
+///
+fn backup_path(dump_path: &Path) -> PathBuf {
+    let name = format!("pre-import-backup-{}.sql", Local::now().format("%Y%m%d-%H%M%S"));
+
+    match dump_path.parent() {
+        Some(parent) => parent.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+///
+/// Returns where the log of an import is written: in the configured output directory, or else next to the dump.
+///
+/// #### Arguments
+///
+/// * `dump_path`: The first dump of the import.
+/// * `instance`: The short name of the target.
+/// * `options`: The options for this run.
+///
+fn log_path(dump_path: &Path, instance: &str, options: &Options) -> PathBuf {
+    let name = format!("import-{}-{}.log", instance, Local::now().format("%Y%m%d-%H%M%S"));
+
+    match (
+        options.output.as_deref().filter(|output| is_output_dir(output)),
+        dump_path.parent(),
+    ) {
+        (Some(output), _) => output.join(name),
+        (None, Some(parent)) => parent.join(name),
+        (None, None) => PathBuf::from(name),
+    }
+}
+
+///
+/// Writes the statements that failed with `--continue-on-error` to a log next to the first dump, named after the time.
+///
+/// #### Arguments
+///
+/// * `dump_path`: The first dump of the import.
+/// * `dumps`: The dumps that were loaded, with the statements of each that failed.
+///
+/// #### Returns
+///
+/// The path of the log, or an error if it could not be written.
+///
+fn write_error_log(dump_path: &Path, dumps: &[(&Path, &[restore::Failure])]) -> Result<PathBuf, Error> {
+    let name = format!("import-errors-{}.log", Local::now().format("%Y%m%d-%H%M%S"));
+    let path = match dump_path.parent() {
+        Some(parent) => parent.join(name),
+        None => PathBuf::from(name),
+    };
+
+    let mut log = String::new();
+
+    for (dump, failures) in dumps {
+        for failure in failures.iter() {
+            log.push_str(&format!(
+                "-- {}{}\n{}\n\n",
+                dump.display(),
+                failure.line.map(|line| format!(", line {}", line)).unwrap_or_default(),
+                failure.describe(None)
+            ));
+        }
+    }
+
+    std::fs::write(&path, log).map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    })?;
+
+    Ok(path)
+}
+
+///
+/// Exports the target of an import before anything is loaded into it.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `target`: The running instance to back up.
+/// * `dump_path`: The dump that is about to be imported.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// The path of the backup, or an error, in which case the import must not start.
+///
+async fn backup_target(
+    docker: &Docker,
+    target: &Instance,
+    dump_path: &Path,
+    options: &Options,
+) -> Result<PathBuf, Error> {
+    let path = backup_path(dump_path);
+    output::status(
+        format!(
+            "{} Backing up {} to {}...",
+            Glyph::Pending,
+            format_name(&target.name),
+            path.display()
+        )
+        .yellow(),
+    );
+
+    let args = ExportArgs {
+        output: Some(path.clone()),
+        force: true,
+        no_config: true,
+        ..Default::default()
+    };
+
+    let failed = |message: String| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("The backup of the target failed, nothing was imported: {}", message),
+        )
+    };
+
+    match run_export(docker, target, &args, options).await {
+        Ok(Some(summary)) => {
+            output::status(
+                format!(
+                    "{} Backed up {} ({}).",
+                    Glyph::Ok,
+                    format_name(&target.name),
+                    format_size(summary.bytes)
+                )
+                .dimmed(),
+            );
+            Ok(summary.dump_path)
         }
+        Ok(None) => Err(failed("no backup was written".to_string())),
+        Err(error) => Err(failed(error.message)),
     }
 }
 
+///
+/// The operations offered by the interactive menu.
+///
+enum Operation {
+    Export,
+    Import,
+}
+
 ///
 /// Promps the user to select an operation (export or import).
 ///
 /// #### Arguments
 ///
 /// * `docker`: The Docker client.
-/// * `instances`: A HashMap of Ballsdex instances names.
-///
-async fn prompt(docker: &Docker, instances: &HashMap<String, String>) {
-    println!(
-        "{}",
-        "Welcome to NDMIG, a Ballsdex to NationDex migration tool!\n"
-            .bold()
-            .bright_white()
+/// * `instances`: The discovered Ballsdex instances.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the selected operation failed.
+///
+async fn prompt(docker: &Docker, instances: &[Instance], options: &Options) -> Result<(), Error> {
+    loop {
+        output::info(
+            "Welcome to NDMIG, a Ballsdex to NationDex migration tool!\n"
+                .bold()
+                .bright_white(),
+        );
+
+        output::info("  1. Export"); // TODO: Make this look better
+        output::info("  2. Import");
+
+        let operation = prompt::ask(options, "Operation (q to quit):", |answer| match answer {
+            "1" => Ok(Operation::Export),
+            "2" => Ok(Operation::Import),
+            _ => Err("Invalid operation ('1' or '2').".to_string()),
+        })?;
+
+        match operation {
+            Some(Operation::Export) => match select_instances(docker, instances, options).await? {
+                Some(selected) => {
+                    let args = ExportArgs {
+                        output: options.output.clone(),
+                        config_pg_dump_args: options.pg_dump_args.clone(),
+                        ..Default::default()
+                    };
+
+                    return export_batch(docker, &selected, &args, options).await;
+                }
+                None => {
+                    clearscreen::clear().expect("Failed to clear screen");
+                }
+            },
+            Some(Operation::Import) => {
+                return import(docker, instances, &ImportArgs::default(), options).await;
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+///
+/// Prints the effective configuration.
+///
+/// #### Arguments
+///
+/// * `options`: The options for this run.
+///
+fn show_config(options: &Options) {
+    let effective = options.effective();
+
+    if output::is_json() {
+        output::json(&serde_json::json!({ "config_file": options.config_file, "config": effective }));
+        return;
+    }
+
+    let source = match &options.config_file {
+        Some(path) => path.display().to_string(),
+        None => "none".to_string(),
+    };
+
+    output::info(format!("# config file: {}", source).dimmed());
+    output::info(
+        toml::to_string_pretty(&effective)
+            .expect("Failed to serialize configuration")
+            .trim_end(),
     );
+}
+
+///
+/// Runs the operation selected on the command line.
+///
+/// #### Arguments
+///
+/// * `cli`: The parsed command line.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or the error that ended the run.
+///
+async fn run(cli: Cli, options: &Options) -> Result<(), Error> {
+    match &cli.command {
+        Some(Command::Doctor(args)) => return doctor::run(args).await,
+        Some(Command::History(args)) => return history::run(args),
+        Some(Command::Clean(args)) => return clean::run(args, options),
+        Some(Command::Exports(args)) => return exports::run(args, options),
+        Some(Command::Inspect(args)) => return inspect::run(args),
+        Some(Command::Transform(args)) => return transform::run(args).await,
+        Some(Command::Config(ConfigCommand::Show)) => {
+            show_config(options);
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    println!("  1. Export"); // TODO: Make this look better
-    println!("  2. Import");
+    if matches!(cli.command, None | Some(Command::Tui)) {
+        // Checked before connecting to Docker, so a misconfigured cron job fails with the hint right away.
+        prompt::require_interactive(
+            "ask which operation to run",
+            "a subcommand and its arguments, e.g. `ndmig export --instance <name> --yes`,",
+        )?;
 
-    print!("\n{}", "Operation: ".bold().white());
-    let _ = io::stdout().flush();
+        clearscreen::clear().expect("Failed to clear screen");
+    }
 
-    let mut operation = String::new();
-    io::stdin().read_line(&mut operation).expect("Failed to read input");
+    if let Some(Command::Import(args)) = &cli.command {
+        if args.file.len() > 1 && args.strategy != Strategy::Merge && !args.rehearse {
+            return Err(Error::new(
+                ErrorKind::Config,
+                "Several dumps can only be imported with --strategy merge, which merges them one after the other.",
+            ));
+        }
 
-    match operation.trim() {
-        "1" => export(docker, instances).await,
-        "2" => println!("TBA"),
-        _ => {
-            eprintln!("{}", "✗ Invalid operation ('1' or '2').".red().bold());
-            process::exit(1);
+        if args.triggers == merge::Triggers::Disable && args.strategy != Strategy::Merge {
+            return Err(Error::new(
+                ErrorKind::Config,
+                "--triggers disable only applies to --strategy merge, a dump loaded by psql creates its triggers \
+                 after its rows.",
+            ));
+        }
+
+        // Checked before connecting to Docker too, so a mistyped path fails the same on a machine without it. An
+        // encrypted dump is only decrypted once, by the import itself.
+        for path in &args.file {
+            if !encrypt::is_encrypted(&restore::resolve(path)?) {
+                restore::open(path).await?;
+            }
+        }
+    }
+
+    let docker = Docker::connect_with_local_defaults().map_err(|e| {
+        Error::new(
+            ErrorKind::DockerConnection,
+            format!("Failed to connect to Docker: {}", e),
+        )
+    })?;
+
+    let (ignored, instances): (Vec<Instance>, Vec<Instance>) = discover_instances(&docker)
+        .await
+        .map_err(|e| Error::new(ErrorKind::DockerConnection, format!("Failed to list containers: {}", e)))?
+        .into_iter()
+        .partition(|instance| options.is_ignored(instance));
+
+    if !ignored.is_empty() {
+        output::debug(format!("Ignoring {} instance(s) from the ignore list", ignored.len()));
+    }
+
+    match cli.command {
+        Some(Command::Export(args)) if args.all => {
+            if instances.is_empty() {
+                return Err(Error::new(ErrorKind::InstanceNotFound, "No Ballsdex instances found."));
+            }
+
+            let args = ExportArgs {
+                output: args.output.or_else(|| options.output.clone()),
+                config_pg_dump_args: options.pg_dump_args.clone(),
+                ..*args
+            };
+
+            export_batch(&docker, &instances.iter().collect::<Vec<_>>(), &args, options).await
+        }
+        Some(Command::Export(mut args)) => {
+            args.instance = args.instance.or_else(|| options.instance.clone());
+            args.output = args.output.or_else(|| options.output.clone());
+            args.config_pg_dump_args = options.pg_dump_args.clone();
+
+            // Set before anything is printed or asked, so stdout only ever carries the dump.
+            output::set_stdout_dump(is_stdout(args.output.as_deref()));
+
+            let instance = match args.instance.as_deref() {
+                Some(name) => match_instance(&instances, name, options)
+                    .map_err(|message| Error::new(ErrorKind::InstanceNotFound, message))?,
+                None => select_instance(&docker, &instances, options)
+                    .await?
+                    .ok_or_else(|| Error::new(ErrorKind::Aborted, "Export cancelled."))?,
+            };
+
+            export(&docker, instance, &args, options).await.map(|_| ())
+        }
+        Some(Command::Import(args)) => import(&docker, &instances, &args, options).await,
+        Some(Command::Info(args)) => info::run(&docker, &instances, &args, options).await,
+        Some(Command::Audit(args)) => audit::command(&docker, &instances, &args, options).await,
+        Some(Command::List(args)) => {
+            list(&instances, &ignored, &args);
+            Ok(())
         }
+        Some(
+            Command::Doctor(_)
+            | Command::Config(_)
+            | Command::History(_)
+            | Command::Clean(_)
+            | Command::Exports(_)
+            | Command::Inspect(_)
+            | Command::Transform(_),
+        ) => unreachable!(),
+        Some(Command::Tui) | None if tui::supported() => tui::run(&docker, instances, options).await,
+        Some(Command::Tui) | None => prompt(&docker, &instances, options).await,
     }
 }
 
@@ -223,47 +5260,166 @@ async fn prompt(docker: &Docker, instances: &HashMap<String, String>) {
 ///
 #[tokio::main]
 async fn main() {
-    clearscreen::clear().expect("Failed to clear screen");
+    let cli = Cli::parse();
 
-    let docker = match Docker::connect_with_local_defaults() {
-        Ok(docker) => docker,
-        Err(e) => {
-            eprintln!("{} {}", "✗ Failed to connect to Docker:".red().bold(), e);
-            process::exit(1);
+    // Handled before the configuration is loaded, so it works even with a broken config file.
+    if cli.version {
+        output::set_json(cli.json);
+        output::set_plain(cli.no_color || !io::stdout().is_terminal());
+        version::run().await;
+        return;
+    }
+
+    let options = match Options::load(&cli) {
+        Ok(options) => options,
+        Err(error) => {
+            output::set_json(cli.json);
+            output::set_plain(cli.no_color || !io::stdout().is_terminal());
+            output::error(&error);
+            process::exit(error.kind.exit_code());
         }
     };
 
-    let options = Some(ListContainersOptions {
-        all: true,
-        ..Default::default()
-    });
+    output::set_level(options.level);
+    output::set_json(options.json);
+    output::set_plain(options.plain);
+    container::set_exec_attempts(options.exec_attempts);
 
-    let all = docker
-        .list_containers(options)
-        .await
-        .expect("Failed to list containers");
+    for warning in &options.warnings {
+        eprintln!("{}", format!("{} {}", Glyph::Warning, warning).yellow());
+    }
 
-    let mut instances: HashMap<String, String> = HashMap::new();
+    interrupt::install();
 
-    for container in all {
-        let id = match container.id.as_deref() {
-            Some(id) => id,
-            None => continue,
-        };
-        if is_ballsdex_instance(&docker, id).await {
-            let project_name = container
-                .names
-                .iter()
-                .flatten()
-                .next()
-                .map(|name| name.trim_start_matches("/").to_string())
-                .unwrap_or_else(|| id.to_string());
+    if let Err(error) = run(cli, &options).await {
+        output::error(&error);
+        process::exit(error.kind.exit_code());
+    }
+}
 
-            if project_name.ends_with("postgres-db-1") {
-                instances.insert(project_name, id.to_string());
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// Writes a plain dump to the temporary directory and describes it as an import would.
+    ///
+    /// #### Arguments
+    ///
+    /// * `name`: The file name of the dump.
+    /// * `sql`: The dump.
+    ///
+    fn plain_dump(name: &str, sql: &str) -> restore::Dump {
+        let path = std::env::temp_dir().join(format!("ndmig-test-{}-{}", process::id(), name));
+        std::fs::write(&path, sql).unwrap();
+
+        restore::Dump {
+            path,
+            bytes: sql.len() as u64,
+            manifest: None,
+            sha256: None,
+            tables: None,
+            bundle: None,
+            verified: false,
+            format: DumpFormat::Plain,
+            compression: Compression::None,
+            decrypted: None,
         }
     }
 
-    prompt(&docker, &instances).await;
+    const DUMP: &str = "CREATE TABLE public.player (\n    id integer NOT NULL\n);\n\
+                        CREATE TABLE public.ballinstance (\n    id integer NOT NULL,\n    player_id integer NOT NULL\n);\n\
+                        CREATE TABLE public.config (\n    id integer NOT NULL\n);\n\
+                        ALTER TABLE ONLY public.ballinstance\n    ADD CONSTRAINT ballinstance_player_id_fkey \
+                        FOREIGN KEY (player_id) REFERENCES public.player(id);\n";
+
+    ///
+    /// Describes the postgres containers of the given compose projects.
+    ///
+    fn instances(projects: &[&str]) -> Vec<Instance> {
+        projects
+            .iter()
+            .map(|project| Instance {
+                name: format!("{}{}", project, INSTANCE_SUFFIX),
+                container_id: format!("{}-id", project),
+                running: true,
+                flavor: container::Flavor::Ballsdex,
+            })
+            .collect()
+    }
+
+    ///
+    /// Returns the project names of matched instances.
+    ///
+    fn names(matches: Vec<&Instance>) -> Vec<&str> {
+        matches
+            .into_iter()
+            .map(|instance| project_name(&instance.name))
+            .collect()
+    }
+
+    #[test]
+    fn matches_names_in_any_case() {
+        let instances = instances(&["mydex", "otherdex"]);
+
+        assert_eq!(names(case_insensitive_matches(&instances, "MyDex")), ["mydex"]);
+        assert_eq!(resolve_instance(&instances, "MYDEX").unwrap().container_id, "mydex-id");
+        assert!(resolve_instance(&instances, "mydexx").is_none());
+    }
+
+    #[test]
+    fn ignores_surrounding_whitespace() {
+        let instances = instances(&["mydex", "otherdex"]);
+
+        assert_eq!(names(case_insensitive_matches(&instances, "otherdex \n")), ["otherdex"]);
+        assert_eq!(
+            resolve_instance(&instances, " mydex\t").unwrap().container_id,
+            "mydex-id"
+        );
+        assert_eq!(
+            resolve_instance(&instances, " MyDex ").unwrap().container_id,
+            "mydex-id"
+        );
+    }
+
+    #[test]
+    fn names_that_only_differ_by_case_are_ambiguous() {
+        let instances = instances(&["MyDex", "mydex"]);
+
+        // The exact name still selects its instance, any other spelling matches both.
+        assert_eq!(resolve_instance(&instances, "MyDex").unwrap().container_id, "MyDex-id");
+        assert_eq!(resolve_instance(&instances, "mydex").unwrap().container_id, "mydex-id");
+        assert!(resolve_instance(&instances, "MYDEX").is_none());
+        assert_eq!(names(case_insensitive_matches(&instances, "MYDEX")), ["MyDex", "mydex"]);
+    }
+
+    #[tokio::test]
+    async fn select_tables_keeps_a_table_without_references() {
+        let mut dump = plain_dump("select-config.sql", DUMP);
+        let args = ImportArgs {
+            tables: vec!["config".to_string()],
+            ..Default::default()
+        };
+
+        let selected = select_tables(&mut dump, &args).await;
+        std::fs::remove_file(&dump.path).unwrap();
+
+        selected.unwrap();
+        assert_eq!(dump.tables, Some(BTreeSet::from(["config".to_string()])));
+    }
+
+    #[tokio::test]
+    async fn select_tables_refuses_a_missing_reference() {
+        let mut dump = plain_dump("select-ballinstance.sql", DUMP);
+        let args = ImportArgs {
+            tables: vec!["ballinstance".to_string()],
+            ..Default::default()
+        };
+
+        let selected = select_tables(&mut dump, &args).await;
+        std::fs::remove_file(&dump.path).unwrap();
+
+        assert_eq!(selected.unwrap_err().kind, ErrorKind::Config);
+        assert_eq!(dump.tables, None);
+    }
 }
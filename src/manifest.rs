@@ -0,0 +1,263 @@
+/*
+    The manifest written next to a dump, describing where it came from.
+*/
+
+use crate::incremental::Mark;
+use crate::split::Split;
+use crate::summary::Summary;
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What is appended to the dump path to get the path of its manifest.
+pub const SUFFIX: &str = ".manifest.json";
+
+/// The version of the manifest layout written by this build. Bumped when a field changes meaning.
+pub const VERSION: u32 = 1;
+
+///
+/// The contents of `<dump>.manifest.json`.
+///
+/// Every field is optional when reading, so manifests written by other ndmig versions still load.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The layout version of the manifest, see [`VERSION`]. Missing in manifests written before it was added.
+    pub manifest_version: Option<u32>,
+    /// The version of ndmig that wrote the dump.
+    pub ndmig_version: Option<String>,
+    /// The short name of the exported instance.
+    pub instance: Option<String>,
+    /// The container ID of the exported instance.
+    pub container_id: Option<String>,
+    /// The image of the exported container, e.g. `postgres:16`.
+    pub image: Option<String>,
+    /// The version of the postgres server the dump was taken from.
+    pub server_version: Option<String>,
+    /// The version of the pg_dump that wrote the dump, which pg_restore has to be at least as new as for an archive.
+    pub pg_dump_version: Option<String>,
+    /// When the export finished, as RFC 3339.
+    pub created_at: Option<String>,
+    /// How long the export took.
+    pub duration_ms: Option<u128>,
+    /// The pg_dump output format, e.g. `plain`.
+    pub format: Option<String>,
+    /// The number of parallel `pg_dump` jobs of a directory dump, which `pg_restore -j` can match on import.
+    pub jobs: Option<u32>,
+    /// The `pg_dump` command that wrote the dump.
+    pub pg_dump_args: Option<Vec<String>>,
+    /// Whether the dump sets the owners of the objects, with `--keep-owner`. `None` for formats not written by pg_dump.
+    ///
+    /// Such a dump can only be restored as is into a target with the same role names.
+    pub owner: Option<bool>,
+    /// Whether the dump grants the privileges of the source roles, with `--keep-privileges`.
+    pub privileges: Option<bool>,
+    /// Whether the rows of every table were sorted by primary key by `--stable`.
+    pub stable: Option<bool>,
+    /// Whether the Discord IDs were replaced with pseudonyms by `--anonymize`.
+    ///
+    /// An anonymized dump is meant for debugging and must never be imported into a production instance.
+    pub anonymized: Option<bool>,
+    /// How the dump is encrypted, e.g. `age (recipient)`, or `None` if it is not. The key is never recorded.
+    ///
+    /// The SHA-256 and the sizes are those of the ciphertext on disk.
+    pub encryption: Option<String>,
+    /// The compression of the dump file, e.g. `gzip`.
+    pub compression: Option<String>,
+    /// The tables the dump was limited to with `--tables`, or `None` if it holds every table.
+    pub tables: Option<Vec<String>>,
+    /// The tables left out with `--exclude-tables`, or `None` if none were.
+    pub excluded_tables: Option<Vec<String>>,
+    /// What the dump holds: `full`, `schema`, `data`, `delta`, `guild`, `player` or `filtered`.
+    ///
+    /// A `data` dump can only be imported into a database that already has the schema.
+    pub mode: Option<String>,
+    /// The Discord server a `guild` dump was reduced to. Such a dump only holds the rows of that server and the rows
+    /// they reference.
+    pub guild_id: Option<u64>,
+    /// The Discord user whose data a `player` export holds.
+    pub player: Option<u64>,
+    /// The conditions a `filtered` export picked the rows of its tables with, before the foreign keys carried them
+    /// over to the tables that reference them.
+    pub filters: Option<BTreeMap<String, String>>,
+    /// The schema and the data file of a `--split` export, the same in the manifests of both.
+    ///
+    /// The data file can only be loaded once the schema file was applied.
+    pub split: Option<Split>,
+    /// The file name of the export a `delta` dump builds on.
+    pub base: Option<String>,
+    /// The SHA-256 of the base, so a delta is never applied on top of another dump.
+    pub base_sha256: Option<String>,
+    /// The highest primary key per table with a single integer key, where the rows of the next delta start.
+    pub high_water_marks: Option<BTreeMap<String, Mark>>,
+    /// The hex-encoded SHA-256 of the dump file, as also written to `<dump>.sha256`.
+    pub sha256: Option<String>,
+    /// The size of the dump file.
+    pub bytes: Option<u64>,
+    /// The size of the dump before compression.
+    pub uncompressed_bytes: Option<u64>,
+    /// The number of rows of the core Ballsdex tables when the export started.
+    pub row_counts: Option<BTreeMap<String, u64>>,
+    /// Whether a Redis snapshot was saved next to the dump, as `<dump>.redis.rdb`.
+    pub redis: Option<bool>,
+    /// The size of the Redis snapshot.
+    pub redis_bytes: Option<u64>,
+    /// Whether the roles of the server were saved next to the dump, as `<dump>.globals.sql`.
+    pub globals: Option<bool>,
+    /// The size of the saved roles.
+    pub globals_bytes: Option<u64>,
+    /// Whether the bot's `config.yml` was copied next to the dump, as `<dump>.config.yml`.
+    pub bot_config: Option<bool>,
+    /// The number of files in the media archive next to the dump, as `<dump>.media.tar`.
+    pub media_files: Option<u64>,
+    /// The total size of the media files.
+    pub media_bytes: Option<u64>,
+    /// Whether the dump was read back after the export and found complete.
+    pub verified: Option<bool>,
+}
+
+impl Manifest {
+    ///
+    /// Creates the manifest of a finished export.
+    ///
+    /// #### Arguments
+    ///
+    /// * `summary`: The summary of the export.
+    ///
+    pub fn new(summary: &Summary) -> Self {
+        Manifest {
+            manifest_version: Some(VERSION),
+            ndmig_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            instance: Some(summary.instance.clone()),
+            container_id: Some(summary.container_id.clone()),
+            image: summary.image.clone(),
+            server_version: summary.server_version.clone(),
+            pg_dump_version: summary.pg_dump_version.clone(),
+            created_at: Some(Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)),
+            duration_ms: Some(summary.duration_ms),
+            format: Some(summary.format.name().to_string()),
+            jobs: summary.jobs,
+            pg_dump_args: Some(summary.pg_dump_args.clone()),
+            owner: (!summary.pg_dump_args.is_empty()).then_some(summary.owner),
+            privileges: (!summary.pg_dump_args.is_empty()).then_some(summary.privileges),
+            stable: Some(summary.stable),
+            anonymized: Some(summary.anonymized),
+            encryption: summary.encryption.map(str::to_string),
+            compression: Some(summary.compression.name().to_string()),
+            tables: (!summary.tables.is_empty()).then(|| summary.tables.clone()),
+            excluded_tables: (!summary.excluded_tables.is_empty()).then(|| summary.excluded_tables.clone()),
+            mode: Some(summary.mode.to_string()),
+            guild_id: summary.guild_id,
+            player: summary.player,
+            filters: summary.filters.clone(),
+            split: None,
+            base: summary
+                .base
+                .as_ref()
+                .map(|base| base.file_name().unwrap_or_default().to_string_lossy().into_owned()),
+            base_sha256: summary.base_sha256.clone(),
+            high_water_marks: summary.high_water_marks.clone(),
+            sha256: summary.checksum.clone(),
+            bytes: Some(summary.bytes),
+            uncompressed_bytes: Some(summary.uncompressed_bytes),
+            row_counts: (!summary.row_counts.is_empty()).then(|| summary.row_counts.clone()),
+            redis: Some(summary.redis_bytes.is_some()),
+            redis_bytes: summary.redis_bytes,
+            globals: Some(summary.globals_bytes.is_some()),
+            globals_bytes: summary.globals_bytes,
+            bot_config: Some(summary.bot_config),
+            media_files: summary.media.map(|media| media.files),
+            media_bytes: summary.media.map(|media| media.bytes),
+            verified: Some(summary.verified),
+        }
+    }
+
+    ///
+    /// Returns the role the dump was taken with, the `-U` of the recorded `pg_dump` command.
+    ///
+    pub fn user(&self) -> Option<&str> {
+        let args = self.pg_dump_args.as_deref()?;
+
+        args.iter()
+            .position(|arg| arg == "-U")
+            .and_then(|index| args.get(index + 1))
+            .map(String::as_str)
+    }
+
+    ///
+    /// Checks whether this build of ndmig understands the manifest.
+    ///
+    /// #### Returns
+    ///
+    /// Nothing, or a message if the manifest was written by a newer ndmig with a layout this build does not know.
+    ///
+    pub fn check_compatible(&self) -> Result<(), String> {
+        match self.manifest_version {
+            Some(version) if version > VERSION => Err(format!(
+                "Manifest version {} was written by ndmig {}, this build only reads up to version {}",
+                version,
+                self.ndmig_version.as_deref().unwrap_or("unknown"),
+                VERSION
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+///
+/// Returns the path of the manifest that belongs to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// Reads the manifest of a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+/// #### Returns
+///
+/// The manifest, `None` if the dump has none, or a message if it exists but cannot be read.
+///
+pub fn read(dump: &Path) -> Result<Option<Manifest>, String> {
+    let path = path(dump);
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e))
+}
+
+///
+/// Writes the manifest of a dump, replacing an existing one.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+/// * `manifest`: The manifest to write.
+///
+/// #### Returns
+///
+/// Nothing, or a message if the manifest could not be written.
+///
+pub fn write(dump: &Path, manifest: &Manifest) -> Result<(), String> {
+    let path = path(dump);
+    let contents = serde_json::to_string_pretty(manifest).expect("Failed to serialize manifest");
+
+    std::fs::write(&path, contents + "\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,126 @@
+/*
+    Exports the files uploaded through the admin panel, such as ball artwork, for `--include-media`.
+*/
+
+use crate::archive::{self, ArchiveContents};
+use crate::container::{self, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use crate::progress::Progress;
+use bollard::Docker;
+use colored::*;
+use std::path::{Path, PathBuf};
+
+/// What is appended to the dump path to get the path of the media archive.
+pub const SUFFIX: &str = ".media.tar";
+
+/// Where the Ballsdex compose file puts the uploads when they are not on a volume of their own.
+const DEFAULT_MEDIA_PATH: &str = "/code/admin_panel/media";
+
+/// The services whose containers see the uploads, in the order they are searched.
+const SERVICES: [&str; 2] = ["admin-panel", "bot"];
+
+///
+/// Returns the path of the media archive that belongs to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// Finds the media directory of an instance.
+///
+/// A mount ending in `/media` in the admin panel or bot container wins. Without one, the uploads are expected at
+/// their default path inside the first of those containers that exists.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+///
+/// #### Returns
+///
+/// The container and the directory inside it, or an error if the project has neither container.
+///
+pub async fn find(docker: &Docker, instance: &str) -> Result<(Instance, String), Error> {
+    let mut fallback = None;
+
+    for service in SERVICES {
+        let Some(candidate) = container::find_service(docker, instance, service)
+            .await
+            .map_err(|e| Error::new(ErrorKind::DumpFailed, format!("Failed to list the containers: {}", e)))?
+        else {
+            continue;
+        };
+
+        output::debug(format!("inspect_container {}", candidate.container_id));
+
+        let mounts = docker
+            .inspect_container(&candidate.container_id, None)
+            .await
+            .ok()
+            .and_then(|info| info.mounts)
+            .unwrap_or_default();
+
+        let media = mounts
+            .into_iter()
+            .filter_map(|mount| mount.destination)
+            .find(|destination| destination.trim_end_matches('/').ends_with("/media"));
+
+        if let Some(media) = media {
+            return Ok((candidate, media));
+        }
+
+        fallback.get_or_insert(candidate);
+    }
+
+    fallback
+        .map(|candidate| (candidate, DEFAULT_MEDIA_PATH.to_string()))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InstanceNotFound,
+                format!(
+                    "--include-media needs a {0}-admin-panel-1 or {0}-bot-1 container, but the project has neither.",
+                    container::project(instance)
+                ),
+            )
+        })
+}
+
+///
+/// Copies the media directory of an instance next to the dump as a tar archive.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+/// * `dump_path`: The path of the dump the media belongs to.
+///
+/// #### Returns
+///
+/// The number and total size of the files, or an error.
+///
+pub async fn export(docker: &Docker, instance: &str, dump_path: &Path) -> Result<ArchiveContents, Error> {
+    let (source, directory) = find(docker, instance).await?;
+
+    output::status(format!("{} Copying {} out of {}...", Glyph::Pending, directory, source.name).yellow());
+
+    let destination = path(dump_path);
+    let progress = Progress::new(None).with_label(&crate::format_name(instance));
+    let contents = archive::download_archive(docker, &source.container_id, &directory, &destination, &progress).await?;
+
+    output::debug(format!(
+        "Wrote {} ({} files, {} bytes)",
+        destination.display(),
+        contents.files,
+        contents.bytes
+    ));
+
+    Ok(contents)
+}
@@ -0,0 +1,794 @@
+/*
+    Merging a dump into a database that already holds data, for `import --strategy merge`.
+
+    The dump is first loaded into the schema [`SCHEMA`] next to the tables of the target, so its statements run as
+    they are. Its rows are then inserted into the tables of the target, the referenced tables first. A row that
+    matches an existing one by its primary key or a unique key is left out and the rows pointing at it point at the
+    existing row instead. A row whose generated ID is already taken gets a new one from the sequence of the target,
+    and the rows pointing at it follow.
+*/
+
+use crate::container::{self, Database};
+use crate::error::{Error, ErrorKind};
+use crate::incremental;
+use crate::json::quote;
+use crate::output;
+use crate::restore;
+use bollard::Docker;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The schema the tables of the dump are loaded into before their rows are merged.
+pub const SCHEMA: &str = "ndmig_merge";
+
+/// The tables that describe the target itself rather than its data, which keep their rows.
+const OWN_TABLES: &[&str] = &["aerich"];
+
+/// The table in [`SCHEMA`] the merge counts its rows in.
+const STATS_TABLE: &str = "ndmig_stats";
+
+/// The table of the Discord users, and the column that tells them apart across instances.
+const PLAYER_TABLE: (&str, &str) = ("player", "discord_id");
+
+/// The column of the player table that says whether a player accepts donations. Lower values allow more.
+const DONATION_POLICY: &str = "donation_policy";
+
+/// How many Discord IDs of duplicate players an error names.
+const DUPLICATES_SHOWN: usize = 5;
+
+///
+/// What a merge does with a player of the dump whose Discord ID the target already has.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePlayers {
+    /// Keep the player of the target, combine their settings and give it the rows of the dump's player.
+    #[default]
+    Merge,
+    /// Keep the player of the target as it is and leave out the rows of the dump that belong to the duplicate.
+    Skip,
+    /// Stop the merge.
+    Error,
+}
+
+impl DuplicatePlayers {
+    ///
+    /// Returns the name used on the command line, e.g. `skip`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            DuplicatePlayers::Merge => "merge",
+            DuplicatePlayers::Skip => "skip",
+            DuplicatePlayers::Error => "error",
+        }
+    }
+}
+
+///
+/// Which donation policy a merged player keeps when the two players disagree.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DonationPolicy {
+    /// The one that allows more donations.
+    #[default]
+    Permissive,
+    /// The one that allows fewer donations.
+    Strict,
+    /// The one of the target.
+    Target,
+}
+
+///
+/// Whether the triggers of the target fire while the rows of a dump are merged into it.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Triggers {
+    /// Every trigger fires, including those postgres checks the foreign keys with.
+    #[default]
+    Enable,
+    /// No trigger fires, which also skips the checks of the foreign keys. Needs a superuser.
+    Disable,
+}
+
+impl Triggers {
+    ///
+    /// Returns the name used on the command line, e.g. `disable`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Triggers::Enable => "enable",
+            Triggers::Disable => "disable",
+        }
+    }
+}
+
+///
+/// The choices of a merge about players that exist on both sides, and about the triggers of the target.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rules {
+    pub duplicate_players: DuplicatePlayers,
+    pub donation_policy: DonationPolicy,
+    pub triggers: Triggers,
+}
+
+///
+/// What happened to the rows of one table.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    /// The rows inserted with the key they had in the dump.
+    pub inserted: u64,
+    /// The rows inserted with a new ID, as theirs was taken.
+    pub remapped: u64,
+    /// The rows left out, as the target already holds them.
+    pub skipped: u64,
+    /// The rows left out with the duplicate players they belong to, with `--on-duplicate-player skip`.
+    pub dropped: u64,
+}
+
+///
+/// The result of a merge.
+///
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Merge {
+    /// The dump that was merged.
+    pub dump: PathBuf,
+    /// What happened to the rows, per table of the target.
+    pub tables: BTreeMap<String, Counts>,
+    /// The players of the dump whose Discord ID the target already had.
+    pub duplicate_players: u64,
+    /// The tables of the dump the target does not have, or keeps its own rows of, whose rows were left out.
+    pub ignored: Vec<String>,
+}
+
+///
+/// The single-column primary key of a table.
+///
+struct PrimaryKey {
+    column: String,
+    /// The type of the column, e.g. `integer`.
+    kind: String,
+    /// The sequence that generates the key, `None` if the key is not generated.
+    sequence: Option<String>,
+}
+
+///
+/// A single-column foreign key of the target.
+///
+struct Reference {
+    table: String,
+    column: String,
+    referenced: String,
+    referenced_column: String,
+}
+
+///
+/// What the merge needs to know about the tables of the target and the loaded dump.
+///
+#[derive(Default)]
+struct Layout {
+    /// The columns of every table of the target, in table order.
+    target: BTreeMap<String, Vec<String>>,
+    /// The type of every column of the target, by table and column, e.g. `integer`.
+    kinds: BTreeMap<(String, String), String>,
+    /// The columns of every table of the dump.
+    staged: BTreeMap<String, Vec<String>>,
+    primary_keys: BTreeMap<String, PrimaryKey>,
+    /// The columns of the unique keys of every table of the target, without the primary keys.
+    unique_keys: BTreeMap<String, Vec<Vec<String>>>,
+    references: Vec<Reference>,
+}
+
+///
+/// Returns the SQL to run before the dump is loaded, which makes room for its tables.
+///
+pub fn prelude() -> String {
+    format!(
+        "DROP SCHEMA IF EXISTS {schema} CASCADE;\nCREATE SCHEMA {schema};\n",
+        schema = SCHEMA
+    )
+}
+
+///
+/// Reads the tables of the target and of the loaded dump.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to connect with.
+///
+async fn layout(docker: &Docker, container_id: &str, database: &Database) -> Result<Layout, Error> {
+    let mut layout = Layout::default();
+
+    let columns = container::rows(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT table_schema, table_name, column_name, data_type FROM information_schema.columns \
+             WHERE table_schema IN ('public', '{}') AND is_generated = 'NEVER' \
+             ORDER BY table_schema, table_name, ordinal_position",
+            SCHEMA
+        ),
+        ErrorKind::ImportFailed,
+        "read the tables to merge",
+    )
+    .await?;
+
+    for row in columns {
+        if let [schema, table, column, kind] = row.as_slice() {
+            if schema == "public" {
+                layout.kinds.insert((table.clone(), column.clone()), kind.clone());
+            }
+
+            let tables = match schema.as_str() {
+                "public" => &mut layout.target,
+                _ => &mut layout.staged,
+            };
+            tables.entry(table.clone()).or_default().push(column.clone());
+        }
+    }
+
+    let primary_keys = container::rows(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, a.attname, format_type(a.atttypid, a.atttypmod), \
+         coalesce(pg_get_serial_sequence(format('%I.%I', n.nspname, c.relname), a.attname), '') \
+         FROM pg_constraint k JOIN pg_class c ON c.oid = k.conrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = k.conrelid AND a.attnum = k.conkey[1] \
+         WHERE k.contype = 'p' AND n.nspname = 'public' AND cardinality(k.conkey) = 1",
+        ErrorKind::ImportFailed,
+        "read the tables to merge",
+    )
+    .await?;
+
+    for row in primary_keys {
+        if let [table, column, kind, sequence] = row.as_slice() {
+            layout.primary_keys.insert(
+                table.clone(),
+                PrimaryKey {
+                    column: column.clone(),
+                    kind: kind.clone(),
+                    sequence: Some(sequence.clone()).filter(|sequence| !sequence.is_empty()),
+                },
+            );
+        }
+    }
+
+    // Partial and expression indexes do not say when two rows are the same, so only plain ones count.
+    let unique_keys = container::rows(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, string_agg(a.attname, ',' ORDER BY array_position(i.indkey::int2[], a.attnum)) \
+         FROM pg_index i JOIN pg_class c ON c.oid = i.indrelid JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = ANY(i.indkey) \
+         WHERE i.indisunique AND NOT i.indisprimary AND i.indpred IS NULL AND i.indexprs IS NULL \
+         AND n.nspname = 'public' GROUP BY c.relname, i.indexrelid",
+        ErrorKind::ImportFailed,
+        "read the tables to merge",
+    )
+    .await?;
+
+    for row in unique_keys {
+        if let [table, columns] = row.as_slice() {
+            layout
+                .unique_keys
+                .entry(table.clone())
+                .or_default()
+                .push(columns.split(',').map(str::to_string).collect());
+        }
+    }
+
+    let references = container::rows(
+        docker,
+        container_id,
+        database,
+        "SELECT c.relname, a.attname, r.relname, ra.attname FROM pg_constraint k \
+         JOIN pg_class c ON c.oid = k.conrelid JOIN pg_class r ON r.oid = k.confrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         JOIN pg_attribute a ON a.attrelid = k.conrelid AND a.attnum = k.conkey[1] \
+         JOIN pg_attribute ra ON ra.attrelid = k.confrelid AND ra.attnum = k.confkey[1] \
+         WHERE k.contype = 'f' AND n.nspname = 'public' AND cardinality(k.conkey) = 1",
+        ErrorKind::ImportFailed,
+        "read the tables to merge",
+    )
+    .await?;
+
+    for row in references {
+        if let [table, column, referenced, referenced_column] = row.as_slice() {
+            layout.references.push(Reference {
+                table: table.clone(),
+                column: column.clone(),
+                referenced: referenced.clone(),
+                referenced_column: referenced_column.clone(),
+            });
+        }
+    }
+
+    Ok(layout)
+}
+
+///
+/// Returns the table in [`SCHEMA`] that maps the keys a table had in the dump to those its rows have in the target.
+///
+/// #### Arguments
+///
+/// * `table`: The table.
+///
+fn map_table(table: &str) -> String {
+    format!("{}.{}", SCHEMA, quote(&format!("ndmig_map_{}", table)))
+}
+
+///
+/// Builds the SQL that merges the rows of one table.
+///
+/// #### Arguments
+///
+/// * `layout`: The tables of the target and the dump.
+/// * `table`: The table to merge, which both have.
+/// * `columns`: The columns both have, in the order of the target.
+/// * `rules`: What to do with players that exist on both sides.
+///
+fn merge_table(layout: &Layout, table: &str, columns: &[String], rules: Rules) -> String {
+    let staged = format!("{}.{}", SCHEMA, quote(table));
+    let target = format!("public.{}", quote(table));
+    let literal = table.replace('\'', "''");
+
+    // The foreign keys whose values go through the map of the referenced table.
+    let mapped = |reference: &&Reference| {
+        reference.table == table
+            && columns.contains(&reference.column)
+            && layout.staged.contains_key(&reference.referenced)
+            && layout
+                .primary_keys
+                .get(&reference.referenced)
+                .is_some_and(|key| key.column == reference.referenced_column)
+    };
+
+    // The value of a column in the target: keys of merged tables go through their maps, anything else as it is.
+    let value = |column: &str, own: bool| {
+        let reference = layout
+            .references
+            .iter()
+            .filter(mapped)
+            .find(|reference| reference.column == column && (own || reference.referenced != table));
+
+        match reference {
+            Some(reference) => format!(
+                "(SELECT m.new FROM {} m WHERE m.old = s.{})",
+                map_table(&reference.referenced),
+                quote(column)
+            ),
+            None => format!("s.{}", quote(column)),
+        }
+    };
+
+    let list = columns
+        .iter()
+        .map(|column| quote(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut sql = String::new();
+
+    // Rows that point at a row left out with its duplicate player go as well.
+    let dropped = layout
+        .references
+        .iter()
+        .filter(mapped)
+        .filter(|reference| reference.referenced != table)
+        .map(|reference| {
+            format!(
+                "EXISTS (SELECT 1 FROM {} d WHERE d.old = s.{} AND d.action = 'dropped')",
+                map_table(&reference.referenced),
+                quote(&reference.column)
+            )
+        })
+        .collect::<Vec<_>>();
+    let dropped = match dropped.is_empty() {
+        true => "false".to_string(),
+        false => dropped.join(" OR "),
+    };
+
+    let Some(key) = layout
+        .primary_keys
+        .get(table)
+        .filter(|key| columns.contains(&key.column))
+    else {
+        // Without a key to map, the rows are inserted unless they clash with an existing one.
+        let values = columns
+            .iter()
+            .map(|column| value(column, true))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        sql.push_str(&format!(
+            "WITH inserted AS (INSERT INTO {target} ({list}) OVERRIDING SYSTEM VALUE SELECT {values} FROM {staged} s \
+             WHERE NOT ({dropped}) ON CONFLICT DO NOTHING RETURNING 1)\n\
+             INSERT INTO {schema}.{stats} SELECT '{literal}', 'inserted', count(*) FROM inserted;\n\
+             INSERT INTO {schema}.{stats} SELECT '{literal}', 'dropped', count(*) FROM {staged} s WHERE {dropped};\n\
+             INSERT INTO {schema}.{stats} SELECT '{literal}', 'skipped', (SELECT count(*) FROM {staged}) - \
+             (SELECT sum(rows) FROM {schema}.{stats} WHERE table_name = '{literal}');\n",
+            schema = SCHEMA,
+            stats = STATS_TABLE,
+        ));
+        return sql;
+    };
+
+    let map = map_table(table);
+    let pk = quote(&key.column);
+
+    sql.push_str(&format!(
+        "CREATE TABLE {map} (old {kind} PRIMARY KEY, new {kind} NOT NULL, action text NOT NULL);\n\
+         INSERT INTO {map} SELECT s.{pk}, s.{pk}, 'dropped' FROM {staged} s WHERE {dropped};\n",
+        kind = key.kind
+    ));
+
+    // Rows the target already holds, found by a key that is not generated.
+    let mut natural_keys: Vec<Vec<String>> = Vec::new();
+
+    if key.sequence.is_none() {
+        natural_keys.push(vec![key.column.clone()]);
+    }
+
+    natural_keys.extend(
+        layout
+            .unique_keys
+            .get(table)
+            .into_iter()
+            .flatten()
+            .filter(|unique| unique.iter().all(|column| columns.contains(column)))
+            .cloned(),
+    );
+
+    let is_player = |unique: &[String]| table == PLAYER_TABLE.0 && unique == [PLAYER_TABLE.1];
+
+    for unique in &natural_keys {
+        let matches = unique
+            .iter()
+            .map(|column| format!("t.{} = {}", quote(column), value(column, false)))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        let action = match is_player(unique) && rules.duplicate_players == DuplicatePlayers::Skip {
+            true => "dropped",
+            false => "skipped",
+        };
+
+        sql.push_str(&format!(
+            "INSERT INTO {map} SELECT s.{pk}, t.{pk}, '{action}' FROM {staged} s JOIN {target} t ON {matches} \
+             ON CONFLICT (old) DO NOTHING;\n"
+        ));
+    }
+
+    if natural_keys.iter().any(|unique| is_player(unique)) && rules.duplicate_players == DuplicatePlayers::Merge {
+        sql.push_str(&combine_players(layout, columns, &key.column, rules));
+    }
+
+    match &key.sequence {
+        Some(sequence) => {
+            let sequence = sequence.replace('\'', "''");
+
+            sql.push_str(&format!(
+                "INSERT INTO {map} SELECT s.{pk}, s.{pk}, 'inserted' FROM {staged} s \
+                 WHERE NOT EXISTS (SELECT 1 FROM {target} t WHERE t.{pk} = s.{pk}) ON CONFLICT (old) DO NOTHING;\n\
+                 SELECT setval('{sequence}', GREATEST((SELECT max({pk}) FROM {target}), (SELECT max(new) FROM {map}), 1));\n\
+                 INSERT INTO {map} SELECT s.{pk}, nextval('{sequence}'), 'remapped' FROM {staged} s \
+                 WHERE NOT EXISTS (SELECT 1 FROM {map} m WHERE m.old = s.{pk}) ORDER BY s.{pk};\n"
+            ));
+        }
+        None => {
+            sql.push_str(&format!(
+                "INSERT INTO {map} SELECT s.{pk}, {new}, 'inserted' FROM {staged} s ON CONFLICT (old) DO NOTHING;\n",
+                new = value(&key.column, false)
+            ));
+        }
+    }
+
+    let values = columns
+        .iter()
+        .map(|column| match *column == key.column {
+            true => "m.new".to_string(),
+            false => value(column, true),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    sql.push_str(&format!(
+        "INSERT INTO {target} ({list}) OVERRIDING SYSTEM VALUE SELECT {values} FROM {staged} s \
+         JOIN {map} m ON m.old = s.{pk} WHERE m.action IN ('inserted', 'remapped') ORDER BY m.new;\n\
+         INSERT INTO {schema}.{stats} SELECT '{literal}', action, count(*) FROM {map} GROUP BY action;\n",
+        schema = SCHEMA,
+        stats = STATS_TABLE,
+    ));
+
+    sql
+}
+
+///
+/// Builds the SQL that combines the settings of the players both sides have into the row of the target.
+///
+/// The earliest of two points in time is kept, e.g. when a player first showed up, and the donation policy follows
+/// the rules. Everything else stays as the target has it.
+///
+/// #### Arguments
+///
+/// * `layout`: The tables of the target and the dump.
+/// * `columns`: The columns of the player table both have.
+/// * `key`: The primary key of the player table.
+/// * `rules`: Which donation policy wins.
+///
+fn combine_players(layout: &Layout, columns: &[String], key: &str, rules: Rules) -> String {
+    let (table, _) = PLAYER_TABLE;
+
+    let assignments: Vec<String> = columns
+        .iter()
+        .filter(|column| *column != key)
+        .filter_map(|column| {
+            let kind = layout.kinds.get(&(table.to_string(), column.clone()))?;
+
+            let combined = match (column.as_str(), rules.donation_policy) {
+                (DONATION_POLICY, DonationPolicy::Permissive) => "LEAST",
+                (DONATION_POLICY, DonationPolicy::Strict) => "GREATEST",
+                (DONATION_POLICY, DonationPolicy::Target) => return None,
+                _ if kind.starts_with("timestamp") || kind == "date" => "LEAST",
+                _ => return None,
+            };
+
+            let column = quote(column);
+            Some(format!("{column} = {combined}(t.{column}, s.{column})"))
+        })
+        .collect();
+
+    if assignments.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "UPDATE public.{table} t SET {assignments} FROM {schema}.{table} s JOIN {map} m ON m.old = s.{key} \
+         WHERE m.action = 'skipped' AND t.{key} = m.new;\n",
+        table = quote(table),
+        assignments = assignments.join(", "),
+        schema = SCHEMA,
+        map = map_table(table),
+        key = quote(key),
+    )
+}
+
+///
+/// Builds the SQL that merges the loaded dump into the target.
+///
+/// #### Arguments
+///
+/// * `layout`: The tables of the target and the dump.
+/// * `order`: The tables of the target, each after the tables it references.
+/// * `rules`: What to do with players that exist on both sides.
+///
+/// #### Returns
+///
+/// The SQL and the tables of the dump whose rows are left out.
+///
+fn script(layout: &Layout, order: &[String], rules: Rules) -> (String, Vec<String>) {
+    let mut sql = format!(
+        "CREATE TABLE {}.{} (table_name text, action text, rows bigint);\n",
+        SCHEMA, STATS_TABLE
+    );
+
+    // The rows are copied in the order of the foreign keys and remapped along, so they hold without the checks.
+    if rules.triggers == Triggers::Disable {
+        sql.push_str("SET session_replication_role = replica;\n");
+    }
+
+    let ignored: Vec<String> = layout
+        .staged
+        .keys()
+        .filter(|table| !layout.target.contains_key(*table) || OWN_TABLES.contains(&table.as_str()))
+        .cloned()
+        .collect();
+
+    for table in order.iter().filter(|table| !ignored.contains(table)) {
+        let (Some(target), Some(staged)) = (layout.target.get(table), layout.staged.get(table)) else {
+            continue;
+        };
+
+        let columns: Vec<String> = target
+            .iter()
+            .filter(|column| staged.contains(column))
+            .cloned()
+            .collect();
+
+        for column in staged.iter().filter(|column| !target.contains(column)) {
+            output::debug(format!(
+                "{}.{} is not in the target, its values are left out",
+                table, column
+            ));
+        }
+
+        if !columns.is_empty() {
+            sql.push_str(&merge_table(layout, table, &columns, rules));
+        }
+    }
+
+    if rules.triggers == Triggers::Disable {
+        sql.push_str("SET session_replication_role = DEFAULT;\n");
+    }
+
+    (sql, ignored)
+}
+
+///
+/// Lists the players of the loaded dump whose Discord ID the target already has.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database the dump was loaded into.
+/// * `layout`: The tables of the target and the dump.
+///
+/// #### Returns
+///
+/// The Discord IDs, none if either side has no players, or an error if the query failed.
+///
+async fn duplicate_players(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    layout: &Layout,
+) -> Result<Vec<String>, Error> {
+    let (table, column) = PLAYER_TABLE;
+    let has_column = |tables: &BTreeMap<String, Vec<String>>| {
+        tables
+            .get(table)
+            .is_some_and(|columns| columns.iter().any(|c| c == column))
+    };
+
+    if !has_column(&layout.target) || !has_column(&layout.staged) {
+        return Ok(Vec::new());
+    }
+
+    let duplicates = container::rows(
+        docker,
+        container_id,
+        database,
+        &format!(
+            "SELECT s.{column} FROM {schema}.{table} s JOIN public.{table} t ON t.{column} = s.{column} ORDER BY 1",
+            column = quote(column),
+            schema = SCHEMA,
+            table = quote(table),
+        ),
+        ErrorKind::ImportFailed,
+        "look for players in both databases",
+    )
+    .await?;
+
+    Ok(duplicates.into_iter().flatten().collect())
+}
+
+///
+/// Removes the loaded dump again, also after a failed merge.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to connect with.
+///
+pub async fn clean_up(docker: &Docker, container_id: &str, database: &Database) {
+    let dropped = container::query(
+        docker,
+        container_id,
+        database,
+        &format!("DROP SCHEMA IF EXISTS {} CASCADE", SCHEMA),
+    )
+    .await;
+
+    if dropped.is_none() {
+        output::debug(format!("Failed to drop the schema {}", SCHEMA));
+    }
+}
+
+///
+/// Merges a dump loaded into [`SCHEMA`] into the tables of the target.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database the dump was loaded into.
+/// * `dump`: The path of the loaded dump, for the report.
+/// * `rules`: What to do with players that exist on both sides.
+/// * `single_transaction`: Whether to merge all rows in one transaction, so an error leaves the target as it was.
+///
+/// #### Returns
+///
+/// What happened to the rows of every table, or an error naming the statement that failed or the duplicate players.
+///
+pub async fn run(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    dump: &Path,
+    rules: Rules,
+    single_transaction: bool,
+) -> Result<Merge, Error> {
+    let layout = layout(docker, container_id, database).await?;
+    let duplicates = duplicate_players(docker, container_id, database, &layout).await?;
+
+    if !duplicates.is_empty() && rules.duplicate_players == DuplicatePlayers::Error {
+        let shown = duplicates.iter().take(DUPLICATES_SHOWN).cloned().collect::<Vec<_>>();
+
+        return Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "{} player(s) of {} are already in the target, e.g. the Discord ID(s) {}. Pass \
+                 --on-duplicate-player merge or skip to import them anyway.",
+                duplicates.len(),
+                dump.display(),
+                shown.join(", ")
+            ),
+        ));
+    }
+
+    let order =
+        incremental::dependency_order(docker, container_id, database, layout.target.keys().cloned().collect()).await;
+    let (sql, ignored) = script(&layout, &order, rules);
+
+    for table in &ignored {
+        output::debug(format!(
+            "Left out the rows of {}, which the target keeps its own of",
+            table
+        ));
+    }
+
+    restore::run_script(docker, container_id, database, &sql, single_transaction)
+        .await
+        .map_err(|error| {
+            let outcome = match single_transaction {
+                true => "The merge was rolled back and the target is as it was before.",
+                false => "The target may be partially modified and hold some of the rows of the dump.",
+            };
+
+            Error::new(error.kind, format!("{}\n{}", error.message, outcome))
+        })?;
+
+    let counts = container::rows(
+        docker,
+        container_id,
+        database,
+        &format!("SELECT table_name, action, rows FROM {}.{}", SCHEMA, STATS_TABLE),
+        ErrorKind::ImportFailed,
+        "read how many rows were merged",
+    )
+    .await?;
+
+    let mut tables: BTreeMap<String, Counts> = BTreeMap::new();
+
+    for row in counts {
+        if let [table, action, count] = row.as_slice() {
+            let counts = tables.entry(table.clone()).or_default();
+            let count: u64 = count.parse().unwrap_or_default();
+
+            match action.as_str() {
+                "inserted" => counts.inserted += count,
+                "remapped" => counts.remapped += count,
+                "dropped" => counts.dropped += count,
+                _ => counts.skipped += count,
+            }
+        }
+    }
+
+    Ok(Merge {
+        dump: dump.to_path_buf(),
+        tables,
+        duplicate_players: duplicates.len() as u64,
+        ignored,
+    })
+}
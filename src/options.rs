@@ -0,0 +1,297 @@
+/*
+    Options shared by every ndmig operation.
+*/
+
+use crate::bot;
+use crate::cli::Cli;
+use crate::config;
+use crate::container::{Database, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::glob;
+use crate::output::Level;
+use crate::template::NameTemplate;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+/// How many invalid answers a prompt accepts when nothing else is configured.
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// How often a dump command is started when nothing else is configured.
+const DEFAULT_EXEC_ATTEMPTS: u32 = 3;
+
+/// The postgres role Ballsdex uses out of the box.
+const DEFAULT_DB_USER: &str = "ballsdex";
+
+///
+/// Settings that apply to the whole run, regardless of the operation.
+///
+/// Every prompt and output helper reads its behaviour from here, so new prompts should take an `&Options`
+/// instead of looking at the command line themselves.
+///
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Answer every non-destructive confirmation with yes.
+    pub assume_yes: bool,
+
+    /// How much to print while working.
+    pub level: Level,
+
+    /// Print machine-readable JSON instead of human-oriented text.
+    pub json: bool,
+
+    /// Print without colors and with ASCII markers.
+    pub plain: bool,
+
+    /// How many invalid answers a prompt accepts before giving up.
+    pub max_attempts: u32,
+
+    /// How often a dump command is started before a Docker error is given up on.
+    pub exec_attempts: u32,
+
+    /// The instance to export when none is given on the command line.
+    pub instance: Option<String>,
+
+    /// Where to write dumps when `--output` is not given.
+    pub output: Option<PathBuf>,
+
+    /// The postgres role used to run `pg_dump` and `psql`, if one was configured.
+    pub db_user: Option<String>,
+
+    /// The database to dump and query, if one was configured.
+    pub db_name: Option<String>,
+
+    /// The path of `config.yml` inside the bot container, if one was configured.
+    pub bot_config: Option<String>,
+
+    /// Extra arguments passed to `pg_dump`, from the configuration file.
+    pub pg_dump_args: Vec<String>,
+
+    /// The dump file name template when `--name-template` is not given.
+    pub name_template: NameTemplate,
+
+    /// Instance names or glob patterns that are hidden after discovery.
+    pub ignore: Vec<String>,
+
+    /// Only print what would be done.
+    pub dry_run: bool,
+
+    /// The configuration file the options were read from, if any.
+    pub config_file: Option<PathBuf>,
+
+    /// Problems found while loading the configuration that did not stop the run.
+    pub warnings: Vec<String>,
+}
+
+///
+/// The effective configuration as printed by `ndmig config show`.
+///
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    pub yes: bool,
+    pub verbose: bool,
+    pub quiet: bool,
+    pub json: bool,
+    pub no_color: bool,
+    pub max_attempts: u32,
+    pub exec_attempts: u32,
+    pub instance: Option<String>,
+    pub output: Option<PathBuf>,
+    pub db_user: String,
+    pub db_name: Option<String>,
+    pub bot_config: String,
+    pub pg_dump_args: Vec<String>,
+    pub name_template: String,
+    pub ignore: Vec<String>,
+}
+
+///
+/// Reads a string from an environment variable.
+///
+/// #### Arguments
+///
+/// * `name`: The name of the variable.
+///
+/// #### Returns
+///
+/// The value, or `None` if the variable is unset or empty.
+///
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+///
+/// Reads a boolean from an environment variable.
+///
+/// #### Arguments
+///
+/// * `name`: The name of the variable.
+///
+/// #### Returns
+///
+/// The value, `None` if the variable is unset or empty, or an error naming the variable if it is not a boolean.
+///
+fn env_bool(name: &str) -> Result<Option<bool>, Error> {
+    let Some(value) = env_string(name) else {
+        return Ok(None);
+    };
+
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(Some(true)),
+        "0" | "false" | "no" => Ok(Some(false)),
+        _ => Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "Invalid value for {}: '{}' (expected 1/true/yes or 0/false/no)",
+                name, value
+            ),
+        )),
+    }
+}
+
+impl Options {
+    ///
+    /// Builds the options from the command line, the environment and the configuration file.
+    ///
+    /// Values given on the command line win over `NDMIG_*` environment variables, which win over the
+    /// configuration file, which wins over the defaults.
+    ///
+    /// #### Arguments
+    ///
+    /// * `cli`: The parsed command line.
+    ///
+    /// #### Returns
+    ///
+    /// The options for this run, or an error if the configuration file is invalid.
+    ///
+    pub fn load(cli: &Cli) -> Result<Self, Error> {
+        let loaded = config::load(cli.config.as_deref())?;
+        let config = loaded.config;
+
+        let env_yes = env_bool("NDMIG_ASSUME_YES")?;
+        let env_no_color = env_bool("NDMIG_NO_COLOR")?;
+
+        // NDMIG_OUTPUT_DIR always names a directory, even if it does not exist yet.
+        let env_output = env_string("NDMIG_OUTPUT_DIR").map(|dir| {
+            let mut dir = PathBuf::from(dir).into_os_string();
+            dir.push(std::path::MAIN_SEPARATOR_STR);
+            PathBuf::from(dir)
+        });
+
+        let name_template = match &config.name_template {
+            Some(template) => template
+                .parse()
+                .map_err(|e| Error::new(ErrorKind::Config, format!("Invalid name_template: {}", e)))?,
+            None => NameTemplate::default(),
+        };
+
+        let level = if cli.quiet {
+            Level::Quiet
+        } else if cli.verbose {
+            Level::Verbose
+        } else if config.quiet == Some(true) {
+            Level::Quiet
+        } else if config.verbose == Some(true) {
+            Level::Verbose
+        } else {
+            Level::Normal
+        };
+
+        Ok(Options {
+            assume_yes: cli.yes || env_yes.or(config.yes).unwrap_or(false),
+            level,
+            json: cli.json || config.json == Some(true),
+            plain: cli.no_color
+                || env_no_color.or(config.no_color).unwrap_or(false)
+                || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+                || !std::io::stdout().is_terminal(),
+            max_attempts: cli.max_attempts.or(config.max_attempts).unwrap_or(DEFAULT_MAX_ATTEMPTS),
+            exec_attempts: cli
+                .exec_attempts
+                .or(config.exec_attempts)
+                .unwrap_or(DEFAULT_EXEC_ATTEMPTS),
+            instance: env_string("NDMIG_INSTANCE").or(config.instance),
+            output: env_output.or(config.output),
+            db_user: cli
+                .db_user
+                .clone()
+                .or_else(|| env_string("NDMIG_DB_USER"))
+                .or(config.db_user),
+            db_name: cli
+                .db_name
+                .clone()
+                .or_else(|| env_string("NDMIG_DB_NAME"))
+                .or(config.db_name),
+            bot_config: config.bot_config,
+            pg_dump_args: config.pg_dump_args.unwrap_or_default(),
+            name_template,
+            ignore: config.ignore.unwrap_or_default(),
+            dry_run: cli.dry_run,
+            config_file: loaded.path,
+            warnings: loaded.warnings,
+        })
+    }
+
+    ///
+    /// Returns the options in the same shape as the configuration file.
+    ///
+    pub fn effective(&self) -> EffectiveConfig {
+        EffectiveConfig {
+            yes: self.assume_yes,
+            verbose: self.level == Level::Verbose,
+            quiet: self.level == Level::Quiet,
+            json: self.json,
+            no_color: self.plain,
+            max_attempts: self.max_attempts,
+            exec_attempts: self.exec_attempts,
+            instance: self.instance.clone(),
+            output: self.output.clone(),
+            db_user: self.database().user,
+            db_name: self.db_name.clone(),
+            bot_config: self
+                .bot_config
+                .clone()
+                .unwrap_or_else(|| bot::DEFAULT_CONFIG_PATH.to_string()),
+            pg_dump_args: self.pg_dump_args.clone(),
+            name_template: self.name_template.to_string(),
+            ignore: self.ignore.clone(),
+        }
+    }
+
+    ///
+    /// Returns the role and database to connect with, without looking at the container.
+    ///
+    /// See [`crate::container::database`] for the database of a specific container.
+    ///
+    /// #### Returns
+    ///
+    /// The configured role, or `ballsdex` if none was configured, and the configured database.
+    ///
+    pub fn database(&self) -> Database {
+        Database {
+            user: self.db_user.clone().unwrap_or_else(|| DEFAULT_DB_USER.to_string()),
+            name: self.db_name.clone(),
+            password: None,
+            default_user: self.db_user.is_none(),
+        }
+    }
+
+    ///
+    /// Checks whether an instance is on the ignore list.
+    ///
+    /// #### Arguments
+    ///
+    /// * `instance`: The instance to check.
+    ///
+    /// #### Returns
+    ///
+    /// Whether its short or full container name matches one of the `ignore` patterns.
+    ///
+    pub fn is_ignored(&self, instance: &Instance) -> bool {
+        let name = crate::format_name(&instance.name);
+
+        self.ignore
+            .iter()
+            .any(|pattern| glob::matches(pattern, &name) || glob::matches(pattern, &instance.name))
+    }
+}
@@ -0,0 +1,244 @@
+/*
+    Output helpers that respect the selected verbosity and output format.
+*/
+
+use crate::error::Error;
+use colored::*;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+///
+/// How much ndmig prints while it works.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    /// Only the final result and errors.
+    Quiet,
+    /// Status lines and progress.
+    #[default]
+    Normal,
+    /// Everything, including Docker API calls and timings.
+    Verbose,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static STDOUT_DUMP: AtomicBool = AtomicBool::new(false);
+
+///
+/// The status symbols ndmig prints, with ASCII fallbacks for `--no-color`.
+///
+#[derive(Debug, Clone, Copy)]
+pub enum Glyph {
+    Ok,
+    Error,
+    Pending,
+    Arrow,
+    Warning,
+}
+
+impl Display for Glyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unicode, ascii) = match self {
+            Glyph::Ok => ("✓", "[ok]"),
+            Glyph::Error => ("✗", "[error]"),
+            Glyph::Pending => ("⧗", "[..]"),
+            Glyph::Arrow => ("›", "->"),
+            Glyph::Warning => ("⚠", "[warn]"),
+        };
+
+        write!(f, "{}", if is_plain() { ascii } else { unicode })
+    }
+}
+
+///
+/// Sets the verbosity for the rest of the run.
+///
+/// #### Arguments
+///
+/// * `level`: The new verbosity.
+///
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+///
+/// Returns the current verbosity.
+///
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        _ => Level::Verbose,
+    }
+}
+
+///
+/// Switches JSON output on or off for the rest of the run.
+///
+/// In JSON mode stdout only carries the final JSON document and every human-oriented line goes to stderr.
+///
+/// #### Arguments
+///
+/// * `json`: Whether to print JSON.
+///
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+///
+/// Returns whether JSON output is enabled.
+///
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+///
+/// Reserves stdout for a dump written with `--output -`.
+///
+/// Every line ndmig prints then goes to stderr, including the JSON document with `--json`.
+///
+/// #### Arguments
+///
+/// * `stdout_dump`: Whether the dump is written to stdout.
+///
+pub fn set_stdout_dump(stdout_dump: bool) {
+    STDOUT_DUMP.store(stdout_dump, Ordering::Relaxed);
+}
+
+///
+/// Returns whether the dump is written to stdout.
+///
+pub fn is_stdout_dump() -> bool {
+    STDOUT_DUMP.load(Ordering::Relaxed)
+}
+
+///
+/// Switches plain output on or off for the rest of the run.
+///
+/// Plain output has no colors and uses ASCII markers instead of Unicode symbols.
+///
+/// #### Arguments
+///
+/// * `plain`: Whether to print plain output.
+///
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+    colored::control::set_override(!plain);
+    dialoguer::console::set_colors_enabled(!plain);
+    dialoguer::console::set_colors_enabled_stderr(!plain);
+}
+
+///
+/// Returns whether plain output is enabled.
+///
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+///
+/// Returns the theme for interactive prompts.
+///
+pub fn theme() -> Box<dyn Theme> {
+    if is_plain() {
+        Box::new(SimpleTheme)
+    } else {
+        Box::new(ColorfulTheme::default())
+    }
+}
+
+///
+/// Prints a human-oriented line that is part of the result, e.g. the success message.
+///
+/// #### Arguments
+///
+/// * `message`: The line to print.
+///
+pub fn info(message: impl Display) {
+    crate::progress::suspend(|| {
+        if is_json() || is_stdout_dump() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    });
+}
+
+///
+/// Prints a status line, unless `--quiet` was passed.
+///
+/// #### Arguments
+///
+/// * `message`: The line to print.
+///
+pub fn status(message: impl Display) {
+    if level() >= Level::Normal {
+        info(message);
+    }
+}
+
+///
+/// Prints a diagnostic line to stderr when `--verbose` was passed.
+///
+/// #### Arguments
+///
+/// * `message`: The line to print.
+///
+pub fn debug(message: impl Display) {
+    if level() >= Level::Verbose {
+        eprintln!("{} {}", "debug:".dimmed(), message.to_string().dimmed());
+    }
+}
+
+///
+/// Prints a prompt without a trailing newline.
+///
+/// #### Arguments
+///
+/// * `message`: The prompt to print.
+///
+pub fn prompt(message: impl Display) {
+    if is_json() || is_stdout_dump() {
+        eprint!("{}", message);
+        let _ = io::stderr().flush();
+    } else {
+        print!("{}", message);
+        let _ = io::stdout().flush();
+    }
+}
+
+///
+/// Prints the JSON document of a run to stdout.
+///
+/// #### Arguments
+///
+/// * `value`: The document to print.
+///
+pub fn json(value: &impl Serialize) {
+    let json = serde_json::to_string_pretty(value).expect("Failed to serialize JSON output");
+
+    if is_stdout_dump() {
+        eprintln!("{}", json);
+    } else {
+        println!("{}", json);
+    }
+}
+
+///
+/// Reports the error that ended the run.
+///
+/// #### Arguments
+///
+/// * `error`: The error to report.
+///
+pub fn error(error: &Error) {
+    if is_json() {
+        json(&serde_json::json!({ "status": "failed", "error": error }));
+    } else {
+        eprintln!("{}", format!("{} {}", Glyph::Error, error).red().bold());
+    }
+}
@@ -0,0 +1,359 @@
+/*
+    Exports of everything an instance stores about one Discord user, for `--player`, e.g. to answer a data access
+    request.
+
+    The document is built by a single read-only query, with the foreign keys replaced by what they point at: ball and
+    special names instead of their IDs, and Discord IDs instead of player IDs.
+*/
+
+use crate::container::{self, Database, DatabaseDump};
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::output;
+use bollard::Docker;
+use chrono::{Local, SecondsFormat};
+use serde_json::{Map, Value};
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+///
+/// Returns the sections of the document that the tables of the database allow, and the query of each.
+///
+/// #### Arguments
+///
+/// * `discord_id`: The Discord ID of the user.
+/// * `tables`: The tables of the database.
+///
+/// #### Returns
+///
+/// The key and the query of every section, each yielding a JSON value.
+///
+fn sections(discord_id: u64, tables: &[String]) -> Vec<(&'static str, String)> {
+    let has = |names: &[&str]| names.iter().all(|name| tables.iter().any(|table| table == name));
+    let player = format!("SELECT id FROM player WHERE discord_id = {}", discord_id);
+    let mut sections = vec![(
+        "player",
+        format!(
+            "SELECT to_jsonb(p) - 'id' || jsonb_build_object('discord_id', p.discord_id::text) \
+             FROM player p WHERE p.discord_id = {}",
+            discord_id
+        ),
+    )];
+
+    if has(&["ballinstance", "ball", "special"]) {
+        sections.push((
+            "balls",
+            format!(
+                "SELECT coalesce(jsonb_agg(to_jsonb(bi) - 'ball_id' - 'player_id' - 'trade_player_id' - 'special_id' \
+                 || jsonb_build_object('ball', b.country, 'special', s.name, 'server_id', bi.server_id::text, \
+                 'traded_by', tp.discord_id::text) ORDER BY bi.id), '[]') \
+                 FROM ballinstance bi JOIN ball b ON b.id = bi.ball_id LEFT JOIN special s ON s.id = bi.special_id \
+                 LEFT JOIN player tp ON tp.id = bi.trade_player_id WHERE bi.player_id IN ({})",
+                player
+            ),
+        ));
+    }
+
+    if has(&["trade", "tradeobject", "ballinstance", "ball"]) {
+        // The balls of a trade are listed as Ballsdex shows them, e.g. `France #1A`.
+        let objects = |side: &str| {
+            format!(
+                "(SELECT coalesce(jsonb_agg(format('%s #%s', b.country, upper(to_hex(bi.id))) ORDER BY o.id), '[]') \
+                 FROM tradeobject o JOIN ballinstance bi ON bi.id = o.ballinstance_id JOIN ball b ON b.id = bi.ball_id \
+                 WHERE o.trade_id = t.id AND o.player_id {} p.id)",
+                side
+            )
+        };
+
+        sections.push((
+            "trades",
+            format!(
+                "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id' - 'player1_id' - 'player2_id' \
+                 || jsonb_build_object('with', other.discord_id::text, 'given', {}, 'received', {}) ORDER BY t.id), '[]') \
+                 FROM trade t JOIN player p ON p.id IN (t.player1_id, t.player2_id) \
+                 LEFT JOIN player other ON other.id = CASE WHEN t.player1_id = p.id THEN t.player2_id ELSE t.player1_id END \
+                 WHERE p.discord_id = {}",
+                objects("="),
+                objects("<>"),
+                discord_id
+            ),
+        ));
+    }
+
+    for (key, table) in [
+        ("blacklist", "blacklistedid"),
+        ("blacklist_history", "blacklisthistory"),
+    ] {
+        if has(&[table]) {
+            sections.push((
+                key,
+                format!(
+                    "SELECT coalesce(jsonb_agg(to_jsonb(x) - 'id' || jsonb_build_object('discord_id', x.discord_id::text) \
+                     ORDER BY x.id), '[]') FROM {} x WHERE x.discord_id = {}",
+                    table, discord_id
+                ),
+            ));
+        }
+    }
+
+    for (key, table) in [("friendships", "friendship"), ("blocks", "block")] {
+        if has(&[table]) {
+            sections.push((
+                key,
+                format!(
+                    "SELECT coalesce(jsonb_agg(to_jsonb(x) - 'id' - 'player1_id' - 'player2_id' \
+                     || jsonb_build_object('initiated', x.player1_id = p.id, 'with', other.discord_id::text) \
+                     ORDER BY x.id), '[]') \
+                     FROM {} x JOIN player p ON p.id IN (x.player1_id, x.player2_id) \
+                     LEFT JOIN player other ON other.id = CASE WHEN x.player1_id = p.id THEN x.player2_id ELSE x.player1_id END \
+                     WHERE p.discord_id = {}",
+                    table, discord_id
+                ),
+            ));
+        }
+    }
+
+    sections
+}
+
+///
+/// Reads everything stored about a user.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `discord_id`: The Discord ID of the user.
+/// * `database`: The role and database to read.
+///
+/// #### Returns
+///
+/// The sections of the document, or an error if the query failed or nothing is stored about the user.
+///
+async fn read(
+    docker: &Docker,
+    container_id: &str,
+    discord_id: u64,
+    database: &Database,
+) -> Result<Map<String, Value>, Error> {
+    let failed = || {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("Failed to read the data of Discord user {}.", discord_id),
+        )
+    };
+
+    let tables = container::tables(docker, container_id, database)
+        .await
+        .ok_or_else(failed)?;
+
+    if !tables.iter().any(|table| table == "player") {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            "The database has no player table. Is this a Ballsdex database?",
+        ));
+    }
+
+    let fields = sections(discord_id, &tables)
+        .into_iter()
+        .map(|(key, query)| format!("'{}', ({})", key, query))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let output = container::query(
+        docker,
+        container_id,
+        database,
+        &format!("SELECT jsonb_build_object({})", fields),
+    )
+    .await
+    .ok_or_else(failed)?;
+
+    let document: Map<String, Value> = serde_json::from_str(output.trim()).map_err(|_| failed())?;
+
+    let stored = document.iter().any(|(_, value)| match value {
+        Value::Array(rows) => !rows.is_empty(),
+        value => !value.is_null(),
+    });
+
+    if !stored {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!("Nothing is stored about Discord user {} in this instance.", discord_id),
+        ));
+    }
+
+    Ok(document)
+}
+
+///
+/// Writes everything stored about a user as one JSON document.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `instance`: The short instance name, recorded in the metadata.
+/// * `discord_id`: The Discord ID of the user.
+/// * `database`: The role and database to read.
+/// * `writer`: Where the document is written.
+///
+/// #### Returns
+///
+/// The size of the document, or an error.
+///
+pub async fn export(
+    docker: &Docker,
+    container_id: &str,
+    instance: &str,
+    discord_id: u64,
+    database: &Database,
+    writer: &mut (impl AsyncWrite + Unpin + Send),
+) -> Result<DatabaseDump, Error> {
+    let mut document = Map::new();
+    document.insert(
+        "metadata".to_string(),
+        serde_json::json!({
+            "instance": instance,
+            "discord_id": discord_id.to_string(),
+            "ndmig_version": env!("CARGO_PKG_VERSION"),
+            "created_at": Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        }),
+    );
+    document.extend(read(docker, container_id, discord_id, database).await?);
+
+    let contents = serde_json::to_string_pretty(&document).expect("Failed to serialize the player data") + "\n";
+
+    writer
+        .write_all(contents.as_bytes())
+        .await
+        .map_err(|e: io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to write the dump: {}", e)))?;
+
+    Ok(DatabaseDump {
+        bytes: contents.len() as u64,
+        warnings: Vec::new(),
+    })
+}
+
+///
+/// Writes everything stored about a user as one CSV file per section into a directory.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `directory`: The directory to write, which is only moved into place once it is complete.
+/// * `discord_id`: The Discord ID of the user.
+/// * `database`: The role and database to read.
+///
+/// #### Returns
+///
+/// The total size of the files, or an error.
+///
+pub async fn export_csv(
+    docker: &Docker,
+    container_id: &str,
+    directory: &Path,
+    discord_id: u64,
+    database: &Database,
+) -> Result<DatabaseDump, Error> {
+    let document = read(docker, container_id, discord_id, database).await?;
+
+    let partial = crate::partial_path(directory);
+    let write_error = |path: &Path, e: io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    };
+
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+    }
+
+    std::fs::create_dir_all(&partial).map_err(|e| write_error(&partial, e))?;
+
+    let mut bytes = 0;
+
+    for (section, value) in &document {
+        let rows = match value {
+            Value::Array(rows) => rows.clone(),
+            Value::Null => Vec::new(),
+            row => vec![row.clone()],
+        };
+
+        let path = partial.join(format!("{}.{}", section, DumpFormat::Csv.extension()));
+        let contents = to_csv(&rows);
+
+        std::fs::write(&path, &contents).map_err(|e| write_error(&path, e))?;
+        output::debug(format!("Wrote {} ({} rows)", path.display(), rows.len()));
+        bytes += contents.len() as u64;
+    }
+
+    // The overwrite was confirmed before the export started, and a directory cannot be renamed onto a full one.
+    if directory.exists() {
+        std::fs::remove_dir_all(directory).map_err(|e| write_error(directory, e))?;
+    }
+
+    std::fs::rename(&partial, directory).map_err(|e| write_error(directory, e))?;
+
+    Ok(DatabaseDump {
+        bytes,
+        warnings: Vec::new(),
+    })
+}
+
+///
+/// Turns the rows of a section into a CSV file with a header.
+///
+/// #### Arguments
+///
+/// * `rows`: The JSON objects of the section. The columns are every key in the order they first appear.
+///
+fn to_csv(rows: &[Value]) -> String {
+    let mut columns: Vec<&str> = Vec::new();
+
+    for key in rows.iter().filter_map(Value::as_object).flat_map(Map::keys) {
+        if !columns.contains(&key.as_str()) {
+            columns.push(key);
+        }
+    }
+
+    let line = |fields: Vec<String>| fields.join(",") + "\n";
+    let mut contents = line(columns.iter().map(|column| csv_field(column)).collect());
+
+    for row in rows {
+        contents += &line(
+            columns
+                .iter()
+                .map(|column| match row.get(column) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(text)) => csv_field(text),
+                    // The balls of a trade, listed in one field.
+                    Some(Value::Array(items)) => csv_field(
+                        &items
+                            .iter()
+                            .map(|item| item.as_str().map_or_else(|| item.to_string(), str::to_string))
+                            .collect::<Vec<_>>()
+                            .join("; "),
+                    ),
+                    Some(value) => csv_field(&value.to_string()),
+                })
+                .collect(),
+        );
+    }
+
+    contents
+}
+
+///
+/// Quotes a CSV field if it holds a comma, a quote or a line break.
+///
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
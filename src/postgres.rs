@@ -0,0 +1,500 @@
+/*
+    Compares the postgres versions a dump was written with and those of the target it is imported into.
+
+    Ballsdex stacks run postgres 13 to 16 and later. A dump from a newer server can set parameters or use syntax an
+    older one rejects, and pg_restore cannot read the archives of a newer pg_dump. Both sides are checked before the
+    import, so a failure names the two versions instead of surfacing as a syntax error halfway through the dump.
+*/
+
+use crate::container;
+use crate::format::DumpFormat;
+use crate::restore::Dump;
+use bollard::Docker;
+use serde::Serialize;
+use std::fmt;
+use tokio::io::AsyncReadExt;
+
+/// How much of a dump is read for the versions it names, which pg_dump writes in its first lines or header.
+const HEAD_BYTES: u64 = 4096;
+
+/// The comment of a plain dump naming the version of the server it was taken from.
+const DUMPED_FROM: &str = "-- Dumped from database version ";
+
+/// The comment of a plain dump naming the version of pg_dump.
+const DUMPED_BY: &str = "-- Dumped by pg_dump version ";
+
+/// The setting pg_dump 17 starts plain dumps with, which older servers do not know.
+const TRANSACTION_TIMEOUT: &str = "SET transaction_timeout = ";
+
+/// The command the plain dumps of recent pg_dump releases start with, which older psql does not know.
+const RESTRICT: &str = "\\restrict ";
+
+/// The first major version that knows `transaction_timeout`.
+const TRANSACTION_TIMEOUT_SINCE: u32 = 17;
+
+/// The first release of each major version whose pg_dump writes `\restrict` into plain dumps, and whose psql knows it.
+///
+/// Every later major version has it from its first release.
+const RESTRICT_SINCE: [(u32, u32); 5] = [(13, 22), (14, 19), (15, 14), (16, 10), (17, 6)];
+
+///
+/// A postgres version, e.g. `16.2`.
+///
+/// Before postgres 10 the major version had two parts, `9.6` is kept as major 9 and minor 6, which is close enough
+/// for the versions Ballsdex runs.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Version {
+    ///
+    /// Reads the version out of what postgres or its tools print, e.g. `16.2 (Debian 16.2-1.pgdg120+2)`,
+    /// `pg_dump (PostgreSQL) 15.4` or `17beta1`.
+    ///
+    /// #### Arguments
+    ///
+    /// * `text`: The text naming the version.
+    ///
+    /// #### Returns
+    ///
+    /// The version, or `None` if the text holds none.
+    ///
+    pub fn parse(text: &str) -> Option<Version> {
+        let word = text
+            .split_whitespace()
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+        let mut numbers = word
+            .split(|c: char| !c.is_ascii_digit())
+            .map(|number| number.parse::<u32>().ok());
+
+        let major = numbers.next()??;
+        let minor = match word[major.to_string().len()..].starts_with('.') {
+            true => numbers.next().flatten().unwrap_or(0),
+            false => 0,
+        };
+
+        Some(Version { major, minor })
+    }
+
+    ///
+    /// Returns whether psql and pg_dump of this version know the `\restrict` command, which the plain dumps of pg_dump
+    /// start with since the releases of August 2025.
+    ///
+    fn has_restrict(self) -> bool {
+        match RESTRICT_SINCE.iter().find(|(major, _)| *major == self.major) {
+            Some((_, minor)) => self.minor >= *minor,
+            None => self.major > RESTRICT_SINCE[RESTRICT_SINCE.len() - 1].0,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+///
+/// The versions a dump was written with.
+///
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Source {
+    /// The version of the server the dump was taken from.
+    pub server: Option<Version>,
+    /// The version of the pg_dump that wrote it.
+    pub pg_dump: Option<Version>,
+    /// Whether the plain dump sets `transaction_timeout`.
+    pub transaction_timeout: bool,
+    /// Whether the plain dump uses the `\restrict` command of psql.
+    pub restrict: bool,
+}
+
+impl Source {
+    ///
+    /// Describes the versions, e.g. `postgres 16.2 (pg_dump 16.2)`.
+    ///
+    pub fn describe(&self) -> String {
+        let server = match self.server {
+            Some(server) => format!("postgres {}", server),
+            None => "an unknown postgres version".to_string(),
+        };
+
+        match self.pg_dump {
+            Some(pg_dump) => format!("{} (pg_dump {})", server, pg_dump),
+            None => server,
+        }
+    }
+}
+
+///
+/// The versions of the postgres container a dump is imported into.
+///
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Target {
+    /// The version of the server.
+    pub server: Option<Version>,
+    /// The version of the psql that loads plain dumps.
+    pub psql: Option<Version>,
+    /// The version of the pg_restore that reads archives.
+    pub pg_restore: Option<Version>,
+}
+
+impl Target {
+    ///
+    /// Describes the versions, e.g. `postgres 15.4`, with the client tools if they differ from the server.
+    ///
+    pub fn describe(&self) -> String {
+        let server = match self.server {
+            Some(server) => format!("postgres {}", server),
+            None => "an unknown postgres version".to_string(),
+        };
+
+        match self.psql {
+            Some(psql) if Some(psql) != self.server => format!("{} (psql {})", server, psql),
+            _ => server,
+        }
+    }
+}
+
+///
+/// A combination of versions that is known to go wrong, or may.
+///
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Whether the import fails for sure, and is refused unless `--allow-version-mismatch` is passed.
+    pub fatal: bool,
+    /// What goes wrong, naming both versions.
+    pub message: String,
+}
+
+///
+/// Runs `<tool> --version` in a container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID.
+/// * `tool`: The tool, e.g. `pg_dump`.
+///
+/// #### Returns
+///
+/// The version, or `None` if the tool cannot be run, e.g. because the container is stopped.
+///
+pub async fn tool_version(docker: &Docker, container_id: &str, tool: &str) -> Option<Version> {
+    let output = container::exec(docker, container_id, vec![tool, "--version"], Vec::new())
+        .await
+        .ok()
+        .filter(|output| output.exit_code == Some(0))?;
+
+    Version::parse(&output.stdout_text())
+}
+
+///
+/// Reads the versions of the postgres container of a target.
+///
+/// The tools are asked when the container runs. A stopped one falls back to the `PG_VERSION` its image sets, which
+/// the official images ship the server and the tools of.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+///
+pub async fn of_target(docker: &Docker, container_id: &str) -> Target {
+    let image = container::variable(&container::environment(docker, container_id).await, "PG_VERSION")
+        .and_then(|version| Version::parse(&version));
+
+    Target {
+        server: tool_version(docker, container_id, "postgres").await.or(image),
+        psql: tool_version(docker, container_id, "psql").await.or(image),
+        pg_restore: tool_version(docker, container_id, "pg_restore").await.or(image),
+    }
+}
+
+///
+/// Reads the versions a dump was written with, from the dump itself or else from its manifest.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump, as opened by [`crate::restore::open`].
+///
+pub async fn of_dump(dump: &Dump) -> Source {
+    let manifest = dump.manifest.as_ref();
+    let read = read_source(dump).await.unwrap_or_default();
+
+    Source {
+        server: read.server.or_else(|| {
+            manifest
+                .and_then(|manifest| manifest.server_version.as_deref())
+                .and_then(Version::parse)
+        }),
+        pg_dump: read.pg_dump.or_else(|| {
+            manifest
+                .and_then(|manifest| manifest.pg_dump_version.as_deref())
+                .and_then(Version::parse)
+        }),
+        ..read
+    }
+}
+
+///
+/// Reads the versions pg_dump wrote into a dump, in the comments of a plain dump or the header of an archive.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump.
+///
+async fn read_source(dump: &Dump) -> std::io::Result<Source> {
+    let mut head = Vec::new();
+
+    match dump.format {
+        DumpFormat::Directory => {
+            tokio::fs::File::open(dump.path.join("toc.dat"))
+                .await?
+                .take(HEAD_BYTES)
+                .read_to_end(&mut head)
+                .await?;
+        }
+        _ => {
+            dump.decompressed()
+                .await?
+                .take(HEAD_BYTES)
+                .read_to_end(&mut head)
+                .await?;
+        }
+    }
+
+    Ok(match dump.format {
+        DumpFormat::Plain => plain_source(&String::from_utf8_lossy(&head)),
+        _ => archive_source(&head).unwrap_or_default(),
+    })
+}
+
+///
+/// Reads the versions out of the comments a plain dump starts with, and whether its first statements need them.
+///
+/// #### Arguments
+///
+/// * `head`: The first lines of the dump.
+///
+fn plain_source(head: &str) -> Source {
+    let version = |prefix: &str| {
+        head.lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .and_then(Version::parse)
+    };
+
+    Source {
+        server: version(DUMPED_FROM),
+        pg_dump: version(DUMPED_BY),
+        transaction_timeout: head.lines().any(|line| line.starts_with(TRANSACTION_TIMEOUT)),
+        restrict: head.lines().any(|line| line.starts_with(RESTRICT)),
+    }
+}
+
+///
+/// Reads the fields of an archive header, as pg_dump writes them.
+///
+struct Header<'a> {
+    bytes: std::slice::Iter<'a, u8>,
+    /// The size of an integer, which the header sets.
+    int_size: usize,
+}
+
+impl Header<'_> {
+    ///
+    /// Reads a byte.
+    ///
+    fn byte(&mut self) -> Option<u8> {
+        self.bytes.next().copied()
+    }
+
+    ///
+    /// Reads an integer, stored as a sign byte and then its magnitude in little endian.
+    ///
+    fn int(&mut self) -> Option<i64> {
+        let negative = self.byte()? != 0;
+        let mut value = 0i64;
+
+        for i in 0..self.int_size {
+            value |= i64::from(self.byte()?) << (8 * i);
+        }
+
+        Some(if negative { -value } else { value })
+    }
+
+    ///
+    /// Reads a string, stored as its length and then its bytes. A negative length stands for a missing string.
+    ///
+    fn string(&mut self) -> Option<Option<String>> {
+        let length = self.int()?;
+
+        if length < 0 {
+            return Some(None);
+        }
+
+        let value: Vec<u8> = (0..length).map(|_| self.byte()).collect::<Option<_>>()?;
+        Some(Some(String::from_utf8_lossy(&value).into_owned()))
+    }
+}
+
+///
+/// Reads the versions out of the header of a custom archive or the `toc.dat` of a directory archive, laid out as
+/// `ReadHead` in pg_dump's `pg_backup_archiver.c` reads it.
+///
+/// #### Arguments
+///
+/// * `head`: The first bytes of the archive.
+///
+/// #### Returns
+///
+/// The versions, or `None` if the header is cut off or older than archive version 1.10, which was the first to
+/// record them.
+///
+fn archive_source(head: &[u8]) -> Option<Source> {
+    let mut header = Header {
+        bytes: head.strip_prefix(b"PGDMP")?.iter(),
+        int_size: 0,
+    };
+    let version = (header.byte()?, header.byte()?);
+
+    if version > (1, 0) {
+        header.byte()?;
+    }
+
+    header.int_size = header.byte()? as usize;
+
+    if version < (1, 10) || header.int_size > 8 {
+        return None;
+    }
+
+    // The size of an offset, and the format.
+    header.byte()?;
+    header.byte()?;
+
+    // The compression, a single byte naming the algorithm since pg_dump 16.
+    match version >= (1, 15) {
+        true => header.byte().map(|_| ())?,
+        false => header.int().map(|_| ())?,
+    }
+
+    // The time the dump started, as seconds, minutes, hours, day, month, year and daylight saving.
+    for _ in 0..7 {
+        header.int()?;
+    }
+
+    // The name of the database, then the versions.
+    header.string()?;
+    let server = header.string()?;
+    let pg_dump = header.string()?;
+
+    Some(Source {
+        server: server.as_deref().and_then(Version::parse),
+        pg_dump: pg_dump.as_deref().and_then(Version::parse),
+        ..Source::default()
+    })
+}
+
+///
+/// Returns whether a dump and the target differ in a way that can explain a failing import: servers of different
+/// major versions, or a pg_dump newer than the tools of the target.
+///
+/// #### Arguments
+///
+/// * `source`: The versions the dump was written with.
+/// * `target`: The versions of the target.
+///
+pub fn differ(source: &Source, target: &Target) -> bool {
+    let major = |version: Option<Version>| version.map(|version| version.major);
+    let servers = matches!((major(source.server), major(target.server)), (Some(from), Some(to)) if from != to);
+    let tools = [target.psql, target.pg_restore]
+        .iter()
+        .any(|tool| matches!((major(source.pg_dump), major(*tool)), (Some(from), Some(to)) if from > to));
+
+    servers || tools
+}
+
+///
+/// Compares the versions of a dump with those of the target, for the combinations known to go wrong.
+///
+/// Versions that are not known are not compared.
+///
+/// #### Arguments
+///
+/// * `format`: The format of the dump, which decides whether psql or pg_restore reads it.
+/// * `source`: The versions the dump was written with.
+/// * `target`: The versions of the target.
+///
+/// #### Returns
+///
+/// What goes wrong, empty if nothing is known to.
+///
+pub fn compare(format: DumpFormat, source: &Source, target: &Target) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let archive = matches!(format, DumpFormat::Custom | DumpFormat::Directory);
+
+    if archive
+        && let (Some(pg_dump), Some(pg_restore)) = (source.pg_dump, target.pg_restore)
+        && pg_dump.major > pg_restore.major
+    {
+        mismatches.push(Mismatch {
+            fatal: true,
+            message: format!(
+                "The dump is an archive written by pg_dump {}, which the pg_restore {} of the target cannot read. \
+                 Use a postgres {} image or newer for the target, or export a plain dump.",
+                pg_dump, pg_restore, pg_dump.major
+            ),
+        });
+    }
+
+    let pg_dump = match source.pg_dump {
+        Some(pg_dump) => format!("pg_dump {}", pg_dump),
+        None => "pg_dump".to_string(),
+    };
+
+    if source.transaction_timeout
+        && let Some(server) = target.server
+        && server.major < TRANSACTION_TIMEOUT_SINCE
+    {
+        mismatches.push(Mismatch {
+            fatal: true,
+            message: format!(
+                "The dump was written by {}, which sets transaction_timeout, and the postgres {} of the target does \
+                 not know that parameter. Export it again with a pg_dump older than {}.",
+                pg_dump, server, TRANSACTION_TIMEOUT_SINCE
+            ),
+        });
+    }
+
+    if source.restrict
+        && let Some(psql) = target.psql
+        && !psql.has_restrict()
+    {
+        mismatches.push(Mismatch {
+            fatal: true,
+            message: format!(
+                "The dump was written by {}, which starts plain dumps with \\restrict, and the psql {} of the target \
+                 does not know that command. Update the postgres image of the target to its latest {} release.",
+                pg_dump, psql, psql.major
+            ),
+        });
+    }
+
+    if let (Some(from), Some(to)) = (source.server, target.server)
+        && from.major > to.major
+    {
+        mismatches.push(Mismatch {
+            fatal: false,
+            message: format!(
+                "The dump comes from postgres {}, and the target runs the older postgres {}. It may use syntax or \
+                 features the target does not have, upgrade the target to postgres {} if the import fails.",
+                from, to, from.major
+            ),
+        });
+    }
+
+    mismatches
+}
@@ -0,0 +1,497 @@
+/*
+    Progress reporting for long-running dumps and imports.
+*/
+
+use crate::output::{self, Glyph, Level};
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, IsTerminal};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// How often a status line is printed when stdout is not a terminal.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The throughput of a slow and a fast export, in bytes per second, for the estimate before an export starts.
+const EXPECTED_RATES: (u64, u64) = (7 * 1024 * 1024, 14 * 1024 * 1024);
+
+/// Every visible progress bar, so bars of concurrent exports are stacked instead of drawn over each other.
+static BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+static CONCURRENT: AtomicBool = AtomicBool::new(false);
+
+///
+/// Sets whether several transfers run at once, so progress is labelled with the instance it belongs to.
+///
+/// #### Arguments
+///
+/// * `concurrent`: Whether several transfers run at once.
+///
+pub fn set_concurrent(concurrent: bool) {
+    CONCURRENT.store(concurrent, Ordering::Relaxed);
+}
+
+///
+/// Runs a closure with every progress bar hidden, so it can print without garbling them.
+///
+/// #### Arguments
+///
+/// * `f`: The closure to run.
+///
+pub fn suspend<F: FnOnce() -> R, R>(f: F) -> R {
+    BARS.suspend(f)
+}
+
+///
+/// Shows a spinner while waiting for something that has no size, such as a database that is starting up.
+///
+/// #### Arguments
+///
+/// * `message`: What is being waited for.
+///
+/// #### Returns
+///
+/// The spinner, to clear once the wait is over. Without a terminal the message is printed once instead, and nothing
+/// is returned.
+///
+pub fn spinner(message: &str) -> Option<ProgressBar> {
+    if output::level() < Level::Normal || !std::io::stdout().is_terminal() {
+        output::status(format!("{} {}", Glyph::Pending, message).yellow());
+        return None;
+    }
+
+    let mut style = ProgressStyle::default_spinner()
+        .template("  {spinner:.yellow} {msg}")
+        .expect("Invalid progress template");
+
+    if output::is_plain() {
+        style = style.tick_chars("|/-\\ ");
+    }
+
+    let spinner = BARS.add(ProgressBar::new_spinner());
+    spinner.set_style(style);
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(120));
+    Some(spinner)
+}
+
+///
+/// Describes how long an export of a database is expected to take, before it starts.
+///
+/// #### Arguments
+///
+/// * `database_bytes`: The size of the database, from `pg_database_size()`.
+/// * `plain`: Whether the dump is a plain SQL script, whose size differs the most from the database size.
+///
+/// #### Returns
+///
+/// A line such as `Database is ~2.4 GiB, expect roughly 3-6 minutes (rough estimate)`.
+///
+pub fn estimate(database_bytes: u64, plain: bool) -> String {
+    let (slow, fast) = EXPECTED_RATES;
+    let shortest = Duration::from_secs(database_bytes / fast);
+    let longest = Duration::from_secs(database_bytes / slow);
+
+    format!(
+        "Database is ~{}, expect {} ({})",
+        crate::format_size(database_bytes),
+        format_duration_range(shortest, longest),
+        if plain {
+            "rough estimate, a plain dump can be much larger or smaller than the database"
+        } else {
+            "rough estimate"
+        }
+    )
+}
+
+///
+/// Formats a range of durations for humans, e.g. `roughly 3-6 minutes`.
+///
+/// #### Arguments
+///
+/// * `shortest`: The lower end of the range.
+/// * `longest`: The upper end of the range.
+///
+fn format_duration_range(shortest: Duration, longest: Duration) -> String {
+    let (shortest, longest) = (shortest.as_secs(), longest.as_secs());
+    let dash = if output::is_plain() { "-" } else { "\u{2013}" };
+
+    if longest < 60 {
+        "under a minute".to_string()
+    } else if longest < 2 * 3600 {
+        format!(
+            "roughly {}{}{} minutes",
+            (shortest / 60).max(1),
+            dash,
+            longest.div_ceil(60)
+        )
+    } else {
+        format!(
+            "roughly {}{}{} hours",
+            (shortest / 3600).max(1),
+            dash,
+            longest.div_ceil(3600)
+        )
+    }
+}
+
+///
+/// Formats the time left of a transfer, e.g. `4m 20s`.
+///
+/// #### Arguments
+///
+/// * `left`: The time left.
+///
+fn format_eta(left: Duration) -> String {
+    let seconds = left.as_secs();
+
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+///
+/// Reports the progress of a transfer, either as a progress bar or as periodic status lines.
+///
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    label: Option<String>,
+    /// What happens to the bytes, e.g. `received`.
+    verb: &'static str,
+    total: Option<u64>,
+    bytes: AtomicU64,
+    started: Instant,
+    last_line: Mutex<Instant>,
+}
+
+impl Progress {
+    ///
+    /// Creates a new progress reporter.
+    ///
+    /// #### Arguments
+    ///
+    /// * `total`: The expected number of bytes, if known.
+    ///
+    /// #### Returns
+    ///
+    /// A progress bar when stdout is a terminal, a plain-text reporter otherwise. Nothing is shown with
+    /// `--quiet`. When the dump is written to stdout, stderr is checked instead.
+    ///
+    pub fn new(total: Option<u64>) -> Self {
+        Self::build(
+            total,
+            "received",
+            "  {prefix:.bold}{bar:30.yellow/white} {bytes}/~{total_bytes} ({percent}%) [{elapsed_precise}, ~{eta} left]",
+        )
+    }
+
+    ///
+    /// Creates a progress reporter for a dump that is fed to a database.
+    ///
+    /// #### Arguments
+    ///
+    /// * `total`: The size of the dump.
+    ///
+    /// #### Returns
+    ///
+    /// A progress bar with the throughput when stdout is a terminal, a plain-text reporter otherwise.
+    ///
+    pub fn import(total: u64) -> Self {
+        Self::build(
+            Some(total),
+            "loaded",
+            "  {prefix:.bold}{bar:30.yellow/white} {bytes}/{total_bytes} ({percent}%, {binary_bytes_per_sec}) \
+             [{elapsed_precise}, ~{eta} left]",
+        )
+    }
+
+    ///
+    /// Creates a progress reporter for a dump that is read to compute its checksum.
+    ///
+    /// #### Arguments
+    ///
+    /// * `total`: The size of the dump.
+    ///
+    /// #### Returns
+    ///
+    /// A progress bar with the throughput when stdout is a terminal, a plain-text reporter otherwise.
+    ///
+    pub fn checksum(total: u64) -> Self {
+        Self::build(
+            Some(total),
+            "checked",
+            "  {prefix:.bold}{bar:30.yellow/white} {bytes}/{total_bytes} ({percent}%, {binary_bytes_per_sec}) \
+             [{elapsed_precise}, ~{eta} left]",
+        )
+    }
+
+    ///
+    /// Creates a progress reporter with a template for the bar.
+    ///
+    /// #### Arguments
+    ///
+    /// * `total`: The expected number of bytes, if known.
+    /// * `verb`: What happens to the bytes, for the status lines.
+    /// * `template`: The template of the bar when the number of bytes is known.
+    ///
+    fn build(total: Option<u64>, verb: &'static str, template: &'static str) -> Self {
+        let terminal = if output::is_stdout_dump() {
+            std::io::stderr().is_terminal()
+        } else {
+            std::io::stdout().is_terminal()
+        };
+        let show_bar = output::level() >= Level::Normal && terminal;
+
+        let bar = show_bar.then(|| {
+            let (template, style) = match total {
+                Some(_) => (template, ProgressStyle::default_bar()),
+                None => (
+                    "  {prefix:.bold}{spinner:.yellow} {bytes} received [{elapsed_precise}]",
+                    ProgressStyle::default_spinner(),
+                ),
+            };
+
+            let mut style = style.template(template).expect("Invalid progress template");
+
+            if output::is_plain() {
+                style = style.progress_chars("#>-").tick_chars("|/-\\ ");
+            }
+
+            let bar = match total {
+                Some(total) => ProgressBar::new(total),
+                None => ProgressBar::new_spinner(),
+            };
+
+            bar.set_style(style);
+            bar.enable_steady_tick(Duration::from_millis(120));
+            BARS.add(bar)
+        });
+
+        let now = Instant::now();
+
+        Progress {
+            bar,
+            label: None,
+            verb,
+            total,
+            bytes: AtomicU64::new(0),
+            started: now,
+            last_line: Mutex::new(now),
+        }
+    }
+
+    ///
+    /// Labels the progress with the instance it belongs to, if several transfers run at once.
+    ///
+    /// #### Arguments
+    ///
+    /// * `label`: The short instance name.
+    ///
+    pub fn with_label(mut self, label: &str) -> Self {
+        if !CONCURRENT.load(Ordering::Relaxed) {
+            return self;
+        }
+
+        if let Some(bar) = &self.bar {
+            bar.set_prefix(format!("{} ", label));
+        }
+
+        self.label = Some(label.to_string());
+        self
+    }
+
+    ///
+    /// Records that more bytes have been received.
+    ///
+    /// #### Arguments
+    ///
+    /// * `bytes`: The number of bytes received since the last call.
+    ///
+    pub fn inc(&self, bytes: u64) {
+        let received = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+
+        if let Some(bar) = &self.bar {
+            // The database size is only an estimate, so never let the bar overflow.
+            if bar.length().is_some_and(|length| received > length) {
+                bar.set_length(received);
+            }
+            bar.set_position(received);
+        } else {
+            let mut last_line = self.last_line.lock().unwrap();
+
+            if last_line.elapsed() >= PLAIN_INTERVAL {
+                *last_line = Instant::now();
+                output::status(self.status_line());
+            }
+        }
+    }
+
+    ///
+    /// Returns the number of bytes received so far.
+    ///
+    pub fn received(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Returns the expected number of bytes, if known.
+    ///
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    ///
+    /// Runs a closure with the progress bar hidden, so it can print without garbling the bar.
+    ///
+    /// #### Arguments
+    ///
+    /// * `f`: The closure to run.
+    ///
+    pub fn suspend<F: FnOnce()>(&self, f: F) {
+        match &self.bar {
+            Some(_) => suspend(f),
+            None => f(),
+        }
+    }
+
+    ///
+    /// Removes the progress bar.
+    ///
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+
+    ///
+    /// Estimates the time left from the bytes received so far and the throughput since the start.
+    ///
+    /// #### Returns
+    ///
+    /// The time left, or `None` without an expected size, before the first bytes, or once the size is exceeded.
+    ///
+    fn eta(&self) -> Option<Duration> {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let total = self.total.filter(|total| *total > bytes)?;
+        let elapsed = self.started.elapsed().as_secs_f64();
+
+        if bytes == 0 || elapsed <= 0.0 {
+            return None;
+        }
+
+        let rate = bytes as f64 / elapsed;
+        Some(Duration::from_secs_f64((total - bytes) as f64 / rate))
+    }
+
+    ///
+    /// Builds the status line printed when there is no progress bar.
+    ///
+    fn status_line(&self) -> String {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let received = crate::format_size(bytes);
+        let elapsed = self.started.elapsed().as_secs();
+        let rate = crate::format_size(bytes / elapsed.max(1));
+
+        let status = match self.total {
+            Some(total) if total > 0 => format!(
+                "{} {} {}, ~{}% ({}s, {}/s{})",
+                Glyph::Pending,
+                received,
+                self.verb,
+                (bytes * 100 / total).min(99),
+                elapsed,
+                rate,
+                self.eta()
+                    .map(|left| format!(", ~{} left", format_eta(left)))
+                    .unwrap_or_default()
+            ),
+            _ => format!("{} {} {} ({}s)", Glyph::Pending, received, self.verb, elapsed),
+        };
+
+        let status = match &self.label {
+            Some(label) => format!("{}: {}", label, status),
+            None => status,
+        };
+
+        status.yellow().to_string()
+    }
+}
+
+///
+/// A reader that reports every byte read through it to a progress reporter.
+///
+pub struct Reader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R> Reader<'a, R> {
+    ///
+    /// Wraps a reader.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The reader the bytes come from.
+    /// * `progress`: Where to report them.
+    ///
+    pub fn new(inner: R, progress: &'a Progress) -> Self {
+        Reader { inner, progress }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Reader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            this.progress.inc((buf.filled().len() - before) as u64);
+        }
+
+        poll
+    }
+}
+
+///
+/// A reader that counts the bytes read through it, e.g. the SQL a compressed dump decompresses to.
+///
+pub struct Counter<'a, R> {
+    inner: R,
+    bytes: &'a AtomicU64,
+}
+
+impl<'a, R> Counter<'a, R> {
+    ///
+    /// Wraps a reader.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The reader the bytes come from.
+    /// * `bytes`: What the bytes are added to.
+    ///
+    pub fn new(inner: R, bytes: &'a AtomicU64) -> Self {
+        Counter { inner, bytes }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Counter<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            this.bytes
+                .fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        }
+
+        poll
+    }
+}
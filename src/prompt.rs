@@ -0,0 +1,168 @@
+/*
+    Helpers for reading and validating user input.
+*/
+
+use crate::error::{Error, ErrorKind};
+use crate::options::Options;
+use crate::output::{self, Glyph};
+use colored::*;
+use std::io::{self, IsTerminal};
+
+///
+/// Reads a line from stdin.
+///
+/// #### Arguments
+///
+/// * `buf`: The buffer to append the line to.
+///
+/// #### Returns
+///
+/// The number of bytes read (0 on EOF) or an error.
+///
+pub fn read_line(buf: &mut String) -> Result<usize, Error> {
+    io::stdin()
+        .read_line(buf)
+        .map_err(|e| Error::new(ErrorKind::Aborted, format!("Failed to read input: {}", e)))
+}
+
+///
+/// Checks whether the user can be asked anything.
+///
+/// #### Returns
+///
+/// Whether stdin is a terminal. Piped or redirected input is never read as answers, and nothing is asked while the
+/// dump is written to stdout.
+///
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && !output::is_stdout_dump()
+}
+
+///
+/// Fails if the user cannot be asked anything.
+///
+/// #### Arguments
+///
+/// * `what`: What would have been asked, completing "cannot ...".
+/// * `flags`: The flags that make the question unnecessary.
+///
+/// #### Returns
+///
+/// Nothing, or an error explaining which flags to pass if stdin is not a terminal.
+///
+pub fn require_interactive(what: &str, flags: &str) -> Result<(), Error> {
+    if is_interactive() {
+        return Ok(());
+    }
+
+    let reason = if output::is_stdout_dump() {
+        "The dump is written to stdout"
+    } else {
+        "stdin is not a terminal"
+    };
+
+    Err(Error::new(
+        ErrorKind::NonInteractive,
+        format!(
+            "{}, so ndmig cannot {}. Pass {} to run without prompts.",
+            reason, what, flags
+        ),
+    ))
+}
+
+///
+/// Asks a question until the answer is valid.
+///
+/// Invalid answers are reported inline and the question is asked again, so whatever was printed before stays on
+/// screen. Typing `q` quits.
+///
+/// #### Arguments
+///
+/// * `options`: The options for this run.
+/// * `question`: The question to display.
+/// * `validate`: Turns an answer into a value, or returns the message to show when it is invalid.
+///
+/// #### Returns
+///
+/// The validated value, `None` if the user typed `q`, or an error on EOF or after too many invalid answers.
+///
+pub fn ask<T>(
+    options: &Options,
+    question: &str,
+    mut validate: impl FnMut(&str) -> Result<T, String>,
+) -> Result<Option<T>, Error> {
+    for _ in 0..options.max_attempts {
+        output::prompt(format!("\n{} ", question.bold().white()));
+
+        let mut answer = String::new();
+        if read_line(&mut answer)? == 0 {
+            return Err(Error::new(ErrorKind::Aborted, "No input (end of file)."));
+        }
+
+        let answer = answer.trim();
+        if answer.eq_ignore_ascii_case("q") {
+            return Ok(None);
+        }
+
+        match validate(answer) {
+            Ok(value) => return Ok(Some(value)),
+            Err(message) => output::info(format!("{} {}", Glyph::Error, message).red().bold()),
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::Aborted,
+        format!("Giving up after {} invalid attempt(s).", options.max_attempts),
+    ))
+}
+
+///
+/// Asks the user a yes/no question, unless `--yes` was passed.
+///
+/// #### Arguments
+///
+/// * `options`: The options for this run.
+/// * `question`: The question to display.
+///
+/// #### Returns
+///
+/// Whether the user answered yes, anything else including EOF counts as no. An error if stdin is not a terminal.
+///
+pub fn confirm(options: &Options, question: &str) -> Result<bool, Error> {
+    if options.assume_yes {
+        return Ok(true);
+    }
+
+    require_interactive(&format!("ask \"{}\"", question), "--yes")?;
+
+    output::prompt(format!("{} {} ", question.bold().white(), "[y/N]".dimmed()));
+
+    let mut answer = String::new();
+    if read_line(&mut answer).is_err() {
+        return Ok(false);
+    }
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+///
+/// Asks the user to type a value back, e.g. the name of the instance about to be overwritten.
+///
+/// Unlike [`confirm`] this is never answered by `--yes`.
+///
+/// #### Arguments
+///
+/// * `question`: The question to display.
+/// * `expected`: The exact answer that confirms.
+///
+/// #### Returns
+///
+/// Whether the user typed the expected value, or an error if stdin could not be read.
+///
+pub fn confirm_typed(question: &str, expected: &str) -> Result<bool, Error> {
+    output::prompt(format!("{} ", question.bold().white()));
+
+    let mut answer = String::new();
+    read_line(&mut answer)?;
+
+    Ok(answer.trim() == expected)
+}
@@ -0,0 +1,160 @@
+/*
+    Creates the role and database an import connects with, when the postgres container of the target has neither.
+
+    The bot creates them on its first boot, so the postgres container of a target whose bot never ran only has its
+    superuser, and psql fails to connect before the dump is even read.
+*/
+
+use crate::container::{self, Database};
+use crate::error::{Error, ErrorKind};
+use crate::json::quote;
+use bollard::Docker;
+
+/// The database every postgres server has, which the missing ones are created from.
+const MAINTENANCE_DATABASE: &str = "postgres";
+
+/// The superuser of the official postgres image when `POSTGRES_USER` is not set.
+const DEFAULT_SUPERUSER: &str = "postgres";
+
+///
+/// What had to be created in the postgres container of the target.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Provisioned {
+    /// The role that was created, `None` if it existed.
+    pub role: Option<String>,
+    /// The database that was created, `None` if it existed.
+    pub database: Option<String>,
+}
+
+///
+/// Quotes a string as a SQL literal.
+///
+/// #### Arguments
+///
+/// * `value`: The string to quote.
+///
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+///
+/// Returns whether a query of the catalog finds a row.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `admin`: The superuser and the maintenance database.
+/// * `sql`: The query, which returns a row if the object exists.
+///
+async fn exists(docker: &Docker, container_id: &str, admin: &Database, sql: &str) -> Result<bool, Error> {
+    let output = container::query(docker, container_id, admin, sql)
+        .await
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::ImportFailed,
+                format!(
+                    "Failed to connect to the {} database as {} to check for the role and database of the import.",
+                    MAINTENANCE_DATABASE, admin.user
+                ),
+            )
+        })?;
+
+    Ok(!output.trim().is_empty())
+}
+
+///
+/// Runs a statement that creates a role or database.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `admin`: The superuser and the maintenance database.
+/// * `sql`: The statement.
+/// * `what`: What it creates, e.g. `the role nationdex`.
+///
+async fn create(docker: &Docker, container_id: &str, admin: &Database, sql: &str, what: &str) -> Result<(), Error> {
+    container::query(docker, container_id, admin, sql)
+        .await
+        .map(|_| ())
+        .ok_or_else(|| Error::new(ErrorKind::ImportFailed, format!("Failed to create {}.", what)))
+}
+
+///
+/// Creates the role and database an import connects with if they do not exist yet. Running it again changes nothing.
+///
+/// The superuser is the `POSTGRES_USER` of the container, which the postgres image created on its first start, and
+/// the role gets the `POSTGRES_PASSWORD` of the container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running target.
+/// * `database`: The role and database the import connects with.
+///
+/// #### Returns
+///
+/// What was created, or an error if the server could not be reached or refused.
+///
+pub async fn ensure(docker: &Docker, container_id: &str, database: &Database) -> Result<Provisioned, Error> {
+    let env = container::environment(docker, container_id).await;
+    let admin = Database {
+        user: container::variable(&env, "POSTGRES_USER").unwrap_or_else(|| DEFAULT_SUPERUSER.to_string()),
+        name: Some(MAINTENANCE_DATABASE.to_string()),
+        password: container::variable(&env, "POSTGRES_PASSWORD"),
+        default_user: false,
+    };
+
+    let role = database.user.as_str();
+    let name = database.name.as_deref().unwrap_or(role);
+    let mut provisioned = Provisioned::default();
+
+    let role_exists = exists(
+        docker,
+        container_id,
+        &admin,
+        &format!("SELECT 1 FROM pg_roles WHERE rolname = {}", literal(role)),
+    )
+    .await?;
+
+    if !role_exists {
+        let password = match &database.password {
+            Some(password) => format!(" PASSWORD {}", literal(password)),
+            None => String::new(),
+        };
+
+        create(
+            docker,
+            container_id,
+            &admin,
+            &format!("CREATE ROLE {} LOGIN{}", quote(role), password),
+            &format!("the role {}", role),
+        )
+        .await?;
+        provisioned.role = Some(role.to_string());
+    }
+
+    let database_exists = exists(
+        docker,
+        container_id,
+        &admin,
+        &format!("SELECT 1 FROM pg_database WHERE datname = {}", literal(name)),
+    )
+    .await?;
+
+    if !database_exists {
+        create(
+            docker,
+            container_id,
+            &admin,
+            &format!("CREATE DATABASE {} OWNER {}", quote(name), quote(role)),
+            &format!("the database {}", name),
+        )
+        .await?;
+        provisioned.database = Some(name.to_string());
+    }
+
+    Ok(provisioned)
+}
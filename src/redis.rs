@@ -0,0 +1,419 @@
+/*
+    Exports the Redis data of a Ballsdex compose project, for `--include-redis`, and loads it into the Redis of the
+    target on import.
+*/
+
+use crate::archive;
+use crate::bundle;
+use crate::container::{self, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use bollard::Docker;
+use colored::*;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+/// What is appended to the dump path to get the path of the Redis snapshot.
+pub const SUFFIX: &str = ".redis.rdb";
+
+/// How often the progress of `BGSAVE` is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long `BGSAVE` may take before the export gives up.
+const SAVE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Where the Redis image keeps its snapshot, used when `CONFIG GET` is disabled.
+const DEFAULT_SNAPSHOT: &str = "/data/dump.rdb";
+
+///
+/// Returns the path of the Redis snapshot that belongs to a dump.
+///
+/// #### Arguments
+///
+/// * `dump`: The path of the dump.
+///
+pub fn path(dump: &Path) -> PathBuf {
+    let mut path = dump.as_os_str().to_owned();
+    path.push(SUFFIX);
+    PathBuf::from(path)
+}
+
+///
+/// The snapshot an import loads into the Redis of its target, for `--include-redis`.
+///
+pub struct Snapshot {
+    /// The Redis container of the target.
+    pub redis: Instance,
+    /// The snapshot saved next to a dump.
+    pub path: PathBuf,
+}
+
+///
+/// Finds the Redis container of an instance.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the instance.
+///
+/// #### Returns
+///
+/// The running Redis container, or an error if the project has none or it is stopped.
+///
+pub async fn find(docker: &Docker, instance: &str) -> Result<Instance, Error> {
+    let redis = container::find_service(docker, instance, "redis")
+        .await
+        .map_err(|e| Error::new(ErrorKind::DumpFailed, format!("Failed to list the containers: {}", e)))?
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InstanceNotFound,
+                format!(
+                    "--include-redis needs a {}-redis-1 container, but the project has none.",
+                    container::project(instance)
+                ),
+            )
+        })?;
+
+    if !redis.running {
+        return Err(Error::new(
+            ErrorKind::DumpFailed,
+            format!("{} is stopped. Start it to export its data.", redis.name),
+        ));
+    }
+
+    Ok(redis)
+}
+
+///
+/// Runs `redis-cli` inside the Redis container.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of Redis.
+/// * `args`: The arguments of `redis-cli`.
+///
+/// #### Returns
+///
+/// The output of the command, or an error if it failed.
+///
+async fn redis_cli(docker: &Docker, container_id: &str, args: &[&str]) -> Result<String, Error> {
+    let mut cmd = vec!["redis-cli"];
+    cmd.extend_from_slice(args);
+
+    let failed = |message: String| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!("redis-cli {} failed: {}", args.join(" "), message),
+        )
+    };
+
+    let output = container::exec(docker, container_id, cmd, Vec::new())
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+
+    // redis-cli exits with 0 even for most errors, which it reports on stdout instead.
+    let stdout = output.stdout_text();
+
+    if output.exit_code != Some(0) || stdout.starts_with("ERR") {
+        let message = [output.stderr_text(), stdout]
+            .into_iter()
+            .find(|text| !text.is_empty())
+            .unwrap_or_else(|| "no output".to_string());
+        return Err(failed(message));
+    }
+
+    Ok(stdout)
+}
+
+///
+/// Reads a field of `INFO persistence`.
+///
+/// #### Arguments
+///
+/// * `info`: The output of `INFO persistence`.
+/// * `field`: The field, e.g. `rdb_bgsave_in_progress`.
+///
+fn info_field<'a>(info: &'a str, field: &str) -> Option<&'a str> {
+    info.lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(key, _)| *key == field)
+        .map(|(_, value)| value)
+}
+
+///
+/// Saves a snapshot with `BGSAVE` and waits until it is written.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of Redis.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the snapshot failed or took too long.
+///
+async fn save(docker: &Docker, container_id: &str) -> Result<(), Error> {
+    let info = redis_cli(docker, container_id, &["INFO", "persistence"]).await?;
+    let last_save = info_field(&info, "rdb_last_save_time").map(str::to_string);
+
+    let reply = match redis_cli(docker, container_id, &["BGSAVE"]).await {
+        Ok(reply) => reply,
+        // A snapshot someone else started is just as recent, so it is waited for instead.
+        Err(error) if error.message.contains("already in progress") => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    // "Background saving started" means the save is already running, other replies mean it starts later.
+    let started = reply.contains("started");
+    let deadline = Instant::now() + SAVE_TIMEOUT;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let info = redis_cli(docker, container_id, &["INFO", "persistence"]).await?;
+        let in_progress = info_field(&info, "rdb_bgsave_in_progress") != Some("0");
+        let saved = started || info_field(&info, "rdb_last_save_time").map(str::to_string) != last_save;
+
+        if !in_progress && saved {
+            return match info_field(&info, "rdb_last_bgsave_status") {
+                Some("ok") | None => Ok(()),
+                Some(status) => Err(Error::new(
+                    ErrorKind::DumpFailed,
+                    format!("Redis failed to save its snapshot (status {}), see its logs.", status),
+                )),
+            };
+        }
+
+        if Instant::now() > deadline {
+            return Err(Error::new(
+                ErrorKind::DumpFailed,
+                format!(
+                    "Redis did not finish its snapshot within {} seconds.",
+                    SAVE_TIMEOUT.as_secs()
+                ),
+            ));
+        }
+    }
+}
+
+///
+/// Reads a setting of Redis with `CONFIG GET`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of Redis.
+/// * `name`: The setting, e.g. `dir`.
+///
+/// #### Returns
+///
+/// The value, or `None` if it is empty or `CONFIG GET` is not allowed.
+///
+async fn config(docker: &Docker, container_id: &str, name: &str) -> Option<String> {
+    // Prints the name on one line and the value on the next.
+    redis_cli(docker, container_id, &["CONFIG", "GET", name])
+        .await
+        .ok()
+        .and_then(|output| output.lines().nth(1).map(|value| value.trim().to_string()))
+        .filter(|value| !value.is_empty())
+}
+
+///
+/// Works out where Redis writes its snapshot.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of Redis.
+///
+/// #### Returns
+///
+/// The absolute path inside the container, the default of the Redis image if `CONFIG GET` is not allowed.
+///
+async fn snapshot_path(docker: &Docker, container_id: &str) -> String {
+    match (
+        config(docker, container_id, "dir").await,
+        config(docker, container_id, "dbfilename").await,
+    ) {
+        (Some(dir), Some(file)) => format!("{}/{}", dir.trim_end_matches('/'), file),
+        _ => {
+            output::debug(format!("CONFIG GET is not available, using {}", DEFAULT_SNAPSHOT));
+            DEFAULT_SNAPSHOT.to_string()
+        }
+    }
+}
+
+///
+/// Saves a snapshot of Redis and copies it next to the dump.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `redis`: The Redis container.
+/// * `dump_path`: The path of the dump the snapshot belongs to.
+///
+/// #### Returns
+///
+/// The size of the snapshot, or an error.
+///
+pub async fn export(docker: &Docker, redis: &Instance, dump_path: &Path) -> Result<u64, Error> {
+    output::status(format!("{} Saving a Redis snapshot of {}...", Glyph::Pending, redis.name).yellow());
+
+    save(docker, &redis.container_id).await?;
+
+    let source = snapshot_path(docker, &redis.container_id).await;
+    let destination = path(dump_path);
+
+    let bytes = archive::download_file(docker, &redis.container_id, &source, &destination)
+        .await?
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::DumpFailed,
+                format!("Redis saved its snapshot, but {} does not exist.", source),
+            )
+        })?;
+
+    output::debug(format!("Wrote {} ({} bytes)", destination.display(), bytes));
+
+    Ok(bytes)
+}
+
+///
+/// Finds the snapshot an import loads and the Redis container of its target, before anything is imported.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The full container name of the target.
+/// * `dumps`: The paths of the dumps of the import, in the order they are loaded.
+///
+/// #### Returns
+///
+/// The snapshot next to the last dump that has one, or an error if none has or the project of the target has no
+/// Redis container.
+///
+pub async fn find_snapshot(docker: &Docker, instance: &str, dumps: &[&Path]) -> Result<Snapshot, Error> {
+    let snapshot = dumps
+        .iter()
+        .rev()
+        .map(|dump| path(dump))
+        .find(|snapshot| snapshot.is_file())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Config,
+                format!(
+                    "--include-redis loads the Redis snapshot saved by `export --include-redis`, but there is no {}.",
+                    path(dumps[0]).display()
+                ),
+            )
+        })?;
+
+    let redis = container::find_service(docker, instance, "redis")
+        .await
+        .map_err(|e| Error::new(ErrorKind::ImportFailed, format!("Failed to list the containers: {}", e)))?
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InstanceNotFound,
+                format!(
+                    "--include-redis needs a {}-redis-1 container, but the project has none.",
+                    container::project(instance)
+                ),
+            )
+        })?;
+
+    Ok(Snapshot { redis, path: snapshot })
+}
+
+///
+/// Replaces the data of the Redis of the target with a snapshot.
+///
+/// Redis writes a snapshot of its own when it shuts down, so it is stopped before the file is replaced, and only
+/// reads the new one when it starts again. A Redis that was stopped is left stopped and reads it on its next start.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `snapshot`: The snapshot and the Redis container, as found by [`find_snapshot`].
+///
+/// #### Returns
+///
+/// The size of the snapshot, or an error if it could not be copied or Redis would not read it.
+///
+pub async fn import(docker: &Docker, snapshot: &Snapshot) -> Result<u64, Error> {
+    let redis = &snapshot.redis;
+    let container_id = redis.container_id.as_str();
+    let failed = |message: String| Error::new(ErrorKind::ImportFailed, message);
+
+    output::status(
+        format!(
+            "{} Loading the Redis snapshot {} into {}...",
+            Glyph::Pending,
+            snapshot.path.display(),
+            redis.name
+        )
+        .yellow(),
+    );
+
+    let running = container::is_running(docker, container_id)
+        .await
+        .unwrap_or(redis.running);
+
+    let destination = match running {
+        true => {
+            // With the append-only file on, Redis starts from that file and ignores the snapshot.
+            if config(docker, container_id, "appendonly").await.as_deref() == Some("yes") {
+                return Err(failed(format!(
+                    "{} keeps an append-only file, which Redis reads instead of a snapshot on start. Turn appendonly \
+                     off to load {}.",
+                    redis.name,
+                    snapshot.path.display()
+                )));
+            }
+
+            snapshot_path(docker, container_id).await
+        }
+        false => DEFAULT_SNAPSHOT.to_string(),
+    };
+
+    let (directory, name) = destination.rsplit_once('/').unwrap_or(("/data", destination.as_str()));
+    let file = tokio::fs::File::open(&snapshot.path)
+        .await
+        .map_err(|e| failed(format!("Failed to read {}: {}", snapshot.path.display(), e)))?;
+    let bytes = file
+        .metadata()
+        .await
+        .map_err(|e| failed(format!("Failed to read {}: {}", snapshot.path.display(), e)))?
+        .len();
+
+    // The upload endpoint takes an archive, so the snapshot gets one of its own.
+    let header = bundle::header(name, bytes).map_err(failed)?;
+    let archive = std::io::Cursor::new(header.to_vec())
+        .chain(file.take(bytes))
+        .chain(std::io::Cursor::new(bundle::padding(bytes)))
+        .chain(std::io::Cursor::new(vec![0; 2 * bundle::BLOCK_SIZE as usize]));
+
+    if running {
+        output::debug(format!("stop_container {}", container_id));
+        docker
+            .stop_container(container_id, None)
+            .await
+            .map_err(|e| failed(format!("Failed to stop {}: {}", redis.name, e)))?;
+    }
+
+    let uploaded = archive::upload_archive(docker, container_id, directory, archive).await;
+
+    if running {
+        output::debug(format!("start_container {}", container_id));
+
+        if let Err(e) = docker.start_container(container_id, None).await {
+            return Err(failed(format!("Failed to start {} again: {}", redis.name, e)));
+        }
+    }
+
+    uploaded?;
+    output::status(format!("{} Loaded the Redis snapshot into {}.", Glyph::Ok, redis.name).green());
+
+    Ok(bytes)
+}
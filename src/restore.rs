@@ -0,0 +1,1632 @@
+/*
+    Loading a dump into the database of an instance, for `import`.
+
+    The dump is streamed from disk into the stdin of psql inside the container, so it never has to fit in memory, and
+    `ON_ERROR_STOP` makes psql give up on the first failing statement instead of loading the rest on top of it. On the
+    way, the transformation renames the Ballsdex tables to the NationDex ones.
+
+    The format of a dump is told by its first bytes rather than its extension. A compressed dump is decompressed on the
+    way, and a custom or directory archive is turned back into SQL by `pg_restore -f -` inside the container, so
+    whatever the format, psql loads the same rewritten script. Only `--jobs` hands an archive to `pg_restore` as it is,
+    to load it in parallel.
+
+    With `--continue-on-error`, psql runs every statement instead and `ON_ERROR_ROLLBACK` undoes only the failing ones,
+    even within the single transaction. psql echoes each failing statement after its error, which is where the
+    failures of a load are read from.
+*/
+
+use crate::bundle;
+use crate::checksum;
+use crate::compression::Compression;
+use crate::container::{self, Database, ExecOutput, Stream};
+use crate::encrypt::{self, Decryption};
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::json;
+use crate::manifest::{self, Manifest};
+use crate::merge;
+use crate::output;
+use crate::progress::{self, Progress};
+use crate::transcript::Transcript;
+use crate::transform::{self, Rewrite};
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use bollard::Docker;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// How many lines of the stderr of psql a failed import shows.
+const ERROR_LINES: usize = 5;
+
+/// How many lines of the statement psql stopped at a failed import shows.
+const STATEMENT_LINES: usize = 10;
+
+/// The size of the buffer between the transformation and psql.
+const PIPE_BYTES: usize = 64 * 1024;
+
+/// Where the magic of a tar archive, such as a bundle, sits in its first header.
+const TAR_MAGIC: (usize, &[u8]) = (257, b"ustar");
+
+/// How many bytes of a dump are read to tell its format, after decompressing it.
+const HEAD_BYTES: usize = 64;
+
+/// How many bytes of a dump an unrecognized format is described with.
+const DESCRIBED_BYTES: usize = 16;
+
+/// The table of contents of a directory archive, which tells it from any other directory.
+const DIRECTORY_TOC: &str = "toc.dat";
+
+/// Makes psql fail after the SQL `pg_restore` converted so far, should it stop halfway, so a single transaction rolls
+/// back rather than commit part of the dump. The first line ends a `COPY` that may have been cut off.
+const CONVERSION_FAILED: &str = "\n\\.\nDO $$ BEGIN RAISE EXCEPTION 'pg_restore failed to convert the dump'; END $$;\n";
+
+/// The settings of the session `--fast` loads a dump with. Nothing is lost if the server crashes between commits but
+/// the last ones, and the indexes are built in memory rather than in several passes.
+const FAST_SETTINGS: &str = "SET synchronous_commit = off;\nSET maintenance_work_mem = '512MB';\n";
+
+/// The table aerich records the migrations the bot applied in.
+pub const AERICH_TABLE: &str = "aerich";
+
+/// Copies the migrations the target applied aside before a replace drops them, into a table of the session.
+const SAVE_AERICH: &str = "DO $$ BEGIN IF to_regclass('public.aerich') IS NOT NULL THEN CREATE TEMPORARY TABLE \
+                           ndmig_aerich AS SELECT * FROM public.aerich; END IF; END $$;\n";
+
+/// Puts the migrations of the target back once the dump is loaded, unless its aerich table was kept.
+const RESTORE_AERICH: &str = "DO $$ BEGIN IF to_regclass('pg_temp.ndmig_aerich') IS NOT NULL AND \
+                              to_regclass('public.aerich') IS NOT NULL AND NOT EXISTS (SELECT 1 FROM public.aerich) \
+                              THEN INSERT INTO public.aerich (id, version, app, content) SELECT id, version, app, \
+                              content FROM pg_temp.ndmig_aerich; END IF; END $$;\n";
+
+///
+/// What an import does with the rows a target already holds.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Refuse to import into a target that holds data.
+    #[default]
+    Abort,
+    /// Drop everything in the `public` schema of the target and load the dump instead.
+    Replace,
+    /// Add the rows of the dump to those of the target, giving new IDs to rows whose IDs are taken.
+    Merge,
+}
+
+impl Strategy {
+    ///
+    /// Returns the name used on the command line, e.g. `merge`.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Abort => "abort",
+            Strategy::Replace => "replace",
+            Strategy::Merge => "merge",
+        }
+    }
+
+    ///
+    /// Returns the SQL to run before the dump.
+    ///
+    /// #### Arguments
+    ///
+    /// * `tables`: The tables the import is limited to, by their name in the target. Replacing them only drops them.
+    /// * `keep_aerich`: Whether a replace keeps the migrations the target applied aside, to put them back afterwards.
+    ///
+    fn prelude(self, tables: Option<&[&str]>, keep_aerich: bool) -> String {
+        let save = match keep_aerich {
+            true => SAVE_AERICH,
+            false => "",
+        };
+
+        match self {
+            Strategy::Abort => String::new(),
+            Strategy::Replace if let Some(tables) = tables => {
+                let mut sql = format!("SET client_min_messages = warning;\n{}", save);
+
+                for table in tables {
+                    sql.push_str(&format!(
+                        "DROP TABLE IF EXISTS public.{} CASCADE;\n",
+                        json::quote(table)
+                    ));
+                }
+
+                sql
+            }
+            // Recreated as the connecting role, which therefore owns the restored tables.
+            Strategy::Replace => format!(
+                "SET client_min_messages = warning;\n{}DROP SCHEMA public CASCADE;\nCREATE SCHEMA public;\nGRANT USAGE ON \
+                 SCHEMA public TO PUBLIC;\n",
+                save
+            ),
+            Strategy::Merge => merge::prelude(),
+        }
+    }
+}
+
+///
+/// A dump picked for an import, with the files found next to it or in its bundle.
+///
+pub struct Dump {
+    /// The absolute path of the dump, or of the bundle that holds it.
+    pub path: PathBuf,
+    /// The size of the dump.
+    pub bytes: u64,
+    /// The manifest of the dump, `None` if it has none.
+    pub manifest: Option<Manifest>,
+    /// The SHA-256 the dump was written with, from its checksum file or else its manifest.
+    pub sha256: Option<String>,
+    /// The tables to load by their Ballsdex name, chosen by the import options, `None` for all of them.
+    pub tables: Option<BTreeSet<String>>,
+    /// What the bundle the dump is read from holds, `None` for a dump on its own.
+    pub bundle: Option<bundle::Contents>,
+    /// Whether the dump was found to match its SHA-256 by [`verify`].
+    pub verified: bool,
+    /// The format of the dump, from its first bytes once decompressed.
+    pub format: DumpFormat,
+    /// How the dump is compressed, from its first bytes.
+    pub compression: Compression,
+    /// The decrypted copy of an encrypted dump, which is read instead of the file at `path`. `bytes`, `format` and
+    /// `compression` describe the copy, `sha256` the encrypted file.
+    pub decrypted: Option<encrypt::Decrypted>,
+}
+
+impl Dump {
+    ///
+    /// Opens the dump for reading, inside its bundle if it has one.
+    ///
+    /// #### Returns
+    ///
+    /// A reader over exactly the bytes of the dump.
+    ///
+    pub async fn reader(&self) -> std::io::Result<tokio::io::Take<tokio::fs::File>> {
+        match (&self.bundle, &self.decrypted) {
+            (Some(contents), _) => bundle::open_member(&self.path, &contents.dump).await,
+            (None, Some(decrypted)) => Ok(tokio::fs::File::open(&decrypted.path).await?.take(self.bytes)),
+            (None, None) => Ok(tokio::fs::File::open(&self.path).await?.take(self.bytes)),
+        }
+    }
+
+    ///
+    /// Opens the dump for reading and decompresses it on the fly.
+    ///
+    /// #### Returns
+    ///
+    /// A reader over the dump as pg_dump wrote it, which is SQL for a plain dump.
+    ///
+    pub async fn decompressed(&self) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        Ok(decompress(self.reader().await?, self.compression))
+    }
+
+    ///
+    /// Returns whether the dump only holds rows, which can only be loaded into a database that has the schema: a
+    /// `--data-only` dump, the data file of a `--split` export or a delta.
+    ///
+    /// Without a manifest, the mode is taken from the file name, e.g. `mydex-20250101-120000-data.sql`.
+    ///
+    pub fn is_data_only(&self) -> bool {
+        if let Some(mode) = self.manifest.as_ref().and_then(|manifest| manifest.mode.as_deref()) {
+            return matches!(mode, "data" | "delta");
+        }
+
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = name.split_once('.').map_or(&*name, |(stem, _)| stem);
+
+        stem.ends_with("-data") || stem.ends_with("-delta")
+    }
+}
+
+///
+/// Decompresses a dump on the fly.
+///
+/// #### Arguments
+///
+/// * `reader`: The dump as it is on disk.
+/// * `compression`: How it is compressed.
+///
+fn decompress<'a>(
+    reader: impl AsyncRead + Send + Unpin + 'a,
+    compression: Compression,
+) -> Box<dyn AsyncRead + Send + Unpin + 'a> {
+    let reader = BufReader::new(reader);
+
+    match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => {
+            // `gzip` of several files, or of a dump appended to, writes one member after the other.
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Zstd => Box::new(ZstdDecoder::new(reader)),
+    }
+}
+
+///
+/// How to load a dump.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Load<'a> {
+    /// Whether to rewrite the dump into the NationDex schema on the way.
+    pub transform: bool,
+    /// What to do with the rows the database already holds.
+    pub strategy: Strategy,
+    /// Whether to load the dump in one transaction, so an error leaves the database as it was.
+    pub single_transaction: bool,
+    /// The tables to load by their name in the dump, `None` for all of them.
+    pub tables: Option<&'a BTreeSet<String>>,
+    /// Whether to add the foreign keys into tables that are not loaded without checking the rows against them.
+    pub no_fk_check: bool,
+    /// Whether to load the aerich migrations of the dump, rather than leaving them out and keeping those of the target.
+    pub source_aerich: bool,
+    /// Whether to load with the bulk-load settings and create the indexes and constraints after the rows.
+    pub fast: bool,
+    /// Whether to run the statements after a failing one too, rather than stopping at it.
+    pub continue_on_error: bool,
+    /// The number of jobs `pg_restore` loads an archive with as it is, `None` to convert it for psql.
+    pub jobs: Option<u32>,
+}
+
+///
+/// A statement of a dump that failed to load.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    /// The line psql read the statement at, counted in the dump as it was fed to psql, `None` if it was not part of
+    /// the dump.
+    pub line: Option<u64>,
+    /// The error of the server, with its details, e.g. `ERROR:  extension "pg_trgm" is not available`.
+    pub error: String,
+    /// The statement, `None` if psql did not echo it.
+    pub statement: Option<String>,
+}
+
+impl Failure {
+    ///
+    /// Describes the failure, with at most some lines of its statement.
+    ///
+    /// #### Arguments
+    ///
+    /// * `lines`: How many lines of the statement to show, `None` for all of them.
+    ///
+    pub fn describe(&self, lines: Option<usize>) -> String {
+        let mut text = self.error.clone();
+
+        if let Some(statement) = &self.statement {
+            let shown: Vec<&str> = statement.lines().take(lines.unwrap_or(usize::MAX)).collect();
+            text.push_str(&format!("\nThe statement was:\n{}", shown.join("\n")));
+
+            if shown.len() < statement.lines().count() {
+                text.push_str("\n...");
+            }
+        }
+
+        text
+    }
+}
+
+///
+/// The result of loading a dump.
+///
+pub struct Restore {
+    /// The size of the dump that was fed to psql.
+    pub bytes: u64,
+    /// The size of the dump after decompressing it, the same as `bytes` for an uncompressed dump.
+    pub uncompressed_bytes: u64,
+    /// The number of lines psql wrote to stderr, such as notices.
+    pub warnings: usize,
+    /// The number of identifiers renamed into the NationDex schema, `None` if the dump was loaded as is.
+    pub renamed: Option<u64>,
+    /// The tables that were loaded by their name in the target, `None` if the dump was loaded whole.
+    pub tables: Option<Vec<String>>,
+    /// The rows of every table that was left out by its name in the target, empty if the dump was loaded whole.
+    pub left_out: BTreeMap<String, u64>,
+    /// The indexes and constraints that were created after all rows, with `--fast`.
+    pub deferred: u64,
+    /// The statements that failed and were skipped, with `--continue-on-error`.
+    pub failures: Vec<Failure>,
+}
+
+///
+/// Checks whether a file holds some bytes at an offset, e.g. a magic number.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the file.
+/// * `offset`: Where the bytes are expected.
+/// * `magic`: The bytes.
+///
+fn has_magic(path: &Path, offset: usize, magic: &[u8]) -> bool {
+    let mut head = vec![0; offset + magic.len()];
+
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut head))
+        .is_ok_and(|()| head[offset..] == *magic)
+}
+
+///
+/// Turns the path given with `--file` into an absolute one, expanding a leading `~` to the home directory.
+///
+/// #### Arguments
+///
+/// * `path`: The path as given, absolute, relative to the working directory or starting with `~`.
+///
+/// #### Returns
+///
+/// The absolute path, or an error if nothing exists there.
+///
+pub fn resolve(path: &Path) -> Result<PathBuf, Error> {
+    // The shell leaves the tilde alone in `--file=~/dump.sql`.
+    let expanded = match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    };
+
+    let absolute = std::path::absolute(&expanded).unwrap_or(expanded);
+
+    match std::fs::metadata(&absolute) {
+        Ok(_) => Ok(absolute),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::new(
+            ErrorKind::Filesystem,
+            format!("{} does not exist.", absolute.display()),
+        )),
+        Err(e) => Err(Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", absolute.display(), e),
+        )),
+    }
+}
+
+///
+/// Resolves and checks the dump given with `--file` and reads the manifest and checksum files next to it.
+///
+/// #### Arguments
+///
+/// * `path`: The path as given.
+///
+/// #### Returns
+///
+/// The dump, or an error if it is missing, encrypted, cannot be imported or its manifest is broken.
+///
+pub async fn open(path: &Path) -> Result<Dump, Error> {
+    open_with(path, None).await
+}
+
+///
+/// Resolves and checks the dump given with `--file`, decrypting it first if it is encrypted, and reads the manifest
+/// and checksum files next to it.
+///
+/// #### Arguments
+///
+/// * `path`: The path as given.
+/// * `decryption`: How to decrypt the dump if it is encrypted, `None` to refuse an encrypted dump.
+///
+/// #### Returns
+///
+/// The dump, or an error if it is missing, cannot be decrypted or imported or its manifest is broken.
+///
+pub async fn open_with(path: &Path, decryption: Option<&Decryption>) -> Result<Dump, Error> {
+    let path = resolve(path)?;
+
+    let decrypted = match (encrypt::is_encrypted(&path), decryption) {
+        (false, _) => None,
+        (true, Some(decryption)) => Some(decryption.decrypt(&path).await?),
+        (true, None) => {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} is encrypted, decrypt it with `age --decrypt` first.",
+                    path.display()
+                ),
+            ));
+        }
+    };
+
+    if decrypted.is_none() && (bundle::is_bundle(&path) || has_magic(&path, TAR_MAGIC.0, TAR_MAGIC.1)) {
+        return open_bundle(path).await;
+    }
+
+    let read_from = decrypted
+        .as_ref()
+        .map_or(path.as_path(), |decrypted| decrypted.path.as_path());
+
+    let (bytes, format, compression) = identify(read_from).await?;
+
+    let manifest = manifest::read(&path).map_err(|message| Error::new(ErrorKind::Config, message))?;
+
+    if let Some(manifest) = &manifest {
+        manifest
+            .check_compatible()
+            .map_err(|message| Error::new(ErrorKind::Config, message))?;
+    }
+
+    let sha256 = checksum::read(&path)
+        .map_err(|message| Error::new(ErrorKind::Config, message))?
+        .or_else(|| manifest.as_ref().and_then(|manifest| manifest.sha256.clone()));
+
+    Ok(Dump {
+        path,
+        bytes,
+        manifest,
+        sha256,
+        tables: None,
+        bundle: None,
+        verified: false,
+        format,
+        compression,
+        decrypted,
+    })
+}
+
+///
+/// Reads the dump and the files that belong to it out of a bundle, without extracting anything.
+///
+/// #### Arguments
+///
+/// * `path`: The absolute path of the bundle.
+///
+/// #### Returns
+///
+/// The dump, or an error if the bundle is damaged, holds no dump that can be imported or its manifest is broken.
+///
+async fn open_bundle(path: PathBuf) -> Result<Dump, Error> {
+    let contents = bundle::contents(&path).map_err(|message| Error::new(ErrorKind::Config, message))?;
+    let member = bundle::open_member(&path, &contents.dump).await.map_err(|e| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    })?;
+
+    let what = format!("The dump {} in {}", contents.dump.name, path.display());
+    let (format, compression) = sniff(member, &what).await?;
+
+    if let Some(manifest) = &contents.manifest {
+        manifest
+            .check_compatible()
+            .map_err(|message| Error::new(ErrorKind::Config, message))?;
+    }
+
+    Ok(Dump {
+        bytes: contents.dump.bytes,
+        manifest: contents.manifest.clone(),
+        sha256: contents
+            .sha256
+            .clone()
+            .or_else(|| contents.manifest.as_ref().and_then(|manifest| manifest.sha256.clone())),
+        tables: None,
+        bundle: Some(contents),
+        verified: false,
+        format,
+        compression,
+        path,
+        decrypted: None,
+    })
+}
+
+///
+/// Works out what a dump on its own is, a single file or a directory archive.
+///
+/// #### Arguments
+///
+/// * `path`: The absolute path of the dump.
+///
+/// #### Returns
+///
+/// The size, format and compression of the dump, or an error if it cannot be imported.
+///
+async fn identify(path: &Path) -> Result<(u64, DumpFormat, Compression), Error> {
+    let read_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    };
+
+    let metadata = std::fs::metadata(path).map_err(read_error)?;
+
+    if metadata.is_dir() {
+        if !path.join(DIRECTORY_TOC).is_file() {
+            return Err(Error::new(
+                ErrorKind::Config,
+                format!(
+                    "{} is a directory without the {} of a pg_dump directory archive, e.g. a CSV export, which cannot \
+                     be imported.",
+                    path.display(),
+                    DIRECTORY_TOC
+                ),
+            ));
+        }
+
+        // pg_dump writes the files of a directory archive side by side, without subdirectories.
+        let bytes = std::fs::read_dir(path)
+            .map_err(read_error)?
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        return Ok((bytes, DumpFormat::Directory, Compression::None));
+    }
+
+    let file = tokio::fs::File::open(path).await.map_err(read_error)?;
+    let (format, compression) = sniff(file, &path.display().to_string()).await?;
+
+    Ok((metadata.len(), format, compression))
+}
+
+///
+/// Works out the format and compression of a dump from its first bytes.
+///
+/// #### Arguments
+///
+/// * `reader`: The dump, positioned at its start.
+/// * `what`: The dump, for the errors, e.g. `The dump mydex.sql in mydex.ndmig.tar`.
+///
+/// #### Returns
+///
+/// The format and compression, or an error naming what the dump starts with if it is none ndmig can import.
+///
+async fn sniff(mut reader: impl AsyncRead + Send + Unpin, what: &str) -> Result<(DumpFormat, Compression), Error> {
+    let unsupported = |message: String| Error::new(ErrorKind::Config, message);
+    let read_error = |e: std::io::Error| Error::new(ErrorKind::Filesystem, format!("Failed to read {}: {}", what, e));
+
+    let head = read_head(&mut reader).await.map_err(read_error)?;
+
+    if encrypt::MAGIC.iter().any(|magic| head.starts_with(magic)) {
+        return Err(unsupported(format!(
+            "{} is encrypted, decrypt it with `age --decrypt` first.",
+            what
+        )));
+    }
+
+    let compression = Compression::from_magic(&head);
+    let contents = match compression {
+        Compression::None => head,
+        compression => {
+            let whole = AsyncReadExt::chain(std::io::Cursor::new(head), reader);
+            read_head(&mut decompress(whole, compression))
+                .await
+                .map_err(|e| unsupported(format!("{} is not valid {}: {}", what, compression.name(), e)))?
+        }
+    };
+
+    let compressed = match compression {
+        Compression::None => String::new(),
+        compression => format!(" once decompressed with {}", compression.name()),
+    };
+
+    match DumpFormat::from_magic(&contents) {
+        Some(DumpFormat::Custom) => Ok((DumpFormat::Custom, compression)),
+        Some(DumpFormat::Plain) if is_text(&contents) => Ok((DumpFormat::Plain, compression)),
+        Some(DumpFormat::Json) => Err(unsupported(format!(
+            "{} is a JSON export, which cannot be imported.",
+            what
+        ))),
+        None => Err(unsupported(format!("{} is empty{}.", what, compressed))),
+        Some(_) => Err(unsupported(format!(
+            "{} is not a dump ndmig can import: it starts with {}{}. A plain SQL dump starts with text, a custom \
+             archive with `PGDMP`, and either may be compressed with gzip (1f 8b) or zstd (28 b5 2f fd).",
+            what,
+            describe(&contents),
+            compressed
+        ))),
+    }
+}
+
+///
+/// Reads the first bytes of a dump, as many as there are up to [`HEAD_BYTES`].
+///
+/// #### Arguments
+///
+/// * `reader`: The dump.
+///
+async fn read_head(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(HEAD_BYTES);
+    reader.take(HEAD_BYTES as u64).read_to_end(&mut head).await?;
+
+    Ok(head)
+}
+
+///
+/// Returns whether the first bytes of a dump are text, as those of a plain SQL dump are.
+///
+/// #### Arguments
+///
+/// * `head`: The first bytes, which may end in the middle of a character.
+///
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => head,
+        Err(e) if e.error_len().is_none() => &head[..e.valid_up_to()],
+        Err(_) => return false,
+    };
+
+    // Binary formats, e.g. zip files, have control characters among their first bytes, SQL has none but whitespace.
+    !valid
+        .iter()
+        .any(|byte| byte.is_ascii_control() && !matches!(byte, b'\t' | b'\n' | b'\r' | b'\x0c'))
+}
+
+///
+/// Describes the first bytes of a file in hex and as text, e.g. `50 4b 03 04 ("PK..")`.
+///
+/// #### Arguments
+///
+/// * `head`: The first bytes.
+///
+fn describe(head: &[u8]) -> String {
+    let head = &head[..head.len().min(DESCRIBED_BYTES)];
+    let hex: Vec<String> = head.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let text: String = head
+        .iter()
+        .map(|byte| match byte.is_ascii_graphic() || *byte == b' ' {
+            true => *byte as char,
+            false => '.',
+        })
+        .collect();
+
+    format!("{} (\"{}\")", hex.join(" "), text)
+}
+
+///
+/// Checks a dump against the SHA-256 it was written with, before anything is done to the target.
+///
+/// The dump is hashed as it is read, so it never has to fit in memory.
+///
+/// #### Arguments
+///
+/// * `dump`: The dump, as opened by [`open`], which is marked as verified if it matches.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the dump differs from what was written. A dump without a checksum is left as it is.
+///
+pub async fn verify(dump: &mut Dump) -> Result<(), Error> {
+    let Some(expected) = &dump.sha256 else {
+        return Ok(());
+    };
+
+    let read_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", dump.path.display(), e),
+        )
+    };
+
+    // The checksum of an encrypted dump is that of the file on disk, not of its decrypted copy.
+    let (bytes, reader) = match &dump.decrypted {
+        Some(_) => {
+            let file = tokio::fs::File::open(&dump.path).await.map_err(read_error)?;
+            (file.metadata().await.map_err(read_error)?.len(), file.take(u64::MAX))
+        }
+        None => (dump.bytes, dump.reader().await.map_err(read_error)?),
+    };
+
+    let progress = Progress::checksum(bytes);
+    let actual = checksum::digest(BufReader::new(progress::Reader::new(reader, &progress))).await;
+    progress.finish();
+    let actual = actual.map_err(read_error)?;
+
+    if actual != *expected {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} is corrupted or was modified: its SHA-256 is {}, but it was written with {}. Nothing was \
+                 imported, copy it over again or pass --no-checksum to import it anyway.",
+                dump.path.display(),
+                checksum::short(&actual),
+                checksum::short(expected)
+            ),
+        ));
+    }
+
+    dump.verified = true;
+    Ok(())
+}
+
+///
+/// Builds the `psql` command that reads the dump from stdin.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to load the dump into.
+/// * `single_transaction`: Whether to run everything in one transaction, so an error rolls all of it back.
+/// * `continue_on_error`: Whether to run the statements after a failing one, rolling back only the failing one.
+///
+pub fn psql_command(database: &Database, single_transaction: bool, continue_on_error: bool) -> Vec<String> {
+    let mut cmd = vec!["psql".to_string(), "-X".to_string(), "-q".to_string(), "-b".to_string()];
+
+    match continue_on_error {
+        true => cmd.extend(["-v", "ON_ERROR_STOP=0", "-v", "ON_ERROR_ROLLBACK=on"].map(str::to_string)),
+        false => cmd.extend(["-v", "ON_ERROR_STOP=1"].map(str::to_string)),
+    }
+
+    // Older versions of psql only honour --single-transaction for -f, so stdin is passed as a file.
+    if single_transaction {
+        cmd.extend(["--single-transaction".to_string(), "-f".to_string(), "-".to_string()]);
+    }
+
+    cmd.extend(database.connection_args());
+    cmd
+}
+
+///
+/// Runs psql with its stdin read from a reader, which is dropped as soon as psql exits.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to load the dump into.
+/// * `input`: The SQL to run.
+/// * `single_transaction`: Whether to run it in one transaction.
+///
+async fn run_psql(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    mut input: impl AsyncRead + Unpin,
+    single_transaction: bool,
+) -> Result<ExecOutput, bollard::errors::Error> {
+    let cmd = psql_command(database, single_transaction, false);
+    container::exec_with_input(docker, container_id, cmd, database.env(), &mut input).await
+}
+
+///
+/// Runs psql with its stdin read from a reader and its output written to the log of the import as it arrives.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to load the dump into.
+/// * `cmd`: The psql command, as built by [`psql_command`].
+/// * `input`: The SQL to run.
+/// * `transcript`: The log of the import.
+/// * `stderr`: What is kept of the stderr of psql.
+///
+/// #### Returns
+///
+/// The exit code of psql, or an error if it could not be run.
+///
+async fn stream_psql(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    cmd: Vec<String>,
+    mut input: impl AsyncRead + Unpin,
+    transcript: &mut Transcript,
+    stderr: &mut Stderr,
+) -> Result<Option<i64>, bollard::errors::Error> {
+    let exit_code = container::exec_streaming(
+        docker,
+        container_id,
+        cmd,
+        database.env(),
+        &mut input,
+        |stream, chunk| {
+            for line in transcript.record(stream, chunk) {
+                if stream == Stream::Stderr {
+                    stderr.read(&line);
+                }
+            }
+        },
+    )
+    .await;
+
+    for (stream, line) in transcript.end(exit_code.as_ref().ok().copied().flatten()) {
+        if stream == Stream::Stderr {
+            stderr.read(&line);
+        }
+    }
+
+    exit_code
+}
+
+///
+/// Runs SQL with psql, stopping at the first failing statement.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to run the SQL in.
+/// * `sql`: The statements.
+/// * `single_transaction`: Whether to run them in one transaction, so an error rolls all of them back.
+///
+/// #### Returns
+///
+/// Nothing, or an error with the last lines psql printed.
+///
+pub async fn run_script(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    sql: &str,
+    single_transaction: bool,
+) -> Result<(), Error> {
+    let result = run_psql(docker, container_id, database, sql.as_bytes(), single_transaction)
+        .await
+        .map_err(|e| Error::new(ErrorKind::ImportFailed, format!("Failed to run psql: {}", e)))?;
+
+    if result.exit_code != Some(0) {
+        let stderr = result.stderr_text();
+        let lines: Vec<&str> = stderr.lines().filter(|line| !line.is_empty()).collect();
+
+        return Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "psql stopped with exit code {}:\n{}",
+                result.exit_code.unwrap_or(-1),
+                lines[lines.len().saturating_sub(ERROR_LINES)..].join("\n")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+///
+/// Loads a plain dump into a running container.
+///
+/// With [`Strategy::Replace`] the `public` schema is dropped first, and with [`Strategy::Merge`] the dump is loaded
+/// into [`merge::SCHEMA`] instead, for [`merge::run`] to merge it from there.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to load the dump into.
+/// * `dump`: The dump, as opened by [`open`].
+/// * `load`: How to load the dump.
+/// * `transcript`: The log of the import, which everything psql prints is written to.
+///
+/// #### Returns
+///
+/// The size of the dump and the notices of psql, or an error naming the statement psql stopped at.
+///
+pub async fn restore(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    dump: &Dump,
+    load: Load<'_>,
+    transcript: &mut Transcript,
+) -> Result<Restore, Error> {
+    if let Some(jobs) = load.jobs {
+        return restore_parallel(docker, container_id, database, dump, &load, jobs, transcript).await;
+    }
+
+    let Load {
+        transform,
+        strategy,
+        single_transaction,
+        tables,
+        no_fk_check,
+        source_aerich,
+        fast,
+        continue_on_error,
+        jobs: _,
+    } = load;
+
+    let target_name = |table: &str| match transform {
+        true => transform::target_name(table).to_string(),
+        false => table.to_string(),
+    };
+
+    let path = dump.path.as_path();
+
+    let read_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    };
+
+    let bytes = dump.bytes;
+    let progress = Progress::import(bytes);
+
+    // A directory archive cannot be streamed, pg_restore reads it from a copy inside the container.
+    let staged = match dump.format {
+        DumpFormat::Directory => Some(stage(docker, container_id, dump, &progress).await?),
+        _ => None,
+    };
+
+    let uncompressed = AtomicU64::new(0);
+    let source: Box<dyn AsyncRead + Send + Unpin + '_> = match staged {
+        Some(_) => Box::new(tokio::io::empty()),
+        None => Box::new(progress::Counter::new(
+            decompress(
+                progress::Reader::new(dump.reader().await.map_err(read_error)?, &progress),
+                dump.compression,
+            ),
+            &uncompressed,
+        )),
+    };
+
+    // pg_restore reads an archive on its stdin and writes its SQL into the pipe psql reads a plain dump from.
+    let (mut archive, mut converted, sql): (_, _, Box<dyn AsyncRead + Send + Unpin + '_>) = match dump.format {
+        DumpFormat::Plain => (None, None, source),
+        _ => {
+            let (writer, reader) = tokio::io::duplex(PIPE_BYTES);
+            (Some(source), Some(writer), Box::new(reader))
+        }
+    };
+
+    let dropped: Option<Vec<String>> = tables.map(|tables| tables.iter().map(|table| target_name(table)).collect());
+    let mut prelude = strategy.prelude(
+        dropped
+            .as_ref()
+            .map(|tables| tables.iter().map(String::as_str).collect::<Vec<_>>())
+            .as_deref(),
+        !source_aerich,
+    );
+
+    if fast {
+        prelude.insert_str(0, FAST_SETTINGS);
+    }
+
+    let postlude = match strategy == Strategy::Replace && !source_aerich {
+        true => RESTORE_AERICH,
+        false => "",
+    };
+    let input = BufReader::new(sql);
+    let cmd = psql_command(database, single_transaction, continue_on_error);
+    let mut stderr = Stderr::new(prelude.lines().count() as u64);
+    transcript.begin(&format!("Loading {}", path.display()), &cmd);
+
+    let rewrite = Rewrite {
+        nationdex: transform,
+        schema: (strategy == Strategy::Merge).then_some(merge::SCHEMA),
+        tables: tables.cloned(),
+        unchecked_references: no_fk_check,
+        restore_references: strategy == Strategy::Replace,
+        without_rows: match source_aerich {
+            true => &[],
+            false => &[AERICH_TABLE],
+        },
+        defer_indexes: fast,
+    };
+
+    let filtered = rewrite.tables.is_some() || !rewrite.without_rows.is_empty() || rewrite.defer_indexes;
+    let load = async {
+        match rewrite.nationdex || rewrite.schema.is_some() || filtered {
+            false => {
+                let input = AsyncReadExt::chain(AsyncReadExt::chain(prelude.as_bytes(), input), postlude.as_bytes());
+                let result = stream_psql(docker, container_id, database, cmd, input, transcript, &mut stderr).await;
+                (result, None)
+            }
+            true => {
+                let (mut writer, reader) = tokio::io::duplex(PIPE_BYTES);
+
+                // psql owns the reading end, so it goes away once psql exits and an early exit cannot stall the rewrite.
+                let psql = stream_psql(docker, container_id, database, cmd, reader, transcript, &mut stderr);
+                // Written around the rewritten dump, so the rewrite cannot touch or leave out what they do.
+                let (result, stats) = tokio::join!(psql, async {
+                    // Dropped along with the writer, so pg_restore cannot stall on a rewrite that gave up either.
+                    let mut input = input;
+                    let stats: std::io::Result<transform::Stats> = async {
+                        writer.write_all(prelude.as_bytes()).await?;
+                        let stats = transform::stream(&mut input, &mut writer, &rewrite).await?;
+                        writer.write_all(postlude.as_bytes()).await?;
+                        Ok(stats)
+                    }
+                    .await;
+                    drop(writer);
+                    stats
+                });
+
+                (result, Some(stats))
+            }
+        }
+    };
+
+    let mut conversion = Vec::new();
+    let convert_cmd = convert_command(staged.as_deref());
+    let convert = async {
+        let (archive, writer) = (archive.as_mut()?, converted.as_mut()?);
+        let exit_code = container::exec_piped(
+            docker,
+            container_id,
+            convert_cmd.clone(),
+            Vec::new(),
+            archive,
+            writer,
+            |chunk| conversion.extend_from_slice(chunk),
+        )
+        .await;
+
+        if !matches!(exit_code, Ok(Some(0)) | Ok(None)) {
+            let _ = writer.write_all(CONVERSION_FAILED.as_bytes()).await;
+        }
+
+        drop(converted.take());
+        Some(exit_code)
+    };
+
+    let ((result, stats), conversion_exit_code) = tokio::join!(load, convert);
+    progress.finish();
+
+    if let Some(staged) = &staged {
+        container::remove_staged(docker, container_id, staged).await;
+    }
+
+    let outcome = match single_transaction {
+        true => "the import was rolled back and the database is as it was before",
+        false => "the database may be partially modified and hold part of the dump",
+    };
+
+    // What pg_restore printed goes after what psql printed, as both ran at once.
+    if let Some(exit_code) = conversion_exit_code {
+        transcript.begin(&format!("Converting {}", path.display()), &convert_cmd);
+        transcript.record(Stream::Stderr, &conversion);
+        transcript.end(exit_code.as_ref().ok().copied().flatten());
+
+        let failed = match exit_code {
+            Ok(Some(0)) | Ok(None) => None,
+            Ok(Some(code)) => Some(format!("pg_restore stopped with exit code {}", code)),
+            Err(e) => Some(format!("Failed to run pg_restore: {}", e)),
+        };
+
+        if let Some(failed) = failed {
+            // psql fails on what is appended to the cut off SQL, unless it was told to go on past errors.
+            let outcome = match continue_on_error {
+                true => "the database may be partially modified and hold part of the dump",
+                false => outcome,
+            };
+            let conversion = String::from_utf8_lossy(&conversion);
+            let lines: Vec<&str> = conversion.lines().collect();
+
+            return Err(Error::new(
+                ErrorKind::ImportFailed,
+                format!(
+                    "{} while converting {} for psql, {}:\n{}\nThe full output is in {}.",
+                    failed,
+                    path.display(),
+                    outcome,
+                    lines[lines.len().saturating_sub(ERROR_LINES)..].join("\n"),
+                    transcript.path().display()
+                ),
+            ));
+        }
+    }
+
+    let stats = match (&result, stats) {
+        (_, None) => None,
+        (_, Some(Ok(stats))) => Some(stats),
+        // psql stopped reading, its exit code tells why.
+        (Ok(exit_code), Some(Err(_))) if *exit_code != Some(0) => None,
+        (_, Some(Err(e))) => {
+            return Err(Error::new(
+                ErrorKind::ImportFailed,
+                format!("Failed to rewrite {}: {}", path.display(), e),
+            ));
+        }
+    };
+
+    if let Some(stats) = &stats {
+        transform::print_renames(stats);
+    }
+
+    let kept = stats
+        .as_ref()
+        .filter(|_| tables.is_some())
+        .map(|stats| stats.kept.iter().map(|table| target_name(table)).collect());
+    let left_out = stats
+        .as_ref()
+        .map(|stats| {
+            stats
+                .left_out
+                .iter()
+                .map(|(table, rows)| (target_name(table), *rows))
+                .collect()
+        })
+        .unwrap_or_default();
+    let renamed = stats.as_ref().filter(|_| transform).map(|stats| stats.total());
+
+    let exit_code = result.map_err(|e| {
+        Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "Failed to run psql: {}\nThe output of psql is in {}.",
+                e,
+                transcript.path().display()
+            ),
+        )
+    })?;
+
+    if exit_code != Some(0) {
+        // The statement psql stopped at, or else whatever it printed last, e.g. when the connection failed.
+        let message = match stderr.failures.last().filter(|_| !continue_on_error) {
+            Some(failure) => format!(
+                "psql stopped {}with exit code {}, {}:\n{}",
+                failure
+                    .line
+                    .map(|line| format!("at line {} ", line))
+                    .unwrap_or_default(),
+                exit_code.unwrap_or(-1),
+                outcome,
+                failure.describe(Some(STATEMENT_LINES))
+            ),
+            None => format!(
+                "psql stopped with exit code {}, {}:\n{}",
+                exit_code.unwrap_or(-1),
+                outcome,
+                Vec::from(stderr.tail).join("\n")
+            ),
+        };
+
+        return Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "{}\nThe full output of psql is in {}.",
+                message,
+                transcript.path().display()
+            ),
+        ));
+    }
+
+    let uncompressed_bytes = match dump.compression {
+        Compression::None => bytes,
+        _ => uncompressed.load(Ordering::Relaxed),
+    };
+
+    Ok(Restore {
+        bytes,
+        uncompressed_bytes,
+        warnings: stderr.lines,
+        renamed,
+        tables: kept,
+        left_out,
+        deferred: stats.map_or(0, |stats| stats.deferred),
+        failures: stderr.failures,
+    })
+}
+
+///
+/// Builds the `pg_restore` command that turns an archive back into SQL on its stdout.
+///
+/// #### Arguments
+///
+/// * `staged`: Where the archive was copied to inside the container, `None` to read it from stdin.
+///
+pub fn convert_command(staged: Option<&str>) -> Vec<String> {
+    // The roles of the source rarely exist on the target, like for the plain dumps ndmig writes.
+    let mut cmd = ["pg_restore", "--no-owner", "--no-privileges", "-f", "-"]
+        .map(str::to_string)
+        .to_vec();
+    cmd.extend(staged.map(str::to_string));
+    cmd
+}
+
+///
+/// Builds the `pg_restore` command that loads a copied archive into the database in parallel, for `--jobs`.
+///
+/// #### Arguments
+///
+/// * `database`: The role and database to load the archive into.
+/// * `jobs`: The number of jobs.
+/// * `continue_on_error`: Whether to go on past a failing statement.
+/// * `staged`: Where the archive was copied to inside the container.
+///
+pub fn parallel_command(database: &Database, jobs: u32, continue_on_error: bool, staged: &str) -> Vec<String> {
+    let mut cmd = ["pg_restore", "--no-owner", "--no-privileges", "-j"]
+        .map(str::to_string)
+        .to_vec();
+    cmd.push(jobs.to_string());
+
+    if !continue_on_error {
+        cmd.push("--exit-on-error".to_string());
+    }
+
+    cmd.extend(database.connection_args());
+    cmd.push(staged.to_string());
+    cmd
+}
+
+///
+/// Copies an archive into the container under [`container::staging_path`], for pg_restore to read it from there.
+///
+/// The archive is sent as a tar stream to `tar` inside the container, so it never has to fit in memory, and counts
+/// towards the progress as it goes.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `dump`: The archive, a custom dump as it is on disk or a directory archive.
+/// * `progress`: Reports the bytes of the archive that were copied.
+///
+/// #### Returns
+///
+/// The path of the copy inside the container, or an error.
+///
+async fn stage(docker: &Docker, container_id: &str, dump: &Dump, progress: &Progress) -> Result<String, Error> {
+    let staged = container::staging_path();
+    let (parent, name) = staged.rsplit_once('/').unwrap_or(("/tmp", staged.as_str()));
+    let read_error = |e: std::io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", dump.path.display(), e),
+        )
+    };
+    let name_error = |message: String| Error::new(ErrorKind::Config, message);
+
+    // Left over by an interrupted import, tar would otherwise add to it.
+    container::remove_staged(docker, container_id, &staged).await;
+
+    let mut archive: Box<dyn AsyncRead + Send + Unpin + '_> = Box::new(tokio::io::empty());
+
+    match dump.format {
+        DumpFormat::Directory => {
+            let mut files: Vec<(String, u64)> = std::fs::read_dir(&dump.path)
+                .map_err(read_error)?
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+                    Some((entry.file_name().to_string_lossy().into_owned(), metadata.len()))
+                })
+                .collect();
+            files.sort();
+
+            archive = Box::new(AsyncReadExt::chain(
+                archive,
+                std::io::Cursor::new(bundle::directory_header(name).map_err(name_error)?.to_vec()),
+            ));
+
+            for (file, bytes) in files {
+                let header = bundle::header(&format!("{}/{}", name, file), bytes).map_err(name_error)?;
+                let opened = tokio::fs::File::open(dump.path.join(&file)).await.map_err(read_error)?;
+                archive = append(archive, &header, opened.take(bytes), bytes, progress);
+            }
+        }
+        _ => {
+            let header = bundle::header(name, dump.bytes).map_err(name_error)?;
+            archive = append(
+                archive,
+                &header,
+                dump.reader().await.map_err(read_error)?,
+                dump.bytes,
+                progress,
+            );
+        }
+    }
+
+    let mut archive = AsyncReadExt::chain(archive, &[0; 2 * bundle::BLOCK_SIZE as usize][..]);
+    output::debug(format!(
+        "Copying {} to {} in the container",
+        dump.path.display(),
+        staged
+    ));
+
+    let cmd = ["tar", "-xf", "-", "-C", parent].map(str::to_string).to_vec();
+    let copied = container::exec_with_input(docker, container_id, cmd, Vec::new(), &mut archive).await;
+
+    match copied {
+        Ok(output) if output.exit_code == Some(0) => Ok(staged),
+        Ok(output) => {
+            container::remove_staged(docker, container_id, &staged).await;
+            Err(Error::new(
+                ErrorKind::ImportFailed,
+                format!(
+                    "Failed to copy {} into the container: {}",
+                    dump.path.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ))
+        }
+        Err(e) => Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!("Failed to copy {} into the container: {}", dump.path.display(), e),
+        )),
+    }
+}
+
+///
+/// Adds a file to the tar stream [`stage`] sends, with its header and the padding after it.
+///
+/// #### Arguments
+///
+/// * `archive`: The tar stream so far.
+/// * `header`: The header of the file.
+/// * `file`: The content of the file.
+/// * `bytes`: The size of the file.
+/// * `progress`: Reports the bytes of the file as they are sent.
+///
+fn append<'a>(
+    archive: Box<dyn AsyncRead + Send + Unpin + 'a>,
+    header: &[u8],
+    file: impl AsyncRead + Send + Unpin + 'a,
+    bytes: u64,
+    progress: &'a Progress,
+) -> Box<dyn AsyncRead + Send + Unpin + 'a> {
+    let part = AsyncReadExt::chain(
+        std::io::Cursor::new(header.to_vec()),
+        progress::Reader::new(file, progress),
+    );
+    let part = AsyncReadExt::chain(part, std::io::Cursor::new(bundle::padding(bytes)));
+    Box::new(archive.chain(part))
+}
+
+///
+/// Loads an archive with `pg_restore -j` as it is, for `--jobs`. Nothing is rewritten, which the import options
+/// were checked for, and psql is only used for what the strategy runs before.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to load the archive into.
+/// * `dump`: The archive, as opened by [`open`].
+/// * `load`: How to load the dump.
+/// * `jobs`: The number of jobs.
+/// * `transcript`: The log of the import, which everything pg_restore prints is written to.
+///
+/// #### Returns
+///
+/// The size of the archive and what pg_restore printed, or an error naming the statement it stopped at.
+///
+async fn restore_parallel(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    dump: &Dump,
+    load: &Load<'_>,
+    jobs: u32,
+    transcript: &mut Transcript,
+) -> Result<Restore, Error> {
+    let path = dump.path.as_path();
+    let prelude = load.strategy.prelude(None, false);
+
+    if !prelude.is_empty() {
+        run_script(docker, container_id, database, &prelude, false).await?;
+    }
+
+    let progress = Progress::import(dump.bytes);
+    let staged = stage(docker, container_id, dump, &progress).await;
+    progress.finish();
+    let staged = staged?;
+
+    let cmd = parallel_command(database, jobs, load.continue_on_error, &staged);
+    let mut stderr = RestoreStderr::default();
+    transcript.begin(&format!("Loading {}", path.display()), &cmd);
+
+    let exit_code = container::exec_streaming(
+        docker,
+        container_id,
+        cmd,
+        database.env(),
+        &mut tokio::io::empty(),
+        |stream, chunk| {
+            for line in transcript.record(stream, chunk) {
+                stderr.read(&line);
+            }
+        },
+    )
+    .await;
+
+    for (_, line) in transcript.end(exit_code.as_ref().ok().copied().flatten()) {
+        stderr.read(&line);
+    }
+
+    container::remove_staged(docker, container_id, &staged).await;
+
+    let exit_code = exit_code.map_err(|e| {
+        Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "Failed to run pg_restore: {}\nThe output of pg_restore is in {}.",
+                e,
+                transcript.path().display()
+            ),
+        )
+    })?;
+
+    // pg_restore exits with 1 once it went past errors, which --continue-on-error asked for.
+    if exit_code != Some(0) && (!load.continue_on_error || stderr.failures.is_empty()) {
+        let shown = match stderr.failures.last() {
+            Some(failure) => failure.describe(Some(STATEMENT_LINES)),
+            None => Vec::from(stderr.tail).join("\n"),
+        };
+
+        return Err(Error::new(
+            ErrorKind::ImportFailed,
+            format!(
+                "pg_restore stopped with exit code {}, the database may be partially modified and hold part of the \
+                 dump:\n{}\nThe full output of pg_restore is in {}.",
+                exit_code.unwrap_or(-1),
+                shown,
+                transcript.path().display()
+            ),
+        ));
+    }
+
+    Ok(Restore {
+        bytes: dump.bytes,
+        uncompressed_bytes: dump.bytes,
+        warnings: stderr.lines,
+        renamed: None,
+        tables: None,
+        left_out: BTreeMap::new(),
+        deferred: 0,
+        failures: stderr.failures,
+    })
+}
+
+///
+/// What is kept of the output of `pg_restore`, which prints each failing statement after its error.
+///
+#[derive(Default)]
+struct RestoreStderr {
+    /// The number of lines pg_restore printed.
+    lines: usize,
+    /// The last lines, for when it stops without a failing statement.
+    tail: VecDeque<String>,
+    /// The statements that failed.
+    failures: Vec<Failure>,
+    /// Whether the lines without a prefix continue the statement of the last failure.
+    statement: bool,
+}
+
+impl RestoreStderr {
+    ///
+    /// Reads a line pg_restore printed.
+    ///
+    /// #### Arguments
+    ///
+    /// * `line`: The line, without its timestamp.
+    ///
+    fn read(&mut self, line: &str) {
+        self.lines += 1;
+        self.tail.push_back(line.to_string());
+
+        if self.tail.len() > ERROR_LINES {
+            self.tail.pop_front();
+        }
+
+        if let Some(error) = line.strip_prefix("pg_restore: error: ") {
+            self.failures.push(Failure {
+                line: None,
+                error: error.to_string(),
+                statement: None,
+            });
+            self.statement = false;
+        } else if let Some(statement) = line.strip_prefix("Command was: ")
+            && let Some(failure) = self.failures.last_mut()
+        {
+            failure.statement = Some(statement.to_string());
+            self.statement = true;
+        } else if line.starts_with("pg_restore:") {
+            self.statement = false;
+        } else if self.statement
+            && let Some(statement) = self.failures.last_mut().and_then(|failure| failure.statement.as_mut())
+        {
+            statement.push('\n');
+            statement.push_str(line);
+        }
+    }
+}
+
+///
+/// What a line of the stderr of psql without a prefix continues.
+///
+enum Continued {
+    /// Neither a failure nor its statement, e.g. a notice.
+    Nothing,
+    /// The error of the last failure, e.g. its details.
+    Error,
+    /// The statement of the last failure.
+    Statement,
+}
+
+///
+/// What a load keeps of the stderr of psql, which is read line by line as psql prints it rather than kept whole.
+///
+/// psql prefixes what it prints about a statement with the line it read it at, as in `psql:<stdin>:12: ERROR:  ...`,
+/// and the lines without that prefix continue the one before, such as the details of an error or the rest of its
+/// statement.
+///
+struct Stderr {
+    /// The lines that were fed to psql before the dump, which the line numbers are counted after.
+    offset: u64,
+    /// The statements that failed so far, in the order psql ran them.
+    failures: Vec<Failure>,
+    /// What the next line without a prefix continues.
+    current: Continued,
+    /// The number of lines psql printed.
+    lines: usize,
+    /// The last lines psql printed, for an error that is not about a statement, e.g. a failed connection.
+    tail: VecDeque<String>,
+}
+
+impl Stderr {
+    ///
+    /// Creates an empty stderr.
+    ///
+    /// #### Arguments
+    ///
+    /// * `offset`: The lines that were fed to psql before the dump.
+    ///
+    fn new(offset: u64) -> Self {
+        Stderr {
+            offset,
+            failures: Vec::new(),
+            current: Continued::Nothing,
+            lines: 0,
+            tail: VecDeque::new(),
+        }
+    }
+
+    ///
+    /// Reads the next line psql printed.
+    ///
+    /// #### Arguments
+    ///
+    /// * `text`: The line, without its newline.
+    ///
+    fn read(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        output::debug(format!("psql: {}", text));
+        self.lines += 1;
+
+        if self.tail.len() == ERROR_LINES {
+            self.tail.pop_front();
+        }
+
+        self.tail.push_back(text.to_string());
+
+        let located = text.strip_prefix("psql:").and_then(|rest| {
+            let mut parts = rest.splitn(3, ':');
+            let _input = parts.next()?;
+            let line = parts.next()?.parse::<u64>().ok()?;
+            Some((line, parts.next()?.trim_start()))
+        });
+
+        let Some((line, message)) = located else {
+            if let Some(failure) = self.failures.last_mut() {
+                let continued = match self.current {
+                    Continued::Error => Some(&mut failure.error),
+                    Continued::Statement => failure.statement.as_mut(),
+                    Continued::Nothing => None,
+                };
+
+                if let Some(continued) = continued {
+                    continued.push('\n');
+                    continued.push_str(text);
+                }
+            }
+
+            return;
+        };
+
+        let line = line.checked_sub(self.offset).filter(|line| *line > 0);
+        self.current = Continued::Nothing;
+
+        if let Some(statement) = message.strip_prefix("STATEMENT:") {
+            if let Some(failure) = self.failures.last_mut()
+                && failure.line == line
+                && failure.statement.is_none()
+            {
+                failure.statement = Some(statement.trim_start().to_string());
+                self.current = Continued::Statement;
+            }
+        } else if ["ERROR:", "FATAL:", "error:"]
+            .iter()
+            .any(|prefix| message.starts_with(prefix))
+        {
+            self.failures.push(Failure {
+                line,
+                error: message.to_string(),
+                statement: None,
+            });
+            self.current = Continued::Error;
+        }
+    }
+}
@@ -0,0 +1,168 @@
+/*
+    Moves the sequences of a database past the IDs its tables hold, after an import.
+
+    pg_dump sets every sequence at the end of a dump, but the NationDex rewrite and a merge insert rows with explicit
+    IDs, which leaves the sequences behind. The bot would then fail with duplicate keys on its next insert, e.g. the
+    first catch after the migration.
+*/
+
+use crate::container::{self, Database};
+use crate::error::{Error, ErrorKind};
+use crate::json::quote;
+use bollard::Docker;
+use serde::Serialize;
+
+///
+/// A sequence that is, or was before it was moved, behind the IDs of its table.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Advanced {
+    /// The name of the sequence, e.g. `ballinstance_id_seq`.
+    pub sequence: String,
+    /// The table and column the sequence generates the IDs of, e.g. `ballinstance.id`.
+    pub column: String,
+    /// The last value the sequence handed out before, `None` if it never did.
+    pub from: Option<i64>,
+    /// The highest ID of the table, which the sequence continues after.
+    pub to: i64,
+}
+
+impl Advanced {
+    ///
+    /// Returns how many values the sequence was moved forward by.
+    ///
+    pub fn by(&self) -> i64 {
+        self.to - self.from.unwrap_or(0)
+    }
+}
+
+///
+/// Moves every sequence of a serial or identity column of the `public` schema past the highest ID of its table.
+///
+/// A sequence that is already ahead, e.g. because rows were deleted, is left where it is.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database the dump was imported into.
+///
+/// #### Returns
+///
+/// The sequences that had to be moved, or an error if they could not be read or set.
+///
+pub async fn reset(docker: &Docker, container_id: &str, database: &Database) -> Result<Vec<Advanced>, Error> {
+    compare(docker, container_id, database, true).await
+}
+
+///
+/// Lists the sequences of serial or identity columns of the `public` schema that are behind the IDs of their table.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to check.
+///
+/// #### Returns
+///
+/// The sequences that would hand out an ID that is taken, or an error if they could not be read.
+///
+pub async fn behind(docker: &Docker, container_id: &str, database: &Database) -> Result<Vec<Advanced>, Error> {
+    compare(docker, container_id, database, false).await
+}
+
+///
+/// Compares every sequence with the highest ID of its table.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database to connect with.
+/// * `set`: Whether to move the sequences that are behind.
+///
+/// #### Returns
+///
+/// The sequences that are, or were, behind.
+///
+async fn compare(docker: &Docker, container_id: &str, database: &Database, set: bool) -> Result<Vec<Advanced>, Error> {
+    let sequences = container::rows(
+        docker,
+        container_id,
+        database,
+        "SELECT c.table_name, c.column_name, q.sequence, p.last_value FROM information_schema.columns c \
+         CROSS JOIN LATERAL (SELECT pg_get_serial_sequence(format('public.%I', c.table_name), c.column_name) \
+         AS sequence) q JOIN pg_sequences p ON format('%I.%I', p.schemaname, p.sequencename)::regclass = \
+         q.sequence::regclass WHERE c.table_schema = 'public' AND q.sequence IS NOT NULL ORDER BY 1, 2",
+        ErrorKind::ImportFailed,
+        "read the sequences of the target",
+    )
+    .await?;
+
+    // Every sequence is set in the same statement, and only moves if it is behind the IDs of its table.
+    let statements: Vec<String> = sequences
+        .iter()
+        .filter_map(|row| match row.as_slice() {
+            [table, column, sequence, last] => {
+                let sequence = sequence.replace('\'', "''");
+                let previous = last.parse::<i64>().unwrap_or(0);
+
+                let action = match set {
+                    true => format!(
+                        "setval('{}', GREATEST(max, {}, 1), max IS NOT NULL OR {})",
+                        sequence,
+                        previous,
+                        !last.is_empty()
+                    ),
+                    false => "NULL".to_string(),
+                };
+
+                Some(format!(
+                    "SELECT '{sequence}', '{table}.{column}', '{last}', max, {action} \
+                     FROM (SELECT max({quoted_column})::bigint AS max FROM public.{quoted_table}) m",
+                    table = table.replace('\'', "''"),
+                    column = column.replace('\'', "''"),
+                    quoted_column = quote(column),
+                    quoted_table = quote(table),
+                ))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if statements.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let compared = container::rows(
+        docker,
+        container_id,
+        database,
+        &statements.join(" UNION ALL "),
+        ErrorKind::ImportFailed,
+        match set {
+            true => "move the sequences of the target",
+            false => "read the sequences of the target",
+        },
+    )
+    .await?;
+
+    Ok(compared
+        .into_iter()
+        .filter_map(|row| match row.as_slice() {
+            [sequence, column, last, max, _] => {
+                let from = last.parse().ok();
+                let to = max.parse().ok()?;
+
+                (to > from.unwrap_or(0)).then(|| Advanced {
+                    sequence: sequence.trim_start_matches("public.").to_string(),
+                    column: column.clone(),
+                    from,
+                    to,
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
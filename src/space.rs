@@ -0,0 +1,122 @@
+/*
+    Checks the free disk space before an export, so a dump does not fail halfway on a full disk.
+*/
+
+use crate::cli::ExportArgs;
+use crate::compression::Compression;
+use crate::format::DumpFormat;
+use std::path::{Path, PathBuf};
+
+/// The least headroom kept on top of the estimated dump size.
+const MIN_MARGIN: u64 = 100 * 1024 * 1024;
+
+///
+/// How much space an export needs compared to what the destination has.
+///
+#[derive(Debug, Clone)]
+pub struct SpaceEstimate {
+    /// The size postgres reports for the database.
+    pub database_bytes: u64,
+    /// The expected size of the dump.
+    pub dump_bytes: u64,
+    /// The dump size plus the safety margin.
+    pub required_bytes: u64,
+    /// The free space on the destination filesystem.
+    pub available_bytes: u64,
+    /// The directory the free space was read from.
+    pub directory: PathBuf,
+}
+
+impl SpaceEstimate {
+    ///
+    /// Returns whether the dump is expected to fit.
+    ///
+    pub fn fits(&self) -> bool {
+        self.required_bytes <= self.available_bytes
+    }
+
+    ///
+    /// Describes the numbers, e.g. for the dry run.
+    ///
+    pub fn describe(&self) -> String {
+        format!(
+            "the database is {}, the dump is expected to take ~{} ({} with margin), {} is free in {}",
+            crate::format_size(self.database_bytes),
+            crate::format_size(self.dump_bytes),
+            crate::format_size(self.required_bytes),
+            crate::format_size(self.available_bytes),
+            self.directory.display()
+        )
+    }
+}
+
+///
+/// Returns how large a dump is expected to be compared to the database.
+///
+/// The ratios are on the safe side: a plain dump leaves out the indexes, but a database of small rows can still dump
+/// to about its own size, and JSON repeats every column name. Compression is assumed to only halve the size.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+///
+fn ratio(args: &ExportArgs) -> f64 {
+    let format = match args.format {
+        DumpFormat::Plain | DumpFormat::Csv => 1.0,
+        // pg_dump compresses custom archives on its own.
+        DumpFormat::Custom | DumpFormat::Directory => 0.5,
+        DumpFormat::Json => 1.5,
+    };
+
+    match args.compress {
+        Compression::None => format,
+        Compression::Gzip | Compression::Zstd => format * 0.5,
+    }
+}
+
+///
+/// Estimates whether a dump fits on its destination.
+///
+/// #### Arguments
+///
+/// * `database_bytes`: The size of the database, from `pg_database_size()`.
+/// * `args`: The export arguments.
+/// * `dump_path`: Where the dump is written. The directory does not have to exist yet.
+///
+/// #### Returns
+///
+/// The estimate, or `None` if the free space cannot be read.
+///
+pub fn estimate(database_bytes: u64, args: &ExportArgs, dump_path: &Path) -> Option<SpaceEstimate> {
+    let directory = existing_ancestor(dump_path)?;
+    let available_bytes = fs4::available_space(&directory).ok()?;
+
+    let dump_bytes = (database_bytes as f64 * ratio(args)) as u64;
+    let required_bytes = dump_bytes + (dump_bytes / 10).max(MIN_MARGIN);
+
+    Some(SpaceEstimate {
+        database_bytes,
+        dump_bytes,
+        required_bytes,
+        available_bytes,
+        directory,
+    })
+}
+
+///
+/// Finds the closest directory of a path that already exists, where the free space can be read.
+///
+/// #### Arguments
+///
+/// * `path`: The path of the dump.
+///
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.is_dir())
+        .map(Path::to_path_buf)
+}
@@ -0,0 +1,163 @@
+/*
+    Exports split into a schema and a data file, for `--split`, so the schema can be applied and reviewed before the
+    rows are loaded.
+
+    The schema file ends by creating a small marker table with the ID of the split. The data file starts by checking
+    that the marker holds its ID, which stops psql with `ON_ERROR_STOP` before a single row is loaded into a database
+    that lacks the schema, and drops the marker once all rows are in. The manifests of both files link them by their
+    checksums.
+*/
+
+use crate::container::{self, Database};
+use crate::manifest;
+use crate::summary::Summary;
+use bollard::Docker;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The table the schema file creates to show that it was applied.
+const MARKER_TABLE: &str = "public.ndmig_split";
+
+///
+/// One of the two files of a split export.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Part {
+    /// The file name of the dump, next to which the other part is expected.
+    pub file: String,
+    /// The SHA-256 of the dump.
+    pub sha256: Option<String>,
+}
+
+///
+/// The link between the schema and the data file of a split export, recorded in the manifests of both.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Split {
+    /// The ID the marker of the schema file holds and the data file checks for.
+    pub id: String,
+    pub schema: Part,
+    pub data: Part,
+}
+
+///
+/// Creates the ID of a new split export.
+///
+pub fn new_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+
+    format!("{:x}{:x}", nanos, std::process::id())
+}
+
+///
+/// Returns the statement the schema file ends with, which leaves the marker behind.
+///
+/// #### Arguments
+///
+/// * `id`: The ID of the split.
+///
+pub fn marker(id: &str) -> String {
+    format!("SELECT '{}'::text AS id INTO {};\n", id, MARKER_TABLE)
+}
+
+///
+/// Returns the statement the data file starts with, which fails unless the schema of the same split was applied.
+///
+/// #### Arguments
+///
+/// * `id`: The ID of the split.
+///
+pub fn guard(id: &str) -> String {
+    let raise = format!(
+        "RAISE EXCEPTION 'Apply the -schema file of this export (split {}) before its data'",
+        id
+    );
+
+    // Two checks, as the query of the second one fails on its own if the table does not exist.
+    format!(
+        "DO $ndmig$ BEGIN IF to_regclass('{table}') IS NULL THEN {raise}; END IF; \
+         IF NOT EXISTS (SELECT 1 FROM {table} WHERE id = '{id}') THEN {raise}; END IF; END $ndmig$;\n\n",
+        table = MARKER_TABLE,
+        raise = raise,
+        id = id
+    )
+}
+
+///
+/// Returns the statement the data file ends with, which removes the marker again.
+///
+pub fn release() -> String {
+    format!("DROP TABLE {};\n", MARKER_TABLE)
+}
+
+///
+/// Checks whether the schema file of a split was applied to a database whose data file was not loaded yet.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the running instance.
+/// * `database`: The role and database to check.
+/// * `id`: The ID of the split.
+///
+pub async fn applied(docker: &Docker, container_id: &str, database: &Database, id: &str) -> bool {
+    let sql = format!("SELECT id FROM {} WHERE id = '{}'", MARKER_TABLE, id);
+
+    container::query(docker, container_id, database, &sql)
+        .await
+        .is_some_and(|found| found.trim() == id)
+}
+
+///
+/// Checks whether a line of a plain dump is a statement ndmig adds after the trailer of pg_dump.
+///
+/// #### Arguments
+///
+/// * `line`: The line, without its line break.
+///
+pub fn is_trailing_statement(line: &str) -> bool {
+    line == release().trim_end()
+        || line.starts_with("SELECT '") && line.ends_with(&format!("::text AS id INTO {};", MARKER_TABLE))
+}
+
+///
+/// Records the link between the two parts in both of their manifests.
+///
+/// #### Arguments
+///
+/// * `id`: The ID of the split.
+/// * `schema`: The summary of the schema export.
+/// * `data`: The summary of the data export.
+///
+/// #### Returns
+///
+/// Nothing, or a message if a manifest could not be updated.
+///
+pub fn link(id: &str, schema: &Summary, data: &Summary) -> Result<(), String> {
+    let part = |summary: &Summary| Part {
+        file: summary
+            .dump_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        sha256: summary.checksum.clone(),
+    };
+
+    let split = Split {
+        id: id.to_string(),
+        schema: part(schema),
+        data: part(data),
+    };
+
+    for summary in [schema, data] {
+        let mut manifest = manifest::read(&summary.dump_path)?.unwrap_or_else(|| manifest::Manifest::new(summary));
+        manifest.split = Some(split.clone());
+        manifest::write(&summary.dump_path, &manifest)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,760 @@
+/*
+    Rewrites a plain dump into a stable order while it streams, for `--stable`, so exports of the same data diff cleanly.
+*/
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+use std::task::{Context, Poll, ready};
+use tokio::io::AsyncWrite;
+use tokio::task::{self, JoinHandle};
+
+/// The comment lines that change between runs or versions without the data changing.
+const VOLATILE_COMMENTS: [&str; 4] = [
+    "-- Dumped from database version",
+    "-- Dumped by pg_dump version",
+    "-- Started on",
+    "-- Completed on",
+];
+
+/// How many bytes of rows a `COPY` section holds in memory before they are sorted and spilled to a temporary file.
+#[cfg(not(test))]
+const SPILL_BYTES: usize = 64 * 1024 * 1024;
+
+/// A few rows, so the tests spill without writing 64 MiB.
+#[cfg(test)]
+const SPILL_BYTES: usize = 64;
+
+/// How many bytes of a merged section are queued for the inner writer at once.
+#[cfg(not(test))]
+const MERGE_BYTES: usize = 1024 * 1024;
+
+/// A few rows, so the tests merge in several steps.
+#[cfg(test)]
+const MERGE_BYTES: usize = 32;
+
+/// Numbers the spill files of this process.
+static SPILLS: AtomicU32 = AtomicU32::new(0);
+
+///
+/// A field of the sort key of a row.
+///
+/// Integer keys, such as the `id` of every Ballsdex table, sort by value rather than as text. The derived order puts
+/// numbers before text, which only matters for a column that holds both.
+///
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Field {
+    Number(i128),
+    Text(Vec<u8>),
+}
+
+///
+/// A sorted run of rows in a temporary file, removed when it is dropped.
+///
+struct Spill {
+    path: PathBuf,
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+///
+/// The `COPY` section that is being collected.
+///
+struct Section {
+    /// The indexes of the primary key columns, empty to sort by the whole row.
+    key: Vec<usize>,
+    rows: Vec<Vec<u8>>,
+    bytes: usize,
+    spills: Vec<Spill>,
+}
+
+///
+/// A spill file being read back during a merge.
+///
+struct Run {
+    reader: BufReader<File>,
+    /// The next row of the run and its sort key, `None` once the run is exhausted.
+    head: Option<(Vec<Field>, Vec<u8>)>,
+    _spill: Spill,
+}
+
+///
+/// The outcome of a read or write of the spill files, which runs on the blocking pool.
+///
+enum Job {
+    /// The rows of the section were sorted into a new spill file.
+    Spilled(Spill),
+    /// The next merged rows, each with its newline, and the merge they came from.
+    Merging(Merge, Vec<u8>),
+    /// Every run of the merge is exhausted and its spill files are removed.
+    Merged {
+        /// Whether the section ended with `\.`.
+        terminated: bool,
+    },
+}
+
+///
+/// The merge of the spill files of a section, passed on as the inner writer accepts it.
+///
+struct Merge {
+    key: Vec<usize>,
+    runs: Vec<Run>,
+    /// Whether the section ended with `\.`, which is passed on after the last row.
+    terminated: bool,
+}
+
+impl Merge {
+    ///
+    /// Opens the spill files of a section for a merge, spilling the rows it still holds in memory first.
+    ///
+    /// #### Arguments
+    ///
+    /// * `section`: The section that ended.
+    /// * `terminated`: Whether it ended with `\.`.
+    ///
+    fn open(mut section: Section, terminated: bool) -> io::Result<Merge> {
+        if !section.rows.is_empty() {
+            let rows = std::mem::take(&mut section.rows);
+            section.spills.push(spill(rows, &section.key)?);
+        }
+
+        let mut runs = Vec::new();
+
+        for spill in section.spills {
+            let mut reader = BufReader::new(File::open(&spill.path)?);
+            let head = read_row(&mut reader, &section.key)?;
+            runs.push(Run {
+                reader,
+                head,
+                _spill: spill,
+            });
+        }
+
+        Ok(Merge {
+            key: section.key,
+            runs,
+            terminated,
+        })
+    }
+
+    ///
+    /// Reads the next merged rows, dropping the merge and its spill files once every run is exhausted.
+    ///
+    fn step(mut self) -> io::Result<Job> {
+        let rows = self.fill()?;
+
+        match rows.is_empty() {
+            true => Ok(Job::Merged {
+                terminated: self.terminated,
+            }),
+            false => Ok(Job::Merging(self, rows)),
+        }
+    }
+
+    ///
+    /// Reads the next merged rows.
+    ///
+    /// #### Returns
+    ///
+    /// Up to about [`MERGE_BYTES`] of rows, each with its newline, nothing once every run is exhausted.
+    ///
+    fn fill(&mut self) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+
+        while output.len() < MERGE_BYTES {
+            let next = self
+                .runs
+                .iter()
+                .enumerate()
+                .filter_map(|(index, run)| run.head.as_ref().map(|head| (index, head)))
+                .min_by(|(_, a), (_, b)| compare(a, b))
+                .map(|(index, _)| index);
+
+            let Some(index) = next else {
+                break;
+            };
+
+            let run = &mut self.runs[index];
+            let (_, row) = run.head.take().expect("The run has a head");
+            output.extend_from_slice(&row);
+            output.push(b'\n');
+            run.head = read_row(&mut run.reader, &self.key)?;
+        }
+
+        Ok(output)
+    }
+}
+
+///
+/// A writer that sorts the rows of every `COPY` section of a plain dump by primary key.
+///
+/// Statements keep their order. Runs of `SET` statements are sorted, and the comments naming versions or times are
+/// dropped. A section is sorted in memory up to [`SPILL_BYTES`], beyond that it is sorted in runs on disk which are
+/// merged again, so even the largest table is never held in memory at once. The spill files are written and read on
+/// the blocking pool, and the dump waits while they are.
+///
+pub struct Stabilizer<W> {
+    inner: W,
+    /// The primary key columns per table. Tables without one are sorted by the whole row.
+    primary_keys: BTreeMap<String, Vec<String>>,
+    /// The start of a line that has not been completed yet, or the lines waiting for a merge to finish.
+    input: Vec<u8>,
+    /// Rewritten bytes the inner writer has not accepted yet.
+    output: Vec<u8>,
+    written: usize,
+    /// Consecutive `SET` statements, passed on sorted once the run ends.
+    settings: Vec<Vec<u8>>,
+    section: Option<Section>,
+    merge: Option<Merge>,
+    /// The read or write of the spill files in progress, which the lines after it wait for.
+    job: Option<JoinHandle<io::Result<Job>>>,
+    /// Whether the dump ended, so a last line without a newline is processed as well.
+    finished: bool,
+}
+
+impl<W> Stabilizer<W> {
+    ///
+    /// Wraps a writer.
+    ///
+    /// #### Arguments
+    ///
+    /// * `inner`: The writer the stable dump is passed on to.
+    /// * `primary_keys`: The primary key columns per table, as returned by [`crate::container::primary_keys`].
+    ///
+    pub fn new(inner: W, primary_keys: BTreeMap<String, Vec<String>>) -> Self {
+        Stabilizer {
+            inner,
+            primary_keys,
+            input: Vec::new(),
+            output: Vec::new(),
+            written: 0,
+            settings: Vec::new(),
+            section: None,
+            merge: None,
+            job: None,
+            finished: false,
+        }
+    }
+
+    ///
+    /// Returns whether the lines of the dump wait for a merge or a spill file.
+    ///
+    fn busy(&self) -> bool {
+        self.merge.is_some() || self.job.is_some()
+    }
+
+    ///
+    /// Passes the collected `SET` statements on in sorted order.
+    ///
+    fn flush_settings(&mut self) {
+        self.settings.sort();
+
+        for line in self.settings.drain(..) {
+            self.output.extend_from_slice(&line);
+            self.output.push(b'\n');
+        }
+    }
+
+    ///
+    /// Processes a single line of the dump.
+    ///
+    /// #### Arguments
+    ///
+    /// * `line`: The line, without its newline.
+    /// * `newline`: Whether the line ended with a newline.
+    ///
+    fn process_line(&mut self, line: &[u8], newline: bool) -> io::Result<()> {
+        if let Some(section) = &mut self.section {
+            if line == b"\\." {
+                return self.end_section(true);
+            }
+
+            section.bytes += line.len();
+            section.rows.push(line.to_vec());
+
+            if section.bytes >= SPILL_BYTES {
+                let rows = std::mem::take(&mut section.rows);
+                let key = section.key.clone();
+                section.bytes = 0;
+                self.job = Some(task::spawn_blocking(move || spill(rows, &key).map(Job::Spilled)));
+            }
+
+            return Ok(());
+        }
+
+        if line.starts_with(b"SET ") && newline {
+            self.settings.push(line.to_vec());
+            return Ok(());
+        }
+
+        self.flush_settings();
+
+        if VOLATILE_COMMENTS
+            .iter()
+            .any(|comment| line.starts_with(comment.as_bytes()))
+        {
+            return Ok(());
+        }
+
+        self.output.extend_from_slice(line);
+
+        if newline {
+            self.output.push(b'\n');
+        }
+
+        if line.starts_with(b"COPY ") {
+            self.section = Some(Section {
+                key: key_columns(&String::from_utf8_lossy(line), &self.primary_keys),
+                rows: Vec::new(),
+                bytes: 0,
+                spills: Vec::new(),
+            });
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Passes the rows of the current section on in sorted order, or starts merging them if they were spilled.
+    ///
+    /// #### Arguments
+    ///
+    /// * `terminated`: Whether the section ended with `\.`, rather than with the end of a truncated dump.
+    ///
+    fn end_section(&mut self, terminated: bool) -> io::Result<()> {
+        if self.job.is_some() {
+            return Ok(());
+        }
+
+        let Some(mut section) = self.section.take() else {
+            return Ok(());
+        };
+
+        if section.spills.is_empty() {
+            sort(&mut section.rows, &section.key);
+
+            for row in section.rows {
+                self.output.extend_from_slice(&row);
+                self.output.push(b'\n');
+            }
+
+            if terminated {
+                self.output.extend_from_slice(b"\\.\n");
+            }
+
+            return Ok(());
+        }
+
+        self.job = Some(task::spawn_blocking(move || {
+            Merge::open(section, terminated).and_then(Merge::step)
+        }));
+
+        Ok(())
+    }
+
+    ///
+    /// Processes every complete line of the pending input, stopping early while a merge or a spill file is waited for.
+    ///
+    fn process_input(&mut self) -> io::Result<()> {
+        let input = std::mem::take(&mut self.input);
+        let mut start = 0;
+
+        while !self.busy()
+            && let Some(end) = input[start..].iter().position(|byte| *byte == b'\n')
+        {
+            self.process_line(&input[start..start + end], true)?;
+            start += end + 1;
+        }
+
+        if self.busy() || !self.finished {
+            self.input = input[start..].to_vec();
+            return Ok(());
+        }
+
+        if start < input.len() {
+            self.process_line(&input[start..], false)?;
+        }
+
+        // A truncated dump still ends with the rows it has, so nothing the dump held is lost. A spill started by its
+        // last row is waited for first, and the section ends once this runs again.
+        if self.section.is_some() {
+            self.end_section(false)?;
+        }
+
+        if self.busy() {
+            return Ok(());
+        }
+
+        self.flush_settings();
+        Ok(())
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Stabilizer<W> {
+    ///
+    /// Passes the rewritten bytes on to the inner writer, along with the rows of a merge in progress, and waits for the
+    /// spill files.
+    ///
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            while self.written < self.output.len() {
+                let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+
+                if written == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+
+                self.written += written;
+            }
+
+            self.output.clear();
+            self.written = 0;
+
+            if let Some(job) = &mut self.job {
+                let job = ready!(Pin::new(job).poll(cx)).map_err(io::Error::other)??;
+                self.job = None;
+
+                match job {
+                    Job::Spilled(spill) => {
+                        if let Some(section) = &mut self.section {
+                            section.spills.push(spill);
+                        }
+                    }
+                    Job::Merging(merge, rows) => {
+                        self.output = rows;
+                        self.merge = Some(merge);
+                    }
+                    Job::Merged { terminated } => {
+                        if terminated {
+                            self.output.extend_from_slice(b"\\.\n");
+                        }
+                    }
+                }
+
+                // The lines after a spill or a merge waited for it, so they are processed now.
+                if self.merge.is_none() {
+                    self.process_input()?;
+                }
+
+                continue;
+            }
+
+            let Some(merge) = self.merge.take() else {
+                return Poll::Ready(Ok(()));
+            };
+
+            self.job = Some(task::spawn_blocking(move || merge.step()));
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Stabilizer<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        // Only take new bytes once the previous ones are passed on, so the buffer does not grow without bound.
+        ready!(this.poll_drain(cx))?;
+
+        this.input.extend_from_slice(buf);
+        this.process_input()?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        if !this.finished {
+            this.finished = true;
+            this.process_input()?;
+        }
+
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+///
+/// Works out which columns of a `COPY` section make up the primary key of its table.
+///
+/// #### Arguments
+///
+/// * `header`: The `COPY public.player (id, discord_id, ...) FROM stdin;` line.
+/// * `primary_keys`: The primary key columns per table.
+///
+/// #### Returns
+///
+/// The indexes of the key columns, or nothing to sort by the whole row if the table has no primary key.
+///
+fn key_columns(header: &str, primary_keys: &BTreeMap<String, Vec<String>>) -> Vec<usize> {
+    let parsed = header.strip_prefix("COPY ").and_then(|rest| {
+        let (table, rest) = rest.split_once(" (")?;
+        let (columns, _) = rest.split_once(") FROM stdin")?;
+        let table = table.rsplit('.').next()?.trim_matches('"');
+        Some((table, columns))
+    });
+
+    let Some((table, columns)) = parsed else {
+        return Vec::new();
+    };
+
+    let Some(key) = primary_keys.get(table) else {
+        return Vec::new();
+    };
+
+    let columns: Vec<&str> = columns.split(", ").map(|column| column.trim_matches('"')).collect();
+    let indexes: Vec<usize> = key
+        .iter()
+        .filter_map(|name| columns.iter().position(|column| column == name))
+        .collect();
+
+    // A key column missing from the section would make rows with the same partial key tie.
+    match indexes.len() == key.len() {
+        true => indexes,
+        false => Vec::new(),
+    }
+}
+
+///
+/// Builds the sort key of a row.
+///
+/// #### Arguments
+///
+/// * `row`: The row, tab separated as in `COPY` data.
+/// * `key`: The indexes of the key columns, empty to sort by the whole row.
+///
+fn sort_key(row: &[u8], key: &[usize]) -> Vec<Field> {
+    if key.is_empty() {
+        return vec![Field::Text(row.to_vec())];
+    }
+
+    let fields: Vec<&[u8]> = row.split(|byte| *byte == b'\t').collect();
+
+    key.iter()
+        .map(|index| {
+            let field = fields.get(*index).copied().unwrap_or_default();
+
+            match std::str::from_utf8(field).ok().and_then(|text| text.parse().ok()) {
+                Some(number) => Field::Number(number),
+                None => Field::Text(field.to_vec()),
+            }
+        })
+        .collect()
+}
+
+///
+/// Compares two rows of a merge by their keys, falling back to the rows themselves.
+///
+fn compare(a: &(Vec<Field>, Vec<u8>), b: &(Vec<Field>, Vec<u8>)) -> Ordering {
+    a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1))
+}
+
+///
+/// Sorts rows in memory.
+///
+/// #### Arguments
+///
+/// * `rows`: The rows of a section.
+/// * `key`: The indexes of the key columns, empty to sort by the whole row.
+///
+fn sort(rows: &mut [Vec<u8>], key: &[usize]) {
+    // The rows themselves break ties, so the order never depends on the order pg_dump sent them in.
+    rows.sort_by_cached_key(|row| (sort_key(row, key), row.clone()));
+}
+
+///
+/// Sorts the rows of a section held in memory and writes them to a new spill file.
+///
+/// #### Arguments
+///
+/// * `rows`: The rows, which are moved to the file.
+/// * `key`: The indexes of the key columns, empty to sort by the whole row.
+///
+fn spill(mut rows: Vec<Vec<u8>>, key: &[usize]) -> io::Result<Spill> {
+    sort(&mut rows, key);
+
+    let path = std::env::temp_dir().join(format!(
+        "ndmig-stable-{}-{}.rows",
+        std::process::id(),
+        SPILLS.fetch_add(1, AtomicOrdering::Relaxed)
+    ));
+    let spill = Spill { path };
+    let mut writer = BufWriter::new(File::create(&spill.path)?);
+
+    for row in rows {
+        writer.write_all(&row)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+    Ok(spill)
+}
+
+///
+/// Reads the next row of a spill file.
+///
+/// #### Arguments
+///
+/// * `reader`: The spill file.
+/// * `key`: The indexes of the key columns.
+///
+/// #### Returns
+///
+/// The sort key and the row without its newline, or `None` at the end of the file.
+///
+fn read_row(reader: &mut BufReader<File>, key: &[usize]) -> io::Result<Option<(Vec<Field>, Vec<u8>)>> {
+    let mut row = Vec::new();
+
+    if reader.read_until(b'\n', &mut row)? == 0 {
+        return Ok(None);
+    }
+
+    if row.last() == Some(&b'\n') {
+        row.pop();
+    }
+
+    Ok(Some((sort_key(&row, key), row)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    ///
+    /// Passes a dump through a [`Stabilizer`] in chunks, as pg_dump's output arrives.
+    ///
+    /// #### Arguments
+    ///
+    /// * `dump`: The dump.
+    /// * `chunk`: The size of each write.
+    ///
+    async fn stabilize(dump: &[u8], chunk: usize) -> Vec<u8> {
+        let primary_keys = BTreeMap::from([("player".to_string(), vec!["id".to_string()])]);
+        let mut stabilizer = Stabilizer::new(Vec::new(), primary_keys);
+
+        for part in dump.chunks(chunk) {
+            stabilizer.write_all(part).await.unwrap();
+        }
+
+        stabilizer.shutdown().await.unwrap();
+        stabilizer.inner
+    }
+
+    ///
+    /// Builds a `player` section holding the given IDs, in that order.
+    ///
+    fn players(ids: impl IntoIterator<Item = u32>) -> String {
+        ids.into_iter().map(|id| format!("{}\t{}\n", id, 1000 + id)).collect()
+    }
+
+    ///
+    /// Walks the IDs 0 to `count` in a scrambled but fixed order.
+    ///
+    fn scrambled(count: u32) -> Vec<u32> {
+        (0..count).map(|index| (index * 37) % count).collect()
+    }
+
+    const HEAD: &str = "-- Dumped from database version 16.4\n\
+                        -- Dumped by pg_dump version 16.4\n\
+                        SET statement_timeout = 0;\n\
+                        SET client_encoding = 'UTF8';\n\
+                        SET lock_timeout = 0;\n\
+                        CREATE TABLE public.player (id integer, discord_id bigint);\n";
+
+    #[tokio::test]
+    async fn sorts_rows_and_settings() {
+        let dump = format!(
+            "{}COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n\nSET search_path = '';\nSET check_function_bodies = false;\n",
+            HEAD,
+            players([10, 2, 33, 1])
+        );
+
+        let output = stabilize(dump.as_bytes(), 5).await;
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "SET client_encoding = 'UTF8';\nSET lock_timeout = 0;\nSET statement_timeout = 0;\n\
+                 CREATE TABLE public.player (id integer, discord_id bigint);\n\
+                 COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n\n\
+                 SET check_function_bodies = false;\nSET search_path = '';\n",
+                players([1, 2, 10, 33])
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn output_does_not_depend_on_row_order_or_chunks() {
+        let sorted = format!(
+            "{}COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n",
+            HEAD,
+            players(0..50)
+        );
+        let shuffled = format!(
+            "{}COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n",
+            HEAD,
+            players(scrambled(50))
+        );
+
+        let expected = stabilize(sorted.as_bytes(), sorted.len()).await;
+
+        for chunk in [1, 7, 64, shuffled.len()] {
+            assert_eq!(stabilize(shuffled.as_bytes(), chunk).await, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn merges_a_spilled_section() {
+        let rows = players(scrambled(200));
+        assert!(rows.len() > SPILL_BYTES * 4);
+
+        let dump = format!(
+            "{}COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n\nCOPY public.ball (name) FROM stdin;\nb\na\n\\.\n",
+            HEAD, rows
+        );
+
+        let output = String::from_utf8(stabilize(dump.as_bytes(), 13).await).unwrap();
+
+        assert!(output.ends_with(&format!(
+            "COPY public.player (id, discord_id) FROM stdin;\n{}\\.\n\nCOPY public.ball (name) FROM stdin;\na\nb\n\\.\n",
+            players(0..200)
+        )));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_rows_of_a_truncated_section() {
+        for count in [4, 200] {
+            let mut dump = format!(
+                "{}COPY public.player (id, discord_id) FROM stdin;\n{}",
+                HEAD,
+                players(scrambled(count))
+            );
+            dump.push_str(&format!("{}\t10", count));
+
+            let output = String::from_utf8(stabilize(dump.as_bytes(), 9).await).unwrap();
+
+            assert!(output.ends_with(&format!(
+                "COPY public.player (id, discord_id) FROM stdin;\n{}{}\t10\n",
+                players(0..count),
+                count
+            )));
+        }
+    }
+}
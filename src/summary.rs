@@ -0,0 +1,615 @@
+/*
+    The summary printed at the end of an export or import.
+*/
+
+use crate::archive::ArchiveContents;
+use crate::compression::Compression;
+use crate::format::DumpFormat;
+use crate::incremental::Mark;
+use crate::merge::Merge;
+use crate::output::{self, Glyph, Level};
+use crate::restore::Strategy;
+use crate::sequence::Advanced;
+use crate::validate::{self, Check};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+///
+/// The operation a summary describes.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Export,
+    Import,
+}
+
+impl OperationKind {
+    ///
+    /// Returns the past tense used in the success message, e.g. "exported".
+    ///
+    fn past_tense(self) -> &'static str {
+        match self {
+            OperationKind::Export => "exported",
+            OperationKind::Import => "imported",
+        }
+    }
+}
+
+///
+/// What an operation did, collected while it runs.
+///
+/// Printed as a summary block, or as the JSON document with `--json`.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub operation: OperationKind,
+    pub instance: String,
+    pub container_id: String,
+    pub dump_path: PathBuf,
+    /// The size of the dump on disk.
+    pub bytes: u64,
+    /// The size of the SQL before compression.
+    pub uncompressed_bytes: u64,
+    pub compression: Compression,
+    pub format: DumpFormat,
+    /// The number of parallel `pg_dump` jobs of a directory dump, or `pg_restore` jobs of an import, `None` without
+    /// `--jobs`.
+    pub jobs: Option<u32>,
+    /// What the dump holds: `full`, `schema`, `data`, `delta`, `guild`, `player` or `filtered`.
+    pub mode: &'static str,
+    /// The Discord server a guild export was reduced to, `None` without `--guild-id`.
+    pub guild_id: Option<u64>,
+    /// The Discord user a player export holds the data of, `None` without `--player`.
+    pub player: Option<u64>,
+    /// The condition of every filtered table, `None` without `--since` or `--min-catches`.
+    pub filters: Option<BTreeMap<String, String>>,
+    /// The export a delta builds on, `None` without `--incremental`.
+    pub base: Option<PathBuf>,
+    /// The SHA-256 of the base.
+    pub base_sha256: Option<String>,
+    /// The highest primary key per table, where the next delta starts. `None` for exports that do not hold every row.
+    pub high_water_marks: Option<BTreeMap<String, Mark>>,
+    /// The `pg_dump` command that wrote the dump.
+    pub pg_dump_args: Vec<String>,
+    /// Whether the dump sets the owners of the objects, with `--keep-owner` or in an archive format.
+    pub owner: bool,
+    /// Whether the dump grants the privileges of the source, with `--keep-privileges`.
+    pub privileges: bool,
+    /// Whether the rows were sorted by primary key with `--stable`.
+    pub stable: bool,
+    /// Whether the Discord IDs were replaced with pseudonyms.
+    pub anonymized: bool,
+    /// How the dump is encrypted, e.g. `age (recipient)`, or `None` if it is not.
+    pub encryption: Option<&'static str>,
+    /// The tables the dump was limited to, or those an import touched with `--tables`. Empty if it holds every table.
+    pub tables: Vec<String>,
+    /// The tables left out of the dump.
+    pub excluded_tables: Vec<String>,
+    pub duration_ms: u128,
+    /// How long an import took to load and merge its dumps, `None` for exports.
+    pub load_ms: Option<u128>,
+    /// How long the load would have taken with the other choice of `--fast`, at the rate of the last such import.
+    pub load_estimate_ms: Option<u128>,
+    /// Whether an import loaded its dumps with `--fast`.
+    pub fast: bool,
+    /// The indexes and constraints `--fast` created after all rows.
+    pub deferred: u64,
+    pub warnings: usize,
+    pub checksum: Option<String>,
+    /// The image of the exported container.
+    pub image: Option<String>,
+    /// The postgres version of the exported database, or of the target of an import.
+    pub server_version: Option<String>,
+    /// The version of the pg_dump that wrote the dump.
+    pub pg_dump_version: Option<String>,
+    /// The postgres version the imported dump was taken from, `None` if it is not known.
+    pub source_server_version: Option<String>,
+    /// The number of rows of the core Ballsdex tables when the export started.
+    pub row_counts: BTreeMap<String, u64>,
+    /// The size of the Redis snapshot next to the dump, `None` without `--include-redis`.
+    pub redis_bytes: Option<u64>,
+    /// The size of the roles saved next to the dump, `None` without `--include-globals`.
+    pub globals_bytes: Option<u64>,
+    /// Whether the bot's `config.yml` was copied next to the dump.
+    pub bot_config: bool,
+    /// The files of the media archive next to the dump, `None` without `--include-media`.
+    pub media: Option<ArchiveContents>,
+    /// Whether the dump was read back and found complete.
+    pub verified: bool,
+    /// How an import checked its dumps against their checksums: `verified`, `skipped` with `--no-checksum` or
+    /// `missing` if one of them had none, `None` for exports.
+    pub checksum_check: Option<&'static str>,
+    /// The number of identifiers an import renamed into the NationDex schema, `None` if it loaded the dump as is.
+    pub renamed: Option<u64>,
+    /// What an import did with the blacklist tables, `skipped` or `only`, `None` if it loaded them with the rest.
+    pub blacklists: Option<&'static str>,
+    /// The rows of every table an import left out of the dump, by its name in the target.
+    pub left_out: BTreeMap<String, u64>,
+    /// How long ANALYZE ran on the target after an import, `None` with `--no-analyze`, if it failed or for exports.
+    pub analyze_ms: Option<u128>,
+    /// Whether the target was vacuumed along with ANALYZE, with `--vacuum`.
+    pub vacuumed: bool,
+    /// The log of everything psql printed while an import loaded the dumps, `None` for exports.
+    pub log: Option<PathBuf>,
+    /// The files of a bundle an import copied into the target besides the dump, e.g. `config.yml`.
+    pub restored: Vec<&'static str>,
+    /// The export of the target taken before an import, `None` with `--no-backup`.
+    pub backup: Option<PathBuf>,
+    /// The statements of the dumps that failed and were skipped, with `--continue-on-error`.
+    pub errors: usize,
+    /// The log the failed statements were written to, `None` if none failed.
+    pub error_log: Option<PathBuf>,
+    /// The tables of the target an import dropped along with its public schema, `None` without `--wipe`.
+    pub wiped: Option<Vec<String>>,
+    /// What an import did with the rows the target held, `None` for exports.
+    pub strategy: Option<Strategy>,
+    /// What happened to the rows of every table of every merged dump, in the order they were merged.
+    pub merged: Vec<Merge>,
+    /// The sequences an import moved past the imported IDs, `None` with `--no-sequence-fix` or for exports.
+    pub sequences: Option<Vec<Advanced>>,
+    /// The checks of the target after an import, `None` with `--no-validate` or for exports.
+    pub validation: Option<Vec<Check>>,
+    pub status: &'static str,
+}
+
+impl Summary {
+    ///
+    /// Creates the summary of a successful operation.
+    ///
+    /// #### Arguments
+    ///
+    /// * `operation`: The operation that ran.
+    /// * `instance`: The short instance name.
+    /// * `container_id`: The container ID of the instance.
+    /// * `dump_path`: The dump that was written or read.
+    ///
+    pub fn new(operation: OperationKind, instance: String, container_id: String, dump_path: PathBuf) -> Self {
+        Summary {
+            operation,
+            instance,
+            container_id,
+            dump_path,
+            bytes: 0,
+            uncompressed_bytes: 0,
+            compression: Compression::None,
+            format: DumpFormat::Plain,
+            jobs: None,
+            mode: "full",
+            guild_id: None,
+            player: None,
+            filters: None,
+            base: None,
+            base_sha256: None,
+            high_water_marks: None,
+            pg_dump_args: Vec::new(),
+            owner: false,
+            privileges: false,
+            stable: false,
+            anonymized: false,
+            encryption: None,
+            tables: Vec::new(),
+            excluded_tables: Vec::new(),
+            duration_ms: 0,
+            load_ms: None,
+            load_estimate_ms: None,
+            fast: false,
+            deferred: 0,
+            warnings: 0,
+            checksum: None,
+            image: None,
+            server_version: None,
+            pg_dump_version: None,
+            source_server_version: None,
+            row_counts: BTreeMap::new(),
+            redis_bytes: None,
+            globals_bytes: None,
+            bot_config: false,
+            media: None,
+            verified: false,
+            checksum_check: None,
+            renamed: None,
+            blacklists: None,
+            left_out: BTreeMap::new(),
+            errors: 0,
+            error_log: None,
+            analyze_ms: None,
+            vacuumed: false,
+            log: None,
+            restored: Vec::new(),
+            backup: None,
+            wiped: None,
+            strategy: None,
+            merged: Vec::new(),
+            sequences: None,
+            validation: None,
+            status: "success",
+        }
+    }
+
+    ///
+    /// Returns the name of the format, with the number of jobs that wrote or loaded the dump.
+    ///
+    fn format_name(&self) -> String {
+        match self.jobs {
+            Some(jobs) => format!("{} ({} jobs)", self.format.name(), jobs),
+            None => self.format.name().to_string(),
+        }
+    }
+
+    ///
+    /// Formats the size of the dump, with the uncompressed size for compressed dumps.
+    ///
+    fn size(&self) -> String {
+        match self.compression {
+            Compression::None => crate::format_size(self.bytes),
+            compression => format!(
+                "{} ({}, {} uncompressed)",
+                crate::format_size(self.bytes),
+                compression.name(),
+                crate::format_size(self.uncompressed_bytes)
+            ),
+        }
+    }
+
+    ///
+    /// Returns where the dump went, `stdout` for `--output -`.
+    ///
+    fn dump_location(&self) -> String {
+        if self.dump_path.as_os_str() == "-" {
+            "stdout".to_string()
+        } else {
+            self.dump_path.display().to_string()
+        }
+    }
+
+    ///
+    /// Prints the summary.
+    ///
+    /// With `--quiet` only the result line and the dump path are printed.
+    ///
+    pub fn print(&self) {
+        if output::is_json() {
+            output::json(self);
+            return;
+        }
+
+        output::info(
+            format!(
+                "{} {} has been successfully {}!{}",
+                Glyph::Ok,
+                self.instance,
+                self.operation.past_tense(),
+                self.checksum
+                    .as_deref()
+                    .map(|checksum| format!(" (sha256 {})", crate::checksum::short(checksum)))
+                    .unwrap_or_default()
+            )
+            .green()
+            .bold(),
+        );
+
+        if output::level() == Level::Quiet {
+            output::info(format!(
+                "  {} {}",
+                Glyph::Arrow.to_string().bright_yellow(),
+                self.dump_location().bright_cyan()
+            ));
+            return;
+        }
+
+        let operation = match self.operation {
+            OperationKind::Export => "export",
+            OperationKind::Import => "import",
+        };
+
+        let mut rows = vec![
+            ("Instance", self.instance.clone()),
+            ("Container ID", crate::short_id(&self.container_id).to_string()),
+            ("Operation", operation.to_string()),
+            ("Format", self.format_name()),
+            ("Dump", self.dump_location().bright_cyan().to_string()),
+            ("Size", self.size()),
+            (
+                "Duration",
+                format!("{:.1}s", Duration::from_millis(self.duration_ms as u64).as_secs_f64()),
+            ),
+            ("Warnings", self.warnings.to_string()),
+        ];
+
+        if let Some(guild_id) = self.guild_id {
+            rows.push(("Guild", format!("{} (partial dump)", guild_id)));
+        }
+
+        if let Some(player) = self.player {
+            rows.push(("Player", format!("{} (personal data, share it only with them)", player)));
+        }
+
+        if let Some(filters) = &self.filters {
+            let filters = filters
+                .iter()
+                .map(|(table, expression)| format!("{} WHERE {}", table, expression))
+                .collect::<Vec<_>>();
+            rows.push(("Filters", format!("{} (partial dump)", filters.join(", "))));
+        }
+
+        if let Some(base) = &self.base {
+            rows.push(("Base", format!("{} (delta)", base.display())));
+        }
+
+        if !self.tables.is_empty() {
+            let partial = match self.operation {
+                OperationKind::Export => "partial dump",
+                OperationKind::Import => "only these were touched",
+            };
+
+            rows.push(("Tables", format!("{} ({})", self.tables.join(", "), partial)));
+        }
+
+        if !self.excluded_tables.is_empty() {
+            rows.push((
+                "Excluded",
+                format!("{} (partial dump)", self.excluded_tables.join(", ")),
+            ));
+        }
+
+        if self.bot_config {
+            rows.push((
+                "Bot config",
+                crate::bot::config_path(&self.dump_path).display().to_string(),
+            ));
+        }
+
+        if let Some(bytes) = self.redis_bytes {
+            rows.push((
+                "Redis",
+                format!(
+                    "{} ({})",
+                    crate::redis::path(&self.dump_path).display(),
+                    crate::format_size(bytes)
+                ),
+            ));
+        }
+
+        if let Some(bytes) = self.globals_bytes {
+            rows.push((
+                "Globals",
+                format!(
+                    "{} ({})",
+                    crate::globals::path(&self.dump_path).display(),
+                    crate::format_size(bytes)
+                ),
+            ));
+        }
+
+        if let Some(media) = self.media {
+            rows.push((
+                "Media",
+                format!(
+                    "{} ({} files, {})",
+                    crate::media::path(&self.dump_path).display(),
+                    media.files,
+                    crate::format_size(media.bytes)
+                ),
+            ));
+        }
+
+        if let Some(encryption) = self.encryption {
+            rows.push(("Encryption", encryption.to_string()));
+        }
+
+        if self.anonymized {
+            rows.push(("Anonymized", "yes, do not import into production".yellow().to_string()));
+        }
+
+        if self.verified {
+            rows.push(("Verified", "yes".green().to_string()));
+        }
+
+        if let Some(strategy) = self.strategy {
+            rows.push(("Strategy", strategy.name().to_string()));
+        }
+
+        if let Some(load_ms) = self.load_ms {
+            let seconds = |ms: u128| format!("{:.1}s", Duration::from_millis(ms as u64).as_secs_f64());
+
+            let comparison = match (self.fast, self.load_estimate_ms) {
+                (true, Some(estimate)) => format!(
+                    ", about {} without it ({:.1}x as fast)",
+                    seconds(estimate),
+                    estimate as f64 / load_ms.max(1) as f64
+                ),
+                (true, None) => ", no import without it recorded to compare with".to_string(),
+                (false, Some(estimate)) => format!(", about {} with --fast", seconds(estimate)),
+                (false, None) => String::new(),
+            };
+
+            let deferred = match self.fast {
+                true => format!(" with --fast, {} index(es) and constraint(s) built last", self.deferred),
+                false => String::new(),
+            };
+
+            rows.push(("Load", format!("{}{}{}", seconds(load_ms), deferred, comparison)));
+        }
+
+        if let Some(analyze_ms) = self.analyze_ms {
+            rows.push((
+                "Analyze",
+                format!(
+                    "{:.1}s{}",
+                    Duration::from_millis(analyze_ms as u64).as_secs_f64(),
+                    match self.vacuumed {
+                        true => " with VACUUM",
+                        false => "",
+                    }
+                ),
+            ));
+        }
+
+        if let Some(log) = &self.error_log {
+            rows.push((
+                "Errors",
+                format!(
+                    "{} statement(s) failed and were skipped, see {}",
+                    self.errors,
+                    log.display()
+                )
+                .yellow()
+                .to_string(),
+            ));
+        }
+
+        if let Some(wiped) = &self.wiped {
+            rows.push((
+                "Wiped",
+                match wiped.is_empty() {
+                    true => "public schema, which held no tables".to_string(),
+                    false => format!("public schema with {}", wiped.join(", ")),
+                },
+            ));
+        }
+
+        if let Some(renamed) = self.renamed {
+            rows.push(("Transformed", format!("{} identifier(s) renamed to NationDex", renamed)));
+        }
+
+        if let Some(blacklists) = self.blacklists {
+            rows.push((
+                "Blacklists",
+                match blacklists {
+                    "only" => "only these were imported".to_string(),
+                    _ => "left out of the import".to_string(),
+                },
+            ));
+        }
+
+        if !self.left_out.is_empty() {
+            let tables = self
+                .left_out
+                .iter()
+                .map(|(table, rows)| format!("{} ({} row(s))", table, rows))
+                .collect::<Vec<_>>();
+
+            rows.push(("Left out", tables.join(", ")));
+        }
+
+        if let Some(sequences) = &self.sequences {
+            rows.push((
+                "Sequences",
+                match sequences.len() {
+                    0 => "none were behind".to_string(),
+                    count => format!("{} moved past the imported IDs", count),
+                },
+            ));
+        }
+
+        if let Some(log) = &self.log {
+            rows.push(("Log", log.display().to_string()));
+        }
+
+        if !self.restored.is_empty() {
+            rows.push(("Restored", self.restored.join(", ")));
+        }
+
+        if self.operation == OperationKind::Import
+            && let Some(version) = &self.server_version
+        {
+            let source = match &self.source_server_version {
+                Some(source) => format!("{} (the dump is from {})", version, source),
+                None => version.clone(),
+            };
+
+            rows.push(("PostgreSQL", source));
+        }
+
+        if let Some(backup) = &self.backup {
+            rows.push((
+                "Backup",
+                format!("{} (restore this if the import went wrong)", backup.display()),
+            ));
+        }
+
+        match (&self.checksum, self.checksum_check) {
+            (Some(checksum), Some("verified")) => {
+                rows.push(("Checksum", format!("{} ({})", checksum, "verified".green())))
+            }
+            (Some(checksum), Some("skipped")) => rows.push((
+                "Checksum",
+                format!("{} ({})", checksum, "not verified, --no-checksum".yellow()),
+            )),
+            (Some(checksum), _) => rows.push(("Checksum", checksum.clone())),
+            (None, Some(_)) => rows.push(("Checksum", "none, the dump was not verified".yellow().to_string())),
+            (None, None) => {}
+        }
+
+        output::info("");
+
+        for (label, value) in rows {
+            output::info(format!("  {}  {}", format!("{:12}", label).bold(), value));
+        }
+
+        for (index, merged) in self.merged.iter().enumerate() {
+            let width = merged.tables.keys().map(String::len).max().unwrap_or(0);
+
+            output::info(format!(
+                "\n  {} {}",
+                format!("{}. Merged rows of", index + 1).bold(),
+                merged.dump.display().to_string().bright_cyan()
+            ));
+
+            for (table, counts) in &merged.tables {
+                let dropped = match counts.dropped {
+                    0 => String::new(),
+                    dropped => format!(", {} left out with duplicate players", dropped),
+                };
+
+                output::info(format!(
+                    "    {:width$}  {} inserted, {} remapped, {} skipped{}",
+                    table, counts.inserted, counts.remapped, counts.skipped, dropped
+                ));
+            }
+
+            if merged.duplicate_players > 0 {
+                output::info(format!(
+                    "    {} player(s) were already in the target by Discord ID",
+                    merged.duplicate_players
+                ));
+            }
+
+            if !merged.ignored.is_empty() {
+                output::info(
+                    format!("    Left out: {}", merged.ignored.join(", "))
+                        .dimmed()
+                        .to_string(),
+                );
+            }
+        }
+
+        if let Some(sequences) = self.sequences.as_ref().filter(|sequences| !sequences.is_empty()) {
+            let width = sequences
+                .iter()
+                .map(|advanced| advanced.sequence.len())
+                .max()
+                .unwrap_or(0);
+            output::info(format!("\n  {}", "Advanced sequences".bold()));
+
+            for advanced in sequences {
+                output::info(format!(
+                    "    {:width$}  +{} to {} ({})",
+                    advanced.sequence,
+                    advanced.by(),
+                    advanced.to,
+                    advanced.column
+                ));
+            }
+        }
+
+        if let Some(checks) = &self.validation {
+            output::info(format!("\n  {}", "Validation".bold()));
+            validate::print(checks);
+        }
+    }
+}
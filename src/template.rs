@@ -0,0 +1,154 @@
+/*
+    Templates for dump file names.
+*/
+
+use chrono::{DateTime, Local};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// The file name used when no template is configured.
+pub const DEFAULT_TEMPLATE: &str = "{instance}-{date}-{time}.sql";
+
+/// The placeholders a template may contain.
+const PLACEHOLDERS: &[&str] = &["instance", "container_id", "date", "time"];
+
+///
+/// A piece of a parsed template.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+///
+/// A validated dump file name template, e.g. `{instance}-{date}-{time}.sql`.
+///
+/// #### Placeholders
+///
+/// * `{instance}`: The short instance name.
+/// * `{container_id}`: The short container ID.
+/// * `{date}`: The local date as `YYYYMMDD`.
+/// * `{time}`: The local time as `HHMMSS`.
+///
+/// Literal braces are written as `{{` and `}}`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed '{{' in name template '{}'", source)),
+                        }
+                    }
+
+                    let Some(placeholder) = PLACEHOLDERS.iter().find(|placeholder| **placeholder == name) else {
+                        return Err(format!(
+                            "Unknown placeholder '{{{}}}' in name template '{}' (expected one of {})",
+                            name,
+                            source,
+                            PLACEHOLDERS
+                                .iter()
+                                .map(|placeholder| format!("{{{}}}", placeholder))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    };
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Placeholder(placeholder));
+                }
+                '}' => return Err(format!("Unmatched '}}' in name template '{}'", source)),
+                c if std::path::is_separator(c) => {
+                    return Err(format!(
+                        "Name template '{}' must be a file name, use --output for the directory",
+                        source
+                    ));
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        if parts.is_empty() {
+            return Err("The name template must not be empty".to_string());
+        }
+
+        Ok(NameTemplate {
+            source: source.to_string(),
+            parts,
+        })
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE.parse().expect("Invalid default name template")
+    }
+}
+
+impl Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl NameTemplate {
+    ///
+    /// Fills in the placeholders.
+    ///
+    /// #### Arguments
+    ///
+    /// * `instance`: The short instance name.
+    /// * `container_id`: The container ID of the instance.
+    /// * `now`: The time of the export.
+    ///
+    /// #### Returns
+    ///
+    /// The file name.
+    ///
+    pub fn render(&self, instance: &str, container_id: &str, now: DateTime<Local>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Placeholder("instance") => instance.to_string(),
+                Part::Placeholder("container_id") => crate::short_id(container_id).to_string(),
+                Part::Placeholder("date") => now.format("%Y%m%d").to_string(),
+                Part::Placeholder("time") => now.format("%H%M%S").to_string(),
+                Part::Placeholder(other) => unreachable!("Unhandled placeholder {}", other),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,199 @@
+/*
+    The log of an import, which keeps everything psql printed while loading the dumps.
+
+    A big restore prints megabytes of notices and an error may come after tens of thousands of statements, so every
+    line is written to disk as it arrives, with the time it arrived at, rather than kept until psql exits.
+*/
+
+use crate::container::Stream;
+use crate::error::{Error, ErrorKind};
+use crate::output::{self, Glyph};
+use chrono::Local;
+use colored::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+///
+/// The log file of an import.
+///
+pub struct Transcript {
+    /// Where the log is written.
+    path: PathBuf,
+    /// The open log, `None` once writing it failed.
+    writer: Option<BufWriter<File>>,
+    /// The end of the last chunk of each output that did not end with a newline yet, for stdout and stderr.
+    partial: [Vec<u8>; 2],
+}
+
+impl Transcript {
+    ///
+    /// Creates the log, replacing a file that is in the way.
+    ///
+    /// #### Arguments
+    ///
+    /// * `path`: Where to write the log.
+    ///
+    /// #### Returns
+    ///
+    /// The log, or an error if it could not be created.
+    ///
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let file = File::create(path).map_err(|e| {
+            Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to create {}: {}", path.display(), e),
+            )
+        })?;
+
+        Ok(Transcript {
+            path: path.to_path_buf(),
+            writer: Some(BufWriter::new(file)),
+            partial: [Vec::new(), Vec::new()],
+        })
+    }
+
+    ///
+    /// Returns where the log is written.
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    ///
+    /// Starts the part of the log about a command, e.g. psql loading one dump.
+    ///
+    /// #### Arguments
+    ///
+    /// * `title`: What the command does.
+    /// * `cmd`: The command and its arguments.
+    ///
+    pub fn begin(&mut self, title: &str, cmd: &[String]) {
+        self.write(format!("{} ==== {}: {}\n", timestamp(), title, cmd.join(" ")).as_bytes());
+    }
+
+    ///
+    /// Writes a chunk of what a command printed, line by line.
+    ///
+    /// #### Arguments
+    ///
+    /// * `stream`: Which output the chunk was written to.
+    /// * `chunk`: The bytes, which may end in the middle of a line.
+    ///
+    /// #### Returns
+    ///
+    /// The lines the chunk completed, without their newline.
+    ///
+    pub fn record(&mut self, stream: Stream, chunk: &[u8]) -> Vec<String> {
+        let partial = &mut self.partial[stream as usize];
+        partial.extend_from_slice(chunk);
+
+        let Some(end) = partial.iter().rposition(|byte| *byte == b'\n') else {
+            return Vec::new();
+        };
+
+        let rest = partial.split_off(end + 1);
+        let complete = std::mem::replace(partial, rest);
+        let lines: Vec<String> = String::from_utf8_lossy(&complete).lines().map(str::to_string).collect();
+
+        self.write_lines(stream, &lines);
+        lines
+    }
+
+    ///
+    /// Writes what is left of the output of a command once it exited, and the exit code.
+    ///
+    /// #### Arguments
+    ///
+    /// * `exit_code`: The exit code of the command, `None` if it is unknown.
+    ///
+    /// #### Returns
+    ///
+    /// The last line of each output if it did not end with a newline.
+    ///
+    pub fn end(&mut self, exit_code: Option<i64>) -> Vec<(Stream, String)> {
+        let mut left = Vec::new();
+
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let partial = std::mem::take(&mut self.partial[stream as usize]);
+
+            if !partial.is_empty() {
+                let line = String::from_utf8_lossy(&partial).to_string();
+                self.write_lines(stream, std::slice::from_ref(&line));
+                left.push((stream, line));
+            }
+        }
+
+        let exit_code = exit_code.map_or_else(|| "unknown".to_string(), |code| code.to_string());
+        self.write(format!("{} ==== exited with {}\n", timestamp(), exit_code).as_bytes());
+
+        if let Some(writer) = &mut self.writer
+            && let Err(e) = writer.flush()
+        {
+            self.failed(e);
+        }
+
+        left
+    }
+
+    ///
+    /// Writes lines of an output, each with the time.
+    ///
+    /// #### Arguments
+    ///
+    /// * `stream`: Which output the lines were written to.
+    /// * `lines`: The lines, without their newline.
+    ///
+    fn write_lines(&mut self, stream: Stream, lines: &[String]) {
+        let time = timestamp();
+        let mut text = String::new();
+
+        for line in lines {
+            text.push_str(&format!("{} {} | {}\n", time, stream.name(), line));
+        }
+
+        self.write(text.as_bytes());
+    }
+
+    ///
+    /// Writes bytes to the log, giving up on it after the first error, as the import should not fail for its log.
+    ///
+    /// #### Arguments
+    ///
+    /// * `bytes`: What to write.
+    ///
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(writer) = &mut self.writer
+            && let Err(e) = writer.write_all(bytes)
+        {
+            self.failed(e);
+        }
+    }
+
+    ///
+    /// Stops writing the log after an error.
+    ///
+    /// #### Arguments
+    ///
+    /// * `error`: What went wrong.
+    ///
+    fn failed(&mut self, error: std::io::Error) {
+        self.writer = None;
+        output::status(
+            format!(
+                "{} Failed to write {}, the rest of the output of psql is not logged: {}",
+                Glyph::Warning,
+                self.path.display(),
+                error
+            )
+            .yellow(),
+        );
+    }
+}
+
+///
+/// Returns the time a line of the log arrived at, to the millisecond.
+///
+fn timestamp() -> String {
+    Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string()
+}
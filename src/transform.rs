@@ -0,0 +1,1204 @@
+/*
+    Rewrites a plain Ballsdex dump into the NationDex schema, for `import` and the `transform` subcommand.
+
+    NationDex calls a ball a nation, so its tables and the columns pointing at them carry other names. The rewrite
+    works on one statement at a time while the dump streams: identifiers are renamed according to [`TABLES`], along
+    with the sequences, indexes and constraints named after them, while string literals, comments, function bodies
+    and the rows of `COPY` sections pass through byte for byte.
+
+    The same pass can limit a dump to some tables, for `import --tables`. Every statement is attributed to the table
+    it creates, changes or fills, and the statements of other tables are left out along with their rows.
+*/
+
+use crate::bundle;
+use crate::cli::TransformArgs;
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::output::{self, Glyph};
+use colored::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+
+///
+/// A Ballsdex table, with the names NationDex gives it and its columns.
+///
+pub struct Table {
+    pub from: &'static str,
+    pub to: &'static str,
+    /// The renamed columns, each as the Ballsdex and the NationDex name.
+    pub columns: &'static [(&'static str, &'static str)],
+}
+
+/// The differences between the schemas. Tables that keep their name are only listed for their renamed columns.
+pub const TABLES: &[Table] = &[
+    Table {
+        from: "ball",
+        to: "nation",
+        columns: &[],
+    },
+    Table {
+        from: "ballinstance",
+        to: "nationinstance",
+        columns: &[("ball_id", "nation_id")],
+    },
+    Table {
+        from: "tradeobject",
+        to: "tradeobject",
+        columns: &[("ballinstance_id", "nationinstance_id")],
+    },
+];
+
+/// The endings postgres gives the names it derives from a table and its columns, e.g. `ball_id_seq`.
+const DERIVED_SUFFIXES: &[&str] = &["_seq", "_pkey", "_key", "_fkey", "_idx", "_check", "_excl"];
+
+/// The beginnings of the index and constraint names Tortoise ORM derives, e.g. `idx_ballinstanc_ball_id_04328c`.
+const DERIVED_PREFIXES: &[&str] = &["idx_", "uid_", "fk_"];
+
+/// The functions whose string argument names a sequence or a table rather than holding data.
+const REGCLASS_FUNCTIONS: &[&str] = &["setval", "nextval", "currval", "pg_get_serial_sequence"];
+
+///
+/// Returns the NationDex name of a Ballsdex table, e.g. `nation` for `ball`.
+///
+/// #### Arguments
+///
+/// * `table`: The Ballsdex name, which is returned for tables that keep their name.
+///
+pub fn target_name(table: &str) -> &str {
+    TABLES
+        .iter()
+        .find(|mapping| mapping.from == table)
+        .map_or(table, |mapping| mapping.to)
+}
+
+///
+/// Returns the NationDex name of a column of a Ballsdex table, e.g. `nation_id` for the `ball_id` of `ballinstance`.
+///
+/// #### Arguments
+///
+/// * `table`: The Ballsdex name of the table.
+/// * `column`: The Ballsdex name of the column, which is returned for columns that keep their name.
+///
+pub fn target_column<'a>(table: &str, column: &'a str) -> &'a str {
+    TABLES
+        .iter()
+        .filter(|mapping| mapping.from == table)
+        .flat_map(|mapping| mapping.columns)
+        .find(|(from, _)| *from == column)
+        .map_or(column, |(_, to)| to)
+}
+
+///
+/// Returns the Ballsdex name of a NationDex table, e.g. `ball` for `nation`.
+///
+/// #### Arguments
+///
+/// * `table`: The NationDex name, which is returned for tables that keep their name.
+///
+pub fn source_name(table: &str) -> &str {
+    TABLES
+        .iter()
+        .find(|mapping| mapping.to == table)
+        .map_or(table, |mapping| mapping.from)
+}
+
+///
+/// What a rewrite changes.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Rewrite {
+    /// Whether to rename the Ballsdex tables to the NationDex ones.
+    pub nationdex: bool,
+    /// The schema the objects of the `public` schema are moved to, e.g. for a merge, `None` to leave them.
+    pub schema: Option<&'static str>,
+    /// The tables to keep by their name in the dump, `None` to keep everything. Statements that belong to no table
+    /// are left out as well, apart from the settings of the session.
+    pub tables: Option<BTreeSet<String>>,
+    /// Whether to add the foreign keys of the kept tables into other tables without checking the rows against them.
+    pub unchecked_references: bool,
+    /// Whether to also keep the foreign keys of other tables into the kept ones, which dropping the kept tables removed.
+    pub restore_references: bool,
+    /// The tables to create without their rows by their name in the dump, such as `aerich`.
+    pub without_rows: &'static [&'static str],
+    /// Whether to hold the indexes and constraints back until the end of the dump, so they are built once over all rows.
+    pub defer_indexes: bool,
+}
+
+///
+/// How often each identifier was renamed.
+///
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// The number of statements read, without the rows of `COPY` sections.
+    pub statements: u64,
+    /// The number of times each rename was applied, by the Ballsdex and the NationDex name.
+    pub renamed: BTreeMap<(String, String), u64>,
+    /// The tables the dump creates, by their name in the dump.
+    pub created: BTreeSet<String>,
+    /// The foreign keys of the dump, each as the table that has it and the table it references.
+    pub references: BTreeSet<(String, String)>,
+    /// The index and constraint statements held back until the end, with [`Rewrite::defer_indexes`].
+    pub deferred: u64,
+    /// The tables whose statements were kept, with [`Rewrite::tables`].
+    pub kept: BTreeSet<String>,
+    /// The rows left out per table, with [`Rewrite::tables`].
+    pub left_out: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    ///
+    /// Returns the total number of renamed identifiers.
+    ///
+    pub fn total(&self) -> u64 {
+        self.renamed.values().sum()
+    }
+}
+
+///
+/// A piece of a statement.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Identifier,
+    QuotedIdentifier,
+    /// A single-quoted string, `E'...'` included.
+    Literal,
+    /// Whitespace and comments.
+    Space,
+    /// A single character such as `(` or `;`.
+    Punctuation,
+    /// Numbers and dollar-quoted strings, which are never rewritten.
+    Opaque,
+}
+
+///
+/// Splits SQL into tokens.
+///
+/// #### Arguments
+///
+/// * `sql`: The SQL, as bytes since a dump does not have to be valid UTF-8.
+///
+/// #### Returns
+///
+/// The tokens with their ranges, which cover the whole input, and whether the input ends outside of a string, a
+/// quoted identifier or a comment.
+///
+fn tokenize(sql: &[u8]) -> (Vec<(Token, std::ops::Range<usize>)>, bool) {
+    let is_start = |byte: u8| byte.is_ascii_alphabetic() || byte == b'_' || byte >= 0x80;
+    let is_part = |byte: u8| is_start(byte) || byte.is_ascii_digit() || byte == b'$';
+
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < sql.len() {
+        let start = i;
+        let byte = sql[i];
+
+        let token = match byte {
+            _ if byte.is_ascii_whitespace() => {
+                while i < sql.len() && sql[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                Token::Space
+            }
+            b'-' if sql.get(i + 1) == Some(&b'-') => {
+                while i < sql.len() && sql[i] != b'\n' {
+                    i += 1;
+                }
+                Token::Space
+            }
+            b'/' if sql.get(i + 1) == Some(&b'*') => {
+                let mut depth = 0;
+
+                loop {
+                    if i + 1 >= sql.len() {
+                        return (tokens, false);
+                    }
+
+                    match &sql[i..i + 2] {
+                        b"/*" => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        b"*/" => {
+                            depth -= 1;
+                            i += 2;
+
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => i += 1,
+                    }
+                }
+
+                Token::Space
+            }
+            b'\'' => {
+                // E'...' was started by the identifier `E` before it, which lets a backslash escape the quote.
+                let escapes = tokens
+                    .last()
+                    .is_some_and(|(token, range): &(Token, std::ops::Range<usize>)| {
+                        *token == Token::Identifier && range.end == i && sql[range.clone()].eq_ignore_ascii_case(b"e")
+                    });
+
+                i += 1;
+
+                loop {
+                    match sql.get(i) {
+                        None => return (tokens, false),
+                        Some(b'\\') if escapes => i += 2,
+                        Some(b'\'') if sql.get(i + 1) == Some(&b'\'') => i += 2,
+                        Some(b'\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(_) => i += 1,
+                    }
+                }
+
+                Token::Literal
+            }
+            b'"' => {
+                i += 1;
+
+                loop {
+                    match sql.get(i) {
+                        None => return (tokens, false),
+                        Some(b'"') if sql.get(i + 1) == Some(&b'"') => i += 2,
+                        Some(b'"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(_) => i += 1,
+                    }
+                }
+
+                Token::QuotedIdentifier
+            }
+            b'$' if !sql.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let tag_end = sql[i + 1..]
+                    .iter()
+                    .position(|byte| !(byte.is_ascii_alphanumeric() || *byte == b'_'))
+                    .map(|position| i + 1 + position);
+
+                match tag_end {
+                    Some(tag_end) if sql[tag_end] == b'$' => {
+                        let tag = &sql[i..=tag_end];
+                        let body = tag_end + 1;
+
+                        match sql[body..].windows(tag.len()).position(|window| window == tag) {
+                            Some(position) => i = body + position + tag.len(),
+                            None => return (tokens, false),
+                        }
+
+                        Token::Opaque
+                    }
+                    _ => {
+                        i += 1;
+                        Token::Punctuation
+                    }
+                }
+            }
+            _ if byte.is_ascii_digit() => {
+                while i < sql.len() && (is_part(sql[i]) || sql[i] == b'.') {
+                    i += 1;
+                }
+                Token::Opaque
+            }
+            _ if is_start(byte) => {
+                while i < sql.len() && is_part(sql[i]) {
+                    i += 1;
+                }
+                Token::Identifier
+            }
+            _ => {
+                i += 1;
+                Token::Punctuation
+            }
+        };
+
+        tokens.push((token, start..i));
+    }
+
+    (tokens, true)
+}
+
+///
+/// Returns the name an identifier stands for: lowercased unless it is quoted, as postgres folds it.
+///
+/// #### Arguments
+///
+/// * `token`: Whether the identifier is quoted.
+/// * `text`: The identifier as written.
+///
+/// #### Returns
+///
+/// The name, or `None` for a name that is not valid UTF-8 and can therefore not be in [`TABLES`].
+///
+fn name_of(token: Token, text: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(text).ok()?;
+
+    match token {
+        Token::QuotedIdentifier => Some(text[1..text.len() - 1].replace("\"\"", "\"")),
+        _ => Some(text.to_lowercase()),
+    }
+}
+
+///
+/// What a statement belongs to.
+///
+#[derive(Debug, PartialEq, Eq)]
+enum Subject {
+    /// A setting of the session, such as `SET statement_timeout = 0`.
+    Session,
+    /// A table, by its name without the schema.
+    Table(String),
+    /// A sequence, by its name without the schema.
+    Sequence(String),
+    /// Anything else, such as a schema, an extension or a function.
+    Other,
+}
+
+///
+/// Reads a name that may be qualified, e.g. `public.ball`.
+///
+/// #### Arguments
+///
+/// * `words`: The significant tokens of a statement, with their names.
+/// * `start`: The index of the first part of the name.
+///
+/// #### Returns
+///
+/// The parts of the name, empty if there is no name at `start`.
+///
+fn qualified_name(words: &[(Token, String)], start: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut index = start;
+
+    while let Some((Token::Identifier | Token::QuotedIdentifier, name)) = words.get(index) {
+        parts.push(name.as_str());
+
+        match words.get(index + 1) {
+            Some((Token::Punctuation, dot)) if dot == "." => index += 2,
+            _ => break,
+        }
+    }
+
+    parts
+}
+
+///
+/// Returns what a statement of pg_dump belongs to.
+///
+/// #### Arguments
+///
+/// * `words`: The significant tokens of the statement, with identifiers lowercased and literals without quotes.
+///
+fn subject(words: &[(Token, String)]) -> Subject {
+    let is = |index: usize, keyword: &str| {
+        words
+            .get(index)
+            .is_some_and(|(token, text)| *token == Token::Identifier && text.eq_ignore_ascii_case(keyword))
+    };
+
+    // Skips optional keywords such as `ONLY` or `IF NOT EXISTS`.
+    let skip = |mut index: usize, keywords: &[&str]| {
+        while keywords.iter().any(|keyword| is(index, keyword)) {
+            index += 1;
+        }
+        index
+    };
+
+    let last = |index: usize| qualified_name(words, index).last().map(|name| name.to_string());
+    let table =
+        |index: usize| last(skip(index, &["only", "if", "not", "exists"])).map_or(Subject::Other, Subject::Table);
+    let sequence = |index: usize| last(skip(index, &["if", "not", "exists"])).map_or(Subject::Other, Subject::Sequence);
+
+    if is(0, "set") {
+        return Subject::Session;
+    }
+
+    if is(0, "select") {
+        let function = qualified_name(words, 1);
+
+        return match function.last().copied() {
+            Some("set_config") => Subject::Session,
+            Some("setval") => match words.get(1 + 2 * function.len()) {
+                Some((Token::Literal, name)) => {
+                    Subject::Sequence(name.rsplit('.').next().unwrap_or_default().to_lowercase())
+                }
+                _ => Subject::Other,
+            },
+            _ => Subject::Other,
+        };
+    }
+
+    if is(0, "copy") {
+        return table(1);
+    }
+
+    if is(0, "create") || is(0, "alter") {
+        let index = skip(1, &["unique", "unlogged"]);
+
+        if is(index, "table") {
+            return table(index + 1);
+        }
+
+        if is(index, "sequence") {
+            return sequence(index + 1);
+        }
+
+        if is(index, "index") {
+            return match (index..words.len()).find(|index| is(*index, "on")) {
+                Some(on) => table(on + 1),
+                None => Subject::Other,
+            };
+        }
+
+        return Subject::Other;
+    }
+
+    if is(0, "comment") && is(1, "on") {
+        if is(2, "table") {
+            return table(3);
+        }
+
+        if is(2, "sequence") {
+            return sequence(3);
+        }
+
+        // The table of `COMMENT ON COLUMN public.ball.country` is the part before the column.
+        if is(2, "column") {
+            let parts = qualified_name(words, 3);
+            return match parts.len() {
+                2.. => Subject::Table(parts[parts.len() - 2].to_string()),
+                _ => Subject::Other,
+            };
+        }
+
+        return Subject::Other;
+    }
+
+    if (is(0, "grant") || is(0, "revoke"))
+        && let Some(on) = (1..words.len()).find(|index| is(*index, "on"))
+    {
+        return match is(on + 1, "sequence") {
+            true => sequence(on + 2),
+            false => table(skip(on + 1, &["table"])),
+        };
+    }
+
+    Subject::Other
+}
+
+///
+/// Rewrites the statements of a dump, keeping track of whether the lines are statements or rows.
+///
+#[derive(Default)]
+struct Transformer {
+    /// The lines of the statement that is not complete yet.
+    pending: Vec<u8>,
+    /// Whether the lines are the rows of a `COPY ... FROM stdin` section.
+    in_copy: bool,
+    /// The table whose rows are left out, while they are read.
+    skipping: Option<String>,
+    /// The statements held back until the end of the dump.
+    deferred: Vec<u8>,
+    rewrite: Rewrite,
+    stats: Stats,
+}
+
+impl Transformer {
+    ///
+    /// Processes one line of the dump.
+    ///
+    /// #### Arguments
+    ///
+    /// * `line`: The line, with its line break.
+    /// * `out`: Where the rewritten SQL is appended. Lines of an incomplete statement are held back.
+    ///
+    fn line(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        if self.in_copy {
+            if line.trim_ascii_end() == b"\\." {
+                self.in_copy = false;
+
+                if self.skipping.take().is_some() {
+                    return;
+                }
+            } else if let Some(table) = &self.skipping {
+                *self.stats.left_out.entry(table.clone()).or_default() += 1;
+                return;
+            }
+
+            out.extend_from_slice(line);
+            return;
+        }
+
+        // Commands of psql itself, such as `\connect` or `\restrict`, end with the line rather than a semicolon.
+        if self.pending.iter().all(u8::is_ascii_whitespace) && line.trim_ascii_start().starts_with(b"\\") {
+            out.extend_from_slice(&self.pending);
+            out.extend_from_slice(line);
+            self.pending.clear();
+            return;
+        }
+
+        self.pending.extend_from_slice(line);
+
+        let (tokens, complete) = tokenize(&self.pending);
+        let ends_statement = complete
+            && tokens
+                .iter()
+                .rev()
+                .find(|(token, _)| *token != Token::Space)
+                .is_some_and(|(token, range)| *token == Token::Punctuation && self.pending[range.start] == b';');
+
+        if ends_statement {
+            self.flush(tokens, out);
+        }
+    }
+
+    ///
+    /// Writes what is left of the dump, such as a last statement without a semicolon.
+    ///
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if !self.pending.is_empty() {
+            let (tokens, _) = tokenize(&self.pending);
+            self.flush(tokens, out);
+        }
+
+        out.append(&mut self.deferred);
+    }
+
+    ///
+    /// Rewrites the pending statement.
+    ///
+    /// #### Arguments
+    ///
+    /// * `tokens`: The tokens of the pending statement.
+    /// * `out`: Where the rewritten statement is appended.
+    ///
+    fn flush(&mut self, tokens: Vec<(Token, std::ops::Range<usize>)>, out: &mut Vec<u8>) {
+        let sql = std::mem::take(&mut self.pending);
+        let significant: Vec<usize> = (0..tokens.len())
+            .filter(|index| tokens[*index].0 != Token::Space)
+            .collect();
+
+        let words: Vec<(Token, String)> = significant
+            .iter()
+            .map(|index| {
+                let (token, range) = &tokens[*index];
+                let text = &sql[range.clone()];
+                let word = match token {
+                    Token::Identifier | Token::QuotedIdentifier => name_of(*token, text).unwrap_or_default(),
+                    Token::Literal => literal_names(text).join("."),
+                    _ => String::from_utf8_lossy(text).into_owned(),
+                };
+                (*token, word)
+            })
+            .collect();
+
+        let subject = subject(&words);
+        let starts_with = |keyword: &str| {
+            words
+                .first()
+                .is_some_and(|(token, word)| *token == Token::Identifier && word == keyword)
+        };
+
+        // The comments pg_dump writes above a statement are part of it, so its first word tells what it is.
+        let is_copy = starts_with("copy")
+            && String::from_utf8_lossy(sql.trim_ascii())
+                .to_uppercase()
+                .ends_with("FROM STDIN;");
+
+        if !words.is_empty() {
+            self.stats.statements += 1;
+        }
+
+        // pg_dump adds the foreign keys with `ALTER TABLE ONLY ... ADD CONSTRAINT ... FOREIGN KEY ... REFERENCES ...`.
+        let referenced = match &subject {
+            Subject::Table(_) => words
+                .iter()
+                .position(|(token, word)| *token == Token::Identifier && word == "references")
+                .and_then(|index| qualified_name(&words, index + 1).last().map(|name| name.to_string())),
+            _ => None,
+        };
+
+        if let Subject::Table(table) = &subject {
+            if starts_with("create") {
+                self.stats.created.insert(table.clone());
+            }
+
+            if let Some(referenced) = &referenced {
+                self.stats.references.insert((table.clone(), referenced.clone()));
+            }
+        }
+
+        // A sequence belongs to the table its name starts with, e.g. `ballinstance_id_seq` to `ballinstance`.
+        let owner = |sequence: &str| {
+            self.stats
+                .created
+                .iter()
+                .filter(|table| sequence.starts_with(&format!("{}_", table)))
+                .max_by_key(|table| table.len())
+                .cloned()
+        };
+
+        let (keep, unchecked) = match &self.rewrite.tables {
+            None => (true, false),
+            Some(tables) => {
+                let outside = referenced
+                    .as_ref()
+                    .is_some_and(|referenced| !tables.contains(referenced));
+
+                match &subject {
+                    Subject::Session => (true, false),
+                    Subject::Table(table) if tables.contains(table) => {
+                        (true, outside && self.rewrite.unchecked_references)
+                    }
+                    Subject::Table(_) => (
+                        self.rewrite.restore_references && !outside && referenced.is_some(),
+                        false,
+                    ),
+                    Subject::Sequence(sequence) => {
+                        (owner(sequence).is_some_and(|table| tables.contains(&table)), false)
+                    }
+                    Subject::Other => (false, false),
+                }
+            }
+        };
+
+        // The rows of a table created empty are all in its `COPY` section.
+        let keep = keep
+            && !(is_copy
+                && matches!(&subject, Subject::Table(table) if self.rewrite.without_rows.contains(&table.as_str())));
+
+        if !keep {
+            if is_copy {
+                self.in_copy = true;
+
+                if let Subject::Table(table) = subject {
+                    self.stats.left_out.entry(table.clone()).or_default();
+                    self.skipping = Some(table);
+                }
+            }
+
+            return;
+        }
+
+        if let (Some(_), Subject::Table(table)) = (&self.rewrite.tables, &subject)
+            && referenced.is_none()
+        {
+            self.stats.kept.insert(table.clone());
+        }
+
+        // `CREATE [UNIQUE] INDEX ...` and `ALTER TABLE ... ADD CONSTRAINT ...`, which pg_dump writes after the rows.
+        let is_keyword = |index: usize, keyword: &str| {
+            words
+                .get(index)
+                .is_some_and(|(token, word)| *token == Token::Identifier && word == keyword)
+        };
+        let deferred = self.rewrite.defer_indexes
+            && ((starts_with("create") && (is_keyword(1, "index") || is_keyword(2, "index")))
+                || (starts_with("alter")
+                    && (1..words.len()).any(|index| is_keyword(index, "add") && is_keyword(index + 1, "constraint"))));
+
+        let start = out.len();
+
+        // Whether a literal names a sequence or a table, e.g. in `setval('public.ball_id_seq', 3, true)`.
+        let names_relation = |position: usize| {
+            let text = |offset: usize| {
+                position
+                    .checked_sub(offset)
+                    .map(|index| &tokens[significant[index]])
+                    .map(|(token, range)| (*token, &sql[range.clone()]))
+            };
+
+            let is_function = |token: Option<(Token, &[u8])>| {
+                token.is_some_and(|(token, text)| {
+                    token == Token::Identifier
+                        && REGCLASS_FUNCTIONS
+                            .iter()
+                            .any(|function| text.eq_ignore_ascii_case(function.as_bytes()))
+                })
+            };
+
+            match (text(1), text(2)) {
+                (Some((Token::Punctuation, b"(")), function) => is_function(function),
+                // The column argument of pg_get_serial_sequence('public.ball', 'id').
+                (Some((Token::Punctuation, b",")), Some((Token::Literal, _))) => {
+                    text(3) == Some((Token::Punctuation, b"(")) && is_function(text(4))
+                }
+                _ => false,
+            }
+        };
+
+        // The tables a statement mentions decide which column renames apply to it.
+        let mut tables = BTreeSet::new();
+
+        for (position, index) in significant.iter().enumerate() {
+            let (token, range) = &tokens[*index];
+            let names = match token {
+                Token::Identifier | Token::QuotedIdentifier => {
+                    name_of(*token, &sql[range.clone()]).into_iter().collect()
+                }
+                Token::Literal if names_relation(position) => literal_names(&sql[range.clone()]),
+                _ => Vec::new(),
+            };
+
+            for name in names.into_iter().filter(|_| self.rewrite.nationdex) {
+                if let Some(table) = TABLES.iter().find(|table| table.from == name) {
+                    tables.insert(table.from);
+                }
+            }
+        }
+
+        let columns: Vec<(&str, &str)> = TABLES
+            .iter()
+            .filter(|table| tables.contains(table.from))
+            .flat_map(|table| table.columns.iter().copied())
+            .collect();
+
+        let nationdex = self.rewrite.nationdex;
+        let schema = self.rewrite.schema;
+
+        let mut apply = |name: &str| -> Option<String> {
+            let renamed = rename(name, &columns).filter(|_| nationdex)?;
+            *self
+                .stats
+                .renamed
+                .entry((name.to_string(), renamed.clone()))
+                .or_default() += 1;
+            Some(renamed)
+        };
+
+        let mut position = 0;
+
+        for (token, range) in &tokens {
+            let text = &sql[range.clone()];
+
+            // Only `public` in front of a dot is the schema, e.g. not in `COMMENT ON SCHEMA public`.
+            let qualifies = || {
+                significant
+                    .get(position + 1)
+                    .is_some_and(|next| tokens[*next].0 == Token::Punctuation && sql[tokens[*next].1.start] == b'.')
+            };
+
+            match token {
+                Token::Identifier | Token::QuotedIdentifier
+                    if schema.is_some() && name_of(*token, text).as_deref() == Some("public") && qualifies() =>
+                {
+                    out.extend_from_slice(schema.unwrap_or_default().as_bytes());
+                }
+                Token::Identifier | Token::QuotedIdentifier => {
+                    match name_of(*token, text).and_then(|name| apply(&name)) {
+                        Some(renamed) if *token == Token::QuotedIdentifier => {
+                            out.extend_from_slice(format!("\"{}\"", renamed.replace('"', "\"\"")).as_bytes());
+                        }
+                        Some(renamed) => out.extend_from_slice(renamed.as_bytes()),
+                        None => out.extend_from_slice(text),
+                    }
+                }
+                Token::Literal if names_relation(position) && !text.contains(&b'"') => {
+                    let inner = String::from_utf8_lossy(&text[1..text.len() - 1]).into_owned();
+                    let parts: Vec<&str> = inner.split('.').collect();
+                    let renamed: Vec<String> = parts
+                        .iter()
+                        .enumerate()
+                        .map(|(index, part)| match schema {
+                            Some(schema) if index == 0 && parts.len() > 1 && part.eq_ignore_ascii_case("public") => {
+                                schema.to_string()
+                            }
+                            _ => apply(part).unwrap_or_else(|| part.to_string()),
+                        })
+                        .collect();
+
+                    out.push(b'\'');
+                    out.extend_from_slice(renamed.join(".").as_bytes());
+                    out.push(b'\'');
+                }
+                _ => out.extend_from_slice(text),
+            }
+
+            if *token != Token::Space {
+                position += 1;
+            }
+        }
+
+        // The rows are not checked against a table that is not imported along, which may not hold them.
+        if unchecked && let Some(end) = out[start..].iter().rposition(|byte| *byte == b';') {
+            out.splice(start + end..start + end, b" NOT VALID".iter().copied());
+        }
+
+        if deferred {
+            self.deferred.extend(out.drain(start..));
+            self.stats.deferred += 1;
+        }
+
+        if is_copy {
+            self.in_copy = true;
+        }
+    }
+}
+
+///
+/// Returns the parts of a literal that names a relation, e.g. `public` and `ball_id_seq` for `'public.ball_id_seq'`.
+///
+fn literal_names(literal: &[u8]) -> Vec<String> {
+    let inner = String::from_utf8_lossy(&literal[1..literal.len() - 1]).into_owned();
+
+    match inner.contains('"') {
+        true => Vec::new(),
+        false => inner.split('.').map(str::to_lowercase).collect(),
+    }
+}
+
+///
+/// Returns the NationDex name of an identifier.
+///
+/// #### Arguments
+///
+/// * `name`: The identifier, as postgres sees it.
+/// * `columns`: The column renames of the tables the statement mentions.
+///
+/// #### Returns
+///
+/// The new name, or `None` if it stays the same.
+///
+fn rename(name: &str, columns: &[(&str, &str)]) -> Option<String> {
+    let renamed = if let Some(table) = TABLES.iter().find(|table| table.from == name) {
+        table.to.to_string()
+    } else if let Some((_, to)) = columns.iter().find(|(from, _)| *from == name) {
+        to.to_string()
+    } else if DERIVED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+        || DERIVED_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+    {
+        rename_derived(name, columns)
+    } else {
+        return None;
+    };
+
+    (renamed != name).then_some(renamed)
+}
+
+///
+/// Renames the parts of a name postgres or Tortoise derived from tables and columns, e.g. `ballinstance_ball_id_fkey`.
+///
+/// The name is split at its underscores, and the longest run of parts that spells a renamed table or column is
+/// replaced first, so `ball_id` wins over `ball`.
+///
+/// #### Arguments
+///
+/// * `name`: The derived name.
+/// * `columns`: The column renames of the tables the statement mentions.
+///
+fn rename_derived(name: &str, columns: &[(&str, &str)]) -> String {
+    let mut renames: Vec<(Vec<&str>, &str)> = TABLES
+        .iter()
+        .filter(|table| table.from != table.to)
+        .map(|table| (table.from, table.to))
+        .chain(columns.iter().copied())
+        .map(|(from, to)| (from.split('_').collect(), to))
+        .collect();
+    renames.sort_by_key(|(parts, _)| std::cmp::Reverse(parts.len()));
+
+    let parts: Vec<&str> = name.split('_').collect();
+    let mut renamed: Vec<&str> = Vec::new();
+    let mut i = 0;
+
+    while i < parts.len() {
+        match renames.iter().find(|(from, _)| parts[i..].starts_with(from)) {
+            Some((from, to)) => {
+                renamed.push(to);
+                i += from.len();
+            }
+            None => {
+                renamed.push(parts[i]);
+                i += 1;
+            }
+        }
+    }
+
+    renamed.join("_")
+}
+
+///
+/// Rewrites a plain dump while it streams.
+///
+/// #### Arguments
+///
+/// * `reader`: The Ballsdex dump.
+/// * `writer`: Where the NationDex dump is written.
+/// * `rewrite`: What to change.
+///
+/// #### Returns
+///
+/// How often each identifier was renamed, or the error of reading or writing.
+///
+pub async fn stream(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    rewrite: &Rewrite,
+) -> io::Result<Stats> {
+    let mut transformer = Transformer {
+        rewrite: rewrite.clone(),
+        ..Transformer::default()
+    };
+    let mut line = Vec::new();
+    let mut out = Vec::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            break;
+        }
+
+        transformer.line(&line, &mut out);
+
+        if !out.is_empty() {
+            writer.write_all(&out).await?;
+            out.clear();
+        }
+    }
+
+    transformer.finish(&mut out);
+    writer.write_all(&out).await?;
+    writer.flush().await?;
+
+    Ok(transformer.stats)
+}
+
+///
+/// Reads a plain dump for its tables and the foreign keys between them, without rewriting it.
+///
+/// #### Arguments
+///
+/// * `reader`: The dump, e.g. a file or the member of a bundle.
+///
+/// #### Returns
+///
+/// The tables and foreign keys in [`Stats`], or the error of reading the dump.
+///
+pub async fn scan(reader: impl AsyncRead + Unpin) -> io::Result<Stats> {
+    let mut reader = BufReader::new(reader);
+    stream(&mut reader, &mut tokio::io::sink(), &Rewrite::default()).await
+}
+
+///
+/// Prints which identifiers were renamed how often.
+///
+/// #### Arguments
+///
+/// * `stats`: The counts of the rewrite.
+///
+pub fn print_renames(stats: &Stats) {
+    for ((from, to), count) in &stats.renamed {
+        output::debug(format!("Renamed {} to {} ({}x)", from, to, count));
+    }
+}
+
+///
+/// Writes the NationDex version of a dump to a file, for reviewing the rewrite before importing it.
+///
+/// The dump is opened as for an import, so a compressed dump or a bundle is read as it is. An archive is left to
+/// `ndmig import`, which turns it into SQL with the pg_restore of the target.
+///
+/// #### Arguments
+///
+/// * `args`: The transform arguments.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the dump cannot be read or the result cannot be written.
+///
+pub async fn run(args: &TransformArgs) -> Result<(), Error> {
+    let dump = crate::restore::open(&args.path).await?;
+
+    if dump.format != DumpFormat::Plain {
+        return Err(Error::new(
+            ErrorKind::Config,
+            format!(
+                "{} is a {} archive, only plain SQL dumps can be transformed. `ndmig import` loads it as it is, or \
+                 convert it with `pg_restore -f dump.sql` first to review the rewrite.",
+                dump.path.display(),
+                dump.format.name()
+            ),
+        ));
+    }
+
+    let destination = args.output.clone().unwrap_or_else(|| {
+        let name = dump.path.file_name().unwrap_or_default().to_string_lossy();
+        let name = name.strip_suffix(bundle::SUFFIX).unwrap_or(&name);
+        let name = match dump.compression.extension() {
+            Some(extension) => name.strip_suffix(&format!(".{}", extension)).unwrap_or(name),
+            None => name,
+        };
+        let stem = name.strip_suffix(".sql").unwrap_or(name);
+        dump.path.with_file_name(format!("{}.nationdex.sql", stem))
+    });
+
+    if std::path::absolute(&destination).is_ok_and(|destination| destination == dump.path) {
+        return Err(Error::new(
+            ErrorKind::Config,
+            "The rewritten dump cannot replace the original, pass another --output.",
+        ));
+    }
+
+    let read_error = |e: io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to read {}: {}", args.path.display(), e),
+        )
+    };
+    let transform_error = |e: io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to rewrite {}: {}", args.path.display(), e),
+        )
+    };
+    let write_error = |e: io::Error| {
+        Error::new(
+            ErrorKind::Filesystem,
+            format!("Failed to write {}: {}", destination.display(), e),
+        )
+    };
+
+    let mut reader = BufReader::new(dump.decompressed().await.map_err(read_error)?);
+    let partial = crate::partial_path(&destination);
+    let mut writer = BufWriter::new(tokio::fs::File::create(&partial).await.map_err(write_error)?);
+
+    let rewrite = Rewrite {
+        nationdex: true,
+        ..Rewrite::default()
+    };
+
+    let stats = match stream(&mut reader, &mut writer, &rewrite).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(transform_error(e));
+        }
+    };
+
+    std::fs::rename(&partial, &destination).map_err(write_error)?;
+    print_renames(&stats);
+
+    if output::is_json() {
+        #[derive(Serialize)]
+        struct Rename<'a> {
+            from: &'a str,
+            to: &'a str,
+            count: u64,
+        }
+
+        output::json(&serde_json::json!({
+            "path": args.path,
+            "output": destination,
+            "statements": stats.statements,
+            "renamed": stats
+                .renamed
+                .iter()
+                .map(|((from, to), count)| Rename { from, to, count: *count })
+                .collect::<Vec<_>>(),
+        }));
+        return Ok(());
+    }
+
+    output::info(
+        format!(
+            "{} Rewrote {} statement(s), renamed {} identifier(s).",
+            Glyph::Ok,
+            stats.statements,
+            stats.total()
+        )
+        .green()
+        .bold(),
+    );
+    output::info(format!(
+        "  {} {}",
+        Glyph::Arrow.to_string().bright_yellow(),
+        destination.display().to_string().bright_cyan()
+    ));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// Rewrites a dump into the NationDex schema.
+    ///
+    async fn nationdex(sql: &str) -> String {
+        let rewrite = Rewrite {
+            nationdex: true,
+            ..Rewrite::default()
+        };
+        let mut output = Vec::new();
+
+        stream(&mut sql.as_bytes(), &mut output, &rewrite).await.unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[tokio::test]
+    async fn renames_quoted_identifiers() {
+        let sql = "CREATE TABLE public.\"ballinstance\" (\n    id integer NOT NULL,\n    \"ball_id\" integer\n);\n";
+
+        assert_eq!(
+            nationdex(sql).await,
+            "CREATE TABLE public.\"nationinstance\" (\n    id integer NOT NULL,\n    \"nation_id\" integer\n);\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn renames_the_columns_of_a_copy() {
+        let sql = "COPY public.ballinstance (id, ball_id, player_id) FROM stdin;\n1\t2\t3\n\\.\n";
+
+        assert_eq!(
+            nationdex(sql).await,
+            "COPY public.nationinstance (id, nation_id, player_id) FROM stdin;\n1\t2\t3\n\\.\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_rows_and_literals_alone() {
+        let sql = "COPY public.ball (id, country, description) FROM stdin;\n\
+                   1\tball\tA ball of ballinstance ball_id \"ball\"\n\\.\n\
+                   COMMENT ON TABLE public.ball IS 'the ball table';\n";
+
+        assert_eq!(
+            nationdex(sql).await,
+            "COPY public.nation (id, country, description) FROM stdin;\n\
+             1\tball\tA ball of ballinstance ball_id \"ball\"\n\\.\n\
+             COMMENT ON TABLE public.nation IS 'the ball table';\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn renames_derived_names() {
+        let sql = "ALTER TABLE ONLY public.ballinstance\n    ADD CONSTRAINT ballinstance_ball_id_fkey FOREIGN KEY (ball_id) \
+                   REFERENCES public.ball(id);\nSELECT pg_catalog.setval('public.ballinstance_id_seq', 42, true);\n";
+
+        assert_eq!(
+            nationdex(sql).await,
+            "ALTER TABLE ONLY public.nationinstance\n    ADD CONSTRAINT nationinstance_nation_id_fkey FOREIGN KEY \
+             (nation_id) REFERENCES public.nation(id);\nSELECT pg_catalog.setval('public.nationinstance_id_seq', 42, true);\n"
+        );
+    }
+
+    #[test]
+    fn tokenizes_quoted_identifiers_as_one_token() {
+        let sql = b"SELECT \"ball \"\"x\"\"\" FROM ball;";
+        let (tokens, _) = tokenize(sql);
+        let names: Vec<String> = tokens
+            .into_iter()
+            .filter_map(|(token, range)| name_of(token, &sql[range]))
+            .collect();
+
+        assert!(names.contains(&"ball \"x\"".to_string()), "{:?}", names);
+    }
+}
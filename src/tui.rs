@@ -0,0 +1,1069 @@
+/*
+    Full-screen terminal UI for browsing and exporting Ballsdex instances.
+*/
+
+use crate::cli::ExportArgs;
+use crate::container::{self, Instance};
+use crate::error::{Error, ErrorKind};
+use crate::history;
+use crate::options::Options;
+use crate::output::{self, Glyph, Level};
+use crate::progress::Progress;
+use crate::summary::{OperationKind, Summary};
+use bollard::Docker;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures_util::StreamExt;
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// How often the screen is redrawn while nothing happens, so the export progress stays current.
+const TICK: Duration = Duration::from_millis(200);
+
+/// How many log lines are kept.
+const MAX_LOG_LINES: usize = 500;
+
+///
+/// Checks whether the terminal can show the TUI.
+///
+/// #### Returns
+///
+/// Whether stdin and stdout are terminals, `TERM` is not `dumb` and JSON output is off.
+///
+pub fn supported() -> bool {
+    io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+        && !output::is_json()
+        && std::env::var("TERM").map_or(cfg!(windows), |term| term != "dumb")
+}
+
+///
+/// Messages sent from a running export back to the UI.
+///
+enum Message {
+    Log(String),
+    Finished(Result<PathBuf, Error>),
+}
+
+///
+/// An export running in the background.
+///
+struct RunningExport {
+    name: String,
+    progress: Arc<Progress>,
+    cancel: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+    started: Instant,
+}
+
+///
+/// The progress of several exports started together.
+///
+struct Batch {
+    total: usize,
+    failed: Vec<String>,
+}
+
+///
+/// The state of the TUI.
+///
+struct App {
+    instances: Vec<Instance>,
+    sizes: Vec<Option<u64>>,
+    selected: ListState,
+    log: Vec<String>,
+    confirm: Option<String>,
+    export: Option<RunningExport>,
+    marked: HashSet<String>,
+    filter: String,
+    filtering: bool,
+    queue: VecDeque<Instance>,
+    batch: Option<Batch>,
+    quit_after_export: bool,
+    quit: bool,
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+///
+/// Returns a foreground style, or no style at all with `--no-color`.
+///
+/// #### Arguments
+///
+/// * `color`: The foreground color.
+///
+fn fg(color: Color) -> Style {
+    if output::is_plain() {
+        Style::default()
+    } else {
+        Style::default().fg(color)
+    }
+}
+
+///
+/// Wraps a terminal I/O error.
+///
+/// #### Arguments
+///
+/// * `error`: The I/O error.
+///
+fn terminal_error(error: io::Error) -> Error {
+    Error::new(ErrorKind::Aborted, format!("Terminal error: {}", error))
+}
+
+///
+/// Exports an instance, reporting progress through the UI channel instead of stdout.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instance`: The instance to export.
+/// * `dump_path`: Where to write the dump.
+/// * `options`: The options for this run.
+/// * `progress`: Reports the received bytes.
+/// * `cancel`: Resolves when the user cancels the export.
+/// * `sender`: The channel to log to.
+///
+/// #### Returns
+///
+/// The path of the dump, or an error.
+///
+async fn export(
+    docker: &Docker,
+    instance: &Instance,
+    dump_path: PathBuf,
+    options: &Options,
+    progress: &Progress,
+    cancel: oneshot::Receiver<()>,
+    sender: &mpsc::UnboundedSender<Message>,
+) -> Result<PathBuf, Error> {
+    let log = |line: String| {
+        let _ = sender.send(Message::Log(line));
+    };
+
+    let name = crate::format_name(&instance.name);
+    let container_id = instance.container_id.as_str();
+
+    let args = ExportArgs {
+        config_pg_dump_args: options.pg_dump_args.clone(),
+        ..Default::default()
+    };
+    crate::check_pg_dump_args(&args, options)?;
+
+    if let Some(parent) = dump_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            Error::new(
+                ErrorKind::Filesystem,
+                format!("Failed to create {}: {}", parent.display(), e),
+            )
+        })?;
+    }
+
+    let running = container::is_running(docker, container_id)
+        .await
+        .map_err(|e| Error::new(ErrorKind::DumpFailed, format!("Export failed: {}", e)))?;
+
+    if !running {
+        log(format!(
+            "{} Starting {} temporarily, it is stopped again after the export...",
+            Glyph::Pending,
+            name
+        ));
+
+        docker
+            .start_container(container_id, None)
+            .await
+            .map_err(|e| Error::new(ErrorKind::DumpFailed, format!("Failed to start container: {}", e)))?;
+    }
+
+    let database = container::database(docker, container_id, options).await;
+
+    if !running {
+        log(format!("{} Waiting for the database to start...", Glyph::Pending));
+
+        if let Err(error) = container::wait_until_ready(docker, container_id, &instance.name, &database).await {
+            if let Err(message) = crate::stop_started_container(docker, instance).await {
+                log(format!("{} {}", Glyph::Warning, message));
+            }
+
+            return Err(error);
+        }
+    }
+
+    log(format!("{} Exporting {}...", Glyph::Pending, name));
+    let row_counts = container::row_counts(docker, container_id, &database, &container::CORE_TABLES)
+        .await
+        .unwrap_or_default();
+    let started = Instant::now();
+
+    let result = tokio::select! {
+        written = crate::write_dump(docker, instance, &dump_path, progress, &args, &database) => {
+            let (dump, sha256) = written?;
+
+            if !dump.warnings.is_empty() {
+                log(format!("{} pg_dump printed {} line(s) to stderr.", Glyph::Warning, dump.warnings.len()));
+            }
+
+            log(format!("{} Verifying {}...", Glyph::Pending, dump_path.display()));
+            let expected = crate::verify::expected_tables(&args, &row_counts);
+            let verified = crate::verify::run(docker, container_id, &dump_path, &args, &expected).await?;
+
+            let mut summary = crate::export_summary(&instance.name, container_id, dump_path.clone(), &args, &database, &dump, Some(sha256));
+            summary.verified = verified;
+            summary.duration_ms = started.elapsed().as_millis();
+            summary.row_counts = row_counts;
+            crate::describe_source(docker, container_id, &database, &mut summary).await;
+
+            let source = options.bot_config.as_deref().unwrap_or(crate::bot::DEFAULT_CONFIG_PATH);
+            match crate::bot::export_config(docker, &instance.name, source, &dump_path).await {
+                Ok(_) => summary.bot_config = true,
+                Err(message) => log(format!("{} {}", Glyph::Warning, message)),
+            }
+
+            if let Err(message) = crate::write_sidecars(&summary) {
+                log(format!("{} {}", Glyph::Warning, message));
+            }
+
+            Ok(dump_path)
+        }
+        _ = cancel => {
+            log(format!("{} Cancelling, cleaning up...", Glyph::Warning));
+            Err(crate::abort_export(docker, container_id, &crate::partial_path(&dump_path), false, !running).await)
+        }
+    };
+
+    if !running {
+        match crate::stop_started_container(docker, instance).await {
+            Ok(true) => log(format!("{} Stopped {} again.", Glyph::Ok, name)),
+            Ok(false) => {}
+            Err(message) => log(format!("{} {}", Glyph::Warning, message)),
+        }
+    }
+
+    result
+}
+
+impl App {
+    ///
+    /// Adds a line to the log pane.
+    ///
+    /// #### Arguments
+    ///
+    /// * `line`: The line to add.
+    ///
+    fn log(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.remove(0);
+        }
+    }
+
+    ///
+    /// Returns the positions of the instances that match the filter.
+    ///
+    fn visible(&self) -> Vec<usize> {
+        (0..self.instances.len())
+            .filter(|index| crate::matches_filter(&self.instances[*index], &self.filter))
+            .collect()
+    }
+
+    ///
+    /// Returns the position of the currently selected instance in `instances`.
+    ///
+    fn selected_index(&self) -> Option<usize> {
+        self.selected
+            .selected()
+            .and_then(|index| self.visible().get(index).copied())
+    }
+
+    ///
+    /// Returns the currently selected instance.
+    ///
+    fn selected(&self) -> Option<&Instance> {
+        self.selected_index().map(|index| &self.instances[index])
+    }
+
+    ///
+    /// Selects an instance by name if it is visible, or else the first visible one.
+    ///
+    /// #### Arguments
+    ///
+    /// * `name`: The full name of the instance to select.
+    ///
+    fn reselect(&mut self, name: Option<String>) {
+        let visible = self.visible();
+        let index = name
+            .and_then(|name| visible.iter().position(|index| self.instances[*index].name == name))
+            .or((!visible.is_empty()).then_some(0));
+
+        self.selected.select(index);
+    }
+
+    ///
+    /// Rediscovers the instances and their database sizes, keeping the selection if possible.
+    ///
+    /// #### Arguments
+    ///
+    /// * `docker`: The Docker client.
+    /// * `options`: The options for this run.
+    ///
+    async fn refresh(&mut self, docker: &Docker, options: &Options) {
+        let selected = self.selected().map(|instance| instance.name.clone());
+
+        match container::discover_instances(docker).await {
+            Ok(mut instances) => {
+                instances.retain(|instance| !options.is_ignored(instance));
+                self.sizes = container::database_sizes(docker, &instances, options).await;
+                self.instances = instances;
+                self.marked
+                    .retain(|name| self.instances.iter().any(|instance| instance.name == *name));
+                self.log(format!("{} Found {} instance(s).", Glyph::Ok, self.instances.len()));
+            }
+            Err(e) => self.log(format!("{} Failed to list containers: {}", Glyph::Error, e)),
+        }
+
+        self.reselect(selected);
+    }
+
+    ///
+    /// Returns the instances the next export applies to: the marked ones, or else the selected one.
+    ///
+    fn targets(&self) -> Vec<Instance> {
+        let marked: Vec<Instance> = self
+            .instances
+            .iter()
+            .filter(|instance| self.marked.contains(&instance.name))
+            .cloned()
+            .collect();
+
+        if !marked.is_empty() {
+            return marked;
+        }
+
+        self.selected().cloned().into_iter().collect()
+    }
+
+    ///
+    /// Marks or unmarks the selected instance for a batch export.
+    ///
+    fn toggle_mark(&mut self) {
+        let Some(name) = self.selected().map(|instance| instance.name.clone()) else {
+            return;
+        };
+
+        if !self.marked.remove(&name) {
+            self.marked.insert(name);
+        }
+    }
+
+    ///
+    /// Marks every visible instance, or unmarks them all if they already are.
+    ///
+    fn toggle_all(&mut self) {
+        let names: Vec<String> = self
+            .visible()
+            .into_iter()
+            .map(|index| self.instances[index].name.clone())
+            .collect();
+
+        if names.iter().all(|name| self.marked.contains(name)) {
+            for name in &names {
+                self.marked.remove(name);
+            }
+        } else {
+            self.marked.extend(names);
+        }
+    }
+
+    ///
+    /// Handles a key press while the filter is being typed.
+    ///
+    /// #### Arguments
+    ///
+    /// * `key`: The key that was pressed.
+    ///
+    fn on_filter_key(&mut self, key: KeyEvent) {
+        let selected = self.selected().map(|instance| instance.name.clone());
+
+        match key.code {
+            KeyCode::Enter => self.filtering = false,
+            KeyCode::Esc => {
+                self.filtering = false;
+                self.filter.clear();
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+            }
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => {}
+        }
+
+        self.reselect(selected);
+    }
+
+    ///
+    /// Asks for confirmation if needed and then exports the marked instances, or the selected one.
+    ///
+    /// #### Arguments
+    ///
+    /// * `docker`: The Docker client.
+    /// * `options`: The options for this run.
+    /// * `confirmed`: Whether the user already confirmed the export.
+    ///
+    fn start_export(&mut self, docker: &Docker, options: &Options, confirmed: bool) {
+        if self.export.is_some() {
+            self.log(format!("{} An export is already running.", Glyph::Warning));
+            return;
+        }
+
+        let targets = self.targets();
+
+        if targets.is_empty() {
+            return;
+        }
+
+        if options.dry_run {
+            for instance in &targets {
+                let dump_path = crate::dump_path(
+                    options.output.as_deref(),
+                    &options.name_template,
+                    &instance.name,
+                    &instance.container_id,
+                );
+
+                for action in crate::plan_export(
+                    instance,
+                    &dump_path,
+                    &ExportArgs {
+                        config_pg_dump_args: options.pg_dump_args.clone(),
+                        ..Default::default()
+                    },
+                    None,
+                    &options.database(),
+                    options,
+                ) {
+                    self.log(format!("DRY RUN would {}", action));
+                }
+            }
+            return;
+        }
+
+        if targets.len() > 1
+            && let Some(output) = &options.output
+            && !crate::is_output_dir(output)
+        {
+            self.log(format!(
+                "{} {} is a file, the output must be a directory when exporting several instances.",
+                Glyph::Error,
+                output.display()
+            ));
+            return;
+        }
+
+        if !confirmed && !options.assume_yes {
+            let mut warnings = Vec::new();
+
+            for instance in &targets {
+                let dump_path = crate::dump_path(
+                    options.output.as_deref(),
+                    &options.name_template,
+                    &instance.name,
+                    &instance.container_id,
+                );
+
+                if !instance.running {
+                    warnings.push(format!(
+                        "{} is stopped and will be started.",
+                        crate::format_name(&instance.name)
+                    ));
+                }
+
+                if dump_path.exists() {
+                    warnings.push(format!(
+                        "{} already exists and will be overwritten.",
+                        dump_path.display()
+                    ));
+                }
+            }
+
+            if !warnings.is_empty() {
+                self.confirm = Some(warnings.join(" "));
+                return;
+            }
+        }
+
+        self.marked.clear();
+        self.batch = (targets.len() > 1).then_some(Batch {
+            total: targets.len(),
+            failed: Vec::new(),
+        });
+        self.queue = targets.into();
+        self.next_export(docker, options);
+    }
+
+    ///
+    /// Starts the next queued export, if any.
+    ///
+    /// #### Arguments
+    ///
+    /// * `docker`: The Docker client.
+    /// * `options`: The options for this run.
+    ///
+    fn next_export(&mut self, docker: &Docker, options: &Options) {
+        let Some(instance) = self.queue.pop_front() else {
+            return;
+        };
+
+        let name = crate::format_name(&instance.name);
+        let dump_path = crate::dump_path(
+            options.output.as_deref(),
+            &options.name_template,
+            &instance.name,
+            &instance.container_id,
+        );
+
+        let size = self
+            .instances
+            .iter()
+            .position(|candidate| candidate.name == instance.name)
+            .and_then(|index| self.sizes[index]);
+
+        let progress = Arc::new(Progress::new(size));
+        let (cancel, cancelled) = oneshot::channel();
+        let sender = self.sender.clone();
+
+        let handle = tokio::spawn({
+            let docker = docker.clone();
+            let options = options.clone();
+            let progress = progress.clone();
+
+            async move {
+                let started = Instant::now();
+                let result = export(&docker, &instance, dump_path, &options, &progress, cancelled, &sender).await;
+
+                let entry = match &result {
+                    Ok(path) => {
+                        let mut summary = Summary::new(
+                            OperationKind::Export,
+                            crate::format_name(&instance.name),
+                            instance.container_id.clone(),
+                            path.clone(),
+                        );
+                        summary.bytes = progress.received();
+                        summary.duration_ms = started.elapsed().as_millis();
+
+                        history::Entry::success(&summary)
+                    }
+                    Err(e) => history::Entry::failure(OperationKind::Export, &instance, e, started.elapsed()),
+                };
+                history::record(&entry);
+
+                let _ = sender.send(Message::Finished(result));
+            }
+        });
+
+        self.export = Some(RunningExport {
+            name,
+            progress,
+            cancel: Some(cancel),
+            handle,
+            started: Instant::now(),
+        });
+    }
+
+    ///
+    /// Handles a message from a running export.
+    ///
+    /// #### Arguments
+    ///
+    /// * `message`: The message.
+    /// * `docker`: The Docker client.
+    /// * `options`: The options for this run.
+    ///
+    fn on_message(&mut self, message: Message, docker: &Docker, options: &Options) {
+        match message {
+            Message::Log(line) => self.log(line),
+            Message::Finished(result) => {
+                let Some(export) = self.export.take() else {
+                    return;
+                };
+
+                match result {
+                    Ok(path) => {
+                        self.log(format!(
+                            "{} {} exported to {} ({} in {:.1}s)",
+                            Glyph::Ok,
+                            export.name,
+                            path.display(),
+                            crate::format_size(export.progress.received()),
+                            export.started.elapsed().as_secs_f64()
+                        ));
+
+                        // The export may have started the container.
+                        if let Some(instance) = self
+                            .instances
+                            .iter_mut()
+                            .find(|instance| crate::format_name(&instance.name) == export.name)
+                        {
+                            instance.running = true;
+                        }
+                    }
+                    Err(e) => {
+                        self.log(format!("{} {}: {}", Glyph::Error, export.name, e));
+
+                        if let Some(batch) = &mut self.batch {
+                            batch.failed.push(export.name);
+                        }
+                    }
+                }
+
+                if self.quit_after_export {
+                    self.quit = true;
+                    return;
+                }
+
+                if !self.queue.is_empty() {
+                    self.next_export(docker, options);
+                    return;
+                }
+
+                if let Some(batch) = self.batch.take() {
+                    let line = format!(
+                        "{} of {} export(s) succeeded.",
+                        batch.total - batch.failed.len(),
+                        batch.total
+                    );
+
+                    if batch.failed.is_empty() {
+                        self.log(format!("{} {}", Glyph::Ok, line));
+                    } else {
+                        self.log(format!("{} {} Failed: {}", Glyph::Error, line, batch.failed.join(", ")));
+                    }
+                }
+            }
+        }
+    }
+
+    ///
+    /// Handles a key press.
+    ///
+    /// #### Arguments
+    ///
+    /// * `key`: The key that was pressed.
+    /// * `docker`: The Docker client.
+    /// * `options`: The options for this run.
+    ///
+    async fn on_key(&mut self, key: KeyEvent, docker: &Docker, options: &Options) {
+        if self.confirm.take().is_some() {
+            match key.code {
+                KeyCode::Char('y' | 'Y') => self.start_export(docker, options, true),
+                _ => self.log(format!("{} Export cancelled.", Glyph::Warning)),
+            }
+            return;
+        }
+
+        let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+
+        if self.filtering && !ctrl_c {
+            self.on_filter_key(key);
+            return;
+        }
+
+        match key.code {
+            _ if ctrl_c => self.on_quit(),
+            KeyCode::Char('/') => self.filtering = true,
+            KeyCode::Esc if !self.filter.is_empty() => {
+                let selected = self.selected().map(|instance| instance.name.clone());
+                self.filter.clear();
+                self.reselect(selected);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.on_quit(),
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            // select_next would move past the end until the next draw clamps it.
+            KeyCode::Down | KeyCode::Char('j')
+                if self
+                    .selected
+                    .selected()
+                    .is_some_and(|index| index + 1 < self.visible().len()) =>
+            {
+                self.selected.select_next()
+            }
+            KeyCode::Char(' ') => self.toggle_mark(),
+            KeyCode::Char('a') => self.toggle_all(),
+            KeyCode::Enter | KeyCode::Char('e') => self.start_export(docker, options, false),
+            KeyCode::Char('i') => {
+                let command = match self.selected() {
+                    Some(instance) => format!(
+                        "ndmig import --instance {} --file <dump>",
+                        crate::format_name(&instance.name)
+                    ),
+                    None => "ndmig import --file <dump>".to_string(),
+                };
+                self.log(format!(
+                    "{} Imports are not available here yet, run `{}`.",
+                    Glyph::Warning,
+                    command
+                ));
+            }
+            KeyCode::Char('r') => {
+                if self.export.is_some() {
+                    self.log(format!(
+                        "{} Wait for the export to finish before refreshing.",
+                        Glyph::Warning
+                    ));
+                } else {
+                    self.log(format!("{} Refreshing...", Glyph::Pending));
+                    self.refresh(docker, options).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Quits, cancelling a running export first. Quitting again while it cleans up force-quits.
+    ///
+    fn on_quit(&mut self) {
+        let Some(export) = &mut self.export else {
+            self.quit = true;
+            return;
+        };
+
+        match export.cancel.take() {
+            Some(cancel) => {
+                let _ = cancel.send(());
+                self.quit_after_export = true;
+                self.log(format!(
+                    "{} Cancelling the export, press q again to force quit.",
+                    Glyph::Warning
+                ));
+            }
+            None => {
+                export.handle.abort();
+                self.quit = true;
+            }
+        }
+    }
+
+    ///
+    /// Draws the whole screen.
+    ///
+    /// #### Arguments
+    ///
+    /// * `frame`: The frame to draw into.
+    ///
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, log] = Layout::vertical([Constraint::Min(6), Constraint::Length(10)]).areas(frame.area());
+        let [list, details] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(main);
+
+        self.draw_list(frame, list);
+        self.draw_details(frame, details);
+        self.draw_log(frame, log);
+
+        if let Some(question) = &self.confirm {
+            let area = centered(frame.area(), 60, 6);
+            let text = vec![
+                Line::from(question.as_str()),
+                Line::from(""),
+                Line::styled("Continue? [y/N]", fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            ];
+
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(text)
+                    .wrap(Wrap { trim: true })
+                    .block(Block::bordered().title(" Confirm ")),
+                area,
+            );
+        }
+    }
+
+    ///
+    /// Draws the instance list.
+    ///
+    /// #### Arguments
+    ///
+    /// * `frame`: The frame to draw into.
+    /// * `area`: Where to draw.
+    ///
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let width = self
+            .instances
+            .iter()
+            .map(|instance| crate::format_name(&instance.name).len())
+            .max()
+            .unwrap_or(0);
+
+        let items: Vec<ListItem> = self
+            .visible()
+            .into_iter()
+            .map(|index| (&self.instances[index], self.sizes[index]))
+            .map(|(instance, size)| {
+                let (state, color) = if instance.running {
+                    ("running", Color::Green)
+                } else {
+                    ("stopped", Color::DarkGray)
+                };
+
+                let mark = if self.marked.contains(&instance.name) {
+                    "[x] "
+                } else {
+                    "[ ] "
+                };
+
+                ListItem::new(Line::from(vec![
+                    Span::styled(mark, fg(Color::Yellow)),
+                    Span::styled(
+                        format!("{:width$}", crate::format_name(&instance.name)),
+                        fg(Color::Cyan),
+                    ),
+                    Span::raw(format!(
+                        "  {:>10}  ",
+                        size.map(crate::format_size).unwrap_or_else(|| "unknown".to_string())
+                    )),
+                    Span::styled(state, fg(color)),
+                ]))
+            })
+            .collect();
+
+        let title = if self.filtering || !self.filter.is_empty() {
+            format!(
+                " Ballsdex instances /{}{} ",
+                self.filter,
+                if self.filtering { "_" } else { "" }
+            )
+        } else {
+            " Ballsdex instances ".to_string()
+        };
+
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol(if output::is_plain() { "> " } else { "› " });
+
+        frame.render_stateful_widget(list, area, &mut self.selected);
+    }
+
+    ///
+    /// Draws the details of the selected instance.
+    ///
+    /// #### Arguments
+    ///
+    /// * `frame`: The frame to draw into.
+    /// * `area`: Where to draw.
+    ///
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Details ");
+
+        let Some(index) = self.selected_index() else {
+            let message = if self.instances.is_empty() {
+                "No Ballsdex instances found. Press r to refresh."
+            } else {
+                "No instance matches the filter. Press Esc to clear it."
+            };
+
+            frame.render_widget(Paragraph::new(message).block(block), area);
+            return;
+        };
+
+        let instance = &self.instances[index];
+        let row = |label: &str, value: String| {
+            Line::from(vec![
+                Span::styled(format!("{:15}", label), Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(value),
+            ])
+        };
+
+        let lines = vec![
+            row("Name", crate::format_name(&instance.name)),
+            row("Container", instance.name.clone()),
+            row("Container ID", crate::short_id(&instance.container_id).to_string()),
+            row("Bot", instance.flavor.name().to_string()),
+            row(
+                "State",
+                if instance.running {
+                    "running".to_string()
+                } else {
+                    "stopped (will be started)".to_string()
+                },
+            ),
+            row(
+                "Database size",
+                self.sizes[index]
+                    .map(crate::format_size)
+                    .unwrap_or_else(|| "unknown".to_string()),
+            ),
+        ];
+
+        frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }).block(block), area);
+    }
+
+    ///
+    /// Draws the log pane with the progress of the running export.
+    ///
+    /// #### Arguments
+    ///
+    /// * `frame`: The frame to draw into.
+    /// * `area`: Where to draw.
+    ///
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let title = match &self.export {
+            Some(export) => {
+                let received = export.progress.received();
+                let percent = export
+                    .progress
+                    .total()
+                    .filter(|total| *total > 0)
+                    .map(|total| format!(", ~{}%", (received * 100 / total).min(99)))
+                    .unwrap_or_default();
+
+                format!(
+                    " Exporting {}: {} received{} ({}s) ",
+                    export.name,
+                    crate::format_size(received),
+                    percent,
+                    export.started.elapsed().as_secs()
+                )
+            }
+            None => " Log ".to_string(),
+        };
+
+        let block = Block::bordered()
+            .title(title)
+            .title_bottom(" / filter · space mark · a mark all · e export · i import · r refresh · q quit ");
+
+        let height = block.inner(area).height as usize;
+        let lines: Vec<Line> = self.log[self.log.len().saturating_sub(height)..]
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+///
+/// Computes a rectangle centered in another one.
+///
+/// #### Arguments
+///
+/// * `area`: The outer rectangle.
+/// * `width`: The preferred width.
+/// * `height`: The preferred height.
+///
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+///
+/// Runs the event loop until the user quits.
+///
+/// #### Arguments
+///
+/// * `terminal`: The terminal to draw on.
+/// * `app`: The state of the TUI.
+/// * `receiver`: Messages from the running export.
+/// * `docker`: The Docker client.
+/// * `options`: The options for this run.
+///
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    receiver: &mut mpsc::UnboundedReceiver<Message>,
+    docker: &Docker,
+    options: &Options,
+) -> Result<(), Error> {
+    let mut events = EventStream::new();
+    let mut tick = tokio::time::interval(TICK);
+
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame)).map_err(terminal_error)?;
+
+        tokio::select! {
+            _ = tick.tick() => {}
+            Some(message) = receiver.recv() => app.on_message(message, docker, options),
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => app.on_key(key, docker, options).await,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(terminal_error(e)),
+                None => break,
+            },
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// Runs the TUI.
+///
+/// Status output is silenced while the TUI owns the terminal, so nothing draws over it.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `instances`: The discovered Ballsdex instances.
+/// * `options`: The options for this run.
+///
+/// #### Returns
+///
+/// Nothing, or an error if the terminal could not be used.
+///
+pub async fn run(docker: &Docker, instances: Vec<Instance>, options: &Options) -> Result<(), Error> {
+    let level = output::level();
+    output::set_level(Level::Quiet);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let mut app = App {
+        sizes: container::database_sizes(docker, &instances, options).await,
+        selected: ListState::default().with_selected((!instances.is_empty()).then_some(0)),
+        instances,
+        log: vec![format!("{} Welcome to NDMIG!", Glyph::Ok)],
+        confirm: None,
+        export: None,
+        marked: HashSet::new(),
+        filter: String::new(),
+        filtering: false,
+        queue: VecDeque::new(),
+        batch: None,
+        quit_after_export: false,
+        quit: false,
+        sender,
+    };
+
+    let result = match ratatui::try_init() {
+        Ok(mut terminal) => {
+            let result = event_loop(&mut terminal, &mut app, &mut receiver, docker, options).await;
+            ratatui::restore();
+            result
+        }
+        Err(e) => Err(terminal_error(e)),
+    };
+
+    if let Some(export) = app.export.take() {
+        export.handle.abort();
+    }
+
+    output::set_level(level);
+    result
+}
@@ -0,0 +1,451 @@
+/*
+    Checks that a database holds what a dump was exported with, after loading it.
+*/
+
+use crate::container::{self, Database};
+use crate::json::quote;
+use crate::manifest::Manifest;
+use crate::merge::Merge;
+use crate::output::{self, Glyph};
+use crate::restore::{self, Strategy};
+use crate::sequence;
+use crate::transform;
+use bollard::Docker;
+use colored::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+///
+/// The outcome of a single check.
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// What was checked, e.g. `rows of nationinstance`.
+    pub name: String,
+    /// What the check expected, `None` if there was nothing to compare with.
+    pub expected: Option<String>,
+    /// What the database holds.
+    pub actual: String,
+    pub passed: bool,
+}
+
+///
+/// Returns whether the row counts of a manifest describe every row of the dump.
+///
+/// Partial dumps, such as guild exports or dumps limited to some tables, hold fewer rows than the source had.
+///
+/// #### Arguments
+///
+/// * `manifest`: The manifest of the dump.
+///
+fn counts_every_row(manifest: &Manifest) -> bool {
+    matches!(manifest.mode.as_deref(), None | Some("full"))
+        && manifest.tables.as_ref().is_none_or(Vec::is_empty)
+        && manifest.excluded_tables.as_ref().is_none_or(Vec::is_empty)
+}
+
+///
+/// Compares the rows of the core tables with the counts the manifest of the dump recorded.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database the dump was loaded into.
+/// * `manifest`: The manifest of the dump, `None` if it has none.
+/// * `transformed`: Whether the dump was rewritten into the NationDex schema, which renamed the tables.
+///
+/// #### Returns
+///
+/// One check per core table. Without usable counts in the manifest, the checks only report the rows.
+///
+pub async fn row_counts(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    manifest: Option<&Manifest>,
+    transformed: bool,
+) -> Vec<Check> {
+    let expected = manifest
+        .filter(|manifest| counts_every_row(manifest))
+        .and_then(|manifest| manifest.row_counts.clone());
+
+    let Some(counts) = count_core_tables(docker, container_id, database, transformed).await else {
+        return vec![uncounted()];
+    };
+
+    compare_rows(&counts, expected.as_ref(), transformed)
+}
+
+///
+/// What an import did, to tell what the target should hold afterwards.
+///
+pub struct Imported<'a> {
+    /// The manifest of every imported dump, `None` for a dump without one.
+    pub manifests: Vec<Option<&'a Manifest>>,
+    pub strategy: Strategy,
+    /// What the merge of every dump did, empty if they were not merged.
+    pub merged: &'a [Merge],
+    /// The rows of the core tables before the import by their name in the target, `None` if they were not counted.
+    pub before: Option<&'a BTreeMap<String, u64>>,
+    /// The tables the import was limited to by their Ballsdex name, `None` if it loaded every table.
+    pub tables: Option<&'a BTreeSet<String>>,
+    /// The newest migration the bot of the target expects in the aerich table, `None` if it is not known.
+    pub aerich: Option<&'a str>,
+}
+
+///
+/// Checks the target after an import: its rows against the manifests of the dumps, and that nothing points nowhere.
+///
+/// For a merge, the target is expected to hold the rows it held before and those of every dump that were not left
+/// out as duplicates. The tables outside of `--tables` are expected to hold what they held before.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the target.
+/// * `database`: The role and database the dumps were imported into.
+/// * `imported`: What the import did.
+/// * `transformed`: Whether the dumps were rewritten into the NationDex schema.
+///
+/// #### Returns
+///
+/// One check per core table and one per invariant.
+///
+pub async fn import(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    imported: &Imported<'_>,
+    transformed: bool,
+) -> Vec<Check> {
+    let name = |table: &'static str| target_name(table, transformed);
+    let held = |table: &'static str| imported.before.and_then(|before| before.get(name(table))).copied();
+
+    // Only known if every dump recorded the rows of all its tables.
+    let expected = imported
+        .manifests
+        .iter()
+        .map(|manifest| {
+            manifest
+                .filter(|manifest| counts_every_row(manifest))
+                .and_then(|manifest| manifest.row_counts.as_ref())
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|dumps| {
+            container::CORE_TABLES
+                .iter()
+                .filter_map(|table| {
+                    if imported.tables.is_some_and(|tables| !tables.contains(*table)) {
+                        return Some((table.to_string(), held(table)?));
+                    }
+
+                    let dumped = dumps
+                        .iter()
+                        .map(|counts| counts.get(*table).copied())
+                        .sum::<Option<u64>>()?;
+                    let left_out: u64 = imported
+                        .merged
+                        .iter()
+                        .filter_map(|merge| merge.tables.get(name(table)))
+                        .map(|counts| counts.skipped + counts.dropped)
+                        .sum();
+                    let kept = match imported.strategy {
+                        Strategy::Merge => held(table).unwrap_or(0),
+                        _ => 0,
+                    };
+
+                    Some((table.to_string(), (kept + dumped).saturating_sub(left_out)))
+                })
+                .collect::<BTreeMap<_, _>>()
+        });
+
+    let Some(counts) = count_core_tables(docker, container_id, database, transformed).await else {
+        return vec![uncounted()];
+    };
+
+    let mut checks = compare_rows(&counts, expected.as_ref(), transformed);
+    let has = |table: &'static str| counts.contains_key(name(table));
+    let instances = quote_table(name("ballinstance"));
+
+    if has("ballinstance") && has("player") {
+        checks.push(
+            invariant(
+                docker,
+                container_id,
+                database,
+                format!("{} without a player", name("ballinstance")),
+                &format!(
+                    "SELECT count(*) FROM {instances} i WHERE NOT EXISTS \
+                     (SELECT 1 FROM public.player p WHERE p.id = i.player_id)"
+                ),
+            )
+            .await,
+        );
+    }
+
+    if has("ballinstance") && has("ball") {
+        let column = quote(match transformed {
+            true => transform::target_column("ballinstance", "ball_id"),
+            false => "ball_id",
+        });
+
+        checks.push(
+            invariant(
+                docker,
+                container_id,
+                database,
+                format!("{} without a {}", name("ballinstance"), name("ball")),
+                &format!(
+                    "SELECT count(*) FROM {instances} i WHERE NOT EXISTS \
+                     (SELECT 1 FROM {} b WHERE b.id = i.{column})",
+                    quote_table(name("ball"))
+                ),
+            )
+            .await,
+        );
+    }
+
+    if has("guildconfig") {
+        checks.push(
+            invariant(
+                docker,
+                container_id,
+                database,
+                "guildconfig without a guild_id".to_string(),
+                "SELECT count(*) FROM public.guildconfig WHERE guild_id IS NULL",
+            )
+            .await,
+        );
+    }
+
+    // aerich records the file name of a migration, with or without its extension depending on its version.
+    let applied = aerich_version(docker, container_id, database).await;
+    let migration = |version: &str| version.trim_end_matches(".py").to_string();
+
+    checks.push(Check {
+        name: "aerich migration".to_string(),
+        expected: imported.aerich.map(str::to_string),
+        actual: applied.clone().unwrap_or_else(|| "none".to_string()),
+        passed: imported
+            .aerich
+            .is_none_or(|expected| applied.as_deref().map(migration) == Some(migration(expected))),
+    });
+
+    checks.push(match sequence::behind(docker, container_id, database).await {
+        Ok(behind) => Check {
+            name: "sequences behind their IDs".to_string(),
+            expected: Some("0".to_string()),
+            actual: match behind.is_empty() {
+                true => "0".to_string(),
+                false => format!(
+                    "{} ({})",
+                    behind.len(),
+                    behind
+                        .iter()
+                        .map(|advanced| advanced.sequence.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            passed: behind.is_empty(),
+        },
+        Err(_) => Check {
+            name: "sequences behind their IDs".to_string(),
+            expected: Some("0".to_string()),
+            actual: "could not be checked".to_string(),
+            passed: false,
+        },
+    });
+
+    checks
+}
+
+///
+/// Returns the newest migration the aerich table of a database records, e.g. `12_20240105120000_update.py`.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database to read.
+///
+/// #### Returns
+///
+/// The migration, or `None` if the table is missing or empty, or could not be read.
+///
+pub async fn aerich_version(docker: &Docker, container_id: &str, database: &Database) -> Option<String> {
+    let tables = container::tables(docker, container_id, database).await?;
+
+    if !tables.iter().any(|table| table == restore::AERICH_TABLE) {
+        return None;
+    }
+
+    let version = container::query(
+        docker,
+        container_id,
+        database,
+        "SELECT version FROM public.aerich ORDER BY id DESC LIMIT 1",
+    )
+    .await?;
+
+    Some(version.trim().to_string()).filter(|version| !version.is_empty())
+}
+
+///
+/// Returns the name of a core table in the target, e.g. `nation` for `ball` after the NationDex rewrite.
+///
+/// #### Arguments
+///
+/// * `table`: The Ballsdex name of the table.
+/// * `transformed`: Whether the dump was rewritten into the NationDex schema.
+///
+fn target_name(table: &'static str, transformed: bool) -> &'static str {
+    match transformed {
+        true => transform::target_name(table),
+        false => table,
+    }
+}
+
+///
+/// Quotes a table of the `public` schema for a query, e.g. `public."nation"`.
+///
+/// #### Arguments
+///
+/// * `table`: The name of the table.
+///
+fn quote_table(table: &str) -> String {
+    format!("public.{}", quote(table))
+}
+
+///
+/// Returns the check that stands in for the row counts when they could not be read.
+///
+fn uncounted() -> Check {
+    Check {
+        name: "rows of the core tables".to_string(),
+        expected: None,
+        actual: "could not be counted".to_string(),
+        passed: false,
+    }
+}
+
+///
+/// Counts the rows of the core tables of a database.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database to count in.
+/// * `transformed`: Whether the tables carry their NationDex names.
+///
+/// #### Returns
+///
+/// The rows of every existing core table by its name in the database, or `None` if they could not be counted.
+///
+pub async fn count_core_tables(
+    docker: &Docker,
+    container_id: &str,
+    database: &Database,
+    transformed: bool,
+) -> Option<BTreeMap<String, u64>> {
+    let tables: Vec<&str> = container::CORE_TABLES
+        .iter()
+        .map(|table| target_name(table, transformed))
+        .collect();
+
+    container::row_counts(docker, container_id, database, &tables).await
+}
+
+///
+/// Runs a query that counts the rows breaking an invariant, which should be none.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the database.
+/// * `database`: The role and database to check.
+/// * `name`: What is checked, e.g. `nationinstance without a player`.
+/// * `sql`: The query, which returns a single count.
+///
+async fn invariant(docker: &Docker, container_id: &str, database: &Database, name: String, sql: &str) -> Check {
+    let count = container::query(docker, container_id, database, sql)
+        .await
+        .and_then(|output| output.trim().parse::<u64>().ok());
+
+    Check {
+        name,
+        expected: Some("0".to_string()),
+        actual: count.map_or_else(|| "could not be checked".to_string(), |count| count.to_string()),
+        passed: count == Some(0),
+    }
+}
+
+///
+/// Compares the rows of the core tables with the expected counts.
+///
+/// #### Arguments
+///
+/// * `counts`: The rows of every existing core table, by its name in the database.
+/// * `expected`: The expected rows by the Ballsdex name of the table, `None` to only report the rows.
+/// * `transformed`: Whether the tables carry their NationDex names.
+///
+fn compare_rows(
+    counts: &BTreeMap<String, u64>,
+    expected: Option<&BTreeMap<String, u64>>,
+    transformed: bool,
+) -> Vec<Check> {
+    let name = |table: &'static str| target_name(table, transformed);
+
+    container::CORE_TABLES
+        .iter()
+        .filter_map(|table| {
+            let expected = expected.and_then(|counts| counts.get(*table)).copied();
+            let actual = counts.get(name(table)).copied();
+
+            // A table neither the source nor the target has is no finding.
+            if expected.is_none() && actual.is_none() {
+                return None;
+            }
+
+            Some(Check {
+                name: format!("rows of {}", name(table)),
+                expected: expected.map(|count| count.to_string()),
+                actual: actual.map_or_else(|| "missing".to_string(), |count| count.to_string()),
+                passed: actual.is_some() && expected.is_none_or(|expected| Some(expected) == actual),
+            })
+        })
+        .collect()
+}
+
+///
+/// Prints the checks as a table.
+///
+/// #### Arguments
+///
+/// * `checks`: The checks to print.
+///
+pub fn print(checks: &[Check]) {
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+
+    for check in checks {
+        let (glyph, result) = match check.passed {
+            true => (Glyph::Ok.to_string().green(), "pass".green()),
+            false => (Glyph::Error.to_string().red(), "FAIL".red().bold()),
+        };
+
+        let detail = match &check.expected {
+            Some(expected) => format!("{} (expected {})", check.actual, expected),
+            None => check.actual.clone(),
+        };
+
+        output::info(format!(
+            "  {} {}  {}  {}",
+            glyph,
+            format!("{:width$}", check.name).bold(),
+            result,
+            detail
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+/*
+    Checks a freshly written dump for completeness, so a truncated dump is noticed while the source still exists.
+*/
+
+use crate::bundle;
+use crate::cli::ExportArgs;
+use crate::compression::Compression;
+use crate::container;
+use crate::encrypt::Encryption;
+use crate::error::{Error, ErrorKind};
+use crate::format::DumpFormat;
+use crate::output;
+use crate::split;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use bollard::Docker;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/// The comment `pg_dump` writes once a plain dump is complete.
+const TRAILER: &str = "-- PostgreSQL database dump complete";
+
+///
+/// What a dump was found to contain.
+///
+#[derive(Default)]
+struct Contents {
+    /// The tables whose schema is in the dump.
+    schema: BTreeSet<String>,
+    /// The tables whose rows are in the dump.
+    data: BTreeSet<String>,
+    /// Whether the dump ends with the trailer of a complete plain dump.
+    complete: bool,
+}
+
+///
+/// Works out which core Ballsdex tables a dump should hold.
+///
+/// #### Arguments
+///
+/// * `args`: The export arguments.
+/// * `row_counts`: The row counts of the core tables, which only has the tables this Ballsdex version has.
+///
+/// #### Returns
+///
+/// The counted tables, limited by `--tables` and `--exclude-tables`.
+///
+pub fn expected_tables(args: &ExportArgs, row_counts: &BTreeMap<String, u64>) -> Vec<String> {
+    row_counts
+        .keys()
+        .filter(|table| args.tables.is_empty() || args.tables.contains(table))
+        .filter(|table| !args.exclude_tables.contains(table))
+        .cloned()
+        .collect()
+}
+
+///
+/// Verifies a dump after it was written.
+///
+/// Plain dumps must end with the `pg_dump` trailer, custom dumps must have a table of contents `pg_restore` can
+/// read. Both must hold the schema and rows of the expected tables, as far as the export mode includes them.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID of the exported instance, where `pg_restore` runs.
+/// * `dump_path`: The path of the dump.
+/// * `args`: The export arguments.
+/// * `tables`: The core Ballsdex tables the dump should hold.
+///
+/// #### Returns
+///
+/// Whether the dump was verified, `false` for formats that cannot be checked, or an error naming what is missing.
+///
+pub async fn run(
+    docker: &Docker,
+    container_id: &str,
+    dump_path: &Path,
+    args: &ExportArgs,
+    tables: &[String],
+) -> Result<bool, Error> {
+    if !matches!(args.format, DumpFormat::Plain | DumpFormat::Custom) {
+        return Ok(false);
+    }
+
+    // Reading the table of contents attaches to pg_restore, which is what --via-file works around.
+    if args.format == DumpFormat::Custom && args.via_file {
+        output::debug("Not verifying the dump, pg_restore cannot be attached to with --via-file");
+        return Ok(false);
+    }
+
+    if Encryption::from_args(args).is_some() {
+        output::debug("Not verifying the dump, ndmig cannot decrypt it");
+        return Ok(false);
+    }
+
+    // A delta holds some rows of some tables and never the end marker of pg_dump.
+    if args.incremental || args.guild_id.is_some() || crate::filter::is_filtered(args) {
+        output::debug("Not verifying the dump, it only holds some rows");
+        return Ok(false);
+    }
+
+    let failed = |message: String| {
+        Error::new(
+            ErrorKind::DumpFailed,
+            format!(
+                "Verification of {} failed: {}\nThe dump was kept for inspection, do not rely on it. Pass --no-verify to skip this check.",
+                dump_path.display(),
+                message
+            ),
+        )
+    };
+
+    let mut reader = open(dump_path, args)
+        .await
+        .map_err(|e| failed(format!("failed to read it back: {}", e)))?;
+
+    let contents = match args.format {
+        DumpFormat::Custom => read_table_of_contents(docker, container_id, &mut reader)
+            .await
+            .map_err(failed)?,
+        _ => scan_plain(&mut reader)
+            .await
+            .map_err(|e| failed(format!("failed to read it back: {}", e)))?,
+    };
+
+    if args.format == DumpFormat::Plain && !contents.complete {
+        return Err(failed(format!(
+            "it does not end with \"{}\", so it is truncated",
+            TRAILER
+        )));
+    }
+
+    let mut missing = Vec::new();
+
+    for table in tables {
+        if !args.data_only && !contents.schema.contains(table) {
+            missing.push(format!("the schema of {}", table));
+        }
+
+        if !args.schema_only && !contents.data.contains(table) {
+            missing.push(format!("the rows of {}", table));
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(failed(format!("it lacks {}", missing.join(", "))));
+    }
+
+    Ok(true)
+}
+
+///
+/// Opens a dump for reading, decompressing it on the fly.
+///
+/// #### Arguments
+///
+/// * `dump_path`: The path of the dump. With `--bundle`, the dump is read from the bundle that is being written.
+/// * `args`: The export arguments, for the compression and the bundle.
+///
+async fn open(dump_path: &Path, args: &ExportArgs) -> std::io::Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let file: Box<dyn AsyncRead + Unpin + Send> = match args.bundle {
+        true => Box::new(bundle::open_dump(&bundle::path(dump_path)).await?),
+        false => Box::new(tokio::fs::File::open(dump_path).await?),
+    };
+    let file = BufReader::new(file);
+
+    Ok(match args.compress {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(GzipDecoder::new(file)),
+        Compression::Zstd => Box::new(ZstdDecoder::new(file)),
+    })
+}
+
+///
+/// Reads a plain dump line by line, collecting its `CREATE TABLE` and `COPY` statements.
+///
+/// #### Arguments
+///
+/// * `reader`: The uncompressed dump.
+///
+async fn scan_plain(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Contents> {
+    let mut reader = BufReader::new(reader);
+    let mut contents = Contents::default();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            break;
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+
+        if let Some(rest) = text.strip_prefix("CREATE TABLE ") {
+            contents.schema.insert(table_name(rest));
+        } else if let Some(rest) = text.strip_prefix("COPY ") {
+            contents.data.insert(table_name(rest));
+        }
+
+        // Newer pg_dump versions follow the trailer with an `\unrestrict` line instead of ending on it, and the parts of
+        // a split export with the statements of their marker.
+        if text == TRAILER {
+            contents.complete = true;
+        } else if !text.is_empty()
+            && !text.starts_with("--")
+            && !text.starts_with("\\unrestrict")
+            && !split::is_trailing_statement(text)
+        {
+            contents.complete = false;
+        }
+    }
+
+    Ok(contents)
+}
+
+///
+/// Lists a custom dump with `pg_restore --list` in the container, collecting its tables.
+///
+/// #### Arguments
+///
+/// * `docker`: The Docker client.
+/// * `container_id`: The container ID where `pg_restore` runs.
+/// * `reader`: The uncompressed dump, sent to `pg_restore` on stdin.
+///
+/// #### Returns
+///
+/// The tables of the table of contents, or a message if it could not be read.
+///
+async fn read_table_of_contents(
+    docker: &Docker,
+    container_id: &str,
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Contents, String> {
+    let cmd = vec!["pg_restore".to_string(), "--list".to_string()];
+    let output = container::exec_with_input(docker, container_id, cmd, Vec::new(), reader)
+        .await
+        .map_err(|e| format!("failed to run pg_restore: {}", e))?;
+
+    if output.exit_code != Some(0) {
+        return Err(format!(
+            "pg_restore cannot read its table of contents: {}",
+            output.stderr_text()
+        ));
+    }
+
+    let mut contents = Contents {
+        complete: true,
+        ..Default::default()
+    };
+
+    // Entries look like `215; 1259 16390 TABLE public player ballsdex`, comments start with `;`.
+    for line in output.stdout_text().lines().filter(|line| !line.starts_with(';')) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.get(3..6) {
+            Some(["TABLE", "DATA", _]) => {
+                if let Some(table) = words.get(6) {
+                    contents.data.insert(table.to_string());
+                }
+            }
+            Some(["TABLE", _, table]) => {
+                contents.schema.insert(table.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(contents)
+}
+
+///
+/// Reads the table name from a `CREATE TABLE` or `COPY` statement, without its schema and quotes.
+///
+/// #### Arguments
+///
+/// * `statement`: The statement after `CREATE TABLE ` or `COPY `, e.g. `public.player (`.
+///
+fn table_name(statement: &str) -> String {
+    let name = statement.split([' ', '(']).next().unwrap_or_default();
+    let name = name.rsplit('.').next().unwrap_or(name);
+
+    name.trim_matches('"').to_string()
+}
@@ -0,0 +1,95 @@
+/*
+    The `--version` output, with the details a bug report needs.
+*/
+
+use crate::output;
+use bollard::{API_DEFAULT_VERSION, Docker};
+use colored::*;
+use serde::Serialize;
+use std::time::Duration;
+
+/// How long to wait for the Docker daemon, so `--version` never hangs on a dead socket.
+const DOCKER_TIMEOUT: Duration = Duration::from_secs(2);
+
+///
+/// What the Docker daemon reports about itself.
+///
+#[derive(Serialize)]
+struct DockerVersion {
+    version: Option<String>,
+    api_version: Option<String>,
+    os: Option<String>,
+    arch: Option<String>,
+}
+
+///
+/// Everything `--version` prints.
+///
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    docker_api: String,
+    /// `None` if the daemon could not be reached in time.
+    docker: Option<DockerVersion>,
+}
+
+///
+/// Asks the Docker daemon for its version.
+///
+/// #### Returns
+///
+/// The version, or `None` if the daemon is unreachable or does not answer within [`DOCKER_TIMEOUT`].
+///
+async fn docker_version() -> Option<DockerVersion> {
+    let docker = Docker::connect_with_local_defaults().ok()?;
+    let version = tokio::time::timeout(DOCKER_TIMEOUT, docker.version())
+        .await
+        .ok()?
+        .ok()?;
+
+    Some(DockerVersion {
+        version: version.version,
+        api_version: version.api_version,
+        os: version.os,
+        arch: version.arch,
+    })
+}
+
+///
+/// Prints the version of ndmig, the Docker API it speaks and the Docker daemon it talks to.
+///
+pub async fn run() {
+    let info = VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: option_env!("NDMIG_GIT_COMMIT"),
+        docker_api: API_DEFAULT_VERSION.to_string(),
+        docker: docker_version().await,
+    };
+
+    if output::is_json() {
+        output::json(&info);
+        return;
+    }
+
+    let unknown = || "unknown".to_string();
+
+    output::info(format!(
+        "{} {} ({})",
+        "ndmig".bold(),
+        info.version,
+        info.git_commit.unwrap_or("unknown commit")
+    ));
+    output::info(format!("bollard: Docker API {}", info.docker_api));
+
+    match info.docker {
+        Some(docker) => output::info(format!(
+            "docker: {} (API {}, {}/{})",
+            docker.version.unwrap_or_else(unknown),
+            docker.api_version.unwrap_or_else(unknown),
+            docker.os.unwrap_or_else(unknown),
+            docker.arch.unwrap_or_else(unknown)
+        )),
+        None => output::info(format!("docker: {}", "unavailable".dimmed())),
+    }
+}